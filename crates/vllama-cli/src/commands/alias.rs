@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use clap::Subcommand;
+use serde_json::json;
+use std::path::PathBuf;
+use vllama_core::ModelAliases;
+
+use crate::output::{self, OutputMode};
+
+#[derive(Subcommand)]
+pub enum AliasAction {
+    #[command(about = "Make NAME another name for MODEL")]
+    Add {
+        #[arg(help = "Short name (e.g., tiny)")]
+        name: String,
+
+        #[arg(help = "Model, or another alias, it stands for (e.g., meta-llama/Llama-3.2-1B-Instruct)")]
+        model: String,
+    },
+
+    #[command(about = "List aliases")]
+    List,
+
+    #[command(visible_alias = "remove", about = "Remove an alias (the model itself is kept)")]
+    Rm {
+        #[arg(help = "Alias to remove")]
+        name: String,
+    },
+}
+
+fn aliases_path() -> Result<PathBuf> {
+    ModelAliases::default_path().context("cannot locate the config directory (HOME is not set)")
+}
+
+/// The model `name` stands for under the user's aliases
+pub fn resolve(name: &str) -> Result<String> {
+    let Some(path) = ModelAliases::default_path() else {
+        return Ok(name.to_string());
+    };
+    Ok(ModelAliases::load(&path)?.resolve(name)?)
+}
+
+pub async fn execute(action: Option<AliasAction>, output_mode: OutputMode) -> Result<()> {
+    let path = aliases_path()?;
+    let mut aliases = ModelAliases::load(&path)?;
+
+    match action.unwrap_or(AliasAction::List) {
+        AliasAction::Add { name, model } => {
+            aliases.insert(&name, &model)?;
+            aliases.save(&path)?;
+            match output_mode {
+                OutputMode::Json => output::json(&json!({"alias": name, "model": model})),
+                OutputMode::Quiet => {}
+                OutputMode::Normal => println!("{}", output::success(&format!("{} -> {}", name, model))),
+            }
+        }
+        AliasAction::List => match output_mode {
+            OutputMode::Json => output::json(&aliases),
+            OutputMode::Quiet => {
+                for (name, _) in aliases.iter() {
+                    println!("{}", name);
+                }
+            }
+            OutputMode::Normal => {
+                if aliases.is_empty() {
+                    println!("{}", output::info("No aliases defined; add one with `vllama alias add NAME MODEL`"));
+                }
+                for (name, model) in aliases.iter() {
+                    output::kv(name, model);
+                }
+            }
+        },
+        AliasAction::Rm { name } => {
+            let model = aliases.remove(&name).with_context(|| format!("no alias named {}", name))?;
+            aliases.save(&path)?;
+            match output_mode {
+                OutputMode::Json => output::json(&json!({"alias": name, "model": model})),
+                OutputMode::Quiet => {}
+                OutputMode::Normal => println!("{}", output::success(&format!("Removed {} ({})", name, model))),
+            }
+        }
+    }
+
+    Ok(())
+}
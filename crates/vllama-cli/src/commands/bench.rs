@@ -1,20 +1,86 @@
 use anyhow::Result;
 use serde::Serialize;
 use vllama_core::{GenerateRequest, Hardware};
-use vllama_engine::{InferenceEngine, VllmOpenAIEngine};
-use std::time::Instant;
+use vllama_engine::InferenceEngine;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::task::JoinSet;
 use tracing::warn;
 
+use crate::config::VllmEndpoint;
 use crate::output::{self, OutputMode};
 
+/// Engine to compare vllama against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Baseline {
+    /// Ollama on port 11435
+    Ollama,
+    /// Benchmark vllama alone
+    None,
+}
+
+/// Sampling settings applied to every benchmark request
+///
+/// Output length drives tokens/sec, so comparable runs need the same output:
+/// a fixed seed, and greedy decoding for runs that must match exactly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BenchSampling {
+    pub seed: Option<u64>,
+    /// Temperature 0
+    pub deterministic: bool,
+}
+
+impl BenchSampling {
+    fn apply(&self, mut request: GenerateRequest) -> GenerateRequest {
+        request.options.sampling.seed = self.seed;
+        if self.deterministic {
+            request.options.sampling.temperature = 0.0;
+        }
+        request
+    }
+
+    fn ollama_options(&self) -> OllamaOptions {
+        OllamaOptions {
+            num_predict: 50,
+            seed: self.seed,
+            temperature: self.deterministic.then_some(0.0),
+        }
+    }
+}
+
+/// A second vLLM deployment to run the same workload against
+#[derive(Debug, Clone)]
+pub struct CompareTarget {
+    pub label: String,
+    pub endpoint: VllmEndpoint,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComparedEngine {
+    label: String,
+    url: String,
+    stats: Option<EngineStats>,
+    /// Total time of the primary endpoint over this one's; above 1 means
+    /// this configuration is faster
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speedup: Option<f64>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct BenchmarkResult {
     model: String,
     concurrency: usize,
     iterations: usize,
+    /// Seed sent with every request, if any
+    seed: Option<u64>,
+    deterministic: bool,
     vllama: Option<EngineStats>,
-    ollama: Option<EngineStats>,
+    /// None when no baseline was requested; Some(None) when Ollama failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ollama: Option<Option<EngineStats>>,
+    /// Second vLLM configuration from --compare-endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compare: Option<ComparedEngine>,
     hardware: HardwareInfo,
 }
 
@@ -26,6 +92,8 @@ pub struct EngineStats {
     tokens_per_sec: f64,
     total_time_secs: f64,
     requests_per_sec: f64,
+    /// Requests abandoned after --timeout; excluded from the latencies
+    timeouts: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -35,12 +103,19 @@ pub struct HardwareInfo {
     ram_mb: u64,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn execute(
     model: String,
     prompt: String,
     iterations: usize,
     concurrency: usize,
+    baseline: Baseline,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+    vllm: VllmEndpoint,
+    compare: Option<CompareTarget>,
     output_mode: OutputMode,
+    markdown: bool,
 ) -> Result<()> {
     let hw = Hardware::detect();
     let hw_info = HardwareInfo {
@@ -56,57 +131,159 @@ pub async fn execute(
         output::kv("Model", &model);
         output::kv("Concurrency", &concurrency.to_string());
         output::kv("Iterations", &iterations.to_string());
+        if let Some(timeout) = timeout {
+            output::kv("Timeout", &format!("{}s", timeout.as_secs()));
+        }
+        if let Some(seed) = sampling.seed {
+            output::kv("Seed", &seed.to_string());
+        }
+        if sampling.deterministic {
+            output::kv("Sampling", "greedy (temperature 0)");
+        }
         output::kv("Hardware", &format!("{:?}", hw.hw_type));
         output::kv("CPU Cores", &hw.cpu_cores.to_string());
-        output::kv("RAM", &format!("{} MB", hw.ram_total_mb));
+        output::kv("RAM", &output::memory_mb(hw.ram_total_mb));
         println!();
     }
 
-    // Test vLLM
+    let vllama_stats = bench_vllm("vllama", &vllm, &model, &prompt, iterations, concurrency, timeout, sampling, output_mode).await;
+
+    let compare = match compare {
+        Some(target) => {
+            let stats = bench_vllm(
+                &target.label, &target.endpoint, &model, &prompt, iterations, concurrency, timeout, sampling, output_mode,
+            )
+            .await;
+            let speedup = match (&vllama_stats, &stats) {
+                (Some(primary), Some(other)) => Some(primary.total_time_secs / other.total_time_secs),
+                _ => None,
+            };
+            Some(ComparedEngine {
+                label: target.label,
+                url: target.endpoint.url,
+                stats,
+                speedup,
+            })
+        }
+        None => None,
+    };
+
+    let ollama_stats = match baseline {
+        Baseline::Ollama => Some(bench_ollama(&model, &prompt, iterations, concurrency, timeout, sampling, output_mode).await),
+        Baseline::None => None,
+    };
+
+    // Show comparison
+    if output_mode == OutputMode::Normal {
+        if let (Some(vllama), Some(Some(ollama))) = (&vllama_stats, &ollama_stats) {
+            print_comparison("Comparison", ollama, vllama);
+        }
+        if let (Some(vllama), Some(ComparedEngine { label, stats: Some(other), .. })) = (&vllama_stats, &compare) {
+            print_comparison(&format!("Comparison: {} vs vllama", label), vllama, other);
+        }
+    }
+
+    // Output structured results
+    let result = BenchmarkResult {
+        model,
+        concurrency,
+        iterations,
+        seed: sampling.seed,
+        deterministic: sampling.deterministic,
+        vllama: vllama_stats,
+        ollama: ollama_stats,
+        compare,
+        hardware: hw_info,
+    };
+
+    if output_mode == OutputMode::Json {
+        output::json(&result);
+    } else if markdown {
+        print!("{}", result.to_markdown());
+    }
+
+    Ok(())
+}
+
+/// How much faster `candidate` ran the workload than `baseline`
+fn print_comparison(title: &str, baseline: &EngineStats, candidate: &EngineStats) {
+    let speedup = baseline.total_time_secs / candidate.total_time_secs;
+    println!("{}", output::section(title));
+    println!();
+    output::kv("Speedup", &format!("{:.2}x faster", speedup));
+    let latency_improvement = (baseline.median_latency_ms - candidate.median_latency_ms) / baseline.median_latency_ms * 100.0;
+    output::kv("Latency improvement", &format!("{:.1}% lower", latency_improvement));
+}
+
+/// Run the workload against one vLLM endpoint, printing its results as `label`
+#[allow(clippy::too_many_arguments)]
+async fn bench_vllm(
+    label: &str,
+    vllm: &VllmEndpoint,
+    model: &str,
+    prompt: &str,
+    iterations: usize,
+    concurrency: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+    output_mode: OutputMode,
+) -> Option<EngineStats> {
     if output_mode == OutputMode::Normal {
-        println!("{}", output::info("Testing vllama..."));
+        println!("{}", output::info(&format!("Testing {}...", label)));
     }
 
-    let vllama_result = if concurrency == 1 {
-        test_vllm_sequential(&model, &prompt, iterations).await
+    let result = if concurrency == 1 {
+        test_vllm_sequential(vllm, model, prompt, iterations, timeout, sampling).await
     } else {
-        test_vllm_concurrent(&model, &prompt, iterations, concurrency).await
+        test_vllm_concurrent(vllm, model, prompt, iterations, concurrency, timeout, sampling).await
     };
 
-    let vllama_stats = match vllama_result {
+    match result {
         Ok(stats) => {
             if output_mode == OutputMode::Normal {
-                println!("{}", output::success("vllama results:"));
+                println!("{}", output::success(&format!("{} results:", label)));
                 output::kv("Median latency", &format!("{:.2} ms", stats.median_latency_ms));
                 output::kv("P99 latency", &format!("{:.2} ms", stats.p99_latency_ms));
                 output::kv("Throughput", &format!("{:.2} req/s", stats.requests_per_sec));
                 output::kv("Tokens/sec", &format!("{:.2}", stats.tokens_per_sec));
+                if stats.timeouts > 0 {
+                    output::kv("Timeouts", &stats.timeouts.to_string());
+                }
                 println!();
             }
             Some(stats)
         }
         Err(e) => {
-            warn!("vllama test failed: {}", e);
+            warn!("{} test failed: {}", label, e);
             if output_mode == OutputMode::Normal {
-                println!("{}", output::error(&format!("vllama test failed: {}", e)));
+                println!("{}", output::error(&format!("{} test failed: {}", label, e)));
                 println!();
             }
             None
         }
-    };
+    }
+}
 
-    // Test Ollama
+async fn bench_ollama(
+    model: &str,
+    prompt: &str,
+    iterations: usize,
+    concurrency: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+    output_mode: OutputMode,
+) -> Option<EngineStats> {
     if output_mode == OutputMode::Normal {
         println!("{}", output::info("Testing Ollama (port 11435)..."));
     }
 
     let ollama_result = if concurrency == 1 {
-        test_ollama_sequential(&model, &prompt, iterations).await
+        test_ollama_sequential(model, prompt, iterations, timeout, sampling).await
     } else {
-        test_ollama_concurrent(&model, &prompt, iterations, concurrency).await
+        test_ollama_concurrent(model, prompt, iterations, concurrency, timeout, sampling).await
     };
 
-    let ollama_stats = match ollama_result {
+    match ollama_result {
         Ok(stats) => {
             if output_mode == OutputMode::Normal {
                 println!("{}", output::success("Ollama results:"));
@@ -114,6 +291,9 @@ pub async fn execute(
                 output::kv("P99 latency", &format!("{:.2} ms", stats.p99_latency_ms));
                 output::kv("Throughput", &format!("{:.2} req/s", stats.requests_per_sec));
                 output::kv("Tokens/sec", &format!("{:.2}", stats.tokens_per_sec));
+                if stats.timeouts > 0 {
+                    output::kv("Timeouts", &stats.timeouts.to_string());
+                }
                 println!();
             }
             Some(stats)
@@ -122,62 +302,106 @@ pub async fn execute(
             warn!("Ollama test failed: {}", e);
             if output_mode == OutputMode::Normal {
                 println!("{}", output::warning("Ollama not available (start on port 11435)"));
+                println!("{}", output::bullet("Use --engine-only to benchmark vllama alone"));
                 println!();
             }
             None
         }
-    };
+    }
+}
 
-    // Show comparison
-    if output_mode == OutputMode::Normal {
-        if let (Some(vllama), Some(ollama)) = (&vllama_stats, &ollama_stats) {
-            let speedup = ollama.total_time_secs / vllama.total_time_secs;
-            println!("{}", output::section("Comparison"));
-            println!();
-            output::kv("Speedup", &format!("{:.2}x faster", speedup));
-            let latency_improvement = (ollama.median_latency_ms - vllama.median_latency_ms) / ollama.median_latency_ms * 100.0;
-            output::kv("Latency improvement", &format!("{:.1}% lower", latency_improvement));
+impl BenchmarkResult {
+    /// Render the results as GitHub-flavored Markdown for pasting into issues
+    fn to_markdown(&self) -> String {
+        let setup = output::markdown_table(
+            &["Model", "Concurrency", "Iterations", "Hardware", "CPU Cores", "RAM"],
+            &[vec![
+                format!("`{}`", self.model),
+                self.concurrency.to_string(),
+                self.iterations.to_string(),
+                self.hardware.hw_type.clone(),
+                self.hardware.cpu_cores.to_string(),
+                output::memory_mb(self.hardware.ram_mb),
+            ]],
+        );
+
+        let mut rows = Vec::new();
+        let mut engines = vec![("vllama", &self.vllama)];
+        if let Some(compare) = &self.compare {
+            engines.push((compare.label.as_str(), &compare.stats));
+        }
+        if let Some(ollama) = &self.ollama {
+            engines.push(("Ollama", ollama));
         }
-    }
 
-    // Output structured results
-    let result = BenchmarkResult {
-        model,
-        concurrency,
-        iterations,
-        vllama: vllama_stats,
-        ollama: ollama_stats,
-        hardware: hw_info,
-    };
+        for (name, stats) in engines {
+            match stats {
+                Some(stats) => rows.push(vec![
+                    name.to_string(),
+                    format!("{:.2}", stats.median_latency_ms),
+                    format!("{:.2}", stats.avg_latency_ms),
+                    format!("{:.2}", stats.p99_latency_ms),
+                    format!("{:.2}", stats.requests_per_sec),
+                    format!("{:.2}", stats.tokens_per_sec),
+                    format!("{:.2}", stats.total_time_secs),
+                    stats.timeouts.to_string(),
+                ]),
+                None => rows.push(vec![
+                    name.to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                    "n/a".to_string(),
+                ]),
+            }
+        }
 
-    if output_mode == OutputMode::Json {
-        output::json(&result);
+        let results = output::markdown_table(
+            &[
+                "Engine",
+                "Median (ms)",
+                "Avg (ms)",
+                "P99 (ms)",
+                "Req/s",
+                "Tokens/s",
+                "Total (s)",
+                "Timeouts",
+            ],
+            &rows,
+        );
+
+        format!("### vllama Benchmark\n\n{}\n{}", setup, results)
     }
-
-    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct BenchStats {
-    median_latency_ms: f64,
-    avg_latency_ms: f64,
-    p99_latency_ms: f64,
-    tokens_per_sec: f64,
-    total_time_secs: f64,
-    requests_per_sec: f64,
-}
-
-impl From<BenchStats> for EngineStats {
-    fn from(stats: BenchStats) -> Self {
-        Self {
-            median_latency_ms: stats.median_latency_ms,
-            avg_latency_ms: stats.avg_latency_ms,
-            p99_latency_ms: stats.p99_latency_ms,
-            tokens_per_sec: stats.tokens_per_sec,
-            total_time_secs: stats.total_time_secs,
-            requests_per_sec: stats.requests_per_sec,
-        }
+/// Latency and throughput over the requests that completed
+///
+/// Errors if every request timed out, since there is nothing to measure.
+fn summarize(
+    latencies: &[f64],
+    total_tokens: usize,
+    total_duration: Duration,
+    timeouts: usize,
+) -> Result<EngineStats> {
+    if latencies.is_empty() {
+        anyhow::bail!("all {} requests timed out", timeouts);
     }
+
+    let mut sorted_latencies = latencies.to_vec();
+    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    Ok(EngineStats {
+        median_latency_ms: calculate_percentile(&sorted_latencies, 50.0),
+        avg_latency_ms: latencies.iter().sum::<f64>() / latencies.len() as f64,
+        p99_latency_ms: calculate_percentile(&sorted_latencies, 99.0),
+        tokens_per_sec: total_tokens as f64 / total_duration.as_secs_f64(),
+        total_time_secs: total_duration.as_secs_f64(),
+        requests_per_sec: latencies.len() as f64 / total_duration.as_secs_f64(),
+        timeouts,
+    })
 }
 
 fn calculate_percentile(sorted_values: &[f64], percentile: f64) -> f64 {
@@ -185,59 +409,77 @@ fn calculate_percentile(sorted_values: &[f64], percentile: f64) -> f64 {
     sorted_values[index]
 }
 
-async fn test_vllm_sequential(model: &str, prompt: &str, iterations: usize) -> Result<EngineStats> {
-    let vllm_engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
+/// Run one benchmark request, returning None if it outlives `timeout`
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    request: impl Future<Output = Result<T>>,
+) -> Result<Option<T>> {
+    match timeout {
+        Some(limit) => match tokio::time::timeout(limit, request).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => request.await.map(Some),
+    }
+}
 
-    if !vllm_engine.health_check().await? {
-        anyhow::bail!("vLLM OpenAI server not available (run: python -m vllm.entrypoints.openai.api_server --model {} --port 8100)", model);
+async fn test_vllm_sequential(
+    vllm: &VllmEndpoint,
+    model: &str,
+    prompt: &str,
+    iterations: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+) -> Result<EngineStats> {
+    let vllm_engine = vllm.engine();
+
+    let healthy = with_timeout(timeout, async { Ok(vllm_engine.health_check().await?) }).await?;
+    if healthy != Some(true) {
+        anyhow::bail!("vLLM OpenAI server not available at {} (run: vllama serve --model {})", vllm.url, model);
     }
 
     let mut latencies = Vec::new();
     let mut total_tokens = 0usize;
+    let mut timeouts = 0usize;
     let start = Instant::now();
 
     for i in 0..iterations {
-        let request = GenerateRequest::new(i as u64, model.to_string(), prompt.to_string())
-            .with_max_tokens(50);
+        let request = sampling.apply(
+            GenerateRequest::new(i as u64, model.to_string(), prompt.to_string()).with_max_tokens(50),
+        );
 
         let iter_start = Instant::now();
-        let response = vllm_engine.generate(request).await?;
-        let iter_duration = iter_start.elapsed();
-
-        latencies.push(iter_duration.as_millis() as f64);
-        total_tokens += response.stats.generated_tokens;
+        match with_timeout(timeout, async { Ok(vllm_engine.generate(request).await?) }).await? {
+            Some(response) => {
+                latencies.push(iter_start.elapsed().as_millis() as f64);
+                total_tokens += response.stats.generated_tokens;
+            }
+            None => timeouts += 1,
+        }
     }
 
-    let total_duration = start.elapsed();
-
-    let mut sorted_latencies = latencies.clone();
-    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let median_latency = calculate_percentile(&sorted_latencies, 50.0);
-    let p99_latency = calculate_percentile(&sorted_latencies, 99.0);
-    let avg_latency = latencies.iter().sum::<f64>() / latencies.len() as f64;
-    let tokens_per_sec = total_tokens as f64 / total_duration.as_secs_f64();
-    let requests_per_sec = iterations as f64 / total_duration.as_secs_f64();
-
-    Ok(BenchStats {
-        median_latency_ms: median_latency,
-        avg_latency_ms: avg_latency,
-        p99_latency_ms: p99_latency,
-        tokens_per_sec,
-        total_time_secs: total_duration.as_secs_f64(),
-        requests_per_sec,
-    }.into())
+    summarize(&latencies, total_tokens, start.elapsed(), timeouts)
 }
 
-async fn test_vllm_concurrent(model: &str, prompt: &str, total_requests: usize, concurrency: usize) -> Result<EngineStats> {
-    let vllm_engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
+async fn test_vllm_concurrent(
+    vllm: &VllmEndpoint,
+    model: &str,
+    prompt: &str,
+    total_requests: usize,
+    concurrency: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+) -> Result<EngineStats> {
+    let vllm_engine = vllm.engine();
 
-    if !vllm_engine.health_check().await? {
+    let healthy = with_timeout(timeout, async { Ok(vllm_engine.health_check().await?) }).await?;
+    if healthy != Some(true) {
         anyhow::bail!("vLLM OpenAI server not available");
     }
 
     let mut latencies = Vec::new();
     let mut total_tokens = 0usize;
+    let mut timeouts = 0usize;
     let start = Instant::now();
 
     let mut tasks = JoinSet::new();
@@ -250,54 +492,39 @@ async fn test_vllm_concurrent(model: &str, prompt: &str, total_requests: usize,
         for _ in batch_start..batch_end {
             let model_clone = model.to_string();
             let prompt_clone = prompt.to_string();
+            let vllm = vllm.clone();
             let req_id = request_id;
             request_id += 1;
 
             tasks.spawn(async move {
-                let engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
-                let request = GenerateRequest::new(req_id, model_clone, prompt_clone)
-                    .with_max_tokens(50);
+                let engine = vllm.engine();
+                let request = sampling.apply(
+                    GenerateRequest::new(req_id, model_clone, prompt_clone).with_max_tokens(50),
+                );
 
                 let iter_start = Instant::now();
-                let response = engine.generate(request).await?;
+                let response = with_timeout(timeout, async { Ok(engine.generate(request).await?) }).await?;
                 let iter_duration = iter_start.elapsed();
 
-                Ok::<(f64, usize), anyhow::Error>((iter_duration.as_millis() as f64, response.stats.generated_tokens))
+                Ok::<_, anyhow::Error>(response.map(|r| (iter_duration.as_millis() as f64, r.stats.generated_tokens)))
             });
         }
 
         // Wait for this batch to complete
         while let Some(result) = tasks.join_next().await {
             match result {
-                Ok(Ok((latency, tokens))) => {
+                Ok(Ok(Some((latency, tokens)))) => {
                     latencies.push(latency);
                     total_tokens += tokens;
                 }
+                Ok(Ok(None)) => timeouts += 1,
                 Ok(Err(e)) => return Err(e),
                 Err(e) => return Err(anyhow::anyhow!("Task join error: {}", e)),
             }
         }
     }
 
-    let total_duration = start.elapsed();
-
-    let mut sorted_latencies = latencies.clone();
-    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let median_latency = calculate_percentile(&sorted_latencies, 50.0);
-    let p99_latency = calculate_percentile(&sorted_latencies, 99.0);
-    let avg_latency = latencies.iter().sum::<f64>() / latencies.len() as f64;
-    let tokens_per_sec = total_tokens as f64 / total_duration.as_secs_f64();
-    let requests_per_sec = total_requests as f64 / total_duration.as_secs_f64();
-
-    Ok(BenchStats {
-        median_latency_ms: median_latency,
-        avg_latency_ms: avg_latency,
-        p99_latency_ms: p99_latency,
-        tokens_per_sec,
-        total_time_secs: total_duration.as_secs_f64(),
-        requests_per_sec,
-    }.into())
+    summarize(&latencies, total_tokens, start.elapsed(), timeouts)
 }
 
 fn translate_to_ollama_model(hf_model: &str) -> &str {
@@ -311,107 +538,99 @@ fn translate_to_ollama_model(hf_model: &str) -> &str {
     }
 }
 
-async fn test_ollama_sequential(model: &str, prompt: &str, iterations: usize) -> Result<EngineStats> {
-    let client = reqwest::Client::new();
-    let ollama_model = translate_to_ollama_model(model);
+#[derive(serde::Serialize)]
+struct OllamaRequest {
+    model: String,
+    prompt: String,
+    stream: bool,
+    options: OllamaOptions,
+}
 
-    #[derive(serde::Serialize)]
-    struct OllamaRequest {
-        model: String,
-        prompt: String,
-        stream: bool,
-        options: OllamaOptions,
-    }
+#[derive(serde::Serialize)]
+struct OllamaOptions {
+    num_predict: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
 
-    #[derive(serde::Serialize)]
-    struct OllamaOptions {
-        num_predict: i32,
-    }
+#[derive(serde::Deserialize)]
+struct OllamaResponse {
+    eval_count: Option<usize>,
+}
+
+/// One non-streaming Ollama generation, returning its token count
+async fn ollama_generate(
+    client: &reqwest::Client,
+    model: &str,
+    prompt: &str,
+    sampling: BenchSampling,
+) -> Result<usize> {
+    let request = OllamaRequest {
+        model: model.to_string(),
+        prompt: prompt.to_string(),
+        stream: false,
+        options: sampling.ollama_options(),
+    };
+
+    let response = client
+        .post("http://localhost:11435/api/generate")
+        .json(&request)
+        .send()
+        .await?;
 
-    #[derive(serde::Deserialize)]
-    struct OllamaResponse {
-        eval_count: Option<usize>,
+    if !response.status().is_success() {
+        anyhow::bail!("Ollama request failed: {}", response.status());
     }
 
+    let ollama_resp: OllamaResponse = response.json().await?;
+    Ok(ollama_resp.eval_count.unwrap_or(0))
+}
+
+async fn test_ollama_sequential(
+    model: &str,
+    prompt: &str,
+    iterations: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+) -> Result<EngineStats> {
+    let client = reqwest::Client::new();
+    let ollama_model = translate_to_ollama_model(model);
+
     let mut latencies = Vec::new();
     let mut total_tokens = 0usize;
+    let mut timeouts = 0usize;
     let start = Instant::now();
 
     for _ in 0..iterations {
-        let request = OllamaRequest {
-            model: ollama_model.to_string(),
-            prompt: prompt.to_string(),
-            stream: false,
-            options: OllamaOptions {
-                num_predict: 50,
-            },
-        };
-
         let iter_start = Instant::now();
-        let response = client
-            .post("http://localhost:11435/api/generate")
-            .json(&request)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            anyhow::bail!("Ollama request failed: {}", response.status());
-        }
-
-        let ollama_resp: OllamaResponse = response.json().await?;
-        let iter_duration = iter_start.elapsed();
-
-        latencies.push(iter_duration.as_millis() as f64);
-        if let Some(count) = ollama_resp.eval_count {
-            total_tokens += count;
+        match with_timeout(timeout, ollama_generate(&client, ollama_model, prompt, sampling)).await? {
+            Some(tokens) => {
+                latencies.push(iter_start.elapsed().as_millis() as f64);
+                total_tokens += tokens;
+            }
+            None => timeouts += 1,
         }
     }
 
-    let total_duration = start.elapsed();
-
-    let mut sorted_latencies = latencies.clone();
-    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
-
-    let median_latency = calculate_percentile(&sorted_latencies, 50.0);
-    let p99_latency = calculate_percentile(&sorted_latencies, 99.0);
-    let avg_latency = latencies.iter().sum::<f64>() / latencies.len() as f64;
-    let tokens_per_sec = total_tokens as f64 / total_duration.as_secs_f64();
-    let requests_per_sec = iterations as f64 / total_duration.as_secs_f64();
-
-    Ok(BenchStats {
-        median_latency_ms: median_latency,
-        avg_latency_ms: avg_latency,
-        p99_latency_ms: p99_latency,
-        tokens_per_sec,
-        total_time_secs: total_duration.as_secs_f64(),
-        requests_per_sec,
-    }.into())
+    summarize(&latencies, total_tokens, start.elapsed(), timeouts)
 }
 
-async fn test_ollama_concurrent(model: &str, prompt: &str, total_requests: usize, concurrency: usize) -> Result<EngineStats> {
+async fn test_ollama_concurrent(
+    model: &str,
+    prompt: &str,
+    total_requests: usize,
+    concurrency: usize,
+    timeout: Option<Duration>,
+    sampling: BenchSampling,
+) -> Result<EngineStats> {
     let client = reqwest::Client::new();
     let ollama_model = translate_to_ollama_model(model);
 
-    #[derive(serde::Serialize)]
-    struct OllamaRequest {
-        model: String,
-        prompt: String,
-        stream: bool,
-        options: OllamaOptions,
-    }
-
-    #[derive(serde::Serialize)]
-    struct OllamaOptions {
-        num_predict: i32,
-    }
-
-    #[derive(serde::Deserialize)]
-    struct OllamaResponse {
-        eval_count: Option<usize>,
-    }
-
     let mut latencies = Vec::new();
     let mut total_tokens = 0usize;
+    let mut timeouts = 0usize;
     let start = Instant::now();
 
     let mut tasks = JoinSet::new();
@@ -426,65 +645,112 @@ async fn test_ollama_concurrent(model: &str, prompt: &str, total_requests: usize
             let prompt_clone = prompt.to_string();
 
             tasks.spawn(async move {
-                let request = OllamaRequest {
-                    model: model_clone,
-                    prompt: prompt_clone,
-                    stream: false,
-                    options: OllamaOptions {
-                        num_predict: 50,
-                    },
-                };
-
                 let iter_start = Instant::now();
-                let response = client_clone
-                    .post("http://localhost:11435/api/generate")
-                    .json(&request)
-                    .send()
-                    .await?;
-
-                if !response.status().is_success() {
-                    anyhow::bail!("Ollama request failed: {}", response.status());
-                }
-
-                let ollama_resp: OllamaResponse = response.json().await?;
+                let tokens = with_timeout(timeout, ollama_generate(&client_clone, &model_clone, &prompt_clone, sampling)).await?;
                 let iter_duration = iter_start.elapsed();
 
-                let tokens = ollama_resp.eval_count.unwrap_or(0);
-
-                Ok::<(f64, usize), anyhow::Error>((iter_duration.as_millis() as f64, tokens))
+                Ok::<_, anyhow::Error>(tokens.map(|t| (iter_duration.as_millis() as f64, t)))
             });
         }
 
         // Wait for this batch to complete
         while let Some(result) = tasks.join_next().await {
             match result {
-                Ok(Ok((latency, tokens))) => {
+                Ok(Ok(Some((latency, tokens)))) => {
                     latencies.push(latency);
                     total_tokens += tokens;
                 }
+                Ok(Ok(None)) => timeouts += 1,
                 Ok(Err(e)) => return Err(e),
                 Err(e) => return Err(anyhow::anyhow!("Task join error: {}", e)),
             }
         }
     }
 
-    let total_duration = start.elapsed();
+    summarize(&latencies, total_tokens, start.elapsed(), timeouts)
+}
 
-    let mut sorted_latencies = latencies.clone();
-    sorted_latencies.sort_by(|a, b| a.partial_cmp(b).unwrap());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(ollama: Option<Option<EngineStats>>) -> BenchmarkResult {
+        BenchmarkResult {
+            model: "m".to_string(),
+            concurrency: 1,
+            iterations: 1,
+            seed: None,
+            deterministic: false,
+            vllama: None,
+            ollama,
+            compare: None,
+            hardware: HardwareInfo {
+                hw_type: "Cpu".to_string(),
+                cpu_cores: 1,
+                ram_mb: 0,
+            },
+        }
+    }
 
-    let median_latency = calculate_percentile(&sorted_latencies, 50.0);
-    let p99_latency = calculate_percentile(&sorted_latencies, 99.0);
-    let avg_latency = latencies.iter().sum::<f64>() / latencies.len() as f64;
-    let tokens_per_sec = total_tokens as f64 / total_duration.as_secs_f64();
-    let requests_per_sec = total_requests as f64 / total_duration.as_secs_f64();
-
-    Ok(BenchStats {
-        median_latency_ms: median_latency,
-        avg_latency_ms: avg_latency,
-        p99_latency_ms: p99_latency,
-        tokens_per_sec,
-        total_time_secs: total_duration.as_secs_f64(),
-        requests_per_sec,
-    }.into())
+    #[test]
+    fn test_summarize_counts_timeouts() {
+        let stats = summarize(&[10.0, 30.0, 20.0], 150, Duration::from_secs(2), 1).unwrap();
+        assert_eq!(stats.timeouts, 1);
+        assert_eq!(stats.median_latency_ms, 20.0);
+        assert_eq!(stats.requests_per_sec, 1.5);
+
+        assert!(summarize(&[], 0, Duration::from_secs(2), 3).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        };
+        assert!(with_timeout(Some(Duration::from_millis(10)), slow).await.unwrap().is_none());
+        assert_eq!(with_timeout(None, async { Ok(1) }).await.unwrap(), Some(1));
+    }
+
+    #[test]
+    fn test_bench_sampling() {
+        let sampling = BenchSampling { seed: Some(42), deterministic: true };
+        let request = sampling.apply(GenerateRequest::new(1, "m".to_string(), "p".to_string()));
+        assert_eq!(request.options.sampling.seed, Some(42));
+        assert_eq!(request.options.sampling.temperature, 0.0);
+
+        let options = serde_json::to_value(BenchSampling::default().ollama_options()).unwrap();
+        assert_eq!(options, serde_json::json!({"num_predict": 50}));
+    }
+
+    #[test]
+    fn test_compare_reported() {
+        let mut compared = result(None);
+        compared.compare = Some(ComparedEngine {
+            label: "prefix-cache".to_string(),
+            url: "http://gpu-b:8000".to_string(),
+            stats: Some(summarize(&[10.0], 50, Duration::from_secs(1), 0).unwrap()),
+            speedup: Some(2.0),
+        });
+
+        let json = serde_json::to_value(&compared).unwrap();
+        assert_eq!(json["compare"]["label"], "prefix-cache");
+        assert_eq!(json["compare"]["speedup"], 2.0);
+        assert_eq!(json["compare"]["stats"]["median_latency_ms"], 10.0);
+        assert!(compared.to_markdown().contains("| prefix-cache |"));
+
+        assert!(serde_json::to_value(result(None)).unwrap().get("compare").is_none());
+    }
+
+    #[test]
+    fn test_engine_only_omits_ollama() {
+        let json = serde_json::to_value(result(None)).unwrap();
+        assert!(json.get("ollama").is_none());
+        assert!(!result(None).to_markdown().contains("Ollama"));
+
+        // A failed comparison is still reported
+        let json = serde_json::to_value(result(Some(None))).unwrap();
+        assert!(json["ollama"].is_null());
+        assert!(result(Some(None)).to_markdown().contains("Ollama"));
+    }
 }
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use serde::Serialize;
+use vllama_core::model::{context_length, read_model_json};
+use vllama_core::{templates, ChatMessage, ModelSource, SamplingParams, SpecialTokens};
+
+use crate::output::{self, OutputMode};
+
+/// What vllama applies to a model's requests, resolved from its local config files
+#[derive(Debug, Serialize)]
+struct ModelExport {
+    /// Reference handed to vLLM (repo id or path)
+    model: String,
+    /// Prompt format used when vllama renders the conversation itself
+    template: String,
+    /// A short conversation rendered with `template`
+    template_example: String,
+    /// Tokens stripped from generated text
+    stop_tokens: Vec<String>,
+    /// Sampling sent for options a request leaves out
+    sampling: SamplingParams,
+    /// From `config.json`; None when the config isn't cached
+    context_length: Option<usize>,
+}
+
+fn resolve(model: &str) -> Result<ModelExport> {
+    let source = ModelSource::resolve(model)?;
+    let template = templates::template_for_model(model);
+    let example = [ChatMessage::system("You are a helpful assistant."), ChatMessage::user("Hello!")];
+    let stop_tokens = SpecialTokens::from_configs(
+        read_model_json(model, "tokenizer_config.json"),
+        read_model_json(model, "generation_config.json"),
+    );
+
+    Ok(ModelExport {
+        model: source.as_model_arg(),
+        template: template.name().to_string(),
+        template_example: template.render(&example),
+        stop_tokens: stop_tokens.tokens().to_vec(),
+        sampling: SamplingParams::default(),
+        context_length: read_model_json(model, "config.json").as_ref().and_then(context_length),
+    })
+}
+
+pub async fn execute(model: String, output_mode: OutputMode) -> Result<()> {
+    let export = resolve(&model)?;
+
+    match output_mode {
+        OutputMode::Json => {
+            output::json(&export);
+        }
+        OutputMode::Quiet => {
+            println!("{}", export.template);
+        }
+        OutputMode::Normal => {
+            println!("{}", output::section(&format!("Model: {}", export.model)));
+            output::kv("Template", &export.template);
+            output::kv(
+                "Context length",
+                &export.context_length.map_or_else(|| "unknown (config.json not cached)".to_string(), |n| n.to_string()),
+            );
+            output::kv("Stop tokens", &export.stop_tokens.join(" "));
+
+            let sampling = &export.sampling;
+            println!();
+            println!("{}", output::section("Default sampling"));
+            output::kv("temperature", &sampling.temperature.to_string());
+            output::kv("top_p", &sampling.top_p.to_string());
+            output::kv("repeat_penalty", &sampling.repetition_penalty.to_string());
+            output::kv("max_tokens", &sampling.max_tokens.map_or_else(|| "unlimited".to_string(), |n| n.to_string()));
+
+            println!();
+            println!("{}", output::section("Template example"));
+            println!("{}", export.template_example);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_local_model() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::write(dir.join("tokenizer_config.json"), r#"{"eos_token": "<|im_end|>"}"#).unwrap();
+        std::fs::write(dir.join("config.json"), r#"{"max_position_embeddings": 32768}"#).unwrap();
+
+        let export = resolve(dir.to_str().unwrap()).unwrap();
+        assert_eq!(export.template, "chatml");
+        assert!(export.template_example.ends_with("<|im_start|>assistant\n"));
+        assert!(export.stop_tokens.contains(&"<|im_end|>".to_string()));
+        assert_eq!(export.context_length, Some(32768));
+    }
+}
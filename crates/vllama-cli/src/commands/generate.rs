@@ -1,9 +1,114 @@
-use anyhow::Result;
-use vllama_core::GenerateRequest;
-use vllama_engine::{InferenceEngine, VllmOpenAIEngine};
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::io::Read;
+use std::path::Path;
+use vllama_core::{GenerateRequest, GuidedDecoding};
+use vllama_engine::InferenceEngine;
 use tracing::info;
 
-pub async fn execute(model: String, prompt: String, stream: bool) -> Result<()> {
+use crate::config::VllmEndpoint;
+use crate::error::Interrupted;
+use crate::output;
+
+/// The prompt argument, or all of `stdin` for `--stdin` or a `-` prompt
+pub fn read_prompt(prompt: Option<String>, stdin: bool, mut input: impl Read) -> Result<String> {
+    match prompt {
+        Some(prompt) if !stdin && prompt != "-" => Ok(prompt),
+        _ => {
+            let mut prompt = String::new();
+            input.read_to_string(&mut prompt).context("Failed to read prompt from stdin")?;
+            // Drop the newline `echo` adds
+            let prompt = prompt.trim_end_matches(['\n', '\r']).to_string();
+            if prompt.is_empty() {
+                anyhow::bail!("No prompt on stdin");
+            }
+            Ok(prompt)
+        }
+    }
+}
+
+/// Read a JSON schema for `--json-schema`
+pub fn load_schema(path: &Path) -> Result<Value> {
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("Failed to read schema {}", path.display()))?;
+    let schema: Value =
+        serde_json::from_str(&contents).with_context(|| format!("Schema {} is not valid JSON", path.display()))?;
+    if !schema.is_object() {
+        anyhow::bail!("Schema {} must be a JSON object", path.display());
+    }
+    Ok(schema)
+}
+
+/// The first place `value` breaks `schema`, if any
+///
+/// vLLM enforces the schema while generating, so this is only a sanity
+/// check (e.g. for output cut short by `--max-tokens`) rather than a full
+/// validator: it covers `type`, `enum`, `required`, `properties` and
+/// `items`, and ignores every other keyword.
+fn schema_mismatch(schema: &Value, value: &Value, path: &str) -> Option<String> {
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let matches = |t: &str| match t {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    if !types.is_empty() && !types.iter().any(|t| matches(t)) {
+        return Some(format!("{} should be {}", path, types.join(" or ")));
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            return Some(format!("{} is not one of the allowed values", path));
+        }
+    }
+
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array).into_iter().flatten();
+        if let Some(missing) = required.filter_map(Value::as_str).find(|key| !object.contains_key(*key)) {
+            return Some(format!("{} is missing \"{}\"", path, missing));
+        }
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, property) in properties.into_iter().flatten() {
+            if let Some(field) = object.get(key) {
+                if let Some(mismatch) = schema_mismatch(property, field, &format!("{}.{}", path, key)) {
+                    return Some(mismatch);
+                }
+            }
+        }
+    }
+
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (i, item) in array.iter().enumerate() {
+            if let Some(mismatch) = schema_mismatch(items, item, &format!("{}[{}]", path, i)) {
+                return Some(mismatch);
+            }
+        }
+    }
+
+    None
+}
+
+/// Tokens generated when `--max-tokens` isn't given and there's no schema;
+/// with one, the limit is the model's context so the JSON can be completed
+const DEFAULT_MAX_TOKENS: usize = 100;
+
+pub async fn execute(
+    model: String,
+    prompt: String,
+    stream: bool,
+    json_schema: Option<Value>,
+    max_tokens: Option<usize>,
+    vllm: VllmEndpoint,
+) -> Result<()> {
     info!("Generating with model: {}", model);
     info!("Stream: {}", stream);
 
@@ -12,20 +117,75 @@ pub async fn execute(model: String, prompt: String, stream: bool) -> Result<()>
         return Ok(());
     }
 
-    let vllm_engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
+    let vllm_engine = vllm.engine();
 
     if !vllm_engine.health_check().await? {
         anyhow::bail!("vLLM OpenAI server not available (run: vllama serve --model <model-name>)");
     }
 
-    let request = GenerateRequest::new(1, model.clone(), prompt.clone()).with_max_tokens(100);
+    let mut request = GenerateRequest::new(1, model.clone(), prompt.clone());
+    request.options.sampling.max_tokens = max_tokens.or(json_schema.is_none().then_some(DEFAULT_MAX_TOKENS));
+    request.options.guided = json_schema.clone().map(GuidedDecoding::Json);
 
     println!("Generating response...\n");
 
-    let response = vllm_engine.generate(request).await?;
+    // Dropping the request on Ctrl-C closes the connection, so vLLM stops too
+    let response = tokio::select! {
+        response = vllm_engine.generate(request) => response?,
+        Ok(()) = tokio::signal::ctrl_c() => return Err(Interrupted.into()),
+    };
 
     println!("Response: {}", response.text);
     println!();
 
+    if let Some(schema) = json_schema {
+        let mismatch = match serde_json::from_str::<Value>(&response.text) {
+            Ok(value) => schema_mismatch(&schema, &value, "output"),
+            Err(e) => Some(format!("output is not valid JSON ({})", e)),
+        };
+        if let Some(mismatch) = mismatch {
+            eprintln!("{}", output::warning(&format!("Response doesn't match the schema: {}", mismatch)));
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_prompt() {
+        assert_eq!(read_prompt(Some("hi".to_string()), false, "unused".as_bytes()).unwrap(), "hi");
+        assert_eq!(read_prompt(Some("-".to_string()), false, "from stdin\n".as_bytes()).unwrap(), "from stdin");
+        assert_eq!(read_prompt(None, true, "line 1\nline 2\n".as_bytes()).unwrap(), "line 1\nline 2");
+        assert!(read_prompt(None, true, "".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_schema_mismatch() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["name", "tags"],
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}}
+            }
+        });
+        let check = |value: Value| schema_mismatch(&schema, &value, "output");
+
+        assert_eq!(check(serde_json::json!({"name": "x", "age": 3, "tags": ["a"]})), None);
+        assert_eq!(check(serde_json::json!({"name": "x"})), Some("output is missing \"tags\"".to_string()));
+        assert_eq!(
+            check(serde_json::json!({"name": "x", "age": 3.5, "tags": []})),
+            Some("output.age should be integer".to_string())
+        );
+        assert_eq!(
+            check(serde_json::json!({"name": "x", "tags": ["a", "c"]})),
+            Some("output.tags[1] is not one of the allowed values".to_string())
+        );
+        assert_eq!(check(serde_json::json!([1])), Some("output should be object".to_string()));
+    }
+}
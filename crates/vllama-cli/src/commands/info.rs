@@ -1,26 +1,78 @@
 use anyhow::Result;
-use vllama_core::Hardware;
+use vllama_core::{GpuStatus, Hardware};
 
-pub async fn execute() -> Result<()> {
+use crate::output::{self, ReportFormat};
+
+const UNKNOWN_GPU_NOTE: &str = "NVIDIA driver present but nvidia-smi/NVML failed; vLLM may still use CUDA";
+
+pub async fn execute(format: ReportFormat) -> Result<()> {
     let hw = Hardware::detect();
 
-    println!("System Information:");
-    println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-    println!("Hardware Type: {:?}", hw.hw_type);
-    println!("CPU Cores: {}", hw.cpu_cores);
-    println!("RAM Total: {} MB", hw.ram_total_mb);
-    println!("RAM Available: {} MB", hw.ram_available_mb);
-
-    if let Some(gpu) = hw.gpu_info {
-        println!("\nGPU Information:");
-        println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
-        println!("GPU Name: {}", gpu.name);
-        println!("VRAM Total: {} MB", gpu.vram_total_mb);
-        println!("VRAM Available: {} MB", gpu.vram_available_mb);
-        if let Some((major, minor)) = gpu.compute_capability {
-            println!("Compute Capability: {}.{}", major, minor);
+    match format {
+        ReportFormat::Json => {
+            output::json(&hw);
+        }
+        ReportFormat::Markdown => {
+            print!("{}", to_markdown(&hw));
+        }
+        ReportFormat::Normal => {
+            println!("System Information:");
+            println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+            println!("Hardware Type: {:?}", hw.hw_type);
+            println!("CPU Cores: {}", hw.cpu_cores);
+            println!("RAM Total: {}", output::memory_mb(hw.ram_total_mb));
+            println!("RAM Available: {}", output::memory_mb(hw.ram_available_mb));
+            if let Some(limit) = hw.memory_limit_mb {
+                println!("Container Memory Limit: {} MB", limit);
+            }
+
+            if hw.gpu_status == GpuStatus::Unknown {
+                println!("\nGPU Status: unknown ({})", UNKNOWN_GPU_NOTE);
+            }
+
+            if let Some(gpu) = hw.gpu_info {
+                println!("\nGPU Information:");
+                println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
+                println!("GPU Name: {}", gpu.name);
+                println!("VRAM Total: {} MB", gpu.vram_total_mb);
+                println!("VRAM Available: {} MB", gpu.vram_available_mb);
+                if let Some((major, minor)) = gpu.compute_capability {
+                    println!("Compute Capability: {}.{}", major, minor);
+                }
+            }
         }
     }
 
     Ok(())
 }
+
+fn to_markdown(hw: &Hardware) -> String {
+    let mut rows = vec![
+        vec!["Hardware Type".to_string(), format!("{:?}", hw.hw_type)],
+        vec!["CPU Cores".to_string(), hw.cpu_cores.to_string()],
+        vec!["RAM Total".to_string(), output::memory_mb(hw.ram_total_mb)],
+        vec!["RAM Available".to_string(), output::memory_mb(hw.ram_available_mb)],
+    ];
+
+    if let Some(limit) = hw.memory_limit_mb {
+        rows.push(vec!["Container Memory Limit".to_string(), format!("{} MB", limit)]);
+    }
+
+    if hw.gpu_status == GpuStatus::Unknown {
+        rows.push(vec!["GPU Status".to_string(), format!("unknown ({})", UNKNOWN_GPU_NOTE)]);
+    }
+
+    if let Some(gpu) = &hw.gpu_info {
+        rows.push(vec!["GPU Name".to_string(), gpu.name.clone()]);
+        rows.push(vec!["VRAM Total".to_string(), format!("{} MB", gpu.vram_total_mb)]);
+        rows.push(vec!["VRAM Available".to_string(), format!("{} MB", gpu.vram_available_mb)]);
+        if let Some((major, minor)) = gpu.compute_capability {
+            rows.push(vec!["Compute Capability".to_string(), format!("{}.{}", major, minor)]);
+        }
+    }
+
+    format!(
+        "### System Information\n\n{}",
+        output::markdown_table(&["Property", "Value"], &rows)
+    )
+}
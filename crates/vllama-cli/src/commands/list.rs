@@ -15,6 +15,8 @@ struct ModelEntry {
     name: String,
     size_mb: u64,
     path: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    revisions: Vec<String>,
 }
 
 pub async fn execute(output_mode: OutputMode) -> Result<()> {
@@ -49,6 +51,7 @@ pub async fn execute(output_mode: OutputMode) -> Result<()> {
                     name: m.name.clone(),
                     size_mb: m.size_mb,
                     path: m.path.display().to_string(),
+                    revisions: m.revisions.clone(),
                 }).collect(),
                 total_size_mb,
             });
@@ -67,6 +70,9 @@ pub async fn execute(output_mode: OutputMode) -> Result<()> {
                 println!("  {}", model.name);
                 output::kv("Size", &format!("{} MB", model.size_mb));
                 output::kv("Path", &model.path.display().to_string());
+                if !model.revisions.is_empty() {
+                    output::kv("Pinned", &model.revisions.join(", "));
+                }
                 println!();
             }
 
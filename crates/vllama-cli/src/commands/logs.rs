@@ -0,0 +1,115 @@
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::output::{self, OutputMode};
+
+/// How often `--follow` checks the log for new output
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Bytes read per step when scanning backwards for the last lines
+const TAIL_BLOCK: u64 = 8192;
+
+/// The last `lines` lines of `file`, and the offset reading stopped at
+///
+/// Reads backwards in blocks, so a large log costs only its tail.
+fn read_tail(file: &mut File, lines: usize) -> Result<(Vec<String>, u64)> {
+    let end = file.seek(SeekFrom::End(0))?;
+    let mut start = end;
+    let mut buf = Vec::new();
+
+    // One extra newline: the file normally ends with one
+    while start > 0 && buf.iter().filter(|b| **b == b'\n').count() <= lines {
+        let step = TAIL_BLOCK.min(start);
+        start -= step;
+        file.seek(SeekFrom::Start(start))?;
+        let mut block = vec![0; step as usize];
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&buf);
+        buf = block;
+    }
+
+    let text = String::from_utf8_lossy(&buf);
+    let all: Vec<&str> = text.lines().collect();
+    let tail = all[all.len().saturating_sub(lines)..].iter().map(|l| l.to_string()).collect();
+    Ok((tail, end))
+}
+
+fn print_line(line: &str, output_mode: OutputMode) {
+    match output_mode {
+        OutputMode::Json => output::json(&json!({"line": line})),
+        OutputMode::Normal | OutputMode::Quiet => println!("{}", line),
+    }
+}
+
+pub async fn execute(log_file: &Path, follow: bool, lines: usize, output_mode: OutputMode) -> Result<()> {
+    let mut file = File::open(log_file).with_context(|| format!("no vLLM log at {}", log_file.display()))?;
+
+    let (tail, mut offset) = read_tail(&mut file, lines)?;
+    for line in &tail {
+        print_line(line, output_mode);
+    }
+    if !follow {
+        return Ok(());
+    }
+
+    // Like `tail -f`: print whatever is appended until interrupted
+    let mut partial = String::new();
+    loop {
+        tokio::time::sleep(FOLLOW_INTERVAL).await;
+
+        let len = file.metadata()?.len();
+        if len < offset {
+            // Truncated or replaced by a new serve; start over
+            if output_mode == OutputMode::Normal {
+                eprintln!("{}", output::info("vLLM log truncated"));
+            }
+            offset = 0;
+            partial.clear();
+        }
+        if len == offset {
+            continue;
+        }
+
+        file.seek(SeekFrom::Start(offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        offset += appended.len() as u64;
+
+        partial.push_str(&String::from_utf8_lossy(&appended));
+        while let Some(newline) = partial.find('\n') {
+            let line: String = partial.drain(..=newline).collect();
+            print_line(line.trim_end_matches(['\r', '\n']), output_mode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_tail() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("vllama.log");
+        // Longer than one block so the scan has to step back
+        let text: String = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, &text).unwrap();
+
+        let mut file = File::open(&path).unwrap();
+        let (tail, end) = read_tail(&mut file, 3).unwrap();
+        assert_eq!(tail, vec!["line 1997", "line 1998", "line 1999"]);
+        assert_eq!(end, text.len() as u64);
+
+        // Asking for more lines than exist returns the whole file
+        let (tail, _) = read_tail(&mut file, 5000).unwrap();
+        assert_eq!(tail.len(), 2000);
+        assert_eq!(tail[0], "line 0");
+
+        let (tail, _) = read_tail(&mut file, 0).unwrap();
+        assert!(tail.is_empty());
+    }
+}
@@ -5,6 +5,9 @@ pub mod list;
 pub mod pull;
 pub mod rm;
 pub mod show;
+pub mod export;
 pub mod ps;
+pub mod logs;
 pub mod info;
 pub mod bench;
+pub mod alias;
@@ -1,8 +1,106 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
 
-pub async fn execute() -> Result<()> {
-    println!("Running models:");
-    println!("(No models running yet)");
+use crate::output::{self, OutputMode};
+
+/// A model vLLM is serving, from `/api/ps`
+#[derive(Debug, Serialize, Deserialize)]
+struct RunningModel {
+    name: String,
+    #[serde(default)]
+    in_flight: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct PsResponse {
+    models: Vec<RunningModel>,
+}
+
+/// Lifetime usage of a model, from `/api/stats`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ModelUsage {
+    model: String,
+    requests: u64,
+    eval_count: u64,
+    total_latency_ms: u64,
+    avg_latency_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsResponse {
+    models: Vec<ModelUsage>,
+}
+
+/// The part of `/health` comparing `serve --model` with what vLLM serves
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    #[serde(default)]
+    model_mismatch: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PsResult {
+    running: Vec<RunningModel>,
+    stats: Vec<ModelUsage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model_mismatch: Option<String>,
+}
+
+async fn fetch<T: serde::de::DeserializeOwned>(client: &reqwest::Client, url: String) -> Result<T> {
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .with_context(|| format!("vllama server not reachable at {} (start it with: vllama serve)", url))?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+pub async fn execute(api_url: String, output_mode: OutputMode) -> Result<()> {
+    let client = reqwest::Client::new();
+    let running: PsResponse = fetch(&client, format!("{}/api/ps", api_url)).await?;
+    let stats: StatsResponse = fetch(&client, format!("{}/api/stats", api_url)).await?;
+    let health: HealthResponse = fetch(&client, format!("{}/health", api_url)).await?;
+
+    match output_mode {
+        OutputMode::Json => {
+            output::json(&PsResult {
+                running: running.models,
+                stats: stats.models,
+                model_mismatch: health.model_mismatch,
+            });
+        }
+        OutputMode::Quiet => {
+            for model in &running.models {
+                println!("{}", model.name);
+            }
+        }
+        OutputMode::Normal => {
+            if let Some(warning) = &health.model_mismatch {
+                println!("{}", output::warning(warning));
+                println!();
+            }
+            println!("{}", output::section("Running models"));
+            if running.models.is_empty() {
+                println!("{}", output::info("No models running"));
+            }
+            for model in &running.models {
+                println!("  {}", model.name);
+                output::kv("In flight", &model.in_flight.to_string());
+            }
+
+            if !stats.models.is_empty() {
+                println!();
+                println!("{}", output::section("Usage since start"));
+                for usage in &stats.models {
+                    println!("  {}", usage.model);
+                    output::kv("Requests", &usage.requests.to_string());
+                    output::kv("Tokens", &usage.eval_count.to_string());
+                    output::kv("Avg latency", &format!("{} ms", usage.avg_latency_ms));
+                }
+            }
+        }
+    }
 
     Ok(())
 }
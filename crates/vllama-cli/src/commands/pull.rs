@@ -1,7 +1,7 @@
 use anyhow::Result;
 use serde::Serialize;
 use tracing::info;
-use vllama_core::ModelDownloader;
+use vllama_core::{split_revision, ModelDownloader, ModelSource, RateLimiter};
 
 use crate::output::{self, OutputMode};
 
@@ -12,14 +12,73 @@ struct PullResult {
     path: String,
 }
 
-pub async fn execute(model: String, output_mode: OutputMode) -> Result<()> {
+pub async fn execute(
+    model: String,
+    dry_run: bool,
+    rate_limit: Option<RateLimiter>,
+    output_mode: OutputMode,
+) -> Result<()> {
     info!("Pulling model: {}", model);
 
-    let downloader = ModelDownloader::new()?;
+    let repo_id = match ModelSource::resolve(&model)? {
+        ModelSource::Repo(repo_id) => repo_id,
+        ModelSource::Local(path) => {
+            // Nothing to download; vllama serve loads local paths directly
+            match output_mode {
+                OutputMode::Json => {
+                    output::json(&PullResult {
+                        model: model.clone(),
+                        cached: true,
+                        path: path.display().to_string(),
+                    });
+                }
+                OutputMode::Quiet => {}
+                OutputMode::Normal => {
+                    println!("{}", output::success(&format!("{} is a local model", model)));
+                    output::kv("Location", &path.display().to_string());
+                }
+            }
+            return Ok(());
+        }
+    };
+    // `repo@revision:QUANT`: the revision pins a branch, tag or commit, and
+    // the quant picks one file out of a GGUF repository
+    let (model, quant) = match repo_id.rsplit_once(':') {
+        Some((repo, quant)) => (repo.to_string(), Some(quant.to_string())),
+        None => (repo_id, None),
+    };
+    let (repo, revision) = split_revision(&model);
+    let revision = revision.map(str::to_string);
+    let model = repo.to_string();
 
-    // Check if already cached
-    if downloader.model_exists(&model).await {
-        let path = downloader.get_model_path(&model).await?;
+    let mut downloader = ModelDownloader::new()?;
+
+    if dry_run {
+        let plan = match &quant {
+            Some(quant) => downloader.plan_gguf(&model, revision.as_deref(), quant).await?,
+            None => downloader.plan_download(&model, revision.as_deref()).await?,
+        };
+        match output_mode {
+            OutputMode::Json => output::json(&plan),
+            OutputMode::Quiet => {}
+            OutputMode::Normal => {
+                println!("{}", output::section(&format!("Would download {}", model)));
+                for file in &plan.files {
+                    output::kv(&file.name, &output::bytes(file.size));
+                }
+                output::kv("Total", &output::bytes(plan.total_bytes));
+            }
+        }
+        return Ok(());
+    }
+    if let Some(limiter) = rate_limit {
+        downloader = downloader.with_rate_limit(limiter);
+    }
+
+    // Check if already cached (GGUF repos have no config.json; their
+    // files come from the cache during the download instead)
+    if quant.is_none() && downloader.model_exists(&model, revision.as_deref()).await {
+        let path = downloader.get_model_path(&model, revision.as_deref()).await?;
 
         match output_mode {
             OutputMode::Json => {
@@ -46,26 +105,41 @@ pub async fn execute(model: String, output_mode: OutputMode) -> Result<()> {
         println!("{}", output::section(&format!("Downloading {}", model)));
     }
 
-    // Create progress bar for download
-    let pb = if output_mode == OutputMode::Normal {
-        Some(output::progress_bar(3, "Fetching from HuggingFace Hub"))
+    // One bar for the file being fetched and one for the whole download;
+    // lengths are set once the file sizes are known
+    let bars = if output_mode == OutputMode::Normal {
+        Some(output::DownloadBars::new("Fetching from HuggingFace Hub"))
     } else {
         None
     };
 
     // Download with progress updates
-    let path = downloader.download_model(&model, |progress| {
-        if let Some(ref pb) = pb {
-            pb.set_position(progress.downloaded);
-            if !progress.status.is_empty() && progress.status != "completed" {
-                pb.set_message(progress.status.clone());
+    let report = |progress: vllama_core::DownloadProgress| {
+        if let Some(ref bars) = bars {
+            if progress.total > 0 {
+                bars.overall.set_length(progress.total);
+            }
+            bars.overall.set_position(progress.downloaded);
+            match &progress.current_file {
+                Some(file) => {
+                    bars.file.set_message(format!("[{}/{}] {}", progress.file_index + 1, progress.file_count, file));
+                    bars.file.set_length(progress.file_size);
+                    bars.file.set_position(progress.file_downloaded);
+                }
+                None if !progress.status.is_empty() && progress.status != "completed" => {
+                    bars.file.set_message(progress.status.clone());
+                }
+                None => {}
             }
         }
-    }).await?;
+    };
+    let path = match &quant {
+        Some(quant) => downloader.download_gguf(&model, revision.as_deref(), quant, report).await?,
+        None => downloader.download_model(&model, revision.as_deref(), report).await?,
+    };
 
-    // Finish progress bar
-    if let Some(pb) = pb {
-        pb.finish_and_clear();
+    if let Some(bars) = bars {
+        bars.finish_and_clear();
     }
 
     // Output result
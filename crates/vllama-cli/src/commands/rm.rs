@@ -1,6 +1,6 @@
 use anyhow::Result;
 use serde::Serialize;
-use tracing::info;
+use tracing::{info, warn};
 use vllama_core::ModelDownloader;
 
 use crate::output::{self, OutputMode};
@@ -9,82 +9,174 @@ use crate::output::{self, OutputMode};
 struct RmResult {
     model: String,
     deleted: bool,
+    freed_mb: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
 }
 
-pub async fn execute(model: String, output_mode: OutputMode) -> Result<()> {
-    info!("Removing model: {}", model);
+#[derive(Serialize)]
+struct RmSummary {
+    results: Vec<RmResult>,
+    total_freed_mb: u64,
+}
 
-    let downloader = ModelDownloader::new()?;
+/// Remove `models`, or every cached model with `all`, from `downloader`'s cache
+pub async fn execute(
+    downloader: &ModelDownloader,
+    models: Vec<String>,
+    all: bool,
+    output_mode: OutputMode,
+) -> Result<()> {
+    // Look models up in the local cache only, so removing a model never
+    // triggers a download of its config from the Hub.
+    let cached = downloader.list_cached_models()?;
+
+    let targets: Vec<String> = if all {
+        cached.iter().map(|m| m.name.clone()).collect()
+    } else {
+        models
+    };
 
-    // Check if model exists
-    if !downloader.model_exists(&model).await {
+    if targets.is_empty() {
         match output_mode {
-            OutputMode::Json => {
-                output::json(&RmResult {
-                    model: model.clone(),
-                    deleted: false,
-                });
+            OutputMode::Json => output::json(&RmSummary {
+                results: Vec::new(),
+                total_freed_mb: 0,
+            }),
+            OutputMode::Quiet => {}
+            OutputMode::Normal => println!("{}", output::info("No models cached")),
+        }
+        return Ok(());
+    }
+
+    let mut results = Vec::with_capacity(targets.len());
+
+    for model in targets {
+        info!("Removing model: {}", model);
+
+        let Some(entry) = cached.iter().find(|m| m.name == model) else {
+            if output_mode == OutputMode::Normal {
+                println!("{}", output::error(&format!("Model {} not found in cache", model)));
             }
-            OutputMode::Quiet => {
-                // Silent error - just return error code
+            results.push(RmResult {
+                model,
+                deleted: false,
+                freed_mb: 0,
+                error: Some("not found in cache".to_string()),
+            });
+            continue;
+        };
+
+        match downloader.delete_model(&model) {
+            Ok(()) => {
+                if output_mode == OutputMode::Normal {
+                    println!("{}", output::success(&format!("Deleted {}", model)));
+                    if entry.size_mb > 0 {
+                        output::kv("Freed space", &format!("{} MB", entry.size_mb));
+                    }
+                }
+                results.push(RmResult {
+                    model,
+                    deleted: true,
+                    freed_mb: entry.size_mb,
+                    error: None,
+                });
             }
-            OutputMode::Normal => {
-                println!("{}", output::error(&format!("Model {} not found in cache", model)));
+            Err(e) => {
+                warn!("Failed to remove {}: {}", model, e);
+                if output_mode == OutputMode::Normal {
+                    println!("{}", output::error(&format!("Failed to delete {}: {}", model, e)));
+                }
+                results.push(RmResult {
+                    model,
+                    deleted: false,
+                    freed_mb: 0,
+                    error: Some(e.to_string()),
+                });
             }
         }
-        anyhow::bail!("Model not found");
     }
 
-    // Get size before deleting
-    let size_mb = if let Ok(path) = downloader.get_model_path(&model).await {
-        calculate_dir_size(&path).unwrap_or(0) / 1024 / 1024
-    } else {
-        0
-    };
-
-    // Delete the model
-    downloader.delete_model(&model)?;
+    let total_freed_mb: u64 = results.iter().map(|r| r.freed_mb).sum();
+    let deleted = results.iter().filter(|r| r.deleted).count();
+    let failed: Vec<String> = results
+        .iter()
+        .filter(|r| !r.deleted)
+        .map(|r| r.model.clone())
+        .collect();
 
-    // Output result
     match output_mode {
-        OutputMode::Json => {
-            output::json(&RmResult {
-                model: model.clone(),
-                deleted: true,
-            });
-        }
-        OutputMode::Quiet => {
-            // Silent success
-        }
+        OutputMode::Json => output::json(&RmSummary {
+            results,
+            total_freed_mb,
+        }),
+        OutputMode::Quiet => {}
         OutputMode::Normal => {
-            println!("{}", output::success(&format!("Deleted {}", model)));
-            if size_mb > 0 {
-                output::kv("Freed space", &format!("{} MB", size_mb));
+            if deleted + failed.len() > 1 {
+                println!();
+                println!(
+                    "{}",
+                    output::info(&format!(
+                        "Removed {} of {} models, freed {} MB",
+                        deleted,
+                        deleted + failed.len(),
+                        total_freed_mb
+                    ))
+                );
             }
         }
     }
 
+    if !failed.is_empty() {
+        anyhow::bail!("Failed to remove {}: not found or not deletable", failed.join(", "));
+    }
+
     Ok(())
 }
 
-/// Calculate total size of a directory recursively
-fn calculate_dir_size(path: &std::path::Path) -> Result<u64> {
-    let mut total = 0;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if path.is_file() {
-        return Ok(std::fs::metadata(path)?.len());
+    /// A temporary HuggingFace cache holding `org/<name>` for each of
+    /// `names`, and a downloader using it
+    fn cache_models(names: &[&str]) -> (tempfile::TempDir, ModelDownloader) {
+        let home = tempfile::tempdir().unwrap();
+        for name in names {
+            let snapshot = home.path().join("hub").join(format!("models--org--{}", name)).join("snapshots").join("abc");
+            std::fs::create_dir_all(&snapshot).unwrap();
+            std::fs::write(snapshot.join("config.json"), "{}").unwrap();
+        }
+        let downloader = ModelDownloader::with_cache_dir(home.path()).unwrap();
+        (home, downloader)
     }
 
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        let entry_path = entry.path();
+    #[tokio::test]
+    async fn test_rm_many() {
+        let (home, downloader) = cache_models(&["a", "b", "c"]);
+        let hub = home.path().join("hub");
 
-        if entry_path.is_file() {
-            total += std::fs::metadata(&entry_path)?.len();
-        } else if entry_path.is_dir() {
-            total += calculate_dir_size(&entry_path)?;
-        }
+        // Removes each listed model, keeps going past a missing one, then
+        // reports the failure
+        let models = vec!["org/a".to_string(), "org/missing".to_string(), "org/b".to_string()];
+        let err = execute(&downloader, models, false, OutputMode::Quiet).await.unwrap_err();
+        assert!(err.to_string().contains("org/missing"), "{}", err);
+        assert!(!err.to_string().contains("org/a"), "{}", err);
+        assert!(!hub.join("models--org--a").exists());
+        assert!(!hub.join("models--org--b").exists());
+        assert!(hub.join("models--org--c").exists());
     }
 
-    Ok(total)
+    #[tokio::test]
+    async fn test_rm_all() {
+        let (home, downloader) = cache_models(&["c", "d"]);
+        let hub = home.path().join("hub");
+
+        execute(&downloader, Vec::new(), true, OutputMode::Quiet).await.unwrap();
+        assert!(!hub.join("models--org--c").exists());
+        assert!(!hub.join("models--org--d").exists());
+
+        // Nothing left to remove isn't an error
+        execute(&downloader, Vec::new(), true, OutputMode::Quiet).await.unwrap();
+    }
 }
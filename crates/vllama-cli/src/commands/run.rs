@@ -1,15 +1,494 @@
-use anyhow::Result;
-use tracing::info;
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use tracing::{info, warn};
+use vllama_core::openai::{ChatCompletionRequest, ChatMessage};
+use vllama_core::OpenAIClient;
+use crate::config::{Config, VllmEndpoint};
+use crate::error::Interrupted;
+use crate::output;
 
-pub async fn execute(model: String, prompt: Option<String>) -> Result<()> {
+/// Shown while waiting for the next message
+const PROMPT: &str = ">>> ";
+
+const HELP: &str = "\
+/clear           Forget the conversation (keeps the system prompt)
+/system <prompt> Set the system prompt; without one, remove it
+/save <file>     Write the conversation to <file> (Markdown for .md, otherwise JSON lines)
+/exit            Quit (also /bye or Ctrl-D)";
+
+/// Lines typed in earlier sessions, offered again with the arrow keys
+fn history_path() -> Option<PathBuf> {
+    Some(Config::dir()?.join("history"))
+}
+
+/// A line starting with `/`
+#[derive(Debug, PartialEq)]
+enum ReplCommand {
+    Clear,
+    System(String),
+    Save(String),
+    Exit,
+    Help,
+    Unknown(String),
+}
+
+impl ReplCommand {
+    /// The command `line` spells, or None when it's a message for the model
+    fn parse(line: &str) -> Option<Self> {
+        let rest = line.strip_prefix('/')?;
+        let (name, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let arg = arg.trim().to_string();
+        Some(match name {
+            "clear" => Self::Clear,
+            "system" => Self::System(arg),
+            "save" => Self::Save(arg),
+            "exit" | "bye" => Self::Exit,
+            "help" | "?" => Self::Help,
+            _ => Self::Unknown(name.to_string()),
+        })
+    }
+}
+
+/// The messages sent with each request: an optional system prompt, then
+/// alternating user and assistant turns
+#[derive(Debug, Default)]
+struct Conversation {
+    messages: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    fn has_system(&self) -> bool {
+        self.messages.first().is_some_and(|m| m.role == "system")
+    }
+
+    /// Replace the system prompt; an empty one removes it
+    fn set_system(&mut self, prompt: &str) {
+        if self.has_system() {
+            self.messages.remove(0);
+        }
+        if !prompt.is_empty() {
+            let system = ChatMessage {
+                role: "system".to_string(),
+                content: prompt.to_string(),
+            };
+            self.messages.insert(0, system);
+        }
+    }
+
+    /// Drop every turn, keeping the system prompt
+    fn clear(&mut self) {
+        let keep = usize::from(self.has_system());
+        self.messages.truncate(keep);
+    }
+}
+
+pub async fn execute(
+    model: String,
+    prompt: Option<String>,
+    think: bool,
+    save: Option<PathBuf>,
+    vllm: VllmEndpoint,
+) -> Result<()> {
     info!("Running model: {}", model);
 
-    if let Some(prompt_text) = prompt {
-        println!("Sending prompt: {}", prompt_text);
-    } else {
-        println!("Interactive chat with {}", model);
-        println!("(Interactive mode not yet implemented)");
+    let client = vllm.client();
+    if !client.health().await? {
+        anyhow::bail!("vLLM OpenAI server not available (run: vllama serve --model <model-name>)");
+    }
+
+    let chat = Chat {
+        client,
+        model,
+        think,
+        transcript: save.map(Transcript::new),
+    };
+    let mut conversation = Conversation::default();
+
+    if let Some(prompt) = prompt {
+        let interrupted = chat.turn(&mut conversation, prompt).await?;
+        // Scripts and pipes get the one answer, as before the chat loop
+        if !std::io::stdin().is_terminal() {
+            if interrupted {
+                return Err(Interrupted.into());
+            }
+            return Ok(());
+        }
     }
 
-    Ok(())
+    chat.repl(conversation).await
+}
+
+/// A chat with one model, optionally logged to a transcript
+struct Chat {
+    client: OpenAIClient,
+    model: String,
+    think: bool,
+    transcript: Option<Transcript>,
+}
+
+impl Chat {
+    /// Read lines until /exit or Ctrl-D, sending each to the model
+    async fn repl(&self, mut conversation: Conversation) -> Result<()> {
+        let mut editor = DefaultEditor::new().context("Failed to start the line editor")?;
+        let history = history_path();
+        if let Some(path) = &history {
+            // Missing on the first run
+            let _ = editor.load_history(path);
+        }
+        println!(
+            "{}",
+            output::info(&format!("Chatting with {} (/help for commands, Ctrl-D to quit)", self.model))
+        );
+
+        loop {
+            let line = match tokio::task::block_in_place(|| editor.readline(PROMPT)) {
+                Ok(line) => line,
+                // Ctrl-C discards the line being typed
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e).context("Failed to read input"),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let _ = editor.add_history_entry(line);
+
+            match ReplCommand::parse(line) {
+                None => {
+                    // A failed request ends the turn, not the chat
+                    if let Err(e) = self.turn(&mut conversation, line.to_string()).await {
+                        eprintln!("{}", output::error(&format!("{:#}", e)));
+                    }
+                }
+                Some(ReplCommand::Clear) => {
+                    conversation.clear();
+                    println!("{}", output::info("Conversation cleared"));
+                }
+                Some(ReplCommand::System(prompt)) => {
+                    conversation.set_system(&prompt);
+                    let msg = if prompt.is_empty() { "System prompt removed" } else { "System prompt set" };
+                    println!("{}", output::info(msg));
+                }
+                Some(ReplCommand::Save(file)) if file.is_empty() => {
+                    eprintln!("{}", output::warning("Usage: /save <file>"));
+                }
+                Some(ReplCommand::Save(file)) => {
+                    match Transcript::new(PathBuf::from(&file)).write(&conversation.messages) {
+                        Ok(()) => println!("{}", output::success(&format!("Saved conversation to {}", file))),
+                        Err(e) => eprintln!("{}", output::error(&format!("{:#}", e))),
+                    }
+                }
+                Some(ReplCommand::Exit) => break,
+                Some(ReplCommand::Help) => println!("{}", HELP),
+                Some(ReplCommand::Unknown(name)) => {
+                    eprintln!("{}", output::warning(&format!("Unknown command /{} (/help lists them)", name)));
+                }
+            }
+        }
+
+        if let Some(path) = &history {
+            if let Some(dir) = path.parent() {
+                let _ = std::fs::create_dir_all(dir);
+            }
+            if let Err(e) = editor.save_history(path) {
+                warn!("Failed to save chat history to {}: {}", path.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send `text` with the conversation so far and stream the reply,
+    /// returning whether Ctrl-C cut it short
+    ///
+    /// A cancelled reply is kept as far as it got; one cancelled before any
+    /// text arrived is dropped along with its message.
+    async fn turn(&self, conversation: &mut Conversation, text: String) -> Result<bool> {
+        let user_message = ChatMessage {
+            role: "user".to_string(),
+            content: text,
+        };
+        conversation.messages.push(user_message.clone());
+
+        let (reply, interrupted) = match self.stream_reply(&conversation.messages).await {
+            Ok(result) => result,
+            Err(e) => {
+                conversation.messages.pop();
+                return Err(e);
+            }
+        };
+        if interrupted && reply.is_empty() {
+            conversation.messages.pop();
+            return Ok(true);
+        }
+
+        let assistant_message = ChatMessage {
+            role: "assistant".to_string(),
+            content: reply,
+        };
+        conversation.messages.push(assistant_message.clone());
+        if let Some(transcript) = &self.transcript {
+            transcript.append(&[user_message, assistant_message])?;
+        }
+        Ok(interrupted)
+    }
+
+    /// Print the model's reply to `messages` as it streams in, returning
+    /// the answer (without any thinking trace) and whether Ctrl-C stopped it
+    async fn stream_reply(&self, messages: &[ChatMessage]) -> Result<(String, bool)> {
+        let request = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            max_tokens: None,
+            min_tokens: None,
+            seed: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            min_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            repetition_penalty: None,
+            guided: None,
+            response_format: None,
+            stream: Some(true),
+            stop: None,
+            include_stop_str_in_output: None,
+        };
+
+        let mut stream = Box::pin(self.client.create_chat_completion_stream(request).await?);
+        let mut filter = ThinkFilter::default();
+        let mut stdout = std::io::stdout();
+        let mut thought = false;
+        let mut reply = String::new();
+        let mut interrupted = false;
+
+        // Ctrl-C drops the stream, which closes the connection so vLLM aborts
+        // the generation, and finishes the line instead of killing the process
+        let ctrl_c = tokio::signal::ctrl_c();
+        tokio::pin!(ctrl_c);
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = stream.next() => chunk,
+                Ok(()) = &mut ctrl_c => {
+                    interrupted = true;
+                    break;
+                }
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            let chunk = chunk?;
+            let Some(choice) = chunk.choices.first() else {
+                continue;
+            };
+
+            // vLLM's reasoning parser splits the trace out; otherwise it arrives
+            // inline as <think>...</think> in the content
+            let mut reasoning = choice.delta.reasoning_content.clone().unwrap_or_default();
+            let (inline_reasoning, answer) = filter.push(choice.delta.content.as_deref().unwrap_or_default());
+            reasoning.push_str(&inline_reasoning);
+
+            if self.think && !reasoning.is_empty() {
+                print!("{}", output::dim(&reasoning));
+                thought = true;
+            }
+            if !answer.is_empty() {
+                if thought {
+                    // Separate the trace from the answer once
+                    println!("\n");
+                    thought = false;
+                }
+                print!("{}", answer);
+                reply.push_str(&answer);
+            }
+            stdout.flush()?;
+        }
+
+        drop(stream);
+        println!();
+        Ok((reply, interrupted))
+    }
+}
+
+/// Conversation log that turns are appended to as the chat proceeds
+///
+/// `.md` files get a Markdown section per message; anything else gets one
+/// JSON object per message, so the file reads back as chat history.
+struct Transcript {
+    path: PathBuf,
+    markdown: bool,
+}
+
+impl Transcript {
+    fn new(path: PathBuf) -> Self {
+        let markdown = path.extension().is_some_and(|ext| ext == "md");
+        Self { path, markdown }
+    }
+
+    fn append(&self, messages: &[ChatMessage]) -> Result<()> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open transcript {}", self.path.display()))?;
+        self.write_to(file, messages)
+    }
+
+    /// Replace the file's contents with `messages`
+    fn write(&self, messages: &[ChatMessage]) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .with_context(|| format!("Failed to create transcript {}", self.path.display()))?;
+        self.write_to(file, messages)
+    }
+
+    fn write_to(&self, mut file: std::fs::File, messages: &[ChatMessage]) -> Result<()> {
+        for message in messages {
+            file.write_all(self.render(message).as_bytes())?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    fn render(&self, message: &ChatMessage) -> String {
+        if self.markdown {
+            format!("### {}\n\n{}\n\n", message.role, message.content)
+        } else {
+            format!("{}\n", serde_json::to_string(message).unwrap_or_default())
+        }
+    }
+}
+
+/// Splits `<think>...</think>` spans out of streamed content
+///
+/// Tags may be split across deltas, so a trailing partial tag is held back
+/// until the next delta shows whether it completes.
+#[derive(Debug, Default)]
+struct ThinkFilter {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkFilter {
+    const OPEN: &'static str = "<think>";
+    const CLOSE: &'static str = "</think>";
+
+    /// Feed a content delta, returning the (reasoning, answer) text it completes
+    fn push(&mut self, delta: &str) -> (String, String) {
+        let mut buf = std::mem::take(&mut self.pending);
+        buf.push_str(delta);
+
+        let mut reasoning = String::new();
+        let mut answer = String::new();
+
+        loop {
+            let tag = if self.in_think { Self::CLOSE } else { Self::OPEN };
+            let out = if self.in_think { &mut reasoning } else { &mut answer };
+
+            if let Some(idx) = buf.find(tag) {
+                out.push_str(&buf[..idx]);
+                buf.drain(..idx + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+
+            // Hold back the longest suffix that could start the tag
+            let keep = (1..tag.len())
+                .rev()
+                .find(|&n| buf.len() >= n && buf.is_char_boundary(buf.len() - n) && tag.starts_with(&buf[buf.len() - n..]))
+                .unwrap_or(0);
+            out.push_str(&buf[..buf.len() - keep]);
+            self.pending = buf[buf.len() - keep..].to_string();
+            break;
+        }
+
+        (reasoning, answer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_think_filter_split_tags() {
+        let mut filter = ThinkFilter::default();
+        let mut reasoning = String::new();
+        let mut answer = String::new();
+
+        for delta in ["<thi", "nk>let me ", "think</th", "ink>The answer", " is 4 < 5"] {
+            let (r, a) = filter.push(delta);
+            reasoning.push_str(&r);
+            answer.push_str(&a);
+        }
+
+        assert_eq!(reasoning, "let me think");
+        assert_eq!(answer, "The answer is 4 < 5");
+    }
+
+    #[test]
+    fn test_repl_command_parse() {
+        assert_eq!(ReplCommand::parse("hello /clear"), None);
+        assert_eq!(ReplCommand::parse("/clear"), Some(ReplCommand::Clear));
+        assert_eq!(
+            ReplCommand::parse("/system  You are terse. "),
+            Some(ReplCommand::System("You are terse.".to_string()))
+        );
+        assert_eq!(ReplCommand::parse("/system"), Some(ReplCommand::System(String::new())));
+        assert_eq!(ReplCommand::parse("/save chat.md"), Some(ReplCommand::Save("chat.md".to_string())));
+        assert_eq!(ReplCommand::parse("/bye"), Some(ReplCommand::Exit));
+        assert_eq!(ReplCommand::parse("/quit"), Some(ReplCommand::Unknown("quit".to_string())));
+    }
+
+    #[test]
+    fn test_conversation_system_and_clear() {
+        let message = |role: &str, content: &str| ChatMessage {
+            role: role.to_string(),
+            content: content.to_string(),
+        };
+        let mut conversation = Conversation::default();
+        conversation.messages.push(message("user", "Hi"));
+        conversation.messages.push(message("assistant", "Hello!"));
+
+        conversation.set_system("Be brief.");
+        conversation.set_system("Be very brief.");
+        assert_eq!(conversation.messages.len(), 3);
+        assert_eq!(conversation.messages[0].content, "Be very brief.");
+
+        conversation.clear();
+        assert_eq!(conversation.messages.len(), 1);
+        assert!(conversation.has_system());
+
+        conversation.set_system("");
+        assert!(conversation.messages.is_empty());
+    }
+
+    #[test]
+    fn test_transcript_formats() {
+        let message = ChatMessage {
+            role: "user".to_string(),
+            content: "Hi".to_string(),
+        };
+
+        let markdown = Transcript::new(PathBuf::from("chat.md"));
+        assert_eq!(markdown.render(&message), "### user\n\nHi\n\n");
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let jsonl = Transcript::new(dir.join("chat.jsonl"));
+        jsonl.append(std::slice::from_ref(&message)).unwrap();
+        jsonl.append(&[message]).unwrap();
+        let contents = std::fs::read_to_string(&jsonl.path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert_eq!(contents.lines().next().unwrap(), r#"{"role":"user","content":"Hi"}"#);
+
+        // /save replaces what was there
+        jsonl.write(&[]).unwrap();
+        assert!(std::fs::read_to_string(&jsonl.path).unwrap().is_empty());
+    }
 }
@@ -1,28 +1,247 @@
 use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::time::Duration;
 use tokio::signal;
 use tokio::time::sleep;
 use tracing::{error, info, warn};
-use vllama_server::Server;
+use vllama_server::{Server, ServerConfig, ServerState};
+use vllama_core::ModelSource;
+use crate::config::{Config, LOG_LEVELS};
 use crate::output::{self, OutputMode};
 use serde_json::json;
 
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
 
+/// A LoRA adapter to register with vLLM, given on the command line as `name=path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraModule {
+    pub name: String,
+    pub path: String,
+}
+
+/// Parse a `--lora name=path` argument
+pub fn parse_lora(s: &str) -> std::result::Result<LoraModule, String> {
+    let (name, path) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid LoRA adapter '{}': expected name=path", s))?;
+    let (name, path) = (name.trim(), path.trim());
+    if name.is_empty() || path.is_empty() {
+        return Err(format!("invalid LoRA adapter '{}': name and path must be non-empty", s));
+    }
+    Ok(LoraModule {
+        name: name.to_string(),
+        path: path.to_string(),
+    })
+}
+
+/// vLLM engine settings with a `serve` flag; None leaves vLLM's default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VllmOptions {
+    /// GPUs to shard the model across
+    pub tensor_parallel_size: Option<usize>,
+    /// Weight and activation dtype (`auto`, `half`, `bfloat16`, ...)
+    pub dtype: Option<String>,
+    /// Quantization method (`awq`, `gptq`, `fp8`, ...)
+    pub quantization: Option<String>,
+    /// Context window in tokens
+    pub max_model_len: Option<usize>,
+}
+
+impl VllmOptions {
+    /// The vLLM arguments for the settings that are set
+    fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(size) = self.tensor_parallel_size {
+            args.extend(["--tensor-parallel-size".to_string(), size.to_string()]);
+        }
+        if let Some(dtype) = &self.dtype {
+            args.extend(["--dtype".to_string(), dtype.clone()]);
+        }
+        if let Some(method) = &self.quantization {
+            args.extend(["--quantization".to_string(), method.clone()]);
+        }
+        if let Some(len) = self.max_model_len {
+            args.extend(["--max-model-len".to_string(), len.to_string()]);
+        }
+        args
+    }
+}
+
+/// How long `--watch-config` waits after a config file changes before
+/// reading it, so the several events of one editor save become one reload
+const CONFIG_SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// What `--watch-config` needs to apply config edits like startup did
+pub struct ConfigWatch {
+    /// The config the server started with
+    pub config: Config,
+    /// `--max-loaded-vram`, which takes precedence over the config
+    pub max_loaded_vram: Option<u64>,
+    /// [`gpu_vram_budget_mb`], used when neither sets a budget
+    pub gpu_vram_budget: Option<u64>,
+}
+
+/// VRAM budget for loaded models when none is configured: the GPU's total
+/// VRAM times the share vLLM is started with, across `tensor_parallel_size`
+/// GPUs; None without an NVIDIA GPU
+///
+/// Free VRAM can't be used instead, since it reads near zero once vLLM has
+/// preallocated its share.
+pub fn gpu_vram_budget_mb(gpu_memory_utilization: f32, tensor_parallel_size: Option<usize>) -> Option<u64> {
+    let gpu = vllama_core::Hardware::detect().gpu_info?;
+    Some(vram_budget_mb(gpu.vram_total_mb, gpu_memory_utilization, tensor_parallel_size.unwrap_or(1)))
+}
+
+fn vram_budget_mb(vram_total_mb: u64, gpu_memory_utilization: f32, gpus: usize) -> u64 {
+    (vram_total_mb as f64 * gpu_memory_utilization as f64).round() as u64 * gpus as u64
+}
+
+/// Apply the runtime-safe settings of `config` to the running server
+fn apply_config(state: &ServerState, config: &Config, max_loaded_vram: Option<u64>, gpu_vram_budget: Option<u64>) {
+    state.update_config(|server| {
+        server.report_warnings = config.server.report_warnings;
+        server.warmup_on_load = config.server.warmup_on_load;
+        server.queue_timeout = config.server.queue_timeout.map(Duration::from_secs);
+        server.max_loaded_models = config.server.max_loaded_models;
+        server.max_loaded_vram_mb = max_loaded_vram.or(config.server.max_loaded_vram_mb).or(gpu_vram_budget);
+        server.keep_alive = config.model.keep_alive_secs.map(Duration::from_secs);
+    });
+    crate::set_log_level(&config.logging.level);
+}
+
+/// Reload the config files whenever they change and apply what can be
+/// applied without a restart; runs until the server stops
+///
+/// Watches the directories holding the files rather than the files
+/// themselves: a file may not exist yet, and editors often save by
+/// replacing it. A file that fails to parse is reported and the previous
+/// settings stay in effect.
+async fn watch_config(state: ServerState, watch: ConfigWatch) {
+    use notify::{RecursiveMode, Watcher};
+
+    // Event paths are absolute
+    let files: Vec<PathBuf> = Config::files()
+        .into_iter()
+        .map(|path| std::env::current_dir().map(|dir| dir.join(&path)).unwrap_or(path))
+        .collect();
+    let (events, mut changed) = tokio::sync::mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = events.send(event.paths);
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            warn!("Can't watch the config files: {}", e);
+            return;
+        }
+    };
+    let dirs: std::collections::BTreeSet<&Path> = files.iter().filter_map(|file| file.parent()).collect();
+    for dir in dirs {
+        // A missing ~/.config/vllama only means there is no file to edit
+        if dir.is_dir() {
+            if let Err(e) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+                warn!("Can't watch {} for config changes: {}", dir.display(), e);
+            }
+        }
+    }
+    let mut current = watch.config;
+
+    while let Some(paths) = changed.recv().await {
+        if !paths.iter().any(|path| files.contains(path)) {
+            continue;
+        }
+        sleep(CONFIG_SETTLE_TIME).await;
+        while changed.try_recv().is_ok() {}
+
+        let new = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Ignoring config change: {:#}", e);
+                continue;
+            }
+        };
+        if new.server.max_loaded_models == Some(0) {
+            warn!("Ignoring config change: server.max_loaded_models must be at least 1");
+            continue;
+        }
+        if !LOG_LEVELS.contains(&new.logging.level.as_str()) {
+            warn!("Ignoring config change: logging.level must be one of {}", LOG_LEVELS.join(", "));
+            continue;
+        }
+        let changes = current.changes(&new);
+        if !changes.applied.is_empty() {
+            apply_config(&state, &new, watch.max_loaded_vram, watch.gpu_vram_budget);
+            info!("Applied config changes: {}", changes.applied.join(", "));
+        }
+        if !changes.need_restart.is_empty() {
+            warn!("Restart serve to apply: {}", changes.need_restart.join(", "));
+        }
+        current = new;
+    }
+}
+
+/// vLLM flags vllama sets itself; overriding them breaks the proxy
+const RESERVED_VLLM_ARGS: &[&str] = &["--model", "--port", "--host", "--served-model-name"];
+
+/// Split `--raw-vllm-args` values into individual arguments and reject
+/// flags vllama manages
+///
+/// Each value is split on whitespace, so `-X "--max-model-len 4096"` and
+/// `-X --max-model-len -X 4096` are equivalent. Quoting isn't supported.
+pub fn parse_raw_vllm_args(raw: &[String]) -> Result<Vec<String>> {
+    let args: Vec<String> = raw
+        .iter()
+        .flat_map(|value| value.split_whitespace())
+        .map(str::to_string)
+        .collect();
+
+    for arg in &args {
+        let flag = arg.split('=').next().unwrap_or(arg);
+        if RESERVED_VLLM_ARGS.contains(&flag) {
+            anyhow::bail!(
+                "{} is managed by vllama and can't be passed through --raw-vllm-args",
+                flag
+            );
+        }
+    }
+
+    Ok(args)
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
-    host: String,
-    port: u16,
+    mut server_config: ServerConfig,
     model: Option<String>,
-    vllm_port: u16,
+    served_model_name: Option<String>,
     no_vllm: bool,
     max_num_seqs: usize,
     gpu_memory_utilization: f32,
+    vllm_options: VllmOptions,
+    vllm_log: PathBuf,
+    vllm_max_restarts: u32,
+    trust_remote_code: bool,
+    lora_modules: Vec<LoraModule>,
+    raw_vllm_args: Vec<String>,
+    config_watch: Option<ConfigWatch>,
     output_mode: OutputMode,
 ) -> Result<()> {
+    let ServerConfig { host, port, vllm_port, .. } = server_config.clone();
+    let listen_url = format!("http://{}", server_config.bind_addr()?);
+    let raw_vllm_args = parse_raw_vllm_args(&raw_vllm_args)?;
+    // Short names become repo ids; a missing local path fails before vLLM starts
+    let model = model
+        .map(|m| ModelSource::resolve(&m).map(|source| source.as_model_arg()))
+        .transpose()?;
+    server_config.default_model = served_model_name.clone().or_else(|| model.clone());
+    let state = ServerState::from_config(server_config.clone())
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
     let mut vllm_process: Option<Child> = None;
+    // Command and served name to bring vLLM back with if it exits
+    let mut vllm_restart: Option<(Vec<String>, String)> = None;
 
     // Show header in normal mode
     if output_mode == OutputMode::Normal {
@@ -31,38 +250,85 @@ pub async fn run(
 
     if !no_vllm {
         if let Some(model_name) = &model {
+            // The name clients (and warmup) must use once vLLM is up
+            let served_name = served_model_name.as_deref().unwrap_or(model_name);
             info!("Starting vLLM OpenAI server on port {}", vllm_port);
+            if trust_remote_code {
+                warn!("trust_remote_code enabled: model repository code will be executed");
+            }
 
             match output_mode {
                 OutputMode::Normal => {
                     println!("{}", output::section("Loading model"));
                     output::kv("Model", model_name);
+                    if let Some(alias) = &served_model_name {
+                        output::kv("Served as", alias);
+                    }
                     output::kv("Port", &vllm_port.to_string());
                     output::kv("Max sequences", &max_num_seqs.to_string());
                     output::kv("Batched tokens", "16,384");
                     output::kv("GPU memory", &format!("{:.0}%", gpu_memory_utilization * 100.0));
+                    if let Some(size) = vllm_options.tensor_parallel_size {
+                        output::kv("Tensor parallel", &format!("{} GPUs", size));
+                    }
+                    if let Some(dtype) = &vllm_options.dtype {
+                        output::kv("Dtype", dtype);
+                    }
+                    if let Some(method) = &vllm_options.quantization {
+                        output::kv("Quantization", method);
+                    }
+                    if let Some(len) = vllm_options.max_model_len {
+                        output::kv("Max model length", &len.to_string());
+                    }
                     output::kv("Optimizations", "chunked-prefill, prefix-caching");
-                    output::kv("Logs", "vllm.log");
+                    output::kv("Logs", &format!("{} (vllama logs -f)", vllm_log.display()));
+                    if !lora_modules.is_empty() {
+                        let names: Vec<&str> = lora_modules.iter().map(|l| l.name.as_str()).collect();
+                        output::kv("LoRA adapters", &names.join(", "));
+                    }
+                    if !raw_vllm_args.is_empty() {
+                        output::kv("Extra vLLM args", &raw_vllm_args.join(" "));
+                    }
+                    if trust_remote_code {
+                        println!();
+                        println!("{}", output::warning("--trust-remote-code: executing Python code shipped with the model"));
+                        println!("{}", output::bullet("Only use this with model repositories you trust"));
+                    }
                     println!();
                 }
                 OutputMode::Json => {
                     output::json(&json!({
                         "event": "vllm_starting",
                         "model": model_name,
+                        "served_model_name": served_name,
                         "port": vllm_port,
                         "max_sequences": max_num_seqs,
-                        "gpu_memory_utilization": gpu_memory_utilization
+                        "gpu_memory_utilization": gpu_memory_utilization,
+                        "tensor_parallel_size": vllm_options.tensor_parallel_size,
+                        "dtype": vllm_options.dtype,
+                        "quantization": vllm_options.quantization,
+                        "max_model_len": vllm_options.max_model_len,
+                        "trust_remote_code": trust_remote_code,
+                        "lora_adapters": lora_modules.iter().map(|l| &l.name).collect::<Vec<_>>(),
+                        "raw_vllm_args": raw_vllm_args
                     }));
                 }
                 OutputMode::Quiet => {}
             }
 
-            vllm_process = Some(start_vllm_server(
+            let args = vllm_command_args(
                 model_name,
+                served_model_name.as_deref(),
                 vllm_port,
                 max_num_seqs,
                 gpu_memory_utilization,
-            )?);
+                &vllm_options,
+                trust_remote_code,
+                &lora_modules,
+                &raw_vllm_args,
+            );
+            vllm_process = Some(start_vllm_server(&args, &vllm_log)?);
+            vllm_restart = Some((args, served_name.to_string()));
 
             // Wait for vLLM with spinner
             let spinner = if output_mode == OutputMode::Normal {
@@ -71,7 +337,7 @@ pub async fn run(
                 None
             };
 
-            if !wait_for_vllm_ready(vllm_port).await {
+            if !wait_for_vllm_ready(vllm_port, served_name).await {
                 if let Some(sp) = spinner {
                     sp.finish_and_clear();
                 }
@@ -96,6 +362,27 @@ pub async fn run(
                 output::json(&json!({"event": "vllm_ready"}));
             }
 
+            if server_config.warmup_on_load {
+                let spinner = (output_mode == OutputMode::Normal).then(|| output::spinner("Warming up model..."));
+                let elapsed = state.warm_up(served_name).await;
+                if let Some(sp) = spinner {
+                    match elapsed {
+                        Some(elapsed) => sp.finish_with_message(output::success(&format!(
+                            "Model warmed up ({:.1}s)",
+                            elapsed.as_secs_f64()
+                        ))),
+                        None => sp.finish_with_message(output::warning("Warmup failed; see logs")),
+                    }
+                }
+                if output_mode == OutputMode::Json {
+                    output::json(&json!({
+                        "event": "warmup",
+                        "success": elapsed.is_some(),
+                        "duration_ms": elapsed.map(|d| d.as_millis() as u64)
+                    }));
+                }
+            }
+
             if output_mode == OutputMode::Normal {
                 println!();
             }
@@ -123,14 +410,14 @@ pub async fn run(
 
         match output_mode {
             OutputMode::Normal => {
-                println!("{}", output::info(&format!("Connecting to existing vLLM server on port {}", vllm_port)));
+                println!("{}", output::info(&format!("Connecting to existing vLLM server at {}", server_config.vllm_url())));
                 println!();
             }
             OutputMode::Json => {
                 output::json(&json!({
                     "event": "info",
                     "message": "Using existing vLLM server",
-                    "port": vllm_port
+                    "url": server_config.vllm_url()
                 }));
             }
             OutputMode::Quiet => {}
@@ -142,7 +429,7 @@ pub async fn run(
     match output_mode {
         OutputMode::Normal => {
             println!("{}", output::section("Starting vllama API"));
-            println!("{}", output::success(&format!("Listening on http://{}:{}", host, port)));
+            println!("{}", output::success(&format!("Listening on {}", listen_url)));
             println!();
             println!("  Ollama API:");
             println!("{}", output::bullet("POST /api/generate"));
@@ -154,11 +441,14 @@ pub async fn run(
             println!("{}", output::bullet("POST /v1/completions"));
             println!("{}", output::bullet("POST /v1/chat/completions"));
             println!();
+            if let Some(limit) = server_config.idle_shutdown {
+                println!("{}", output::info(&format!("Stops after {}s without requests (--idle-shutdown)", limit.as_secs())));
+            }
             println!("Press Ctrl+C to stop");
             println!();
         }
         OutputMode::Quiet => {
-            println!("{}", output::success(&format!("Listening on http://{}:{}", host, port)));
+            println!("{}", output::success(&format!("Listening on {}", listen_url)));
         }
         OutputMode::Json => {
             output::json(&json!({
@@ -175,11 +465,35 @@ pub async fn run(
         }
     }
 
-    let server = Server::new(host, port).map_err(|e| anyhow::anyhow!("{}", e))?;
+    if let Some(watch) = config_watch {
+        let files: Vec<String> = Config::files().iter().map(|p| p.display().to_string()).collect();
+        info!("Watching {} for changes", files.join(" and "));
+        tokio::spawn(watch_config(state.clone(), watch));
+    }
+
+    let idle_state = state.clone();
+    let server = Server::with_state(state);
 
     let server_future = server.run();
     let shutdown_signal = shutdown_signal();
+    let supervisor = async {
+        match &vllm_restart {
+            Some((args, served_name)) => {
+                supervise_vllm(&mut vllm_process, args, &vllm_log, vllm_port, served_name, vllm_max_restarts, output_mode)
+                    .await
+            }
+            None => std::future::pending().await,
+        }
+    };
 
+    let idle = async {
+        match server_config.idle_shutdown {
+            Some(limit) => idle_state.wait_until_idle(limit).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let mut vllm_gave_up = None;
     tokio::select! {
         result = server_future => {
             result.map_err(|e| anyhow::anyhow!("{}", e))?;
@@ -191,6 +505,29 @@ pub async fn run(
             }
             info!("Received shutdown signal");
         }
+        _ = idle => {
+            let idle_secs = server_config.idle_shutdown.unwrap_or_default().as_secs();
+            info!("No requests for {}s, shutting down", idle_secs);
+            match output_mode {
+                OutputMode::Normal => {
+                    println!();
+                    println!("{}", output::info(&format!("No requests for {}s; shutting down...", idle_secs)));
+                }
+                OutputMode::Json => output::json(&json!({"event": "idle_shutdown", "idle_seconds": idle_secs})),
+                OutputMode::Quiet => {}
+            }
+        }
+        message = supervisor => {
+            match output_mode {
+                OutputMode::Normal => {
+                    println!("{}", output::error(&message));
+                    println!("{}", output::info("Shutting down..."));
+                }
+                OutputMode::Json => output::json(&json!({"event": "error", "message": message})),
+                OutputMode::Quiet => {}
+            }
+            vllm_gave_up = Some(message);
+        }
     }
 
     if let Some(mut child) = vllm_process {
@@ -221,7 +558,99 @@ pub async fn run(
         OutputMode::Quiet => {}
     }
 
-    Ok(())
+    match vllm_gave_up {
+        Some(message) => Err(anyhow::anyhow!(message)),
+        None => Ok(()),
+    }
+}
+
+/// How often the supervisor checks whether vLLM is still running
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Delay before the `attempt`th restart: 2s, 4s, 8s, ... up to a minute
+fn restart_backoff(attempt: u32) -> Duration {
+    Duration::from_secs((1u64 << attempt.min(6)).min(60))
+}
+
+/// Restart vLLM whenever it exits, at most `max_restarts` times in total
+///
+/// Only returns, with the reason, once vLLM is down and out of restarts.
+async fn supervise_vllm(
+    child: &mut Option<Child>,
+    args: &[String],
+    log_path: &Path,
+    port: u16,
+    model: &str,
+    max_restarts: u32,
+    output_mode: OutputMode,
+) -> String {
+    let mut restarts = 0;
+    loop {
+        sleep(SUPERVISE_INTERVAL).await;
+
+        let exit = match child.as_mut().map(Child::try_wait) {
+            Some(Ok(None)) => continue,
+            Some(Ok(Some(status))) => status.to_string(),
+            Some(Err(e)) => {
+                warn!("Failed to check the vLLM process: {}", e);
+                continue;
+            }
+            // The last restart failed to spawn
+            None => "not running".to_string(),
+        };
+
+        // uv and Python can outlive each other; clear out the whole group
+        if let Some(mut dead) = child.take() {
+            let _ = tokio::task::spawn_blocking(move || {
+                let _ = kill_process_tree(&mut dead);
+                let _ = dead.wait();
+            })
+            .await;
+        }
+
+        if restarts >= max_restarts {
+            let message = format!("vLLM exited ({}) and the restart limit ({}) is reached", exit, max_restarts);
+            error!("{}", message);
+            return message;
+        }
+        restarts += 1;
+        let delay = restart_backoff(restarts);
+        warn!("vLLM exited ({}); restart {}/{} in {}s", exit, restarts, max_restarts, delay.as_secs());
+        if output_mode == OutputMode::Normal {
+            println!(
+                "{}",
+                output::warning(&format!(
+                    "vLLM exited ({}); restarting in {}s ({}/{})",
+                    exit,
+                    delay.as_secs(),
+                    restarts,
+                    max_restarts
+                ))
+            );
+        }
+        sleep(delay).await;
+
+        match start_vllm_server(args, log_path) {
+            Ok(new_child) => *child = Some(new_child),
+            Err(e) => {
+                error!("Failed to restart vLLM: {:#}", e);
+                continue;
+            }
+        }
+        let ready = wait_for_vllm_ready(port, model).await;
+        match output_mode {
+            OutputMode::Normal if ready => println!("{}", output::success("vLLM restarted")),
+            OutputMode::Normal => println!("{}", output::warning("vLLM restarted but didn't load the model; see vllama logs")),
+            OutputMode::Json => output::json(&json!({
+                "event": "vllm_restarted",
+                "attempt": restarts,
+                "max_restarts": max_restarts,
+                "exit_status": exit,
+                "ready": ready
+            })),
+            OutputMode::Quiet => {}
+        }
+    }
 }
 
 /// Kill a child process and all its descendants
@@ -256,93 +685,117 @@ fn kill_process_tree(child: &mut Child) -> std::io::Result<()> {
     child.kill()
 }
 
-fn start_vllm_server(
+/// Arguments to `uv` that start the vLLM OpenAI server
+#[allow(clippy::too_many_arguments)]
+fn vllm_command_args(
     model: &str,
+    served_model_name: Option<&str>,
     port: u16,
     max_num_seqs: usize,
     gpu_memory_utilization: f32,
-) -> Result<Child> {
+    vllm_options: &VllmOptions,
+    trust_remote_code: bool,
+    lora_modules: &[LoraModule],
+    raw_vllm_args: &[String],
+) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "run",
+        "--directory",
+        "python",
+        "python",
+        "-m",
+        "vllm.entrypoints.openai.api_server",
+        "--model",
+        model,
+        "--port",
+        &port.to_string(),
+        // Concurrency & Batching
+        "--max-num-seqs",
+        &max_num_seqs.to_string(),
+        "--max-num-batched-tokens",
+        "16384", // 32x increase from default (512) for better throughput
+        // Performance optimizations
+        "--enable-chunked-prefill", // Better concurrent request handling
+        "--enable-prefix-caching",  // Reuse KV cache for repeated prompts
+        // Memory
+        "--gpu-memory-utilization",
+        &gpu_memory_utilization.to_string(),
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    args.extend(vllm_options.args());
+
+    if let Some(name) = served_model_name {
+        args.push("--served-model-name".to_string());
+        args.push(name.to_string());
+    }
+
+    if trust_remote_code {
+        args.push("--trust-remote-code".to_string());
+    }
+
+    // Adapters are selected per request through the `model` field
+    if !lora_modules.is_empty() {
+        args.push("--enable-lora".to_string());
+        args.push("--lora-modules".to_string());
+        args.extend(lora_modules.iter().map(|l| format!("{}={}", l.name, l.path)));
+    }
+
+    // Appended last so they take precedence over vllama's defaults
+    args.extend(raw_vllm_args.iter().cloned());
+    args
+}
+
+/// Start vLLM with `args`, appending its output to `log_path`
+fn start_vllm_server(args: &[String], log_path: &Path) -> Result<Child> {
     // Redirect vLLM output to log file for clean CLI UX
     use std::fs::OpenOptions;
 
+    if let Some(dir) = log_path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create vLLM log directory {}", dir.display()))?;
+    }
     let log_file = OpenOptions::new()
         .create(true)
         .append(true)
-        .open("vllm.log")
-        .context("Failed to create vllm.log file")?;
+        .open(log_path)
+        .with_context(|| format!("Failed to create vLLM log file {}", log_path.display()))?;
+
+    info!("vLLM command: uv {}", args.join(" "));
+
+    let mut command = Command::new("uv");
+    command
+        .args(args)
+        .stdout(Stdio::from(log_file.try_clone()?))
+        .stderr(Stdio::from(log_file));
 
+    // Create new process group so we can kill the entire tree
     #[cfg(unix)]
-    let child = {
-        Command::new("uv")
-            .args([
-                "run",
-                "--directory",
-                "python",
-                "python",
-                "-m",
-                "vllm.entrypoints.openai.api_server",
-                "--model",
-                model,
-                "--port",
-                &port.to_string(),
-                // Concurrency & Batching
-                "--max-num-seqs",
-                &max_num_seqs.to_string(),
-                "--max-num-batched-tokens",
-                "16384", // 32x increase from default (512) for better throughput
-                // Performance optimizations
-                "--enable-chunked-prefill", // Better concurrent request handling
-                "--enable-prefix-caching",  // Reuse KV cache for repeated prompts
-                // Memory
-                "--gpu-memory-utilization",
-                &gpu_memory_utilization.to_string(),
-            ])
-            .stdout(Stdio::from(log_file.try_clone()?))
-            .stderr(Stdio::from(log_file))
-            // Create new process group so we can kill the entire tree
-            .process_group(0)
-            .spawn()
-            .context("Failed to start vLLM server. Is uv installed? (curl -LsSf https://astral.sh/uv/install.sh | sh)")?
-    };
+    command.process_group(0);
 
-    #[cfg(not(unix))]
-    let child = {
-        Command::new("uv")
-            .args([
-                "run",
-                "--directory",
-                "python",
-                "python",
-                "-m",
-                "vllm.entrypoints.openai.api_server",
-                "--model",
-                model,
-                "--port",
-                &port.to_string(),
-                // Concurrency & Batching
-                "--max-num-seqs",
-                &max_num_seqs.to_string(),
-                "--max-num-batched-tokens",
-                "16384",
-                // Performance optimizations
-                "--enable-chunked-prefill",
-                "--enable-prefix-caching",
-                // Memory
-                "--gpu-memory-utilization",
-                &gpu_memory_utilization.to_string(),
-            ])
-            .stdout(Stdio::from(log_file.try_clone()?))
-            .stderr(Stdio::from(log_file))
-            .spawn()
-            .context("Failed to start vLLM server. Is uv installed? (curl -LsSf https://astral.sh/uv/install.sh | sh)")?
-    };
+    let child = command
+        .spawn()
+        .context("Failed to start vLLM server. Is uv installed? (curl -LsSf https://astral.sh/uv/install.sh | sh)")?;
 
     Ok(child)
 }
 
-async fn wait_for_vllm_ready(port: u16) -> bool {
+/// Whether a vLLM `/v1/models` response lists `model`
+fn model_listed(models: &serde_json::Value, model: &str) -> bool {
+    models["data"]
+        .as_array()
+        .is_some_and(|data| data.iter().any(|m| m["id"].as_str() == Some(model)))
+}
+
+/// Wait until vLLM serves `model`
+///
+/// `/health` answers as soon as the API process is up, before the weights
+/// finish loading, so readiness is the model appearing in `/v1/models`.
+async fn wait_for_vllm_ready(port: u16, model: &str) -> bool {
     let client = reqwest::Client::new();
-    let url = format!("http://127.0.0.1:{}/health", port);
+    let url = format!("http://127.0.0.1:{}/v1/models", port);
+    let mut process_up = false;
 
     // Wait up to 120 seconds for vLLM to start
     // First startup takes ~67s due to CUDA graph compilation
@@ -350,14 +803,27 @@ async fn wait_for_vllm_ready(port: u16) -> bool {
     for _ in 0..120 {
         sleep(Duration::from_secs(1)).await;
 
-        match client.get(&url).send().await {
-            Ok(response) if response.status().is_success() => {
-                return true;
-            }
-            _ => continue,
+        let Ok(response) = client.get(&url).send().await else {
+            continue;
+        };
+        if !process_up {
+            info!("vLLM process is up; waiting for {} to load", model);
+            process_up = true;
+        }
+        let Ok(models) = response.json::<serde_json::Value>().await else {
+            continue;
+        };
+        if model_listed(&models, model) {
+            info!("vLLM model loaded: {}", model);
+            return true;
         }
     }
 
+    if process_up {
+        error!("vLLM answered but never listed {} in /v1/models", model);
+    } else {
+        error!("vLLM process never answered on port {}", port);
+    }
     false
 }
 
@@ -384,3 +850,75 @@ async fn shutdown_signal() {
         _ = terminate => {},
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vram_budget() {
+        assert_eq!(vram_budget_mb(24_000, 0.9, 1), 21_600);
+        assert_eq!(vram_budget_mb(24_000, 0.5, 2), 24_000);
+    }
+
+    #[test]
+    fn test_parse_lora() {
+        let lora = parse_lora("sql=/adapters/sql-lora").unwrap();
+        assert_eq!(lora.name, "sql");
+        assert_eq!(lora.path, "/adapters/sql-lora");
+
+        // HF repo ids are valid paths
+        let lora = parse_lora("chat=org/some-lora").unwrap();
+        assert_eq!(lora.path, "org/some-lora");
+
+        assert!(parse_lora("no-equals").is_err());
+        assert!(parse_lora("=path").is_err());
+        assert!(parse_lora("name=").is_err());
+    }
+
+    #[test]
+    fn test_parse_raw_vllm_args() {
+        let args = parse_raw_vllm_args(&[
+            "--max-model-len 4096".to_string(),
+            "--enforce-eager".to_string(),
+        ])
+        .unwrap();
+        assert_eq!(args, vec!["--max-model-len", "4096", "--enforce-eager"]);
+
+        assert!(parse_raw_vllm_args(&["--port 9000".to_string()]).is_err());
+        assert!(parse_raw_vllm_args(&["--model=other".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let delays: Vec<u64> = (1..=7).map(|attempt| restart_backoff(attempt).as_secs()).collect();
+        assert_eq!(delays, vec![2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(restart_backoff(100).as_secs(), 60);
+    }
+
+    #[test]
+    fn test_model_listed() {
+        let models = json!({"object": "list", "data": [{"id": "llama3.2", "root": "meta-llama/Llama-3.2-1B-Instruct"}]});
+        assert!(model_listed(&models, "llama3.2"));
+        // Clients use the served name, so that's what readiness waits for
+        assert!(!model_listed(&models, "meta-llama/Llama-3.2-1B-Instruct"));
+        assert!(!model_listed(&json!({"data": []}), "llama3.2"));
+        assert!(!model_listed(&json!({"detail": "Not Found"}), "llama3.2"));
+    }
+
+    #[test]
+    fn test_vllm_options_args() {
+        assert!(VllmOptions::default().args().is_empty());
+
+        let options = VllmOptions {
+            tensor_parallel_size: Some(4),
+            dtype: None,
+            quantization: Some("awq".to_string()),
+            max_model_len: Some(8192),
+        };
+        assert_eq!(
+            options.args(),
+            vec!["--tensor-parallel-size", "4", "--quantization", "awq", "--max-model-len", "8192"]
+        );
+    }
+}
@@ -10,7 +10,7 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tracing::debug;
+use tracing::{debug, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -25,6 +25,12 @@ pub struct Config {
 
     #[serde(default)]
     pub output: OutputConfig,
+
+    #[serde(default)]
+    pub huggingface: HuggingFaceConfig,
+
+    #[serde(default)]
+    pub vllm: VllmConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,8 +41,52 @@ pub struct ServerConfig {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// Accept IPv4 and IPv6 clients on one socket (host must be 0.0.0.0 or ::)
+    #[serde(default)]
+    pub dual_stack: bool,
+
     #[serde(default = "default_vllm_port")]
     pub vllm_port: u16,
+
+    /// Full URL of a vLLM server elsewhere (http or https); overrides vllm_port
+    pub vllm_url: Option<String>,
+
+    /// Skip TLS certificate checks for vLLM (self-signed internal deployments)
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+
+    /// Tokio worker threads (defaults to one per CPU core)
+    pub worker_threads: Option<usize>,
+
+    /// Maximum threads in tokio's blocking pool (defaults to 512)
+    pub max_blocking_threads: Option<usize>,
+
+    /// Remove special tokens (e.g. `<|eot_id|>`) that leak into generated text
+    #[serde(default = "default_strip_special_tokens")]
+    pub strip_special_tokens: bool,
+
+    /// Send a one-token request after the model loads to avoid a slow first request
+    #[serde(default = "default_warmup_on_load")]
+    pub warmup_on_load: bool,
+
+    /// Seconds a request may wait for the engine before failing with 503
+    pub queue_timeout: Option<u64>,
+
+    /// Seconds a request to vLLM may take, generation included (default unlimited)
+    pub request_timeout: Option<u64>,
+
+    /// Models kept loaded at once; loading another unloads the least recently used
+    pub max_loaded_models: Option<usize>,
+
+    /// VRAM budget (MB) for loaded models' weights; see `serve --max-loaded-vram`
+    pub max_loaded_vram_mb: Option<u64>,
+
+    /// Stop the server (and vLLM) after this long without requests, e.g. "30m"
+    pub idle_shutdown: Option<String>,
+
+    /// Add a `warnings` list of ignored options to generation replies
+    #[serde(default)]
+    pub report_warnings: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,10 +98,27 @@ pub struct ModelConfig {
 
     #[serde(default = "default_max_num_seqs")]
     pub max_num_seqs: usize,
+
+    /// GPUs to shard the model across; see `serve --tensor-parallel-size`
+    pub tensor_parallel_size: Option<usize>,
+
+    /// vLLM `--dtype`; unset lets vLLM pick from the model config
+    pub dtype: Option<String>,
+
+    /// vLLM `--quantization` method; unset lets vLLM detect it
+    pub quantization: Option<String>,
+
+    /// vLLM `--max-model-len`; unset uses the model's full context
+    pub max_model_len: Option<usize>,
+
+    /// Seconds a pulled model stays loaded after its last request, unless
+    /// the request sets `keep_alive`; unset keeps models loaded
+    pub keep_alive_secs: Option<u64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LoggingConfig {
+    /// One of [`LOG_LEVELS`]
     #[serde(default = "default_log_level")]
     pub level: String,
 
@@ -59,6 +126,19 @@ pub struct LoggingConfig {
     pub json: bool,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HuggingFaceConfig {
+    /// Combined bandwidth cap for model downloads, in bytes per second
+    pub max_download_rate: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VllmConfig {
+    /// Where `serve` writes vLLM's output and `logs` reads it (default
+    /// `~/.local/state/vllama/vllm.log`)
+    pub log_file: Option<PathBuf>,
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OutputConfig {
     #[serde(default)]
@@ -73,7 +153,20 @@ impl Default for ServerConfig {
         Self {
             host: default_host(),
             port: default_port(),
+            dual_stack: false,
             vllm_port: default_vllm_port(),
+            vllm_url: None,
+            accept_invalid_certs: false,
+            worker_threads: None,
+            max_blocking_threads: None,
+            strip_special_tokens: default_strip_special_tokens(),
+            warmup_on_load: default_warmup_on_load(),
+            queue_timeout: None,
+            request_timeout: None,
+            max_loaded_models: None,
+            max_loaded_vram_mb: None,
+            idle_shutdown: None,
+            report_warnings: false,
         }
     }
 }
@@ -84,6 +177,11 @@ impl Default for ModelConfig {
             default_model: None,
             gpu_memory_utilization: default_gpu_memory_utilization(),
             max_num_seqs: default_max_num_seqs(),
+            tensor_parallel_size: None,
+            dtype: None,
+            quantization: None,
+            max_model_len: None,
+            keep_alive_secs: None,
         }
     }
 }
@@ -110,6 +208,14 @@ fn default_vllm_port() -> u16 {
     8100
 }
 
+fn default_strip_special_tokens() -> bool {
+    true
+}
+
+fn default_warmup_on_load() -> bool {
+    true
+}
+
 fn default_gpu_memory_utilization() -> f32 {
     0.9
 }
@@ -118,6 +224,9 @@ fn default_max_num_seqs() -> usize {
     256
 }
 
+/// Values `logging.level` may take
+pub const LOG_LEVELS: [&str; 5] = ["trace", "debug", "info", "warn", "error"];
+
 fn default_log_level() -> String {
     "info".to_string()
 }
@@ -152,6 +261,57 @@ impl Config {
         Ok(config)
     }
 
+    /// The files [`Config::load`] reads, whether or not they exist
+    pub fn files() -> Vec<PathBuf> {
+        Self::user_config_path()
+            .into_iter()
+            .chain(std::iter::once(PathBuf::from("vllama.toml")))
+            .collect()
+    }
+
+    /// Settings that differ in `new`, split into those `serve
+    /// --watch-config` applies to the running server and those that only
+    /// take effect on restart
+    pub fn changes(&self, new: &Config) -> ConfigChanges {
+        let old = self;
+        let applied = [
+            ("logging.level", old.logging.level != new.logging.level),
+            ("server.report_warnings", old.server.report_warnings != new.server.report_warnings),
+            ("server.warmup_on_load", old.server.warmup_on_load != new.server.warmup_on_load),
+            ("server.queue_timeout", old.server.queue_timeout != new.server.queue_timeout),
+            ("server.max_loaded_models", old.server.max_loaded_models != new.server.max_loaded_models),
+            ("server.max_loaded_vram_mb", old.server.max_loaded_vram_mb != new.server.max_loaded_vram_mb),
+            ("model.keep_alive_secs", old.model.keep_alive_secs != new.model.keep_alive_secs),
+        ];
+        let need_restart = [
+            ("server.host", old.server.host != new.server.host),
+            ("server.port", old.server.port != new.server.port),
+            ("server.dual_stack", old.server.dual_stack != new.server.dual_stack),
+            ("server.vllm_port", old.server.vllm_port != new.server.vllm_port),
+            ("server.vllm_url", old.server.vllm_url != new.server.vllm_url),
+            ("server.accept_invalid_certs", old.server.accept_invalid_certs != new.server.accept_invalid_certs),
+            ("server.worker_threads", old.server.worker_threads != new.server.worker_threads),
+            ("server.max_blocking_threads", old.server.max_blocking_threads != new.server.max_blocking_threads),
+            ("server.strip_special_tokens", old.server.strip_special_tokens != new.server.strip_special_tokens),
+            ("server.request_timeout", old.server.request_timeout != new.server.request_timeout),
+            ("server.idle_shutdown", old.server.idle_shutdown != new.server.idle_shutdown),
+            ("model.default_model", old.model.default_model != new.model.default_model),
+            ("model.gpu_memory_utilization", old.model.gpu_memory_utilization != new.model.gpu_memory_utilization),
+            ("model.max_num_seqs", old.model.max_num_seqs != new.model.max_num_seqs),
+            ("model.tensor_parallel_size", old.model.tensor_parallel_size != new.model.tensor_parallel_size),
+            ("model.dtype", old.model.dtype != new.model.dtype),
+            ("model.quantization", old.model.quantization != new.model.quantization),
+            ("model.max_model_len", old.model.max_model_len != new.model.max_model_len),
+            ("logging.json", old.logging.json != new.logging.json),
+            ("huggingface.max_download_rate", old.huggingface.max_download_rate != new.huggingface.max_download_rate),
+            ("vllm.log_file", old.vllm.log_file != new.vllm.log_file),
+        ];
+        let changed = |settings: &[(&'static str, bool)]| {
+            settings.iter().filter(|(_, changed)| *changed).map(|(name, _)| *name).collect()
+        };
+        ConfigChanges { applied: changed(&applied), need_restart: changed(&need_restart) }
+    }
+
     /// Load config from a specific file
     fn load_from_file(path: &PathBuf) -> Result<Self> {
         let content = std::fs::read_to_string(path)
@@ -163,6 +323,11 @@ impl Config {
 
     /// Get user config file path (~/.config/vllama/config.toml)
     fn user_config_path() -> Option<PathBuf> {
+        Some(Self::dir()?.join("config.toml"))
+    }
+
+    /// `$XDG_CONFIG_HOME/vllama`, falling back to `~/.config/vllama`
+    pub fn dir() -> Option<PathBuf> {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .ok()?;
@@ -171,7 +336,7 @@ impl Config {
             .map(PathBuf::from)
             .unwrap_or_else(|_| PathBuf::from(home).join(".config"));
 
-        Some(config_dir.join("vllama").join("config.toml"))
+        Some(config_dir.join("vllama"))
     }
 
     /// Merge another config into this one (other takes priority)
@@ -183,9 +348,48 @@ impl Config {
         if other.server.port != default_port() {
             self.server.port = other.server.port;
         }
+        if other.server.dual_stack {
+            self.server.dual_stack = true;
+        }
         if other.server.vllm_port != default_vllm_port() {
             self.server.vllm_port = other.server.vllm_port;
         }
+        if other.server.vllm_url.is_some() {
+            self.server.vllm_url = other.server.vllm_url;
+        }
+        if other.server.accept_invalid_certs {
+            self.server.accept_invalid_certs = true;
+        }
+        if other.server.worker_threads.is_some() {
+            self.server.worker_threads = other.server.worker_threads;
+        }
+        if other.server.max_blocking_threads.is_some() {
+            self.server.max_blocking_threads = other.server.max_blocking_threads;
+        }
+        if other.server.strip_special_tokens != default_strip_special_tokens() {
+            self.server.strip_special_tokens = other.server.strip_special_tokens;
+        }
+        if other.server.warmup_on_load != default_warmup_on_load() {
+            self.server.warmup_on_load = other.server.warmup_on_load;
+        }
+        if other.server.queue_timeout.is_some() {
+            self.server.queue_timeout = other.server.queue_timeout;
+        }
+        if other.server.request_timeout.is_some() {
+            self.server.request_timeout = other.server.request_timeout;
+        }
+        if other.server.max_loaded_models.is_some() {
+            self.server.max_loaded_models = other.server.max_loaded_models;
+        }
+        if other.server.max_loaded_vram_mb.is_some() {
+            self.server.max_loaded_vram_mb = other.server.max_loaded_vram_mb;
+        }
+        if other.server.idle_shutdown.is_some() {
+            self.server.idle_shutdown = other.server.idle_shutdown;
+        }
+        if other.server.report_warnings {
+            self.server.report_warnings = true;
+        }
 
         // Model settings
         if other.model.default_model.is_some() {
@@ -197,6 +401,21 @@ impl Config {
         if other.model.max_num_seqs != default_max_num_seqs() {
             self.model.max_num_seqs = other.model.max_num_seqs;
         }
+        if other.model.tensor_parallel_size.is_some() {
+            self.model.tensor_parallel_size = other.model.tensor_parallel_size;
+        }
+        if other.model.dtype.is_some() {
+            self.model.dtype = other.model.dtype;
+        }
+        if other.model.quantization.is_some() {
+            self.model.quantization = other.model.quantization;
+        }
+        if other.model.max_model_len.is_some() {
+            self.model.max_model_len = other.model.max_model_len;
+        }
+        if other.model.keep_alive_secs.is_some() {
+            self.model.keep_alive_secs = other.model.keep_alive_secs;
+        }
 
         // Logging settings
         if other.logging.level != default_log_level() {
@@ -206,6 +425,16 @@ impl Config {
             self.logging.json = true;
         }
 
+        // HuggingFace settings
+        if other.huggingface.max_download_rate.is_some() {
+            self.huggingface.max_download_rate = other.huggingface.max_download_rate;
+        }
+
+        // vLLM settings
+        if other.vllm.log_file.is_some() {
+            self.vllm.log_file = other.vllm.log_file;
+        }
+
         // Output settings
         if other.output.quiet {
             self.output.quiet = true;
@@ -217,6 +446,86 @@ impl Config {
         self
     }
 
+    /// Build the tokio runtime, applying the configured thread counts
+    pub fn build_runtime(&self) -> Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+
+        if let Some(threads) = self.server.worker_threads {
+            if threads == 0 {
+                anyhow::bail!("server.worker_threads must be at least 1");
+            }
+            let cpu_cores = vllama_core::Hardware::detect().cpu_cores;
+            if cpu_cores > 0 && threads > cpu_cores {
+                warn!(
+                    "server.worker_threads ({}) exceeds detected CPU cores ({})",
+                    threads, cpu_cores
+                );
+            }
+            builder.worker_threads(threads);
+        }
+
+        if let Some(threads) = self.server.max_blocking_threads {
+            if threads == 0 {
+                anyhow::bail!("server.max_blocking_threads must be at least 1");
+            }
+            builder.max_blocking_threads(threads);
+        }
+
+        builder.build().context("Failed to build tokio runtime")
+    }
+
+    /// The vLLM log `serve` writes and `logs` reads
+    pub fn vllm_log_file(&self) -> PathBuf {
+        self.vllm.log_file.clone().unwrap_or_else(|| {
+            default_vllm_log_file(
+                std::env::var_os("XDG_STATE_HOME").map(PathBuf::from),
+                std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).map(PathBuf::from),
+            )
+        })
+    }
+
+    /// Bandwidth limiter for model downloads, if one is configured
+    pub fn download_limiter(&self) -> Result<Option<vllama_core::RateLimiter>> {
+        match self.huggingface.max_download_rate {
+            Some(0) => anyhow::bail!("huggingface.max_download_rate must be at least 1 byte/s"),
+            rate => Ok(rate.map(vllama_core::RateLimiter::new)),
+        }
+    }
+
+    /// Where to reach vLLM: `server.vllm_url` if set, else localhost on `vllm_port`
+    pub fn vllm_endpoint(&self) -> Result<VllmEndpoint> {
+        let url = match &self.server.vllm_url {
+            Some(url) => check_vllm_url(url)?,
+            None => format!("http://127.0.0.1:{}", self.server.vllm_port),
+        };
+        Ok(VllmEndpoint {
+            url,
+            accept_invalid_certs: self.server.accept_invalid_certs,
+            timeout: self.server.request_timeout.map(std::time::Duration::from_secs),
+        })
+    }
+
+    /// URL of a `vllama serve` started with this config, for client commands
+    pub fn api_url(&self) -> Result<String> {
+        let server = vllama_server::ServerConfig {
+            host: self.server.host.clone(),
+            port: self.server.port,
+            ..vllama_server::ServerConfig::default()
+        };
+        let mut addr = server.bind_addr()?;
+        // A server listening on every interface is reachable on loopback
+        if addr.ip().is_unspecified() {
+            let loopback: std::net::IpAddr = if addr.is_ipv4() {
+                std::net::Ipv4Addr::LOCALHOST.into()
+            } else {
+                std::net::Ipv6Addr::LOCALHOST.into()
+            };
+            addr.set_ip(loopback);
+        }
+        Ok(format!("http://{}", addr))
+    }
+
     /// Generate example config file
     pub fn example() -> String {
         let config = Config::default();
@@ -224,6 +533,76 @@ impl Config {
     }
 }
 
+/// What [`Config::changes`] found
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigChanges {
+    /// Applied to a running server
+    pub applied: Vec<&'static str>,
+    /// Ignored until the server restarts
+    pub need_restart: Vec<&'static str>,
+}
+
+/// A vLLM server the CLI talks to directly
+#[derive(Debug, Clone)]
+pub struct VllmEndpoint {
+    pub url: String,
+    pub accept_invalid_certs: bool,
+    /// `server.request_timeout`; None keeps the client default
+    pub timeout: Option<std::time::Duration>,
+}
+
+impl VllmEndpoint {
+    pub fn engine(&self) -> vllama_engine::VllmOpenAIEngine {
+        let engine = vllama_engine::VllmOpenAIEngine::new(self.url.clone())
+            .with_accept_invalid_certs(self.accept_invalid_certs);
+        match self.timeout {
+            Some(timeout) => engine.with_timeout(timeout),
+            None => engine,
+        }
+    }
+
+    pub fn client(&self) -> vllama_core::OpenAIClient {
+        let client =
+            vllama_core::OpenAIClient::new(self.url.clone()).with_accept_invalid_certs(self.accept_invalid_certs);
+        match self.timeout {
+            Some(timeout) => client.with_timeout(timeout),
+            None => client,
+        }
+    }
+}
+
+/// `$XDG_STATE_HOME/vllama/vllm.log`, falling back to `~/.local/state` and
+/// then the working directory
+fn default_vllm_log_file(state_home: Option<PathBuf>, home: Option<PathBuf>) -> PathBuf {
+    match state_home.or_else(|| home.map(|home| home.join(".local").join("state"))) {
+        Some(dir) => dir.join("vllama").join("vllm.log"),
+        None => PathBuf::from("vllm.log"),
+    }
+}
+
+/// Parse a duration such as `90s`, `30m` or `2h`; a bare number is seconds
+pub fn parse_duration(s: &str) -> std::result::Result<std::time::Duration, String> {
+    let s = s.trim();
+    let (number, unit_secs) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        _ => (s, 1),
+    };
+    match number.trim().parse::<u64>() {
+        Ok(n) if n > 0 => Ok(std::time::Duration::from_secs(n * unit_secs)),
+        _ => Err(format!("invalid duration '{}': expected e.g. 90s, 30m or 2h", s)),
+    }
+}
+
+/// Validate a vLLM base URL, returning it without a trailing slash
+pub fn check_vllm_url(url: &str) -> Result<String> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        anyhow::bail!("vLLM URL must start with http:// or https:// (got '{}')", url);
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -247,6 +626,100 @@ mod tests {
         assert_eq!(merged.server.host, "127.0.0.1"); // unchanged
     }
 
+    #[test]
+    fn test_vllm_endpoint() {
+        let mut config = Config::default();
+        config.server.vllm_port = 9000;
+        assert_eq!(config.vllm_endpoint().unwrap().url, "http://127.0.0.1:9000");
+
+        let mut override_config = Config::default();
+        override_config.server.vllm_url = Some("https://vllm.internal:8443/".to_string());
+        override_config.server.accept_invalid_certs = true;
+        let merged = config.merge(override_config);
+        let endpoint = merged.vllm_endpoint().unwrap();
+        assert_eq!(endpoint.url, "https://vllm.internal:8443");
+        assert!(endpoint.accept_invalid_certs);
+
+        assert!(check_vllm_url("vllm.internal:8000").is_err());
+    }
+
+    #[test]
+    fn test_api_url() {
+        let mut config = Config::default();
+        assert_eq!(config.api_url().unwrap(), "http://127.0.0.1:11435");
+
+        config.server.host = "0.0.0.0".to_string();
+        assert_eq!(config.api_url().unwrap(), "http://127.0.0.1:11435");
+        config.server.host = "::".to_string();
+        assert_eq!(config.api_url().unwrap(), "http://[::1]:11435");
+    }
+
+    #[test]
+    fn test_worker_threads_validation() {
+        let mut config = Config::default();
+        config.server.worker_threads = Some(0);
+        assert!(config.build_runtime().is_err());
+
+        config.server.worker_threads = Some(1);
+        assert!(config.build_runtime().is_ok());
+    }
+
+    #[test]
+    fn test_max_download_rate_merge() {
+        let mut override_config = Config::default();
+        override_config.huggingface.max_download_rate = Some(10_000_000);
+
+        let merged = Config::default().merge(override_config);
+        assert_eq!(merged.huggingface.max_download_rate, Some(10_000_000));
+        assert!(merged.download_limiter().unwrap().is_some());
+
+        let mut config = Config::default();
+        config.huggingface.max_download_rate = Some(0);
+        assert!(config.download_limiter().is_err());
+    }
+
+    #[test]
+    fn test_vllm_log_file() {
+        let home = Some(PathBuf::from("/home/u"));
+        assert_eq!(default_vllm_log_file(None, home.clone()), PathBuf::from("/home/u/.local/state/vllama/vllm.log"));
+        assert_eq!(
+            default_vllm_log_file(Some(PathBuf::from("/state")), home),
+            PathBuf::from("/state/vllama/vllm.log")
+        );
+        assert_eq!(default_vllm_log_file(None, None), PathBuf::from("vllm.log"));
+
+        let mut config = Config::default();
+        config.vllm.log_file = Some(PathBuf::from("/var/log/vllm.log"));
+        assert_eq!(config.vllm_log_file(), PathBuf::from("/var/log/vllm.log"));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        use std::time::Duration;
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn test_config_changes() {
+        let old = Config::default();
+        assert_eq!(old.changes(&old.clone()), ConfigChanges::default());
+
+        let mut new = old.clone();
+        new.logging.level = "debug".to_string();
+        new.model.keep_alive_secs = Some(300);
+        new.server.port = 8000;
+        new.model.default_model = Some("Qwen/Qwen2.5-0.5B-Instruct".to_string());
+        let changes = old.changes(&new);
+        assert_eq!(changes.applied, vec!["logging.level", "model.keep_alive_secs"]);
+        assert_eq!(changes.need_restart, vec!["server.port", "model.default_model"]);
+    }
+
     #[test]
     fn test_example_config() {
         let example = Config::example();
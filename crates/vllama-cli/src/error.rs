@@ -9,6 +9,20 @@ use std::process::ExitCode;
 /// Exit codes following Unix conventions
 pub const EXIT_SUCCESS: u8 = 0;
 pub const EXIT_ERROR: u8 = 1;
+/// 128 + SIGINT, as shells report a command stopped by Ctrl-C
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// A command stopped by Ctrl-C; exits with [`EXIT_INTERRUPTED`] and no message
+#[derive(Debug)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
 
 /// User-facing error with helpful context
 pub struct UserError {
@@ -85,8 +99,63 @@ impl std::error::Error for UserError {}
 
 /// Convert anyhow errors into user-friendly errors
 pub fn handle_error(err: anyhow::Error) -> UserError {
+    // Typed errors from vllama-core first; they don't need string matching
+    match err.downcast_ref::<vllama_core::Error>() {
+        Some(vllama_core::Error::Upstream { attempts, last }) => {
+            return UserError::new(format!("vLLM unreachable after {} attempts", attempts))
+                .with_context(last.clone())
+                .with_suggestion("Is the backend up? Start it with: vllama serve --model <model>")
+                .with_suggestion("Check the vLLM log for startup errors: vllama logs")
+                .with_suggestion("If vLLM runs elsewhere, check the configured vllm_port");
+        }
+        Some(vllama_core::Error::ContextLengthExceeded { max, requested }) => {
+            return UserError::new("Prompt too long for model")
+                .with_context(format!(
+                    "The request needs {} tokens but the model's context window is {}.",
+                    requested, max
+                ))
+                .with_suggestion("Shorten the prompt or conversation history")
+                .with_suggestion("Lower max_tokens (num_predict) to leave room for the prompt");
+        }
+        Some(vllama_core::Error::RateLimited) => {
+            return UserError::new("vLLM is overloaded")
+                .with_context("The backend rejected the request with 429 Too Many Requests.")
+                .with_suggestion("Retry in a few seconds")
+                .with_suggestion("Raise --max-num-seqs when starting the server");
+        }
+        Some(vllama_core::Error::ModelNotServed { available }) => {
+            let mut user_err = UserError::new("Model not served by vLLM")
+                .with_suggestion("vLLM serves one model per instance; restart with: vllama serve --model <model>");
+            if !available.is_empty() {
+                user_err = user_err.with_context(format!("Available: {}", available.join(", ")));
+            }
+            return user_err;
+        }
+        Some(vllama_core::Error::ConfigError(msg)) if msg.starts_with("alias cycle") => {
+            return UserError::new("Aliases refer to each other in a loop")
+                .with_context(msg.clone())
+                .with_suggestion("Point one of them at a model: vllama alias add <name> <model>")
+                .with_suggestion("Or remove one: vllama alias rm <name>");
+        }
+        _ => {}
+    }
+
     let err_str = err.to_string();
 
+    // `vllama logs` without a log to read
+    if err_str.starts_with("no vLLM log at") {
+        return UserError::new("vLLM log not found")
+            .with_context(&err_str)
+            .with_suggestion("Start the server first: vllama serve --model <model>")
+            .with_suggestion("If serve used a different config, set the same log_file under [vllm]");
+    }
+
+    if err_str.starts_with("no alias named") {
+        return UserError::new("Alias not found")
+            .with_context(&err_str)
+            .with_suggestion("List aliases: vllama alias list");
+    }
+
     // Model not found
     if err_str.contains("404") || err_str.contains("not found") {
         return UserError::new("Model not found")
@@ -109,7 +178,7 @@ pub fn handle_error(err: anyhow::Error) -> UserError {
     if err_str.contains("vLLM server failed to start") {
         return UserError::new("vLLM engine failed to start")
             .with_context("The vLLM inference engine could not initialize.")
-            .with_suggestion("Check vllm.log for detailed error messages")
+            .with_suggestion("Check the vLLM log for detailed error messages: vllama logs")
             .with_suggestion("Ensure CUDA is installed: nvidia-smi")
             .with_suggestion("Try with smaller model or lower GPU utilization: --gpu-memory-utilization 0.7")
             .with_suggestion("For 7B models, use --gpu-memory-utilization 0.9");
@@ -117,12 +186,20 @@ pub fn handle_error(err: anyhow::Error) -> UserError {
 
     // OOM / insufficient memory
     if err_str.contains("out of memory") || err_str.contains("No available memory") {
-        return UserError::new("Insufficient GPU memory")
+        let mut user_err = UserError::new("Insufficient GPU memory")
             .with_context("The model is too large for available GPU memory.")
             .with_suggestion("Use a smaller model (e.g., Qwen/Qwen2.5-1.5B-Instruct)")
             .with_suggestion("Increase GPU utilization: --gpu-memory-utilization 0.9")
             .with_suggestion("Check GPU memory: nvidia-smi")
             .with_suggestion("See docs/MODELS.md for memory requirements");
+        // Host RAM is irrelevant inside a container; the cgroup limit is what counts
+        if let Some(limit) = vllama_core::Hardware::detect().memory_limit_mb {
+            user_err = user_err.with_suggestion(format!(
+                "This container is limited to {} MB of RAM; raise it (docker --memory, Kubernetes limits.memory)",
+                limit
+            ));
+        }
+        return user_err;
     }
 
     // uv not installed
@@ -155,7 +232,7 @@ pub fn handle_error(err: anyhow::Error) -> UserError {
     // Generic fallback
     UserError::new("An error occurred")
         .with_context(&err_str)
-        .with_suggestion("Check vllm.log for detailed error information")
+        .with_suggestion("Check the vLLM log for detailed error information: vllama logs")
         .with_suggestion("Report issues at https://github.com/nijaru/vllama/issues")
 }
 
@@ -176,9 +253,36 @@ mod tests {
         assert!(output.contains("Try this first"));
     }
 
+    #[test]
+    fn test_upstream_error_mapping() {
+        let err = anyhow::Error::new(vllama_core::Error::Upstream {
+            attempts: 3,
+            last: "connection refused".to_string(),
+        });
+
+        let user_err = handle_error(err);
+        assert_eq!(user_err.message, "vLLM unreachable after 3 attempts");
+        assert!(user_err.suggestions.iter().any(|s| s.contains("vllama logs")));
+    }
+
+    #[test]
+    fn test_typed_backend_error_mapping() {
+        let err = anyhow::Error::new(vllama_core::Error::ContextLengthExceeded { max: 2048, requested: 3000 });
+        let user_err = handle_error(err);
+        assert_eq!(user_err.message, "Prompt too long for model");
+        assert!(user_err.context.as_deref().unwrap().contains("2048"));
+
+        let err = anyhow::Error::new(vllama_core::Error::ModelNotServed {
+            available: vec!["Qwen/Qwen2.5-1.5B-Instruct".to_string()],
+        });
+        let user_err = handle_error(err);
+        assert!(user_err.context.as_deref().unwrap().contains("Qwen2.5"));
+    }
+
     #[test]
     fn test_exit_codes() {
         assert_eq!(EXIT_SUCCESS, 0);
         assert_eq!(EXIT_ERROR, 1);
+        assert_eq!(EXIT_INTERRUPTED, 130);
     }
 }
@@ -4,12 +4,13 @@ mod error;
 mod output;
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use commands::*;
 use error::{handle_error, EXIT_SUCCESS};
-use output::OutputMode;
+use output::{OutputMode, ReportFormat};
 use std::process::ExitCode;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use std::sync::OnceLock;
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
 
 #[derive(Parser)]
 #[command(name = "vllama")]
@@ -39,20 +40,92 @@ enum Commands {
         #[arg(short, long, default_value = "11435", help = "Server port (11435 works alongside Ollama on 11434)")]
         port: u16,
 
+        #[arg(long, help = "Accept IPv4 and IPv6 clients on one socket (host must be 0.0.0.0 or ::)")]
+        dual_stack: bool,
+
         #[arg(long, help = "Model to load in vLLM (e.g., meta-llama/Llama-3.2-1B-Instruct)")]
         model: Option<String>,
 
+        #[arg(long, value_name = "NAME", help = "Name clients use for the model instead of its repository path (e.g., llama3.2)")]
+        served_model_name: Option<String>,
+
         #[arg(long, default_value = "8100", help = "vLLM OpenAI server port")]
         vllm_port: u16,
 
         #[arg(long, help = "Skip auto-starting vLLM server (use existing instance)")]
         no_vllm: bool,
 
+        #[arg(long, default_value = "3", value_name = "N", help = "Restart vLLM up to N times if it exits; after that the server shuts down (0: never restart)")]
+        vllm_max_restarts: u32,
+
+        #[arg(long, value_name = "URL", help = "Use the vLLM server at this URL (http or https) instead of starting one")]
+        vllm_url: Option<String>,
+
+        #[arg(long, help = "Skip TLS certificate checks for --vllm-url (self-signed certificates)")]
+        accept_invalid_certs: bool,
+
         #[arg(long, default_value = "256", help = "vLLM max concurrent sequences")]
         max_num_seqs: usize,
 
         #[arg(long, default_value = "0.9", help = "vLLM GPU memory utilization (0.0-1.0)")]
         gpu_memory_utilization: f32,
+
+        #[arg(long, value_name = "N", help = "Shard the model across N GPUs (vLLM --tensor-parallel-size)")]
+        tensor_parallel_size: Option<usize>,
+
+        #[arg(long, value_name = "DTYPE", help = "Weight and activation dtype, e.g. bfloat16 or half (vLLM --dtype)")]
+        dtype: Option<String>,
+
+        #[arg(long, value_name = "METHOD", help = "Quantization method, e.g. awq, gptq or fp8 (vLLM --quantization)")]
+        quantization: Option<String>,
+
+        #[arg(long, value_name = "TOKENS", help = "Context window in tokens (vLLM --max-model-len)")]
+        max_model_len: Option<usize>,
+
+        #[arg(
+            long,
+            value_name = "DURATION",
+            value_parser = config::parse_duration,
+            help = "Stop the server and vLLM after this long without requests (e.g. 30m, 2h)"
+        )]
+        idle_shutdown: Option<std::time::Duration>,
+
+        #[arg(
+            long = "max-loaded-vram",
+            value_name = "MB",
+            help = "Refuse or unload least recently used models when a new one's weights wouldn't fit in this much VRAM (default: total VRAM x --gpu-memory-utilization on an NVIDIA GPU)"
+        )]
+        max_loaded_vram: Option<u64>,
+
+        #[arg(
+            long,
+            help = "Allow the model repository to run custom Python code (SECURITY: only use with repositories you trust)"
+        )]
+        trust_remote_code: bool,
+
+        #[arg(
+            long = "lora",
+            value_name = "NAME=PATH",
+            value_parser = serve::parse_lora,
+            help = "Register a LoRA adapter (repeatable); select it per request via the model field"
+        )]
+        lora: Vec<serve::LoraModule>,
+
+        #[arg(
+            long = "raw-vllm-args",
+            short = 'X',
+            value_name = "ARGS",
+            allow_hyphen_values = true,
+            help = "Extra arguments appended to the vLLM command (repeatable; --model/--port/--host are reserved)"
+        )]
+        raw_vllm_args: Vec<String>,
+
+        #[arg(
+            long,
+            visible_alias = "reload-on-config-change",
+            help = "Apply edits to vllama.toml and ~/.config/vllama/config.toml without restarting (settings that can't change at runtime are logged)"
+        )]
+        watch_config: bool,
     },
 
     #[command(about = "Run a model and chat interactively")]
@@ -60,8 +133,14 @@ enum Commands {
         #[arg(help = "Model name to run")]
         model: String,
 
-        #[arg(help = "Optional prompt to send")]
+        #[arg(help = "Optional prompt to send before the chat starts")]
         prompt: Option<String>,
+
+        #[arg(long, help = "Show the thinking trace of reasoning models (hidden by default)")]
+        think: bool,
+
+        #[arg(long, value_name = "FILE", help = "Append the conversation to FILE (Markdown for .md, otherwise JSON lines)")]
+        save: Option<std::path::PathBuf>,
     },
 
     #[command(about = "Generate text from a model")]
@@ -69,11 +148,20 @@ enum Commands {
         #[arg(help = "Model name")]
         model: String,
 
-        #[arg(help = "Prompt text")]
-        prompt: String,
+        #[arg(help = "Prompt text (\"-\" reads it from stdin)", required_unless_present = "stdin")]
+        prompt: Option<String>,
+
+        #[arg(long, conflicts_with = "prompt", help = "Read the prompt from stdin")]
+        stdin: bool,
 
         #[arg(long, help = "Stream the response")]
         stream: bool,
+
+        #[arg(long, visible_alias = "json-schema-file", value_name = "FILE", help = "Constrain the output to JSON matching the schema in FILE (the output is checked against its type, enum, required, properties and items)")]
+        json_schema: Option<std::path::PathBuf>,
+
+        #[arg(long, value_name = "N", help = "Stop after N tokens [default: 100, or no limit with --json-schema]")]
+        max_tokens: Option<usize>,
     },
 
     #[command(about = "List locally available models")]
@@ -81,14 +169,20 @@ enum Commands {
 
     #[command(about = "Download a model from a registry")]
     Pull {
-        #[arg(help = "Model name to download")]
+        #[arg(help = "Model name to download; use repo@REVISION to pin a branch, tag or commit and append :QUANT (e.g. :Q4_K_M) to pick one file of a GGUF repository")]
         model: String,
+
+        #[arg(long, help = "List the files and total size without downloading")]
+        dry_run: bool,
     },
 
-    #[command(about = "Remove a local model")]
+    #[command(about = "Remove one or more local models")]
     Rm {
-        #[arg(help = "Model name to remove")]
-        model: String,
+        #[arg(help = "Model names to remove", required_unless_present = "all")]
+        models: Vec<String>,
+
+        #[arg(long, conflicts_with = "models", help = "Remove all cached models")]
+        all: bool,
     },
 
     #[command(about = "Show information about a model")]
@@ -103,11 +197,29 @@ enum Commands {
         parameters: bool,
     },
 
-    #[command(about = "List currently running models")]
+    #[command(about = "Print the template, stop tokens, sampling defaults and context length vllama uses for a model")]
+    Export {
+        #[arg(help = "Model name")]
+        model: String,
+    },
+
+    #[command(about = "List running models and their usage since the server started")]
     Ps,
 
+    #[command(about = "Print the end of the vLLM log written by serve")]
+    Logs {
+        #[arg(short, long, help = "Keep printing new output as it is written")]
+        follow: bool,
+
+        #[arg(short = 'n', long, default_value = "50", help = "Number of lines to print")]
+        lines: usize,
+    },
+
     #[command(about = "Show system hardware information")]
-    Info,
+    Info {
+        #[arg(long, value_enum, help = "Output format (overrides --json)")]
+        format: Option<ReportFormat>,
+    },
 
     #[command(about = "Benchmark inference engine performance (experimental)")]
     Bench {
@@ -122,6 +234,45 @@ enum Commands {
 
         #[arg(short, long, help = "Concurrent requests (1 = sequential)", default_value = "1")]
         concurrency: usize,
+
+        #[arg(long, value_enum, help = "Engine to compare against [default: ollama, or none with --compare-endpoint]")]
+        baseline: Option<bench::Baseline>,
+
+        #[arg(
+            long,
+            visible_alias = "no-compare",
+            conflicts_with = "baseline",
+            help = "Benchmark vllama only (same as --baseline none)"
+        )]
+        engine_only: bool,
+
+        #[arg(long, value_name = "SECS", help = "Give up on a request after this many seconds and count it as a timeout")]
+        timeout: Option<u64>,
+
+        #[arg(long, help = "Fixed sampling seed for every request, so runs generate the same output")]
+        seed: Option<u64>,
+
+        #[arg(long, help = "Greedy decoding (temperature 0) for exactly repeatable output")]
+        deterministic: bool,
+
+        #[arg(
+            long,
+            value_name = "URL",
+            help = "Also benchmark the vLLM server at this URL and report the speedup (skips the Ollama baseline unless --baseline is given)"
+        )]
+        compare_endpoint: Option<String>,
+
+        #[arg(long, value_name = "LABEL", default_value = "compare", requires = "compare_endpoint", help = "Name for --compare-endpoint in the results")]
+        compare_label: String,
+
+        #[arg(long, value_enum, help = "Output format (overrides --json)")]
+        format: Option<ReportFormat>,
+    },
+
+    #[command(about = "Manage short names for models (stored in ~/.config/vllama/aliases.toml)")]
+    Alias {
+        #[command(subcommand)]
+        action: Option<alias::AliasAction>,
     },
 
     #[command(about = "Generate example configuration file")]
@@ -129,10 +280,15 @@ enum Commands {
         #[arg(long, help = "Show current configuration")]
         show: bool,
     },
+
+    #[command(about = "Generate a shell completion script")]
+    Completions {
+        #[arg(help = "Shell to generate completions for")]
+        shell: clap_complete::Shell,
+    },
 }
 
-#[tokio::main]
-async fn main() -> ExitCode {
+fn main() -> ExitCode {
     let cli = Cli::parse();
 
     // Load configuration files
@@ -144,7 +300,21 @@ async fn main() -> ExitCode {
         }
     };
 
-    init_tracing(cli.verbose || config.logging.level == "debug");
+    init_tracing(cli.verbose, &config.logging.level);
+
+    // Built by hand (not #[tokio::main]) so thread counts come from config
+    let runtime = match config.build_runtime() {
+        Ok(rt) => rt,
+        Err(e) => {
+            eprintln!("{}", handle_error(e));
+            return ExitCode::from(error::EXIT_ERROR);
+        }
+    };
+
+    runtime.block_on(async_main(cli, config))
+}
+
+async fn async_main(cli: Cli, config: config::Config) -> ExitCode {
 
     // Determine output mode (CLI flags override config)
     let output_mode = if cli.json || config.output.json {
@@ -157,6 +327,9 @@ async fn main() -> ExitCode {
 
     // Run command and handle errors gracefully
     if let Err(err) = run_command(cli.command, output_mode, config).await {
+        if err.is::<error::Interrupted>() {
+            return ExitCode::from(error::EXIT_INTERRUPTED);
+        }
         let user_error = handle_error(err);
         eprintln!("{}", user_error);
         return user_error.exit_code();
@@ -170,16 +343,46 @@ async fn run_command(command: Commands, output_mode: OutputMode, config: config:
         Commands::Serve {
             host,
             port,
+            dual_stack,
             model,
+            served_model_name,
             vllm_port,
             no_vllm,
+            vllm_max_restarts,
+            vllm_url,
+            accept_invalid_certs,
             max_num_seqs,
             gpu_memory_utilization,
+            tensor_parallel_size,
+            dtype,
+            quantization,
+            max_model_len,
+            max_loaded_vram,
+            idle_shutdown,
+            trust_remote_code,
+            lora,
+            raw_vllm_args,
+            watch_config,
         } => {
+            let watched_config = watch_config.then(|| config.clone());
+            // Validated here; the server builds its own limiter shared by all pulls
+            config.download_limiter()?;
+            if config.server.max_loaded_models == Some(0) {
+                anyhow::bail!("server.max_loaded_models must be at least 1");
+            }
+
+            let vllm_log = config.vllm_log_file();
+
             // Apply config defaults when CLI flags not provided
             let host = if host == "127.0.0.1" { config.server.host } else { host };
             let port = if port == 11435 { config.server.port } else { port };
             let vllm_port = if vllm_port == 8100 { config.server.vllm_port } else { vllm_port };
+            let vllm_base_url = vllm_url
+                .or(config.server.vllm_url)
+                .map(|url| config::check_vllm_url(&url))
+                .transpose()?;
+            // A remote vLLM is never started locally
+            let no_vllm = no_vllm || vllm_base_url.is_some();
             let model = model.or(config.model.default_model);
             let max_num_seqs = if max_num_seqs == 256 { config.model.max_num_seqs } else { max_num_seqs };
             let gpu_memory_utilization = if (gpu_memory_utilization - 0.9).abs() < 0.001 {
@@ -187,37 +390,101 @@ async fn run_command(command: Commands, output_mode: OutputMode, config: config:
             } else {
                 gpu_memory_utilization
             };
+            let vllm_options = serve::VllmOptions {
+                tensor_parallel_size: tensor_parallel_size.or(config.model.tensor_parallel_size),
+                dtype: dtype.or(config.model.dtype),
+                quantization: quantization.or(config.model.quantization),
+                max_model_len: max_model_len.or(config.model.max_model_len),
+            };
+            // Without a configured budget, loads have to fit the share of the
+            // GPU vLLM starts with; a remote vLLM's GPU isn't visible from here
+            let gpu_vram_budget = if no_vllm {
+                None
+            } else {
+                serve::gpu_vram_budget_mb(gpu_memory_utilization, vllm_options.tensor_parallel_size)
+            };
+            let config_watch = watched_config.map(|config| serve::ConfigWatch { config, max_loaded_vram, gpu_vram_budget });
+            let idle_shutdown = match idle_shutdown {
+                Some(limit) => Some(limit),
+                None => config
+                    .server
+                    .idle_shutdown
+                    .as_deref()
+                    .map(config::parse_duration)
+                    .transpose()
+                    .map_err(|e| anyhow::anyhow!("server.idle_shutdown: {}", e))?,
+            };
+            if vllm_options.tensor_parallel_size == Some(0) {
+                anyhow::bail!("--tensor-parallel-size must be at least 1");
+            }
 
-            serve::run(
+            let server_config = vllama_server::ServerConfig {
                 host,
                 port,
-                model,
+                dual_stack: dual_stack || config.server.dual_stack,
                 vllm_port,
+                vllm_base_url,
+                accept_invalid_certs: accept_invalid_certs || config.server.accept_invalid_certs,
+                strip_special_tokens: config.server.strip_special_tokens,
+                warmup_on_load: config.server.warmup_on_load,
+                max_download_rate: config.huggingface.max_download_rate,
+                queue_timeout: config.server.queue_timeout.map(std::time::Duration::from_secs),
+                request_timeout: config.server.request_timeout.map(std::time::Duration::from_secs),
+                max_loaded_models: config.server.max_loaded_models,
+                max_loaded_vram_mb: max_loaded_vram.or(config.server.max_loaded_vram_mb).or(gpu_vram_budget),
+                // Set by serve::run once --model resolves to a repo id
+                default_model: None,
+                idle_shutdown,
+                report_warnings: config.server.report_warnings,
+                aliases_file: vllama_core::ModelAliases::default_path(),
+                keep_alive: config.model.keep_alive_secs.map(std::time::Duration::from_secs),
+                cache_dir: None,
+            };
+            // Reject a bad --host before vLLM spends minutes loading a model
+            server_config.bind_addr()?;
+
+            serve::run(
+                server_config,
+                model,
+                served_model_name,
                 no_vllm,
                 max_num_seqs,
                 gpu_memory_utilization,
+                vllm_options,
+                vllm_log,
+                vllm_max_restarts,
+                trust_remote_code,
+                lora,
+                raw_vllm_args,
+                config_watch,
                 output_mode,
             )
             .await?;
         }
-        Commands::Run { model, prompt } => {
-            run::execute(model, prompt).await?;
+        Commands::Run { model, prompt, think, save } => {
+            run::execute(alias::resolve(&model)?, prompt, think, save, config.vllm_endpoint()?).await?;
         }
         Commands::Generate {
             model,
             prompt,
+            stdin,
             stream,
+            json_schema,
+            max_tokens,
         } => {
-            generate::execute(model, prompt, stream).await?;
+            let prompt = generate::read_prompt(prompt, stdin, std::io::stdin().lock())?;
+            let schema = json_schema.as_deref().map(generate::load_schema).transpose()?;
+            let vllm = config.vllm_endpoint()?;
+            generate::execute(alias::resolve(&model)?, prompt, stream, schema, max_tokens, vllm).await?;
         }
         Commands::List => {
             list::execute(output_mode).await?;
         }
-        Commands::Pull { model } => {
-            pull::execute(model, output_mode).await?;
+        Commands::Pull { model, dry_run } => {
+            pull::execute(model, dry_run, config.download_limiter()?, output_mode).await?;
         }
-        Commands::Rm { model } => {
-            rm::execute(model, output_mode).await?;
+        Commands::Rm { models, all } => {
+            rm::execute(&vllama_core::ModelDownloader::new()?, models, all, output_mode).await?;
         }
         Commands::Show {
             model,
@@ -226,19 +493,71 @@ async fn run_command(command: Commands, output_mode: OutputMode, config: config:
         } => {
             show::execute(model, modelfile, parameters).await?;
         }
+        Commands::Export { model } => {
+            export::execute(model, output_mode).await?;
+        }
         Commands::Ps => {
-            ps::execute().await?;
+            ps::execute(config.api_url()?, output_mode).await?;
         }
-        Commands::Info => {
-            info::execute().await?;
+        Commands::Logs { follow, lines } => {
+            logs::execute(&config.vllm_log_file(), follow, lines, output_mode).await?;
+        }
+        Commands::Info { format } => {
+            let format = format.unwrap_or(match output_mode {
+                OutputMode::Json => ReportFormat::Json,
+                _ => ReportFormat::Normal,
+            });
+            info::execute(format).await?;
         }
         Commands::Bench {
             model,
             prompt,
             iterations,
             concurrency,
+            baseline,
+            engine_only,
+            timeout,
+            seed,
+            deterministic,
+            compare_endpoint,
+            compare_label,
+            format,
         } => {
-            bench::execute(model, prompt, iterations, concurrency, output_mode).await?;
+            let baseline = match baseline {
+                _ if engine_only => bench::Baseline::None,
+                Some(baseline) => baseline,
+                None if compare_endpoint.is_some() => bench::Baseline::None,
+                None => bench::Baseline::Ollama,
+            };
+            // Markdown is rendered once at the end, so progress output is suppressed
+            let (output_mode, markdown) = match format {
+                Some(ReportFormat::Markdown) => (OutputMode::Quiet, true),
+                Some(ReportFormat::Json) => (OutputMode::Json, false),
+                Some(ReportFormat::Normal) => (OutputMode::Normal, false),
+                None => (output_mode, false),
+            };
+            let timeout = timeout.map(std::time::Duration::from_secs);
+            let vllm = config.vllm_endpoint()?;
+            let sampling = bench::BenchSampling { seed, deterministic };
+            let compare = compare_endpoint
+                .map(|url| -> Result<bench::CompareTarget> {
+                    Ok(bench::CompareTarget {
+                        label: compare_label,
+                        endpoint: config::VllmEndpoint {
+                            url: config::check_vllm_url(&url)?,
+                            accept_invalid_certs: vllm.accept_invalid_certs,
+                            timeout: vllm.timeout,
+                        },
+                    })
+                })
+                .transpose()?;
+            bench::execute(
+                model, prompt, iterations, concurrency, baseline, timeout, sampling, vllm, compare, output_mode, markdown,
+            )
+            .await?;
+        }
+        Commands::Alias { action } => {
+            alias::execute(action, output_mode).await?;
         }
         Commands::Config { show } => {
             if show {
@@ -262,20 +581,53 @@ async fn run_command(command: Commands, output_mode: OutputMode, config: config:
                 println!("{}", config::Config::example());
             }
         }
+        Commands::Completions { shell } => {
+            // Written to stdout so it can be redirected, e.g.
+            // `vllama completions bash > /etc/bash_completion.d/vllama`
+            clap_complete::generate(shell, &mut Cli::command(), "vllama", &mut std::io::stdout());
+        }
     }
 
     Ok(())
 }
 
-fn init_tracing(verbose: bool) {
-    let filter = if verbose {
-        "vllama=debug,info"
-    } else {
-        "vllama=info,warn"
+/// Lets `serve --watch-config` change the log level; unset when
+/// `--verbose` or `RUST_LOG` decides it
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// vllama's logs at `level` (`info` if it isn't one of
+/// [`config::LOG_LEVELS`]), other crates' a step quieter
+fn log_filter(level: &str) -> EnvFilter {
+    let (level, others) = match level {
+        "trace" => ("trace", "debug"),
+        "debug" => ("debug", "info"),
+        "warn" => ("warn", "warn"),
+        "error" => ("error", "error"),
+        _ => ("info", "warn"),
     };
+    EnvFilter::new(format!("vllama={},{}", level, others))
+}
 
-    let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| filter.into());
+/// Change the log level to `level`, unless fixed at startup
+pub fn set_log_level(level: &str) {
+    if let Some(handle) = LOG_FILTER.get() {
+        if let Err(e) = handle.reload(log_filter(level)) {
+            tracing::warn!("Failed to change the log level: {}", e);
+        }
+    }
+}
+
+fn init_tracing(verbose: bool, level: &str) {
+    let env_filter = match EnvFilter::try_from_default_env() {
+        Ok(env_filter) => env_filter,
+        Err(_) if verbose => log_filter("debug"),
+        Err(_) => log_filter(level),
+    };
+    let reloadable = !verbose && std::env::var_os("RUST_LOG").is_none();
+    let (env_filter, handle) = reload::Layer::new(env_filter);
+    if reloadable {
+        let _ = LOG_FILTER.set(handle);
+    }
 
     // Check if JSON logging is requested via environment variable
     let use_json = std::env::var("VLLAMA_LOG_FORMAT")
@@ -7,7 +7,7 @@
 //! - JSON mode for scripting
 
 use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use serde::Serialize;
 use std::time::Duration;
 
@@ -22,6 +22,17 @@ pub enum OutputMode {
     Json,
 }
 
+/// Report format for commands whose results are meant to be shared
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Human-readable terminal output
+    Normal,
+    /// JSON output for scripting
+    Json,
+    /// GitHub-flavored Markdown tables for pasting into issues
+    Markdown,
+}
+
 /// Clean symbols for modern CLI output
 pub struct Symbols;
 
@@ -64,6 +75,11 @@ pub fn bullet(msg: &str) -> String {
     format!("  {} {}", style(Symbols::BULLET).dim(), msg)
 }
 
+/// Format de-emphasized text (e.g. a model's thinking trace)
+pub fn dim(msg: &str) -> String {
+    style(msg).dim().to_string()
+}
+
 /// Create a spinner for indeterminate operations
 pub fn spinner(msg: &str) -> ProgressBar {
     let pb = ProgressBar::new_spinner();
@@ -78,17 +94,40 @@ pub fn spinner(msg: &str) -> ProgressBar {
     pb
 }
 
-/// Create a progress bar for operations with known total
-pub fn progress_bar(total: u64, msg: &str) -> ProgressBar {
-    let pb = ProgressBar::new(total);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg}\n  [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("━━╺"),
-    );
-    pb.set_message(msg.to_string());
-    pb
+/// Progress bars for a multi-file download: the current file, with its
+/// name above the bar, and the whole download below it
+pub struct DownloadBars {
+    /// Keeps both bars drawn together
+    _multi: MultiProgress,
+    pub file: ProgressBar,
+    pub overall: ProgressBar,
+}
+
+impl DownloadBars {
+    pub fn new(msg: &str) -> Self {
+        let multi = MultiProgress::new();
+        let file = multi.add(ProgressBar::new(0));
+        file.set_style(
+            ProgressStyle::default_bar()
+                .template("{msg}\n  [{bar:40.cyan/blue}] {bytes}/{total_bytes}")
+                .unwrap()
+                .progress_chars("━━╺"),
+        );
+        file.set_message(msg.to_string());
+        let overall = multi.add(ProgressBar::new(0));
+        overall.set_style(
+            ProgressStyle::default_bar()
+                .template("  [{bar:40.green}] {percent:>3}% of {total_bytes} ({eta})")
+                .unwrap()
+                .progress_chars("━━╺"),
+        );
+        Self { _multi: multi, file, overall }
+    }
+
+    pub fn finish_and_clear(&self) {
+        self.file.finish_and_clear();
+        self.overall.finish_and_clear();
+    }
 }
 
 /// Output JSON for scripting
@@ -98,15 +137,71 @@ pub fn json<T: Serialize>(data: &T) {
     }
 }
 
+/// Format a memory size in MB, showing 0 (not detectable) as "unknown"
+pub fn memory_mb(mb: u64) -> String {
+    if mb == 0 {
+        "unknown".to_string()
+    } else {
+        format!("{} MB", mb)
+    }
+}
+
+/// Format a byte count with a binary unit (e.g. "1.5 GB")
+pub fn bytes(n: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut value = n as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
 /// Print key-value pair
 pub fn kv(key: &str, value: &str) {
     println!("  {} {}: {}", style(Symbols::BULLET).dim(), style(key).dim(), value);
 }
 
+/// Render a GitHub-flavored Markdown table
+pub fn markdown_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|");
+
+    let mut table = format!("| {} |\n", headers.join(" | "));
+    table.push_str(&format!("|{}\n", "---|".repeat(headers.len())));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|c| escape(c)).collect();
+        table.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    table
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_markdown_table() {
+        let table = markdown_table(
+            &["Key", "Value"],
+            &[vec!["CPU Cores".to_string(), "8".to_string()], vec!["a|b".to_string(), "c".to_string()]],
+        );
+        assert_eq!(
+            table,
+            "| Key | Value |\n|---|---|\n| CPU Cores | 8 |\n| a\\|b | c |\n"
+        );
+    }
+
+    #[test]
+    fn test_bytes() {
+        assert_eq!(bytes(512), "512 B");
+        assert_eq!(bytes(1536), "1.5 KB");
+        assert_eq!(bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
     #[test]
     fn test_symbols_no_emojis() {
         // Ensure we're not using emoji characters
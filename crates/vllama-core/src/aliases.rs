@@ -0,0 +1,152 @@
+//! User-defined model aliases
+//!
+//! Short names for long model references, kept in
+//! `~/.config/vllama/aliases.toml`:
+//!
+//! ```toml
+//! tiny = "meta-llama/Llama-3.2-1B-Instruct"
+//! ```
+//!
+//! An alias may name another alias; chains are followed until a name that
+//! isn't one. Cycles are rejected when an alias is added, and reported
+//! rather than looped on when a hand-edited file contains one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ModelAliases(BTreeMap<String, String>);
+
+impl ModelAliases {
+    /// `$XDG_CONFIG_HOME/vllama/aliases.toml`, falling back to `~/.config`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = match std::env::var("XDG_CONFIG_HOME") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => {
+                let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")).ok()?;
+                PathBuf::from(home).join(".config")
+            }
+        };
+        Some(config_dir.join("vllama").join("aliases.toml"))
+    }
+
+    /// Aliases saved in `path`; a missing file means none
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::ConfigError(format!("failed to read {}: {}", path.display(), e))),
+        };
+        toml::from_str(&contents).map_err(|e| Error::ConfigError(format!("invalid {}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let toml = toml::to_string(self).map_err(|e| Error::ConfigError(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml)
+            .map_err(|e| Error::ConfigError(format!("failed to save aliases to {}: {}", path.display(), e)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Aliases and the name each points at, sorted by alias
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(alias, target)| (alias.as_str(), target.as_str()))
+    }
+
+    /// Point `alias` at `target`, replacing any previous target
+    ///
+    /// Fails, leaving the aliases unchanged, if `target` leads back to `alias`.
+    pub fn insert(&mut self, alias: &str, target: &str) -> Result<()> {
+        if alias.is_empty() || target.is_empty() {
+            return Err(Error::InvalidRequest("alias and model must not be empty".to_string()));
+        }
+        let previous = self.0.insert(alias.to_string(), target.to_string());
+        if let Err(e) = self.resolve(alias) {
+            match previous {
+                Some(previous) => self.0.insert(alias.to_string(), previous),
+                None => self.0.remove(alias),
+            };
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Drop `alias`, returning what it pointed at
+    pub fn remove(&mut self, alias: &str) -> Option<String> {
+        self.0.remove(alias)
+    }
+
+    /// The model `name` stands for, following chained aliases; names that
+    /// aren't aliases are returned unchanged
+    pub fn resolve(&self, name: &str) -> Result<String> {
+        let mut chain = vec![name];
+        let mut current = name;
+        while let Some(target) = self.0.get(current) {
+            let seen = chain.contains(&target.as_str());
+            chain.push(target);
+            if seen {
+                return Err(Error::ConfigError(format!("alias cycle: {}", chain.join(" -> "))));
+            }
+            current = target;
+        }
+        Ok(current.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aliases(pairs: &[(&str, &str)]) -> ModelAliases {
+        ModelAliases(pairs.iter().map(|(a, t)| (a.to_string(), t.to_string())).collect())
+    }
+
+    #[test]
+    fn test_resolve_chain() {
+        let aliases = aliases(&[("tiny", "llama-1b"), ("llama-1b", "meta-llama/Llama-3.2-1B-Instruct")]);
+        assert_eq!(aliases.resolve("tiny").unwrap(), "meta-llama/Llama-3.2-1B-Instruct");
+        assert_eq!(aliases.resolve("Qwen/Qwen2.5-0.5B").unwrap(), "Qwen/Qwen2.5-0.5B");
+    }
+
+    #[test]
+    fn test_alias_cycle() {
+        // A hand-edited file can contain a cycle; resolving reports it
+        let looped = aliases(&[("a", "b"), ("b", "c"), ("c", "a")]);
+        let err = looped.resolve("a").unwrap_err();
+        assert!(err.to_string().contains("a -> b -> c -> a"), "{}", err);
+        assert!(aliases(&[("self", "self")]).resolve("self").is_err());
+
+        // Adding one is refused and leaves the aliases as they were
+        let mut chain = aliases(&[("a", "b"), ("b", "model")]);
+        assert!(chain.insert("b", "a").is_err());
+        assert_eq!(chain.resolve("a").unwrap(), "model");
+        assert!(chain.insert("model", "a").is_err());
+        assert_eq!(chain.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("vllama").join("aliases.toml");
+        assert!(ModelAliases::load(&path).unwrap().is_empty());
+
+        let mut saved = ModelAliases::default();
+        saved.insert("tiny", "meta-llama/Llama-3.2-1B-Instruct").unwrap();
+        saved.save(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap().trim(),
+            r#"tiny = "meta-llama/Llama-3.2-1B-Instruct""#
+        );
+        assert_eq!(ModelAliases::load(&path).unwrap(), saved);
+    }
+}
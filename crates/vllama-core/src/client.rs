@@ -0,0 +1,253 @@
+/// Client for a running vllama server
+///
+/// `VllamaClient` is the library-facing way to talk to `vllama serve`.
+/// Generation goes through the server's OpenAI-compatible endpoints, so it
+/// shares request/response types, SSE parsing, retries and error mapping
+/// with [`OpenAIClient`]; model management uses the Ollama endpoints.
+use serde::{Deserialize, Serialize};
+
+use crate::openai::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, CompletionRequest,
+    CompletionResponse, OpenAIClient,
+};
+use crate::{ChatMessage, Result};
+
+/// Typed client for the vllama HTTP API
+///
+/// ```no_run
+/// # async fn example() -> vllama_core::Result<()> {
+/// use vllama_core::{ChatMessage, VllamaClient};
+///
+/// let client = VllamaClient::new("http://localhost:11435");
+/// let response = client
+///     .chat("Qwen/Qwen2.5-1.5B-Instruct", vec![ChatMessage::user("Hello!")])
+///     .await?;
+/// println!("{}", response.choices[0].message.content);
+/// # Ok(())
+/// # }
+/// ```
+pub struct VllamaClient {
+    openai: OpenAIClient,
+}
+
+impl VllamaClient {
+    /// Create a client for the server at `base_url` (e.g. `http://localhost:11435`)
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            openai: OpenAIClient::new(base_url),
+        }
+    }
+
+    /// The underlying OpenAI-compatible client, for requests that need full
+    /// control over sampling parameters
+    pub fn openai(&self) -> &OpenAIClient {
+        &self.openai
+    }
+
+    /// Complete `prompt` with `model`
+    pub async fn generate(&self, model: &str, prompt: &str) -> Result<CompletionResponse> {
+        self.openai
+            .create_completion(CompletionRequest {
+                model: model.to_string(),
+                prompt: prompt.to_string(),
+                max_tokens: None,
+                min_tokens: None,
+                seed: None,
+                temperature: None,
+                top_p: None,
+                top_k: None,
+                min_p: None,
+                frequency_penalty: None,
+                presence_penalty: None,
+                repetition_penalty: None,
+                guided: None,
+                response_format: None,
+                stream: Some(false),
+                stream_options: None,
+                stop: None,
+                include_stop_str_in_output: None,
+            })
+            .await
+    }
+
+    /// Send a conversation to `model` and wait for the full reply
+    pub async fn chat(&self, model: &str, messages: Vec<ChatMessage>) -> Result<ChatCompletionResponse> {
+        self.openai
+            .create_chat_completion(chat_request(model, messages, false))
+            .await
+    }
+
+    /// Send a conversation to `model` and stream the reply as deltas
+    pub async fn chat_stream(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+    ) -> Result<impl futures::Stream<Item = Result<ChatCompletionChunk>>> {
+        self.openai
+            .create_chat_completion_stream(chat_request(model, messages, true))
+            .await
+    }
+
+    /// Models known to the server
+    pub async fn list(&self) -> Result<Vec<ListedModel>> {
+        let response = self.openai.get("/api/tags").await?;
+        let response = self.openai.check_status(response).await?;
+        let tags: TagsResponse = OpenAIClient::parse_json(response).await?;
+        Ok(tags.models)
+    }
+
+    /// Download `model` and wait until the pull finishes
+    pub async fn pull(&self, model: &str) -> Result<PullStatus> {
+        let body = serde_json::json!({ "model": model, "stream": false });
+        let response = self.openai.post("/api/pull", &body).await?;
+        let response = self.openai.check_status(response).await?;
+        OpenAIClient::parse_json(response).await
+    }
+
+    /// Details and capabilities of `model`
+    pub async fn show(&self, model: &str) -> Result<ShowResponse> {
+        let body = serde_json::json!({ "model": model });
+        let response = self.openai.post("/api/show", &body).await?;
+        let response = self.openai.check_status(response).await?;
+        OpenAIClient::parse_json(response).await
+    }
+}
+
+fn chat_request(model: &str, messages: Vec<ChatMessage>, stream: bool) -> ChatCompletionRequest {
+    ChatCompletionRequest {
+        model: model.to_string(),
+        messages: messages.into_iter().map(Into::into).collect(),
+        max_tokens: None,
+        min_tokens: None,
+        seed: None,
+        temperature: None,
+        top_p: None,
+        top_k: None,
+        min_p: None,
+        frequency_penalty: None,
+        presence_penalty: None,
+        repetition_penalty: None,
+        guided: None,
+        response_format: None,
+        stream: Some(stream),
+        stop: None,
+        include_stop_str_in_output: None,
+    }
+}
+
+// ============================================================================
+// Ollama API Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TagsResponse {
+    models: Vec<ListedModel>,
+}
+
+/// A model entry from `/api/tags`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListedModel {
+    pub name: String,
+    pub size: u64,
+    pub digest: String,
+}
+
+/// Final status of a `/api/pull`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PullStatus {
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    #[serde(default)]
+    pub total: Option<u64>,
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+/// Response of `/api/show`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShowResponse {
+    pub modelfile: String,
+    pub parameters: String,
+    #[serde(default)]
+    pub template: Option<String>,
+    pub details: ShowDetails,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShowDetails {
+    pub parent_model: String,
+    pub format: String,
+    pub family: String,
+    pub parameter_size: String,
+    pub quantization_level: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve a single canned HTTP response on a random local port
+    async fn serve_once(status: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_list_parses_tags() {
+        let url = serve_once(
+            "200 OK",
+            r#"{"models":[{"name":"Qwen/Qwen2.5-1.5B-Instruct","size":3087467520,"digest":"abc"}]}"#,
+        )
+        .await;
+
+        let models = VllamaClient::new(url).list().await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "Qwen/Qwen2.5-1.5B-Instruct");
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_on_generative_model() {
+        let url = serve_once(
+            "400 Bad Request",
+            r#"{"object":"error","message":"The model does not support Embeddings API","code":400}"#,
+        )
+        .await;
+
+        let request = crate::openai::EmbeddingRequest {
+            model: "m".to_string(),
+            input: vec!["hi".to_string()],
+        };
+        match VllamaClient::new(url).openai().create_embeddings(request).await {
+            Err(crate::Error::InvalidRequest(msg)) => assert!(msg.contains("does not support Embeddings API")),
+            other => panic!("expected InvalidRequest, got {:?}", other.map(|r| r.model)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_error_is_reported() {
+        let url = serve_once("500 Internal Server Error", r#"{"error":"model failed to load"}"#).await;
+
+        let err = VllamaClient::new(url).show("m").await.unwrap_err();
+        assert!(err.to_string().contains("model failed to load"));
+    }
+}
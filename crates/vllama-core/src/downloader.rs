@@ -1,14 +1,30 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use crate::{Error, Result};
-use hf_hub::api::tokio::Api;
+use hf_hub::api::tokio::{Api, ApiBuilder, ApiError, ApiRepo, Progress};
+use hf_hub::{Repo, RepoType};
+use tokio::sync::mpsc;
 use tracing::{info, warn};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
+/// Progress of a download, across all its files and within the current one
+#[derive(Debug, Clone, Default)]
 pub struct DownloadProgress {
+    /// Bytes of all files downloaded so far
     pub downloaded: u64,
+    /// Size of all files; 0 until the file list is known
     pub total: u64,
     pub status: String,
+    /// File being fetched; None before the first and after the last
+    pub current_file: Option<String>,
+    /// Position of `current_file` in the download, from 0
+    pub file_index: usize,
+    pub file_count: usize,
+    /// Bytes of `current_file` downloaded so far, out of `file_size`
+    pub file_downloaded: u64,
+    pub file_size: u64,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -16,25 +32,384 @@ pub struct CachedModel {
     pub name: String,
     pub path: PathBuf,
     pub size_mb: u64,
+    /// Revisions pulled besides `main` (branches, tags or commits)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<String>,
 }
 
+/// Split a `repo@revision` reference into the repo id and the revision
+pub fn split_revision(reference: &str) -> (&str, Option<&str>) {
+    match reference.split_once('@') {
+        Some((repo_id, revision)) if !revision.is_empty() => (repo_id, Some(revision)),
+        _ => (reference, None),
+    }
+}
+
+/// A file in a HuggingFace repository and its size in bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RepoFile {
+    pub name: String,
+    pub size: u64,
+}
+
+/// What [`ModelDownloader::download_model`] would fetch for a repository
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadPlan {
+    pub model: String,
+    pub files: Vec<RepoFile>,
+    pub total_bytes: u64,
+}
+
+impl DownloadPlan {
+    fn new(repo_id: &str, files: Vec<RepoFile>) -> Self {
+        Self {
+            model: repo_id.to_string(),
+            total_bytes: files.iter().map(|f| f.size).sum(),
+            files,
+        }
+    }
+}
+
+/// `repo_id` at `revision`, defaulting to `main`
+///
+/// hf-hub keeps each revision's files in its own snapshot directory and
+/// records the commit it resolved to under `refs/<revision>`.
+fn hub_repo(repo_id: &str, revision: Option<&str>) -> Repo {
+    Repo::with_revision(repo_id.to_string(), RepoType::Model, revision.unwrap_or("main").to_string())
+}
+
+/// Weight files in order of preference; only the first one present is
+/// fetched, and for a `.index.json` every shard it lists along with it
+const WEIGHT_FILES: [&str; 4] = [
+    "model.safetensors",
+    "model.safetensors.index.json",
+    "pytorch_model.bin",
+    "pytorch_model.bin.index.json",
+];
+
+/// Tokenizer files fetched alongside the config when the repo has them
+const TOKENIZER_FILES: [&str; 2] = ["tokenizer_config.json", "tokenizer.json"];
+
+/// The weight file `download_model` fetches out of a repository listing
+fn weights_file(files: &[RepoFile]) -> Option<&'static str> {
+    WEIGHT_FILES.into_iter().find(|name| files.iter().any(|f| f.name == *name))
+}
+
+/// The shard files listed in the `weight_map` of a sharded checkpoint's
+/// `.index.json`, in order
+fn index_shards(repo_id: &str, index: &[u8]) -> Result<Vec<String>> {
+    #[derive(Deserialize)]
+    struct Index {
+        weight_map: std::collections::HashMap<String, String>,
+    }
+
+    let index: Index = serde_json::from_slice(index).map_err(|e| {
+        Error::ModelLoadFailed(format!("{} has an invalid weight index: {}", repo_id, e))
+    })?;
+    let mut shards: Vec<String> = index.weight_map.into_values().collect();
+    shards.sort();
+    shards.dedup();
+    Ok(shards)
+}
+
+/// Pick the files `download_model` fetches out of a repository listing;
+/// `shards` are those of the weight index, if [`weights_file`] is one
+fn select_files(repo_id: &str, files: &[RepoFile], shards: &[String]) -> Result<Vec<RepoFile>> {
+    let find = |name: &str| files.iter().find(|f| f.name == name).cloned();
+
+    let config = find("config.json").ok_or_else(|| {
+        Error::ModelLoadFailed(format!("{} has no config.json", repo_id))
+    })?;
+    let weights = weights_file(files).and_then(find).ok_or_else(|| {
+        Error::ModelLoadFailed(format!("{} has no {} weights", repo_id, WEIGHT_FILES.join(" or ")))
+    })?;
+
+    let mut selected = vec![config];
+    selected.extend(TOKENIZER_FILES.into_iter().filter_map(find));
+    if weights.name.ends_with(".index.json") {
+        if shards.is_empty() {
+            return Err(Error::ModelLoadFailed(format!("{} lists no shards in {}", repo_id, weights.name)));
+        }
+        selected.push(weights);
+        for shard in shards {
+            selected.push(find(shard).ok_or_else(|| {
+                Error::ModelLoadFailed(format!("{} is missing shard {}", repo_id, shard))
+            })?);
+        }
+    } else {
+        selected.push(weights);
+    }
+    Ok(selected)
+}
+
+/// The quantization tag of a GGUF file name, e.g. `Q4_K_M` for
+/// `Llama-3.2-1B-Instruct-Q4_K_M.gguf` or `model.Q8_0-00001-of-00002.gguf`
+fn gguf_quant(name: &str) -> Option<&str> {
+    let stem = name.rsplit('/').next()?.strip_suffix(".gguf")?;
+    // Split files end in -00001-of-00003
+    let stem = match stem.rsplit_once("-of-") {
+        Some((head, count)) if count.chars().all(|c| c.is_ascii_digit()) => {
+            head.rsplit_once('-').map_or(head, |(head, _)| head)
+        }
+        _ => stem,
+    };
+    stem.rsplit(['-', '.']).next()
+}
+
+/// Pick the GGUF file(s) tagged `quant` and the tokenizer config out of a
+/// repository listing
+fn select_gguf(repo_id: &str, files: &[RepoFile], quant: &str) -> Result<Vec<RepoFile>> {
+    let mut selected: Vec<RepoFile> = files
+        .iter()
+        .filter(|f| gguf_quant(&f.name).is_some_and(|q| q.eq_ignore_ascii_case(quant)))
+        .cloned()
+        .collect();
+
+    if selected.is_empty() {
+        let mut available: Vec<&str> = files.iter().filter_map(|f| gguf_quant(&f.name)).collect();
+        available.sort();
+        available.dedup();
+        return Err(if available.is_empty() {
+            Error::ModelNotFound(format!("{} has no GGUF files", repo_id))
+        } else {
+            Error::ModelNotFound(format!(
+                "{} has no {} quantization; available: {}",
+                repo_id,
+                quant,
+                available.join(", ")
+            ))
+        });
+    }
+
+    selected.sort_by(|a, b| a.name.cmp(&b.name));
+    selected.extend(files.iter().find(|f| f.name == "tokenizer_config.json").cloned());
+    Ok(selected)
+}
+
+/// Token bucket shared by every download it is attached to
+///
+/// Clones share one budget, so concurrent pulls together stay under
+/// `bytes_per_sec` instead of each getting the full rate. Callers that
+/// overdraw the bucket sleep until it refills, which backpressures the
+/// HTTP stream.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bytes_per_sec: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that may be read right now; negative while callers are waiting
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Limit to `bytes_per_sec`, allowing bursts of up to one second's worth
+    pub fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Self {
+            bytes_per_sec,
+            bucket: Arc::new(Mutex::new(Bucket {
+                available: bytes_per_sec,
+                refilled_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Take `bytes` from the bucket, returning how long to wait before using them
+    fn reserve(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * self.bytes_per_sec;
+        bucket.available = (bucket.available + refill).min(self.bytes_per_sec);
+        bucket.refilled_at = now;
+        bucket.available -= bytes as f64;
+
+        if bucket.available < 0.0 {
+            Duration::from_secs_f64(-bucket.available / self.bytes_per_sec)
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Wait until `bytes` fit in the rate limit
+    pub async fn acquire(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// hf-hub awaits `update` for every chunk it reads, so throttling here
+/// throttles the download itself
+impl Progress for RateLimiter {
+    async fn init(&mut self, _size: usize, _filename: &str) {}
+
+    async fn update(&mut self, size: usize) {
+        self.acquire(size).await;
+    }
+
+    async fn finish(&mut self) {}
+}
+
+/// Forwards hf-hub's per-chunk progress to [`ModelDownloader::download_model`]
+///
+/// hf-hub clones this for each parallel range request, so the byte counts
+/// go over a channel rather than straight to the (non-`Send`) callback.
+#[derive(Clone)]
+struct ByteProgress {
+    bytes: mpsc::UnboundedSender<usize>,
+    rate_limit: Option<RateLimiter>,
+}
+
+impl Progress for ByteProgress {
+    async fn init(&mut self, _size: usize, _filename: &str) {}
+
+    async fn update(&mut self, size: usize) {
+        if let Some(limiter) = &self.rate_limit {
+            limiter.acquire(size).await;
+        }
+        // The receiver is gone once download_model has given up on the file
+        let _ = self.bytes.send(size);
+    }
+
+    async fn finish(&mut self) {}
+}
+
+/// Minimum time between progress reports while a file downloads
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
 pub struct ModelDownloader {
     api: Api,
+    rate_limit: Option<RateLimiter>,
+    /// HuggingFace cache directory; models live in its `hub` subdirectory
+    cache_dir: PathBuf,
 }
 
 impl ModelDownloader {
+    /// A downloader using the HuggingFace cache directory, see
+    /// [`ModelDownloader::cache_dir`]
     pub fn new() -> Result<Self> {
-        // Use default API (no custom builder needed)
-        // This automatically uses https://huggingface.co
-        let api = Api::new()
+        Self::with_cache_dir(Self::cache_dir()?)
+    }
+
+    /// A downloader that downloads into, lists and deletes from
+    /// `cache_dir` instead of the HuggingFace cache directory
+    pub fn with_cache_dir(cache_dir: impl Into<PathBuf>) -> Result<Self> {
+        let cache_dir = cache_dir.into();
+        // Talks to https://huggingface.co
+        let api = ApiBuilder::from_cache(hf_hub::Cache::new(cache_dir.join("hub")))
+            .build()
             .map_err(|e| Error::ConfigError(format!("Failed to create HF API: {}", e)))?;
 
-        Ok(Self { api })
+        Ok(Self { api, rate_limit: None, cache_dir })
+    }
+
+    /// Share `limiter`'s bandwidth budget with every download this makes
+    pub fn with_rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limit = Some(limiter);
+        self
+    }
+
+    /// The `hub` cache models are downloaded into
+    fn cache(&self) -> hf_hub::Cache {
+        hf_hub::Cache::new(self.cache_dir.join("hub"))
+    }
+
+    /// `repo_id` at `revision`, on the Hub
+    fn api_repo(&self, repo_id: &str, revision: Option<&str>) -> ApiRepo {
+        self.api.repo(hub_repo(repo_id, revision))
+    }
+
+    /// Fetch `filename` from `repo`, from the cache if present, throttled
+    /// by the rate limit if one is set; the size of every chunk read is
+    /// sent to `bytes`
+    async fn fetch(
+        &self,
+        repo: &ApiRepo,
+        cached: &Repo,
+        filename: &str,
+        bytes: mpsc::UnboundedSender<usize>,
+    ) -> std::result::Result<PathBuf, ApiError> {
+        if let Some(path) = self.cache().repo(cached.clone()).get(filename) {
+            return Ok(path);
+        }
+        let progress = ByteProgress { bytes, rate_limit: self.rate_limit.clone() };
+        repo.download_with_progress(filename, progress).await
+    }
+
+    /// Every file in `repo_id` with its size, from the Hub's repo info API
+    async fn list_files(&self, repo_id: &str, revision: Option<&str>) -> Result<Vec<RepoFile>> {
+        #[derive(Deserialize)]
+        struct RepoInfo {
+            siblings: Vec<Sibling>,
+        }
+
+        #[derive(Deserialize)]
+        struct Sibling {
+            rfilename: String,
+            #[serde(default)]
+            size: u64,
+        }
+
+        let response = self
+            .api_repo(repo_id, revision)
+            .info_request()
+            .query(&[("blobs", "true")])
+            .send()
+            .await
+            .map_err(|e| Error::ModelLoadFailed(format!("Failed to query HuggingFace: {}", e)))?;
+        if response.status().as_u16() == 404 {
+            return Err(Error::ModelNotFound(match revision {
+                Some(revision) => format!("HuggingFace repository '{}' has no revision '{}'", repo_id, revision),
+                None => format!("HuggingFace repository '{}' not found", repo_id),
+            }));
+        }
+        let info: RepoInfo = response
+            .error_for_status()
+            .map_err(|e| Error::ModelLoadFailed(format!("Failed to query HuggingFace: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| Error::ModelLoadFailed(format!("Invalid HuggingFace repo info: {}", e)))?;
+
+        Ok(info
+            .siblings
+            .into_iter()
+            .map(|s| RepoFile { name: s.rfilename, size: s.size })
+            .collect())
+    }
+
+    /// List the files `download_model` would fetch for `repo_id`, with sizes,
+    /// without downloading anything
+    pub async fn plan_download(&self, repo_id: &str, revision: Option<&str>) -> Result<DownloadPlan> {
+        let listing = self.list_files(repo_id, revision).await?;
+        // A sharded checkpoint's index names its shards; it is small, and
+        // fetched into the cache here so download_model finds it there
+        let shards = match weights_file(&listing).filter(|name| name.ends_with(".index.json")) {
+            Some(index) => {
+                let path = self.api_repo(repo_id, revision).get(index).await.map_err(|e| {
+                    Error::ModelLoadFailed(format!("Failed to fetch {} of {}: {}", index, repo_id, e))
+                })?;
+                index_shards(repo_id, &fs::read(path)?)?
+            }
+            None => Vec::new(),
+        };
+        let files = select_files(repo_id, &listing, &shards)?;
+        Ok(DownloadPlan::new(repo_id, files))
+    }
+
+    /// List the files `download_gguf` would fetch for `repo_id` and `quant`
+    pub async fn plan_gguf(&self, repo_id: &str, revision: Option<&str>, quant: &str) -> Result<DownloadPlan> {
+        let files = select_gguf(repo_id, &self.list_files(repo_id, revision).await?, quant)?;
+        Ok(DownloadPlan::new(repo_id, files))
     }
 
-    /// Get the cached path for a model
-    pub async fn get_model_path(&self, repo_id: &str) -> Result<PathBuf> {
-        let repo = self.api.model(repo_id.to_string());
+    /// Get the cached path for a model at `revision` (default `main`)
+    pub async fn get_model_path(&self, repo_id: &str, revision: Option<&str>) -> Result<PathBuf> {
+        let repo = self.api_repo(repo_id, revision);
 
         // Get config file which gives us the cache directory
         let config_path = repo.get("config.json").await
@@ -47,67 +422,20 @@ impl ModelDownloader {
     }
 
     /// Check if model exists in cache
-    pub async fn model_exists(&self, repo_id: &str) -> bool {
+    pub async fn model_exists(&self, repo_id: &str, revision: Option<&str>) -> bool {
         // Check if config.json exists in cache
-        let repo = self.api.model(repo_id.to_string());
+        let repo = self.api_repo(repo_id, revision);
         repo.get("config.json").await.is_ok()
     }
 
     /// List all cached models in HuggingFace Hub cache
     pub fn list_cached_models(&self) -> Result<Vec<CachedModel>> {
-        let cache_dir = self.get_cache_dir()?;
-        let models_dir = cache_dir.join("hub");
-
-        if !models_dir.exists() {
-            return Ok(Vec::new());
-        }
-
-        let mut models = Vec::new();
-
-        // Scan the hub directory for model folders
-        for entry in fs::read_dir(&models_dir)
-            .map_err(|e| Error::ConfigError(format!("Failed to read cache directory: {}", e)))?
-        {
-            let entry = entry.map_err(|e| Error::ConfigError(format!("Failed to read entry: {}", e)))?;
-            let path = entry.path();
-
-            // Model directories follow the pattern: models--org--name
-            if path.is_dir() {
-                if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
-                    if dir_name.starts_with("models--") {
-                        // Convert "models--org--name" to "org/name"
-                        let model_name = dir_name
-                            .strip_prefix("models--")
-                            .unwrap_or(dir_name)
-                            .replace("--", "/");
-
-                        // Get the actual model files from snapshots directory
-                        let snapshots_dir = path.join("snapshots");
-                        if snapshots_dir.exists() {
-                            // Calculate total size
-                            let size_mb = calculate_dir_size(&snapshots_dir)? / 1024 / 1024;
-
-                            models.push(CachedModel {
-                                name: model_name,
-                                path: snapshots_dir,
-                                size_mb,
-                            });
-                        }
-                    }
-                }
-            }
-        }
-
-        // Sort by name
-        models.sort_by(|a, b| a.name.cmp(&b.name));
-
-        Ok(models)
+        scan_cache(&self.cache_dir.join("hub"))
     }
 
     /// Delete a cached model
     pub fn delete_model(&self, repo_id: &str) -> Result<()> {
-        let cache_dir = self.get_cache_dir()?;
-        let models_dir = cache_dir.join("hub");
+        let models_dir = self.cache_dir.join("hub");
 
         // Convert repo_id to directory format: "org/name" -> "models--org--name"
         let dir_name = format!("models--{}", repo_id.replace('/', "--"));
@@ -126,9 +454,8 @@ impl ModelDownloader {
         Ok(())
     }
 
-    /// Get HuggingFace cache directory
-    fn get_cache_dir(&self) -> Result<PathBuf> {
-        // Default HuggingFace cache location
+    /// HuggingFace cache directory: `$HF_HOME`, else `~/.cache/huggingface`
+    pub fn cache_dir() -> Result<PathBuf> {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .map_err(|_| Error::ConfigError("Could not determine home directory".to_string()))?;
@@ -143,74 +470,164 @@ impl ModelDownloader {
     ///
     /// This uses the official hf-hub crate which provides:
     /// - Automatic resume on network failures
-    /// - Progress tracking
     /// - Authentication via HF_TOKEN env var
     /// - Caching in ~/.cache/huggingface/
     /// - Mirror support and CDN optimization
+    ///
+    /// `progress_callback` gets the bytes downloaded so far out of the total
+    /// while each file downloads (at most every 250ms) and once it's done.
+    /// Cached files count as downloaded without being fetched again.
     pub async fn download_model(
         &self,
         repo_id: &str,
+        revision: Option<&str>,
         progress_callback: impl Fn(DownloadProgress),
     ) -> Result<PathBuf> {
         info!("Downloading model: {}", repo_id);
 
         progress_callback(DownloadProgress {
-            downloaded: 0,
-            total: 0,
             status: format!("Fetching {} from HuggingFace Hub", repo_id),
+            ..Default::default()
         });
 
-        let repo = self.api.model(repo_id.to_string());
+        let files = match self.plan_download(repo_id, revision).await {
+            Ok(plan) => plan.files,
+            Err(e @ Error::ModelNotFound(_)) => return Err(e),
+            Err(e) => {
+                // Offline with the model already cached: the sizes are
+                // unknown, but nothing needs downloading
+                warn!("Could not list files for {}: {}", repo_id, e);
+                let cache = self.cache().repo(hub_repo(repo_id, revision));
+                let mut names: Vec<String> = ["config.json"]
+                    .into_iter()
+                    .chain(TOKENIZER_FILES)
+                    .chain(WEIGHT_FILES)
+                    .map(String::from)
+                    .collect();
+                let shards = WEIGHT_FILES
+                    .into_iter()
+                    .filter(|name| name.ends_with(".index.json"))
+                    .filter_map(|name| cache.get(name))
+                    .filter_map(|path| fs::read(path).ok())
+                    .find_map(|index| index_shards(repo_id, &index).ok())
+                    .unwrap_or_default();
+                names.extend(shards.iter().cloned());
+                let cached: Vec<RepoFile> = names
+                    .into_iter()
+                    .filter(|name| cache.get(name).is_some())
+                    .map(|name| RepoFile { name, size: 0 })
+                    .collect();
+                select_files(repo_id, &cached, &shards).map_err(|_| e)?
+            }
+        };
 
-        // Download essential model files
-        // hf-hub handles all the complexity: resume, retries, progress, etc.
+        let paths = self.download_files(repo_id, revision, &files, progress_callback).await?;
+        info!("Model {} downloaded successfully", repo_id);
 
-        // Download config first (small file)
-        let config_path = repo.get("config.json").await
-            .map_err(|e| Error::ModelLoadFailed(format!("Failed to download config.json: {}", e)))?;
+        // The model directory (parent of config.json, always fetched first)
+        paths[0]
+            .parent()
+            .map(PathBuf::from)
+            .ok_or_else(|| Error::ModelLoadFailed("Invalid model path".to_string()))
+    }
+
+    /// Download one quantization of a GGUF repository
+    ///
+    /// Fetches the `.gguf` file(s) tagged `quant` (e.g. `Q4_K_M`, matched
+    /// case-insensitively) plus the tokenizer config if the repo has one,
+    /// and returns the path of the GGUF file to hand to vLLM. If no file
+    /// has that tag the error lists the ones that exist.
+    pub async fn download_gguf(
+        &self,
+        repo_id: &str,
+        revision: Option<&str>,
+        quant: &str,
+        progress_callback: impl Fn(DownloadProgress),
+    ) -> Result<PathBuf> {
+        info!("Downloading {} quantization of {}", quant, repo_id);
 
         progress_callback(DownloadProgress {
-            downloaded: 1,
-            total: 3,
-            status: "Downloaded config.json".to_string(),
+            status: format!("Fetching {} from HuggingFace Hub", repo_id),
+            ..Default::default()
         });
 
-        // Download tokenizer config
-        let _tokenizer_config = repo.get("tokenizer_config.json").await
-            .map_err(|e| {
-                warn!("tokenizer_config.json not found: {}", e);
-                e
-            })
-            .ok();
+        let plan = self.plan_gguf(repo_id, revision, quant).await?;
+        let paths = self.download_files(repo_id, revision, &plan.files, progress_callback).await?;
+        info!("Model {} ({}) downloaded successfully", repo_id, quant);
 
-        progress_callback(DownloadProgress {
-            downloaded: 2,
-            total: 3,
-            status: "Downloaded tokenizer config".to_string(),
-        });
+        // select_gguf puts the weights first; split files load from part one
+        Ok(paths[0].clone())
+    }
 
-        // Try to download model weights (safetensors preferred)
-        let _model_file = match repo.get("model.safetensors").await {
-            Ok(path) => path,
-            Err(_) => {
-                // Fallback to pytorch_model.bin
-                repo.get("pytorch_model.bin").await
-                    .map_err(|e| Error::ModelLoadFailed(format!("Failed to download model weights: {}", e)))?
+    /// Fetch `files` one after another, reporting bytes downloaded out of
+    /// their total size, and return their paths in the cache
+    async fn download_files(
+        &self,
+        repo_id: &str,
+        revision: Option<&str>,
+        files: &[RepoFile],
+        progress_callback: impl Fn(DownloadProgress),
+    ) -> Result<Vec<PathBuf>> {
+        let cached = hub_repo(repo_id, revision);
+        let repo = self.api.repo(cached.clone());
+        let total: u64 = files.iter().map(|f| f.size).sum();
+        let mut downloaded = 0;
+        let mut paths = Vec::with_capacity(files.len());
+
+        for (file_index, file) in files.iter().enumerate() {
+            let file_start = downloaded;
+            let report = |downloaded: u64| DownloadProgress {
+                downloaded,
+                total,
+                status: format!("Downloading {}", file.name),
+                current_file: Some(file.name.clone()),
+                file_index,
+                file_count: files.len(),
+                file_downloaded: downloaded - file_start,
+                file_size: file.size,
+            };
+            progress_callback(report(downloaded));
+
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            let fetch = self.fetch(&repo, &cached, &file.name, tx);
+            tokio::pin!(fetch);
+            let mut reported_at = Instant::now();
+            let result = loop {
+                tokio::select! {
+                    result = &mut fetch => break result,
+                    Some(bytes) = rx.recv() => {
+                        downloaded += bytes as u64;
+                        if reported_at.elapsed() >= PROGRESS_INTERVAL {
+                            reported_at = Instant::now();
+                            progress_callback(report(downloaded));
+                        }
+                    }
+                }
+            };
+            let path = result.map_err(|e| {
+                Error::ModelLoadFailed(format!("Failed to download {}: {}", file.name, e))
+            })?;
+
+            while let Ok(bytes) = rx.try_recv() {
+                downloaded += bytes as u64;
             }
-        };
+            // Cached and resumed files report fewer bytes than their size
+            if file.size > 0 {
+                downloaded = file_start + file.size;
+            }
+            progress_callback(report(downloaded));
+            paths.push(path);
+        }
 
         progress_callback(DownloadProgress {
-            downloaded: 3,
-            total: 3,
+            downloaded,
+            total,
             status: "completed".to_string(),
+            file_index: files.len(),
+            file_count: files.len(),
+            ..Default::default()
         });
-
-        info!("Model {} downloaded successfully", repo_id);
-
-        // Return the model directory (parent of config file)
-        Ok(config_path.parent()
-            .ok_or_else(|| Error::ModelLoadFailed("Invalid model path".to_string()))?
-            .to_path_buf())
+        Ok(paths)
     }
 }
 
@@ -220,6 +637,69 @@ impl Default for ModelDownloader {
     }
 }
 
+/// Models in the hub cache directory `models_dir`
+fn scan_cache(models_dir: &Path) -> Result<Vec<CachedModel>> {
+    if !models_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut models = Vec::new();
+
+
+    // Scan the hub directory for model folders
+    for entry in fs::read_dir(models_dir)
+        .map_err(|e| Error::ConfigError(format!("Failed to read cache directory: {}", e)))?
+    {
+        let entry = entry.map_err(|e| Error::ConfigError(format!("Failed to read entry: {}", e)))?;
+        let path = entry.path();
+
+        // Model directories follow the pattern: models--org--name
+        if path.is_dir() {
+            if let Some(dir_name) = path.file_name().and_then(|n| n.to_str()) {
+                if dir_name.starts_with("models--") {
+                    // Convert "models--org--name" to "org/name"
+                    let model_name = dir_name
+                        .strip_prefix("models--")
+                        .unwrap_or(dir_name)
+                        .replace("--", "/");
+
+                    // Get the actual model files from snapshots directory
+                    let snapshots_dir = path.join("snapshots");
+                    if snapshots_dir.exists() {
+                        // Calculate total size
+                        let size_mb = calculate_dir_size(&snapshots_dir)? / 1024 / 1024;
+
+                        models.push(CachedModel {
+                            name: model_name,
+                            path: snapshots_dir,
+                            size_mb,
+                            revisions: pinned_revisions(&path),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    // Sort by name
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(models)
+}
+
+/// Revisions other than `main` recorded under `model_dir/refs`
+fn pinned_revisions(model_dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(model_dir.join("refs")) else {
+        return Vec::new();
+    };
+    let mut revisions: Vec<String> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name != "main")
+        .collect();
+    revisions.sort();
+    revisions
+}
+
 /// Calculate total size of a directory recursively
 fn calculate_dir_size(path: &PathBuf) -> Result<u64> {
     let mut total = 0;
@@ -258,6 +738,147 @@ mod tests {
         assert!(downloader.is_ok());
     }
 
+    #[test]
+    fn test_rate_limiter_shares_budget() {
+        let limiter = RateLimiter::new(1000);
+        let other = limiter.clone();
+
+        // One second of burst, then both clones draw on the same deficit
+        assert_eq!(limiter.reserve(1000), Duration::ZERO);
+        let wait = other.reserve(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        assert!(limiter.reserve(500) > Duration::from_millis(900));
+    }
+
+    #[test]
+    fn test_select_gguf() {
+        let file = |name: &str| RepoFile { name: name.to_string(), size: 1 };
+        let listing = vec![
+            file("README.md"),
+            file("Llama-3.2-1B-Instruct-Q4_K_M.gguf"),
+            file("Llama-3.2-1B-Instruct-Q8_0-00002-of-00002.gguf"),
+            file("Llama-3.2-1B-Instruct-Q8_0-00001-of-00002.gguf"),
+            file("tokenizer_config.json"),
+        ];
+
+        let names = |quant| -> Vec<String> {
+            select_gguf("m", &listing, quant).unwrap().into_iter().map(|f| f.name).collect()
+        };
+        assert_eq!(names("q4_k_m"), ["Llama-3.2-1B-Instruct-Q4_K_M.gguf", "tokenizer_config.json"]);
+        assert_eq!(names("Q8_0")[0], "Llama-3.2-1B-Instruct-Q8_0-00001-of-00002.gguf");
+        assert_eq!(names("Q8_0").len(), 3);
+
+        let err = select_gguf("m", &listing, "Q5_K_S").unwrap_err().to_string();
+        assert!(err.contains("available: Q4_K_M, Q8_0"), "{}", err);
+        assert!(select_gguf("m", &listing[..1], "Q4_K_M").unwrap_err().to_string().contains("no GGUF files"));
+    }
+
+    #[test]
+    fn test_revisions_use_separate_snapshots() {
+        let tmp = tempfile::tempdir().unwrap();
+        let hub = tmp.path();
+        let model_dir = hub.join("models--org--m");
+        for (revision, commit) in [("main", "aaa111"), ("v1.0", "bbb222")] {
+            std::fs::create_dir_all(model_dir.join("refs")).unwrap();
+            std::fs::write(model_dir.join("refs").join(revision), commit).unwrap();
+            let snapshot = model_dir.join("snapshots").join(commit);
+            std::fs::create_dir_all(&snapshot).unwrap();
+            std::fs::write(snapshot.join("config.json"), "{}").unwrap();
+        }
+
+        let cache = hf_hub::Cache::new(hub.to_path_buf());
+        let main = cache.repo(hub_repo("org/m", None)).get("config.json").unwrap();
+        let pinned = cache.repo(hub_repo("org/m", Some("v1.0"))).get("config.json").unwrap();
+        assert!(main.ends_with("snapshots/aaa111/config.json"));
+        assert!(pinned.ends_with("snapshots/bbb222/config.json"));
+
+        let models = scan_cache(hub).unwrap();
+        assert_eq!(models[0].name, "org/m");
+        assert_eq!(models[0].revisions, ["v1.0"]);
+    }
+
+    #[test]
+    fn test_split_revision() {
+        assert_eq!(split_revision("org/m@v1.0"), ("org/m", Some("v1.0")));
+        assert_eq!(split_revision("org/m"), ("org/m", None));
+        assert_eq!(split_revision("org/m@"), ("org/m@", None));
+    }
+
+    #[tokio::test]
+    async fn test_byte_progress_forwards_chunks() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut progress = ByteProgress { bytes: tx, rate_limit: None };
+        let mut range = progress.clone();
+
+        progress.update(100).await;
+        range.update(50).await;
+        drop((progress, range));
+
+        let mut total = 0;
+        while let Some(bytes) = rx.recv().await {
+            total += bytes;
+        }
+        assert_eq!(total, 150);
+    }
+
+    #[test]
+    fn test_select_files() {
+        let file = |name: &str, size| RepoFile { name: name.to_string(), size };
+        let listing = vec![
+            file("README.md", 5),
+            file("config.json", 1),
+            file("pytorch_model.bin", 900),
+            file("model.safetensors", 800),
+            file("tokenizer_config.json", 2),
+        ];
+
+        let names: Vec<_> = select_files("m", &listing, &[]).unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, ["config.json", "tokenizer_config.json", "model.safetensors"]);
+
+        assert!(select_files("m", &listing[..2], &[]).is_err());
+    }
+
+    #[test]
+    fn test_select_sharded_files() {
+        let file = |name: &str, size| RepoFile { name: name.to_string(), size };
+        let listing = vec![
+            file("config.json", 1),
+            file("model-00001-of-00002.safetensors", 500),
+            file("model-00002-of-00002.safetensors", 400),
+            file("model.safetensors.index.json", 3),
+            file("tokenizer.json", 4),
+            file("tokenizer_config.json", 2),
+        ];
+        let index = br#"{"metadata": {}, "weight_map": {
+            "lm_head.weight": "model-00002-of-00002.safetensors",
+            "embed.weight": "model-00001-of-00002.safetensors",
+            "norm.weight": "model-00002-of-00002.safetensors"
+        }}"#;
+
+        let shards = index_shards("m", index).unwrap();
+        assert_eq!(shards, ["model-00001-of-00002.safetensors", "model-00002-of-00002.safetensors"]);
+
+        let plan = DownloadPlan::new("m", select_files("m", &listing, &shards).unwrap());
+        let names: Vec<_> = plan.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "config.json",
+                "tokenizer_config.json",
+                "tokenizer.json",
+                "model.safetensors.index.json",
+                "model-00001-of-00002.safetensors",
+                "model-00002-of-00002.safetensors",
+            ]
+        );
+        assert_eq!(plan.total_bytes, 910);
+
+        // A shard the index names but the repo lacks
+        let missing: Vec<_> = listing.iter().filter(|f| !f.name.starts_with("model-00002")).cloned().collect();
+        assert!(select_files("m", &missing, &shards).is_err());
+        assert!(select_files("m", &listing, &[]).is_err());
+    }
+
     #[tokio::test]
     async fn test_model_path() {
         // This test requires network access
@@ -271,6 +892,7 @@ mod tests {
         // Try a small test model
         let result = downloader.download_model(
             "hf-internal-testing/tiny-random-gpt2",
+            None,
             |progress| {
                 println!("Progress: {}/{} - {}",
                     progress.downloaded,
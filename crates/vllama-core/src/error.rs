@@ -8,6 +8,14 @@ pub enum Error {
     InvalidRequest(String),
     HardwareUnsupported(String),
     EngineNotAvailable(String),
+    /// Backend unreachable after exhausting retries
+    Upstream { attempts: u32, last: String },
+    /// Prompt plus requested completion exceed the model's context window
+    ContextLengthExceeded { max: usize, requested: usize },
+    /// Backend rejected the request because it is overloaded
+    RateLimited,
+    /// The backend is up but doesn't serve the requested model
+    ModelNotServed { available: Vec<String> },
     ConfigError(String),
     IoError(std::io::Error),
     SerdeError(serde_json::Error),
@@ -22,6 +30,21 @@ impl fmt::Display for Error {
             Error::InvalidRequest(msg) => write!(f, "Invalid request: {}", msg),
             Error::HardwareUnsupported(msg) => write!(f, "Hardware unsupported: {}", msg),
             Error::EngineNotAvailable(msg) => write!(f, "Engine not available: {}", msg),
+            Error::Upstream { attempts, last } => {
+                write!(f, "Backend unreachable after {} attempts: {}", attempts, last)
+            }
+            Error::ContextLengthExceeded { max, requested } => write!(
+                f,
+                "Context length exceeded: requested {} tokens, model maximum is {}",
+                requested, max
+            ),
+            Error::RateLimited => write!(f, "Rate limited by backend, retry later"),
+            Error::ModelNotServed { available } if available.is_empty() => {
+                write!(f, "Model not served by backend")
+            }
+            Error::ModelNotServed { available } => {
+                write!(f, "Model not served by backend (available: {})", available.join(", "))
+            }
             Error::ConfigError(msg) => write!(f, "Configuration error: {}", msg),
             Error::IoError(e) => write!(f, "I/O error: {}", e),
             Error::SerdeError(e) => write!(f, "Serialization error: {}", e),
@@ -1,4 +1,17 @@
 use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::warn;
+
+/// Where the cgroup filesystem is mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Files the NVIDIA kernel driver creates; present means a GPU is there even
+/// if it can't be queried
+const NVIDIA_DRIVER_PATHS: [&str; 2] = ["/proc/driver/nvidia/version", "/dev/nvidiactl"];
+
+/// cgroup v1 reports "no limit" as a huge page-aligned number rather than
+/// `max`; anything above this is treated as unlimited
+const CGROUP_V1_UNLIMITED_MB: u64 = 1 << 40;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum HardwareType {
@@ -8,6 +21,20 @@ pub enum HardwareType {
     AppleSilicon,
 }
 
+/// How sure detection is about the GPU
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuStatus {
+    /// A GPU was found and queried
+    Detected,
+    /// No GPU driver is present
+    #[default]
+    NotFound,
+    /// The driver is present but querying it failed (e.g. NVML isn't
+    /// loadable); vLLM may still run on CUDA
+    Unknown,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GpuInfo {
     pub name: String,
@@ -22,35 +49,52 @@ pub struct Hardware {
     pub cpu_cores: usize,
     pub ram_total_mb: u64,
     pub ram_available_mb: u64,
+    /// cgroup memory limit when running in a memory-limited container;
+    /// already applied to `ram_total_mb` and `ram_available_mb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit_mb: Option<u64>,
     pub gpu_info: Option<GpuInfo>,
+    #[serde(default)]
+    pub gpu_status: GpuStatus,
 }
 
 impl Hardware {
     pub fn detect() -> Self {
         let sys = sysinfo::System::new_all();
 
-        let cpu_cores = sys.cpus().len();
-        let ram_total_mb = sys.total_memory() / (1024 * 1024);
-        let ram_available_mb = sys.available_memory() / (1024 * 1024);
+        let cgroup = CgroupMemory::read(Path::new(CGROUP_ROOT));
+        let (cpu_cores, ram_total_mb, ram_available_mb) = with_fallbacks(
+            sys.cpus().len(),
+            sys.total_memory() / (1024 * 1024),
+            sys.available_memory() / (1024 * 1024),
+            || std::thread::available_parallelism().map(|n| n.get()).unwrap_or(0),
+            || cgroup.map(|c| c.limit_mb),
+        );
+        let (ram_total_mb, ram_available_mb) = match cgroup {
+            Some(cgroup) => cgroup.clamp(ram_total_mb, ram_available_mb),
+            None => (ram_total_mb, ram_available_mb),
+        };
 
         #[cfg(target_os = "macos")]
-        let (hw_type, gpu_info) = {
+        let (hw_type, gpu_info, gpu_status) = {
             if std::env::consts::ARCH == "aarch64" {
-                (HardwareType::AppleSilicon, None)
+                (HardwareType::AppleSilicon, None, GpuStatus::Detected)
             } else {
-                (HardwareType::Cpu, None)
+                (HardwareType::Cpu, None, GpuStatus::NotFound)
             }
         };
 
         #[cfg(not(target_os = "macos"))]
-        let (hw_type, gpu_info) = (HardwareType::Cpu, None);
+        let (hw_type, gpu_info, gpu_status) = classify_nvidia(query_nvidia_smi().as_deref(), nvidia_driver_present());
 
         Self {
             hw_type,
             cpu_cores,
             ram_total_mb,
             ram_available_mb,
+            memory_limit_mb: cgroup.map(|c| c.limit_mb),
             gpu_info,
+            gpu_status,
         }
     }
 
@@ -61,7 +105,222 @@ impl Hardware {
         )
     }
 
+    /// True unless detection positively found no GPU
+    ///
+    /// Use this to gate GPU-only paths: an inconclusive probe shouldn't stop
+    /// vLLM from trying CUDA.
+    pub fn may_have_gpu(&self) -> bool {
+        self.has_gpu() || self.gpu_status == GpuStatus::Unknown
+    }
+
     pub fn available_vram_mb(&self) -> Option<u64> {
         self.gpu_info.as_ref().map(|gpu| gpu.vram_available_mb)
     }
 }
+
+/// Whether the NVIDIA kernel driver is loaded
+pub fn nvidia_driver_present() -> bool {
+    NVIDIA_DRIVER_PATHS.iter().any(|path| Path::new(path).exists())
+}
+
+/// `nvidia-smi` output for the first GPU, None if it is missing or fails
+///
+/// Drivers older than 510 reject the `compute_cap` field, so the query is
+/// retried without it.
+#[cfg(not(target_os = "macos"))]
+fn query_nvidia_smi() -> Option<String> {
+    let query = |fields: &str| {
+        let output = std::process::Command::new("nvidia-smi")
+            .args([format!("--query-gpu={}", fields).as_str(), "--format=csv,noheader,nounits"])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+    query("name,memory.total,memory.free,compute_cap").or_else(|| query("name,memory.total,memory.free"))
+}
+
+/// Turn an `nvidia-smi` probe into hardware type, GPU info and status
+///
+/// A failed probe with the driver present is "unknown", not "no GPU":
+/// nvidia-smi needs NVML, which can be unloadable while CUDA still works.
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn classify_nvidia(smi_output: Option<&str>, driver_present: bool) -> (HardwareType, Option<GpuInfo>, GpuStatus) {
+    if let Some(gpu) = smi_output.and_then(parse_nvidia_smi) {
+        return (HardwareType::NvidiaGpu, Some(gpu), GpuStatus::Detected);
+    }
+    if driver_present {
+        warn!("NVIDIA driver present but the GPU could not be queried; GPU status unknown");
+        return (HardwareType::NvidiaGpu, None, GpuStatus::Unknown);
+    }
+    (HardwareType::Cpu, None, GpuStatus::NotFound)
+}
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn parse_nvidia_smi(output: &str) -> Option<GpuInfo> {
+    let line = output.lines().next()?;
+    let parts: Vec<&str> = line.split(',').map(str::trim).collect();
+    if parts.len() < 3 || parts[0].is_empty() {
+        return None;
+    }
+    // "8.9"; missing or "[N/A]" on drivers that can't report it
+    let compute_capability = parts.get(3).and_then(|cap| {
+        let (major, minor) = cap.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    });
+    Some(GpuInfo {
+        name: parts[0].to_string(),
+        vram_total_mb: parts[1].parse().ok()?,
+        vram_available_mb: parts[2].parse().ok()?,
+        compute_capability,
+    })
+}
+
+/// Fill in core count and memory when sysinfo reports zero
+///
+/// Some sandboxes hide `/proc`, so sysinfo sees no CPUs and no memory. The
+/// core count then comes from `available_parallelism` and memory from the
+/// cgroup limit; values that can't be determined stay 0 ("unknown").
+fn with_fallbacks(
+    cpu_cores: usize,
+    ram_total_mb: u64,
+    ram_available_mb: u64,
+    fallback_cores: impl FnOnce() -> usize,
+    fallback_memory_mb: impl FnOnce() -> Option<u64>,
+) -> (usize, u64, u64) {
+    let cpu_cores = if cpu_cores == 0 {
+        let cores = fallback_cores();
+        warn!("sysinfo reported 0 CPU cores; using available parallelism ({})", cores);
+        cores
+    } else {
+        cpu_cores
+    };
+
+    let (ram_total_mb, ram_available_mb) = if ram_total_mb == 0 {
+        match fallback_memory_mb() {
+            Some(limit) => {
+                warn!("sysinfo reported 0 MB of RAM; using cgroup memory limit ({} MB)", limit);
+                (limit, limit)
+            }
+            None => {
+                warn!("sysinfo reported 0 MB of RAM and no cgroup limit is set; memory is unknown");
+                (0, 0)
+            }
+        }
+    } else {
+        (ram_total_mb, ram_available_mb)
+    };
+
+    (cpu_cores, ram_total_mb, ram_available_mb)
+}
+
+/// Memory limit and usage of the current cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CgroupMemory {
+    limit_mb: u64,
+    usage_mb: Option<u64>,
+}
+
+impl CgroupMemory {
+    /// Read the memory limit under `root`, trying cgroup v2 then v1
+    ///
+    /// None when there is no cgroup filesystem or no limit is set.
+    fn read(root: &Path) -> Option<Self> {
+        let read_mb = |file: &str| -> Option<u64> {
+            let value = std::fs::read_to_string(root.join(file)).ok()?;
+            let bytes: u64 = value.trim().parse().ok()?;
+            Some(bytes / (1024 * 1024))
+        };
+
+        // v2: a literal "max" means unlimited and fails to parse
+        if root.join("memory.max").exists() {
+            return read_mb("memory.max").map(|limit_mb| Self {
+                limit_mb,
+                usage_mb: read_mb("memory.current"),
+            });
+        }
+
+        let limit_mb = read_mb("memory/memory.limit_in_bytes")?;
+        if limit_mb >= CGROUP_V1_UNLIMITED_MB {
+            return None;
+        }
+        Some(Self {
+            limit_mb,
+            usage_mb: read_mb("memory/memory.usage_in_bytes"),
+        })
+    }
+
+    /// Cap host memory readings at what this cgroup may actually use
+    fn clamp(&self, ram_total_mb: u64, ram_available_mb: u64) -> (u64, u64) {
+        let headroom = self.limit_mb.saturating_sub(self.usage_mb.unwrap_or(0));
+        (ram_total_mb.min(self.limit_mb), ram_available_mb.min(headroom))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zero_sysinfo_uses_fallbacks() {
+        let (cores, total, available) = with_fallbacks(0, 0, 0, || 8, || Some(4096));
+        assert_eq!((cores, total, available), (8, 4096, 4096));
+
+        // Nothing to fall back on: memory stays unknown
+        let (_, total, _) = with_fallbacks(0, 0, 0, || 8, || None);
+        assert_eq!(total, 0);
+
+        // Real readings are kept untouched
+        let (cores, total, available) = with_fallbacks(16, 65536, 32000, || 1, || Some(1));
+        assert_eq!((cores, total, available), (16, 65536, 32000));
+    }
+
+    #[test]
+    fn test_classify_nvidia() {
+        let (hw_type, gpu, status) = classify_nvidia(Some("NVIDIA GeForce RTX 4090, 24564, 23000\n"), true);
+        assert_eq!((hw_type, status), (HardwareType::NvidiaGpu, GpuStatus::Detected));
+        let gpu = gpu.unwrap();
+        assert_eq!(gpu.vram_total_mb, 24564);
+        assert_eq!(gpu.compute_capability, None);
+
+        let (_, gpu, _) = classify_nvidia(Some("NVIDIA A100-SXM4-80GB, 81920, 80000, 8.0\n"), true);
+        assert_eq!(gpu.unwrap().compute_capability, Some((8, 0)));
+
+        // nvidia-smi failed (NVML not loadable) but the driver is there
+        let hardware_unknown = classify_nvidia(None, true);
+        assert_eq!(hardware_unknown.2, GpuStatus::Unknown);
+        assert!(hardware_unknown.1.is_none());
+
+        assert_eq!(classify_nvidia(None, false).0, HardwareType::Cpu);
+        assert_eq!(classify_nvidia(Some("garbage"), false).2, GpuStatus::NotFound);
+    }
+
+    #[test]
+    fn test_cgroup_memory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        let v1 = root.join("v1");
+        let v2 = root.join("v2");
+        std::fs::create_dir_all(v1.join("memory")).unwrap();
+        std::fs::create_dir_all(&v2).unwrap();
+
+        // v2 with a 4 GiB limit, 1 GiB in use
+        std::fs::write(v2.join("memory.max"), "4294967296\n").unwrap();
+        std::fs::write(v2.join("memory.current"), "1073741824\n").unwrap();
+        let cgroup = CgroupMemory::read(&v2).unwrap();
+        assert_eq!(cgroup, CgroupMemory { limit_mb: 4096, usage_mb: Some(1024) });
+        assert_eq!(cgroup.clamp(65536, 32000), (4096, 3072));
+
+        // v2 without a limit
+        std::fs::write(v2.join("memory.max"), "max\n").unwrap();
+        assert_eq!(CgroupMemory::read(&v2), None);
+
+        // v1 with a 2 GiB limit, then v1's "unlimited" sentinel
+        std::fs::write(v1.join("memory/memory.limit_in_bytes"), "2147483648\n").unwrap();
+        assert_eq!(CgroupMemory::read(&v1).unwrap().limit_mb, 2048);
+        std::fs::write(v1.join("memory/memory.limit_in_bytes"), "9223372036854771712\n").unwrap();
+        assert_eq!(CgroupMemory::read(&v1), None);
+    }
+}
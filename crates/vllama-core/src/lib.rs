@@ -6,12 +6,20 @@ pub mod hardware;
 pub mod error;
 pub mod downloader;
 pub mod openai;
+pub mod special_tokens;
+pub mod client;
+pub mod templates;
+pub mod aliases;
 
-pub use downloader::{CachedModel, DownloadProgress, ModelDownloader};
+pub use aliases::ModelAliases;
+pub use client::VllamaClient;
+pub use downloader::{split_revision, CachedModel, DownloadPlan, DownloadProgress, ModelDownloader, RateLimiter, RepoFile};
 pub use error::{Error, Result};
-pub use hardware::{Hardware, HardwareType, GpuInfo};
-pub use model::{ModelHandle, ModelInfo, ModelFormat};
-pub use openai::{OpenAIClient, CompletionRequest, CompletionResponse, ChatCompletionRequest, ChatCompletionResponse};
-pub use request::{ChatMessage, ChatRequest, ChatRole, GenerateRequest, GenerateOptions, SamplingParams};
+pub use hardware::{Hardware, HardwareType, GpuInfo, GpuStatus};
+pub use model::{ModelHandle, ModelInfo, ModelFormat, ModelSource};
+pub use openai::{OpenAIClient, CompletionRequest, CompletionResponse, ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, StreamOptions};
+pub use request::{ChatMessage, ChatRequest, ChatRole, GenerateRequest, GenerateOptions, GuidedDecoding, ResponseFormat, SamplingParams};
 pub use response::{GenerateResponse, TokenInfo, GenerationStats};
+pub use special_tokens::SpecialTokens;
+pub use templates::ChatTemplate;
 pub use types::{RequestId, Token, TokenId};
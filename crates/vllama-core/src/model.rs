@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+use crate::{Error, Result};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ModelHandle(pub u64);
@@ -50,3 +53,275 @@ impl ModelInfo {
         self
     }
 }
+
+/// Short names accepted in place of a HuggingFace repo id
+pub const SHORT_NAMES: &[(&str, &str)] = &[
+    ("qwen2.5:0.5b", "Qwen/Qwen2.5-0.5B-Instruct"),
+    ("qwen2.5:1.5b", "Qwen/Qwen2.5-1.5B-Instruct"),
+    ("qwen2.5:7b", "Qwen/Qwen2.5-7B-Instruct"),
+    ("mistral:7b", "mistralai/Mistral-7B-Instruct-v0.3"),
+    ("llama3.2:1b", "meta-llama/Llama-3.2-1B-Instruct"),
+    ("llama3.2:3b", "meta-llama/Llama-3.2-3B-Instruct"),
+    ("llama3.1:8b", "meta-llama/Llama-3.1-8B-Instruct"),
+];
+
+/// Where a user-supplied model reference points
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelSource {
+    /// An existing file or directory on disk
+    Local(PathBuf),
+    /// A HuggingFace repository id
+    Repo(String),
+}
+
+impl ModelSource {
+    /// Resolve `model` as a local path, then a short name, then a repo id
+    ///
+    /// References that look like paths (absolute, `./`, `../`, `~/`) but
+    /// don't exist are a "path not found" error instead of a Hub lookup; a
+    /// typo'd repo id fails later, when the Hub reports it missing.
+    pub fn resolve(model: &str) -> Result<Self> {
+        let path = expand_home(model);
+        if path.exists() {
+            return Ok(ModelSource::Local(path));
+        }
+        if looks_like_path(model) {
+            return Err(Error::ModelNotFound(format!(
+                "local path {} does not exist",
+                path.display()
+            )));
+        }
+
+        if let Some((_, repo_id)) = SHORT_NAMES.iter().find(|(name, _)| *name == model) {
+            return Ok(ModelSource::Repo(repo_id.to_string()));
+        }
+
+        Ok(ModelSource::Repo(model.to_string()))
+    }
+
+    /// The reference to hand to vLLM: the path or the repo id
+    pub fn as_model_arg(&self) -> String {
+        match self {
+            ModelSource::Local(path) => path.display().to_string(),
+            ModelSource::Repo(repo_id) => repo_id.clone(),
+        }
+    }
+}
+
+fn looks_like_path(model: &str) -> bool {
+    Path::new(model).is_absolute()
+        || ["./", "../", "~/"].iter().any(|prefix| model.starts_with(prefix))
+}
+
+fn expand_home(model: &str) -> PathBuf {
+    match (model.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(model),
+    }
+}
+
+/// Read a JSON file for `repo_id` from the local HuggingFace cache
+///
+/// Never touches the network; returns None if the file isn't cached or
+/// doesn't parse.
+pub fn read_cached_json(repo_id: &str, filename: &str) -> Option<Value> {
+    let path = hf_hub::Cache::from_env().model(repo_id.to_string()).get(filename)?;
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Read a JSON config file for `model` (a repo id, short name or local path)
+///
+/// Local models are read from their directory and repos from the
+/// HuggingFace cache; never touches the network.
+pub fn read_model_json(model: &str, filename: &str) -> Option<Value> {
+    match ModelSource::resolve(model).ok()? {
+        ModelSource::Local(path) => {
+            let dir = if path.is_dir() { path } else { path.parent()?.to_path_buf() };
+            let contents = std::fs::read_to_string(dir.join(filename)).ok()?;
+            serde_json::from_str(&contents).ok()
+        }
+        ModelSource::Repo(repo_id) => read_cached_json(&repo_id, filename),
+    }
+}
+
+/// Context length from a model's `config.json`
+///
+/// vLLM uses this unless `--max-model-len` lowers it. Multimodal configs
+/// keep it under `text_config`.
+pub fn context_length(config: &Value) -> Option<usize> {
+    let max_positions = |c: &Value| c.get("max_position_embeddings").and_then(Value::as_u64);
+    max_positions(config)
+        .or_else(|| config.get("text_config").and_then(max_positions))
+        .map(|n| n as usize)
+}
+
+/// Rough VRAM needed for a model's weights, in MB, from its `config.json`
+///
+/// The parameter count is worked out from the transformer dimensions
+/// (embeddings plus attention and gated MLP per layer) and multiplied by
+/// the bytes per weight of `torch_dtype`, or the quantization bits. This
+/// is a lower bound: the KV cache and activations come on top. Returns
+/// None when the config lacks the dimensions.
+pub fn estimate_vram_mb(config: &Value) -> Option<u64> {
+    // Multimodal configs keep the language model's dimensions in text_config
+    let text = match config.get("hidden_size") {
+        Some(_) => config,
+        None => config.get("text_config")?,
+    };
+    let dim = |key: &str| text.get(key).and_then(Value::as_u64);
+
+    let hidden = dim("hidden_size")?;
+    let layers = dim("num_hidden_layers")?;
+    let vocab = dim("vocab_size")?;
+    let intermediate = dim("intermediate_size").unwrap_or(4 * hidden);
+    let heads = dim("num_attention_heads").unwrap_or(1).max(1);
+    let kv_heads = dim("num_key_value_heads").unwrap_or(heads);
+    let kv_dim = kv_heads * (hidden / heads);
+
+    let attention = 2 * hidden * hidden + 2 * hidden * kv_dim;
+    let mlp = 3 * hidden * intermediate;
+    let tied = text.get("tie_word_embeddings").and_then(Value::as_bool).unwrap_or(false);
+    let embeddings = vocab * hidden * if tied { 1 } else { 2 };
+    let params = embeddings + layers * (attention + mlp);
+
+    let bits = config
+        .get("quantization_config")
+        .and_then(|q| q.get("bits"))
+        .and_then(Value::as_u64)
+        .unwrap_or(match text.get("torch_dtype").and_then(Value::as_str) {
+            Some("float32") => 32,
+            _ => 16,
+        });
+    Some(params * bits / 8 / (1024 * 1024))
+}
+
+/// Capabilities of a cached model, as reported in Ollama's `/api/show`
+pub fn model_capabilities(repo_id: &str) -> Vec<String> {
+    detect_capabilities(
+        read_cached_json(repo_id, "config.json").as_ref(),
+        read_cached_json(repo_id, "tokenizer_config.json").as_ref(),
+    )
+}
+
+/// Derive capabilities from a model's `config.json` and `tokenizer_config.json`
+///
+/// Encoder-only architectures (`BertModel`, ...) are embedding models;
+/// everything else can do completion, plus "chat" if the tokenizer ships a
+/// chat template and "vision" if the config has a vision tower.
+pub fn detect_capabilities(config: Option<&Value>, tokenizer_config: Option<&Value>) -> Vec<String> {
+    let architectures: Vec<&str> = config
+        .and_then(|c| c.get("architectures"))
+        .and_then(Value::as_array)
+        .map(|a| a.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let model_type = config
+        .and_then(|c| c.get("model_type"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+
+    let is_embedding = matches!(model_type, "bert" | "xlm-roberta" | "nomic_bert" | "mpnet")
+        || (!architectures.is_empty() && architectures.iter().all(|a| a.ends_with("Model")));
+    if is_embedding {
+        return vec!["embedding".to_string()];
+    }
+
+    let mut capabilities = vec!["completion".to_string()];
+
+    let has_chat_template = tokenizer_config
+        .and_then(|t| t.get("chat_template"))
+        .is_some_and(|t| !t.is_null());
+    if has_chat_template {
+        capabilities.push("chat".to_string());
+    }
+
+    let has_vision = config.is_some_and(|c| c.get("vision_config").is_some())
+        || architectures.iter().any(|a| a.contains("Vision") || a.contains("VL") || a.starts_with("Llava"));
+    if has_vision {
+        capabilities.push("vision".to_string());
+    }
+
+    capabilities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_capabilities() {
+        let llama = json!({"architectures": ["LlamaForCausalLM"], "model_type": "llama"});
+        let tokenizer = json!({"chat_template": "{% for m in messages %}{% endfor %}"});
+        assert_eq!(
+            detect_capabilities(Some(&llama), Some(&tokenizer)),
+            vec!["completion", "chat"]
+        );
+        assert_eq!(detect_capabilities(Some(&llama), None), vec!["completion"]);
+
+        let vision = json!({"architectures": ["Qwen2VLForConditionalGeneration"], "vision_config": {}});
+        assert_eq!(
+            detect_capabilities(Some(&vision), Some(&tokenizer)),
+            vec!["completion", "chat", "vision"]
+        );
+
+        let bert = json!({"architectures": ["BertModel"], "model_type": "bert"});
+        assert_eq!(detect_capabilities(Some(&bert), None), vec!["embedding"]);
+
+        // Unknown model: assume plain completion
+        assert_eq!(detect_capabilities(None, None), vec!["completion"]);
+    }
+
+    #[test]
+    fn test_context_length() {
+        assert_eq!(context_length(&json!({"max_position_embeddings": 32768})), Some(32768));
+        let vision = json!({"text_config": {"max_position_embeddings": 8192}, "vision_config": {}});
+        assert_eq!(context_length(&vision), Some(8192));
+        assert_eq!(context_length(&json!({})), None);
+    }
+
+    #[test]
+    fn test_estimate_vram_mb() {
+        // Qwen2.5-1.5B-Instruct: ~1.54B parameters in bf16
+        let qwen = json!({
+            "hidden_size": 1536,
+            "intermediate_size": 8960,
+            "num_hidden_layers": 28,
+            "num_attention_heads": 12,
+            "num_key_value_heads": 2,
+            "vocab_size": 151936,
+            "tie_word_embeddings": true,
+            "torch_dtype": "bfloat16"
+        });
+        assert_eq!(estimate_vram_mb(&qwen), Some(2944));
+
+        let mut awq = qwen.clone();
+        awq["quantization_config"] = json!({"bits": 4});
+        assert_eq!(estimate_vram_mb(&awq), Some(736));
+
+        assert_eq!(estimate_vram_mb(&json!({"model_type": "bert"})), None);
+    }
+
+    #[test]
+    fn test_model_source_resolve() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            ModelSource::resolve(dir.to_str().unwrap()).unwrap(),
+            ModelSource::Local(dir.clone())
+        );
+        assert_eq!(
+            ModelSource::resolve("qwen2.5:1.5b").unwrap(),
+            ModelSource::Repo("Qwen/Qwen2.5-1.5B-Instruct".to_string())
+        );
+        assert_eq!(
+            ModelSource::resolve("Qwen/Qwen2.5-7B-Instruct").unwrap(),
+            ModelSource::Repo("Qwen/Qwen2.5-7B-Instruct".to_string())
+        );
+
+        let missing = dir.join("vllama-no-such-model");
+        let err = ModelSource::resolve(missing.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+        let err = ModelSource::resolve("./no-such-model").unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+    }
+}
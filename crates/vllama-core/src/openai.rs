@@ -2,74 +2,249 @@
 ///
 /// This module provides an OpenAI-compatible API client for communicating
 /// with vLLM's OpenAI-compatible server.
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use crate::{Error, Result};
+use tracing::warn;
+
+/// Maximum number of characters of a backend body quoted in error messages
+const BODY_SNIPPET_LEN: usize = 200;
+
+/// Decode a backend body, distinguishing a non-JSON reply from a JSON
+/// payload we failed to understand
+fn decode_body<T: DeserializeOwned>(status: u16, content_type: Option<&str>, body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| {
+        let is_json = content_type.is_some_and(|ct| ct.contains("json"));
+        if is_json {
+            Error::InferenceFailed(format!(
+                "Failed to parse backend response: {} (body: {})",
+                e,
+                body_snippet(body)
+            ))
+        } else {
+            Error::InferenceFailed(format!(
+                "Backend returned non-JSON response (status {}, content-type {}): {}",
+                status,
+                content_type.unwrap_or("unknown"),
+                body_snippet(body)
+            ))
+        }
+    })
+}
+
+/// Error message from a vLLM error body
+///
+/// Accepts vLLM's flat `{"message": ...}`, OpenAI's nested
+/// `{"error": {"message": ...}}` and vllama's own `{"error": "..."}`
+/// shapes, falling back to the raw body.
+fn error_message(body: &str) -> String {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(body) else {
+        return body.to_string();
+    };
+    value
+        .get("message")
+        .or_else(|| value.get("error").and_then(|e| e.get("message")))
+        .or_else(|| value.get("error"))
+        .and_then(|m| m.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| body.to_string())
+}
+
+/// Extract (max, requested) from vLLM's context length error
+///
+/// Handles both "maximum context length is 2048 tokens. However, you
+/// requested 3000 tokens" and the newer "'max_tokens' ... is too large: 500.
+/// This model's maximum context length is 2048 tokens and your request has
+/// 1600 input tokens".
+fn parse_context_length_error(message: &str) -> Option<(usize, usize)> {
+    fn number_after(message: &str, marker: &str) -> Option<usize> {
+        let rest = &message[message.find(marker)? + marker.len()..];
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    }
+
+    let max = number_after(message, "maximum context length is ")?;
+    let requested = number_after(message, "you requested ").or_else(|| {
+        let input = number_after(message, "your request has ")?;
+        Some(input + number_after(message, "is too large: ").unwrap_or(0))
+    })?;
+    Some((max, requested))
+}
+
+/// Truncate a response body for inclusion in an error message
+fn body_snippet(body: &str) -> String {
+    let body = body.trim();
+    match body.char_indices().nth(BODY_SNIPPET_LEN) {
+        Some((idx, _)) => format!("{}...", &body[..idx]),
+        None => body.to_string(),
+    }
+}
+
+/// Attempts made for a request when the backend refuses connections
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry; doubles on each further attempt
+const RETRY_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How long connecting to the backend may take
+///
+/// Generation time isn't bounded by default: vLLM only answers a
+/// non-streaming request once it has finished generating.
+pub const DEFAULT_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Health checks fail fast; a hung backend is unhealthy
+const HEALTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Base URL of a vLLM server started by `vllama serve`
+pub const DEFAULT_VLLM_URL: &str = "http://127.0.0.1:8100";
+
+/// HTTP client for talking to a backend
+///
+/// `accept_invalid_certs` skips TLS certificate verification, for internal
+/// deployments behind a self-signed certificate.
+pub fn http_client(accept_invalid_certs: bool) -> reqwest::Client {
+    reqwest::Client::builder()
+        .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+        .danger_accept_invalid_certs(accept_invalid_certs)
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Failed to build HTTP client: {}", e);
+            reqwest::Client::new()
+        })
+}
 
 /// OpenAI API client
 pub struct OpenAIClient {
     client: reqwest::Client,
     base_url: String,
+    max_attempts: u32,
+    timeout: Option<std::time::Duration>,
 }
 
 impl OpenAIClient {
-    /// Create a new OpenAI API client
+    /// Create a new OpenAI API client for `base_url` (`http://` or `https://`)
     pub fn new(base_url: impl Into<String>) -> Self {
         Self {
-            client: reqwest::Client::new(),
-            base_url: base_url.into(),
+            client: http_client(false),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            timeout: None,
         }
     }
 
-    /// Create completion
-    pub async fn create_completion(&self, request: CompletionRequest) -> Result<CompletionResponse> {
-        let url = format!("{}/v1/completions", self.base_url);
+    /// Skip TLS certificate verification (self-signed internal deployments)
+    pub fn with_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.client = http_client(accept);
+        self
+    }
 
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| Error::ModelLoadFailed(format!("OpenAI API request failed: {}", e)))?;
+    /// Set how many times a request is attempted when the connection fails (minimum 1)
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            return Err(Error::ModelLoadFailed(format!(
-                "OpenAI API error ({}): {}",
-                status, text
-            )));
+    /// Limit how long each attempt may take, reading the response included
+    ///
+    /// Unlimited by default, since a long generation is legitimately slow;
+    /// a streamed reply has to finish within the limit too.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// POST `body` to `path`, retrying connection failures with backoff
+    ///
+    /// Only connect errors are retried: the request never reached the
+    /// backend, so resending it is safe. Once attempts run out the failure
+    /// is reported as [`Error::Upstream`].
+    pub(crate) async fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, path);
+        self.send(&url, || self.client.post(&url).json(body)).await
+    }
+
+    /// GET `path`, retrying connection failures like [`OpenAIClient::post`]
+    pub(crate) async fn get(&self, path: &str) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, path);
+        self.send(&url, || self.client.get(&url)).await
+    }
+
+    async fn send(
+        &self,
+        url: &str,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            let request = match self.timeout {
+                Some(timeout) => build().timeout(timeout),
+                None => build(),
+            };
+            match request.send().await {
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_connect() => {
+                    if attempt >= self.max_attempts {
+                        return Err(Error::Upstream {
+                            attempts: attempt,
+                            last: e.to_string(),
+                        });
+                    }
+                    let delay = RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+                    warn!("Connection to {} failed (attempt {}), retrying in {:?}", url, attempt, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                Err(e) if e.is_timeout() => {
+                    warn!("Request to {} timed out after {:?}", url, self.timeout.unwrap_or_default());
+                    return Err(Error::InferenceFailed(format!(
+                        "request timed out after {:?}",
+                        self.timeout.unwrap_or_default()
+                    )));
+                }
+                Err(e) => {
+                    return Err(Error::ModelLoadFailed(format!("OpenAI API request failed: {}", e)));
+                }
+            }
         }
+    }
 
-        response
-            .json()
-            .await
-            .map_err(|e| Error::ModelLoadFailed(format!("Failed to parse response: {}", e)))
+    /// Create completion
+    pub async fn create_completion(&self, request: CompletionRequest) -> Result<CompletionResponse> {
+        let response = self.post("/v1/completions", &request).await?;
+
+        let response = self.check_status(response).await?;
+
+        Self::parse_json(response).await
     }
 
     /// Create chat completion
     pub async fn create_chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
-        let url = format!("{}/v1/chat/completions", self.base_url);
+        let response = self.post("/v1/chat/completions", &request).await?;
 
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| Error::ModelLoadFailed(format!("OpenAI API request failed: {}", e)))?;
+        let response = self.check_status(response).await?;
+
+        Self::parse_json(response).await
+    }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            return Err(Error::ModelLoadFailed(format!(
-                "OpenAI API error ({}): {}",
-                status, text
+    /// Create embeddings, one per input in request order
+    ///
+    /// vLLM answers 400 when the served model isn't an embedding model; that
+    /// becomes an `InvalidRequest` carrying vLLM's explanation.
+    pub async fn create_embeddings(&self, request: EmbeddingRequest) -> Result<EmbeddingResponse> {
+        let response = self.post("/v1/embeddings", &request).await?;
+
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            let body = response.text().await.unwrap_or_default();
+            return Err(Error::InvalidRequest(format!(
+                "{} can't produce embeddings (is it an embedding model?): {}",
+                request.model,
+                error_message(&body)
             )));
         }
+        let response = self.check_status(response).await?;
 
-        response
-            .json()
-            .await
-            .map_err(|e| Error::ModelLoadFailed(format!("Failed to parse response: {}", e)))
+        Self::parse_json(response).await
     }
 
     /// Create streaming completion
@@ -77,31 +252,34 @@ impl OpenAIClient {
         &self,
         request: CompletionRequest,
     ) -> Result<impl futures::Stream<Item = Result<CompletionChunk>>> {
-        let url = format!("{}/v1/completions", self.base_url);
+        let response = self.post("/v1/completions", &request).await?;
 
-        let response = self.client
-            .post(&url)
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| Error::ModelLoadFailed(format!("OpenAI API request failed: {}", e)))?;
+        let response = self.check_status(response).await?;
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let text = response.text().await.unwrap_or_default();
-            return Err(Error::ModelLoadFailed(format!(
-                "OpenAI API error ({}): {}",
-                status, text
-            )));
-        }
+        Ok(Self::parse_sse_stream(response))
+    }
+
+    /// Create streaming chat completion
+    ///
+    /// Deltas carry `reasoning_content` separately from `content` when vLLM
+    /// runs with a reasoning parser (R1/QwQ-style models).
+    pub async fn create_chat_completion_stream(
+        &self,
+        mut request: ChatCompletionRequest,
+    ) -> Result<impl futures::Stream<Item = Result<ChatCompletionChunk>>> {
+        request.stream = Some(true);
+
+        let response = self.post("/v1/chat/completions", &request).await?;
+
+        let response = self.check_status(response).await?;
 
         Ok(Self::parse_sse_stream(response))
     }
 
-    /// Parse SSE stream into completion chunks
-    fn parse_sse_stream(
+    /// Parse SSE stream into chunks
+    pub(crate) fn parse_sse_stream<T: DeserializeOwned>(
         response: reqwest::Response,
-    ) -> impl futures::Stream<Item = Result<CompletionChunk>> {
+    ) -> impl futures::Stream<Item = Result<T>> {
         use futures::stream::StreamExt;
 
         response
@@ -118,7 +296,7 @@ impl OpenAIClient {
                                 if data == "[DONE]" {
                                     return None;
                                 }
-                                match serde_json::from_str::<CompletionChunk>(data) {
+                                match serde_json::from_str::<T>(data) {
                                     Ok(chunk) => return Some(Ok(chunk)),
                                     Err(e) => {
                                         return Some(Err(Error::ModelLoadFailed(format!(
@@ -136,11 +314,87 @@ impl OpenAIClient {
             })
     }
 
+    /// Turn an error status into a typed error, passing successes through
+    pub(crate) async fn check_status(&self, response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let message = error_message(&body);
+
+        match status.as_u16() {
+            429 => return Err(Error::RateLimited),
+            404 if message.contains("does not exist") => {
+                return Err(Error::ModelNotServed {
+                    available: self.list_models().await.unwrap_or_default(),
+                });
+            }
+            400 => {
+                if let Some((max, requested)) = parse_context_length_error(&message) {
+                    return Err(Error::ContextLengthExceeded { max, requested });
+                }
+            }
+            _ => {}
+        }
+
+        Err(Error::ModelLoadFailed(format!(
+            "OpenAI API error ({}): {}",
+            status,
+            body_snippet(&body)
+        )))
+    }
+
+    /// IDs of the models the backend serves
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct ModelList {
+            data: Vec<ModelEntry>,
+        }
+
+        #[derive(Deserialize)]
+        struct ModelEntry {
+            id: String,
+        }
+
+        let url = format!("{}/v1/models", self.base_url);
+        let response = self.client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| Error::ModelLoadFailed(format!("OpenAI API request failed: {}", e)))?;
+
+        let list: ModelList = Self::parse_json(response).await?;
+        Ok(list.data.into_iter().map(|m| m.id).collect())
+    }
+
+    /// Read a response body as JSON, keeping the raw body for diagnostics
+    ///
+    /// A reverse proxy in front of vLLM will often answer with an HTML error
+    /// page; reporting the status, content type and a snippet of the body
+    /// makes that obvious instead of surfacing a bare serde error.
+    pub(crate) async fn parse_json<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| Error::InferenceFailed(format!("Failed to read response body: {}", e)))?;
+
+        decode_body(status.as_u16(), content_type.as_deref(), &body)
+    }
+
     /// Health check
     pub async fn health(&self) -> Result<bool> {
         let url = format!("{}/health", self.base_url);
 
-        match self.client.get(&url).send().await {
+        match self.client.get(&url).timeout(HEALTH_TIMEOUT).send().await {
             Ok(response) => Ok(response.status().is_success()),
             Err(_) => Ok(false),
         }
@@ -157,14 +411,52 @@ pub struct CompletionRequest {
     pub prompt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
+    /// vLLM extension; minimum tokens before EOS/stop may end generation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    /// vLLM extension; -1 disables top-k filtering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    /// vLLM extension; minimum probability relative to the top token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    /// Penalize tokens by how often they already appear (-2.0 to 2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Penalize tokens that already appear at all (-2.0 to 2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// vLLM extension; multiplicative penalty on repeated tokens (1.0 = off)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default, deserialize_with = "crate::request::optional_stop_sequences", skip_serializing_if = "Option::is_none")]
     pub stop: Option<Vec<String>>,
+    /// vLLM extension; whether a matched `stop` string stays in the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_stop_str_in_output: Option<bool>,
+    /// vLLM guided decoding, sent as `guided_json`/`guided_regex`/`guided_choice`
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub guided: Option<crate::GuidedDecoding>,
+    /// JSON mode or a JSON schema; not combined with `guided`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<crate::ResponseFormat>,
+}
+
+/// Streaming options; `include_usage` asks for a final chunk carrying token usage
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamOptions {
+    #[serde(default)]
+    pub include_usage: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -191,6 +483,9 @@ pub struct CompletionChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<CompletionChoiceChunk>,
+    /// Only present on the final chunk when `stream_options.include_usage` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -206,12 +501,43 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
+    /// vLLM extension; minimum tokens before EOS/stop may end generation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    /// vLLM extension; -1 disables top-k filtering
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<i32>,
+    /// vLLM extension; minimum probability relative to the top token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    /// Penalize tokens by how often they already appear (-2.0 to 2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    /// Penalize tokens that already appear at all (-2.0 to 2.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// vLLM extension; multiplicative penalty on repeated tokens (1.0 = off)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
+    #[serde(default, deserialize_with = "crate::request::optional_stop_sequences", skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// vLLM extension; whether a matched `stop` string stays in the output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_stop_str_in_output: Option<bool>,
+    /// vLLM guided decoding, sent as `guided_json`/`guided_regex`/`guided_choice`
+    #[serde(flatten, default, skip_serializing_if = "Option::is_none")]
+    pub guided: Option<crate::GuidedDecoding>,
+    /// JSON mode or a JSON schema; not combined with `guided`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<crate::ResponseFormat>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -220,6 +546,51 @@ pub struct ChatMessage {
     pub content: String,
 }
 
+impl From<crate::ChatMessage> for ChatMessage {
+    fn from(message: crate::ChatMessage) -> Self {
+        use crate::ChatRole;
+        let role = match message.role {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+            ChatRole::Tool => "tool",
+        };
+        Self {
+            role: role.to_string(),
+            content: message.content,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub created: u64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    pub index: usize,
+    pub delta: ChatDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChatDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// Thinking trace, split out by vLLM's reasoning parser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
     pub id: String,
@@ -237,9 +608,32 @@ pub struct ChatCompletionChoice {
     pub finish_reason: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingRequest {
+    pub model: String,
+    pub input: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingResponse {
+    pub model: String,
+    pub data: Vec<EmbeddingData>,
+    /// Tokens in the inputs, when the server reports them
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingData {
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Usage {
     pub prompt_tokens: usize,
+    /// Absent (0) for embeddings
+    #[serde(default)]
     pub completion_tokens: usize,
     pub total_tokens: usize,
 }
@@ -252,6 +646,9 @@ mod tests {
     fn test_client_creation() {
         let client = OpenAIClient::new("http://localhost:8100");
         assert_eq!(client.base_url, "http://localhost:8100");
+
+        let client = OpenAIClient::new("https://vllm.internal/").with_accept_invalid_certs(true);
+        assert_eq!(client.base_url, "https://vllm.internal");
     }
 
     #[test]
@@ -260,14 +657,159 @@ mod tests {
             model: "test-model".to_string(),
             prompt: "Hello".to_string(),
             max_tokens: Some(50),
+            min_tokens: None,
+            seed: None,
             temperature: Some(0.7),
             top_p: Some(0.9),
+            top_k: None,
+            min_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            repetition_penalty: None,
+            guided: None,
+            response_format: None,
             stream: Some(false),
+            stream_options: None,
             stop: None,
+            include_stop_str_in_output: None,
         };
 
         let json = serde_json::to_string(&request).unwrap();
         assert!(json.contains("test-model"));
         assert!(json.contains("Hello"));
+        assert!(!json.contains("stream_options"));
+        assert!(!json.contains("min_tokens"));
+    }
+
+    #[tokio::test]
+    async fn test_hung_backend_times_out() {
+        // Accepts connections but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let client = OpenAIClient::new(url).with_timeout(std::time::Duration::from_millis(100));
+        let request = CompletionRequest {
+            model: "m".to_string(),
+            prompt: "hi".to_string(),
+            max_tokens: None,
+            min_tokens: None,
+            seed: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            min_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            repetition_penalty: None,
+            guided: None,
+            response_format: None,
+            stream: None,
+            stream_options: None,
+            stop: None,
+            include_stop_str_in_output: None,
+        };
+
+        match client.create_completion(request).await {
+            Err(Error::InferenceFailed(message)) => assert_eq!(message, "request timed out after 100ms"),
+            other => panic!("expected a timeout, got {:?}", other.map(|r| r.id)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_refused_is_upstream_error() {
+        // Port 1 is reserved and nothing listens on it
+        let client = OpenAIClient::new("http://127.0.0.1:1").with_max_attempts(2);
+        let request = CompletionRequest {
+            model: "m".to_string(),
+            prompt: "hi".to_string(),
+            max_tokens: None,
+            min_tokens: None,
+            seed: None,
+            temperature: None,
+            top_p: None,
+            top_k: None,
+            min_p: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            repetition_penalty: None,
+            guided: None,
+            response_format: None,
+            stream: None,
+            stream_options: None,
+            stop: None,
+            include_stop_str_in_output: None,
+        };
+
+        match client.create_completion(request).await {
+            Err(Error::Upstream { attempts, .. }) => assert_eq!(attempts, 2),
+            other => panic!("expected Upstream error, got {:?}", other.map(|r| r.id)),
+        }
+    }
+
+    #[test]
+    fn test_decode_body_non_json() {
+        let html = "<html><body>502 Bad Gateway</body></html>";
+        let err = decode_body::<Usage>(502, Some("text/html"), html).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("non-JSON response"));
+        assert!(msg.contains("status 502"));
+        assert!(msg.contains("text/html"));
+        assert!(msg.contains("502 Bad Gateway"));
+    }
+
+    #[test]
+    fn test_decode_body_malformed_json() {
+        let err = decode_body::<Usage>(200, Some("application/json"), r#"{"unexpected": 1}"#).unwrap_err();
+        assert!(err.to_string().contains("Failed to parse backend response"));
+    }
+
+    #[test]
+    fn test_parse_context_length_error() {
+        let body = r#"{"object":"error","message":"This model's maximum context length is 2048 tokens. However, you requested 3000 tokens (2900 in the messages, 100 in the completion). Please reduce the length of the messages or completion.","type":"BadRequestError","code":400}"#;
+        assert_eq!(parse_context_length_error(&error_message(body)), Some((2048, 3000)));
+
+        let newer = "'max_tokens' or 'max_completion_tokens' is too large: 500. This model's maximum context length is 2048 tokens and your request has 1600 input tokens (500 > 2048 - 1600).";
+        assert_eq!(parse_context_length_error(newer), Some((2048, 2100)));
+
+        assert_eq!(parse_context_length_error("something else"), None);
+    }
+
+    #[test]
+    fn test_error_message_shapes() {
+        assert_eq!(error_message(r#"{"error":{"message":"nested"}}"#), "nested");
+        assert_eq!(error_message(r#"{"message":"flat"}"#), "flat");
+        assert_eq!(error_message(r#"{"error":"plain"}"#), "plain");
+        assert_eq!(error_message("<html>"), "<html>");
+    }
+
+    #[test]
+    fn test_body_snippet_truncates() {
+        let long = "x".repeat(BODY_SNIPPET_LEN + 50);
+        let snippet = body_snippet(&long);
+        assert_eq!(snippet.len(), BODY_SNIPPET_LEN + 3);
+        assert!(snippet.ends_with("..."));
+        assert_eq!(body_snippet("short"), "short");
+    }
+
+    #[test]
+    fn test_chat_chunk_reasoning_deserialization() {
+        let data = r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1,"model":"m","choices":[{"index":0,"delta":{"reasoning_content":"hmm"},"finish_reason":null}]}"#;
+        let chunk: ChatCompletionChunk = serde_json::from_str(data).unwrap();
+        assert_eq!(chunk.choices[0].delta.reasoning_content.as_deref(), Some("hmm"));
+        assert!(chunk.choices[0].delta.content.is_none());
+    }
+
+    #[test]
+    fn test_usage_chunk_deserialization() {
+        let data = r#"{"id":"cmpl-1","object":"text_completion","created":1,"model":"m","choices":[],"usage":{"prompt_tokens":5,"completion_tokens":7,"total_tokens":12}}"#;
+        let chunk: CompletionChunk = serde_json::from_str(data).unwrap();
+        assert!(chunk.choices.is_empty());
+        assert_eq!(chunk.usage.unwrap().total_tokens, 12);
     }
 }
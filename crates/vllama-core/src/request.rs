@@ -1,5 +1,7 @@
 use crate::types::RequestId;
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -49,11 +51,25 @@ pub struct SamplingParams {
     pub temperature: f32,
     pub top_p: f32,
     pub top_k: Option<u32>,
+    /// Drop tokens below this fraction of the top token's probability
+    #[serde(default)]
+    pub min_p: Option<f32>,
     pub repetition_penalty: f32,
     pub frequency_penalty: f32,
     pub presence_penalty: f32,
     pub max_tokens: Option<usize>,
+    /// Don't stop (EOS or stop sequence) before this many tokens
+    #[serde(default)]
+    pub min_tokens: Option<usize>,
+    /// Sampling seed; the same seed and prompt give the same output
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(deserialize_with = "stop_sequences")]
     pub stop_sequences: Vec<String>,
+    /// Keep the matched stop sequence at the end of the output; it is
+    /// trimmed by default, as in OpenAI's API
+    #[serde(default)]
+    pub include_stop_str_in_output: bool,
 }
 
 impl Default for SamplingParams {
@@ -62,15 +78,45 @@ impl Default for SamplingParams {
             temperature: 0.7,
             top_p: 0.9,
             top_k: None,
+            min_p: None,
             repetition_penalty: 1.0,
             frequency_penalty: 0.0,
             presence_penalty: 0.0,
             max_tokens: None,
+            min_tokens: None,
+            seed: None,
             stop_sequences: Vec::new(),
+            include_stop_str_in_output: false,
         }
     }
 }
 
+impl SamplingParams {
+    /// Reject parameter combinations the backend can't satisfy
+    pub fn validate(&self) -> crate::Result<()> {
+        if let (Some(min), Some(max)) = (self.min_tokens, self.max_tokens) {
+            if min > max {
+                return Err(crate::Error::InvalidRequest(format!(
+                    "min_tokens ({}) must not exceed max_tokens ({})",
+                    min, max
+                )));
+            }
+        }
+        for (name, value) in [
+            ("frequency_penalty", self.frequency_penalty),
+            ("presence_penalty", self.presence_penalty),
+        ] {
+            if !(-2.0..=2.0).contains(&value) {
+                return Err(crate::Error::InvalidRequest(format!(
+                    "{} must be between -2.0 and 2.0 (got {})",
+                    name, value
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[derive(Default)]
 pub struct GenerateOptions {
@@ -78,6 +124,65 @@ pub struct GenerateOptions {
     pub sampling: SamplingParams,
     pub return_logprobs: bool,
     pub echo_prompt: bool,
+    /// Ask the backend to report token usage at the end of a stream
+    #[serde(default)]
+    pub include_usage: bool,
+    /// Constrain the output to a schema, pattern or fixed set of answers
+    #[serde(default)]
+    pub guided: Option<GuidedDecoding>,
+    /// OpenAI-style output format, e.g. JSON mode
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// vLLM guided decoding; serialized as the matching `guided_*` field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GuidedDecoding {
+    /// Output must be JSON matching this schema
+    #[serde(rename = "guided_json")]
+    Json(serde_json::Value),
+    /// Output must match this regular expression
+    #[serde(rename = "guided_regex")]
+    Regex(String),
+    /// Output must be exactly one of these strings
+    #[serde(rename = "guided_choice")]
+    Choice(Vec<String>),
+}
+
+/// OpenAI `response_format`, which vLLM enforces with guided decoding
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Unconstrained; the default
+    Text,
+    /// Any valid JSON object
+    JsonObject,
+    /// JSON matching a schema: `{"name": ..., "schema": {...}}`
+    JsonSchema { json_schema: serde_json::Value },
+}
+
+impl GuidedDecoding {
+    /// The single guided mode among a request's `guided_*` fields, if any
+    pub fn from_fields(
+        json: Option<serde_json::Value>,
+        regex: Option<String>,
+        choice: Option<Vec<String>>,
+    ) -> crate::Result<Option<Self>> {
+        let modes = [json.is_some(), regex.is_some(), choice.is_some()];
+        if modes.iter().filter(|set| **set).count() > 1 {
+            return Err(crate::Error::InvalidRequest(
+                "only one of guided_json, guided_regex and guided_choice may be set".to_string(),
+            ));
+        }
+
+        if let Some(choice) = choice {
+            if choice.is_empty() {
+                return Err(crate::Error::InvalidRequest("guided_choice must not be empty".to_string()));
+            }
+            return Ok(Some(Self::Choice(choice)));
+        }
+        Ok(json.map(Self::Json).or(regex.map(Self::Regex)))
+    }
 }
 
 
@@ -171,3 +276,83 @@ impl ChatRequest {
             .join("\n\n")
     }
 }
+
+/// Stop sequences given as one string or a list of them, as OpenAI's
+/// `stop` allows; use with `#[serde(deserialize_with)]`
+pub fn stop_sequences<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(optional_stop_sequences(deserializer)?.unwrap_or_default())
+}
+
+/// [`stop_sequences`] for an optional field; null is None
+///
+/// Needs `#[serde(default)]` as well so the field may be left out.
+pub fn optional_stop_sequences<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<String>>, D::Error> {
+    struct StopVisitor;
+
+    impl<'de> Visitor<'de> for StopVisitor {
+        type Value = Option<Vec<String>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string or a list of strings")
+        }
+
+        fn visit_str<E: de::Error>(self, stop: &str) -> Result<Self::Value, E> {
+            Ok(Some(vec![stop.to_string()]))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut stops = Vec::new();
+            while let Some(stop) = seq.next_element()? {
+                stops.push(stop);
+            }
+            Ok(Some(stops))
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+    }
+
+    deserializer.deserialize_option(StopVisitor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Stops {
+        #[serde(default, deserialize_with = "optional_stop_sequences")]
+        stop: Option<Vec<String>>,
+    }
+
+    fn parse(json: &str) -> Result<Option<Vec<String>>, serde_json::Error> {
+        serde_json::from_str::<Stops>(json).map(|s| s.stop)
+    }
+
+    #[test]
+    fn test_stop_string_or_list() {
+        assert_eq!(parse(r#"{"stop": "\n\n"}"#).unwrap(), Some(vec!["\n\n".to_string()]));
+        assert_eq!(parse(r#"{"stop": ["</s>", "User:"]}"#).unwrap(), Some(vec!["</s>".to_string(), "User:".to_string()]));
+        assert_eq!(parse(r#"{"stop": null}"#).unwrap(), None);
+        assert_eq!(parse("{}").unwrap(), None);
+
+        let err = parse(r#"{"stop": 4}"#).unwrap_err();
+        assert!(err.to_string().contains("a string or a list of strings"));
+        assert!(parse(r#"{"stop": ["a", 1]}"#).is_err());
+
+        // SamplingParams round-trips its list and takes a bare string too
+        let mut sampling = serde_json::to_value(SamplingParams::default()).unwrap();
+        sampling["stop_sequences"] = serde_json::json!("END");
+        let sampling: SamplingParams = serde_json::from_value(sampling).unwrap();
+        assert_eq!(sampling.stop_sequences, vec!["END"]);
+    }
+}
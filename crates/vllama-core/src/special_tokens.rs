@@ -0,0 +1,215 @@
+//! Special token stripping
+//!
+//! Depending on the tokenizer and `skip_special_tokens` handling, vLLM can
+//! emit end-of-turn markers such as `<|eot_id|>` as plain text. This module
+//! derives a model's stop tokens from its cached `tokenizer_config.json` and
+//! `generation_config.json` and removes them from the end of generated text.
+//!
+//! Only the model's own tokens are stripped, and only where they end the
+//! reply: `</s>` in generated HTML or `<|im_end|>` in code about ChatML is
+//! left alone.
+
+use serde_json::Value;
+
+use crate::model::read_cached_json;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecialTokens {
+    tokens: Vec<String>,
+}
+
+impl SpecialTokens {
+    /// Tokens for a model in the local HuggingFace cache
+    ///
+    /// Never touches the network; empty (nothing is stripped) when the
+    /// model's config files aren't cached.
+    pub fn for_model(repo_id: &str) -> Self {
+        Self::from_configs(
+            read_cached_json(repo_id, "tokenizer_config.json"),
+            read_cached_json(repo_id, "generation_config.json"),
+        )
+    }
+
+    /// Derive stop tokens from parsed tokenizer and generation configs
+    ///
+    /// Uses the tokenizer's `eos_token`, plus every `eos_token_id` listed in
+    /// the generation config (Llama 3 lists several) resolved through the
+    /// tokenizer's `added_tokens_decoder`.
+    pub fn from_configs(tokenizer_config: Option<Value>, generation_config: Option<Value>) -> Self {
+        let mut tokens = tokenizer_config
+            .as_ref()
+            .map(|tokenizer| model_eos_tokens(tokenizer, generation_config.as_ref()))
+            .unwrap_or_default();
+
+        tokens.retain(|t| !t.is_empty());
+        tokens.sort();
+        tokens.dedup();
+
+        Self { tokens }
+    }
+
+    pub fn tokens(&self) -> &[String] {
+        &self.tokens
+    }
+
+    /// Remove the special tokens ending `text`; occurrences elsewhere are kept
+    pub fn strip(&self, text: &str) -> String {
+        let mut text = text;
+        while let Some(rest) = self.tokens.iter().find_map(|token| text.strip_suffix(token.as_str())) {
+            text = rest;
+        }
+        text.to_string()
+    }
+
+    /// Whether `text` could be the end of a reply made of special tokens:
+    /// whole tokens followed by the start of one
+    fn could_trail(&self, text: &str) -> bool {
+        text.is_empty()
+            || self.tokens.iter().any(|token| token.starts_with(text))
+            || self
+                .tokens
+                .iter()
+                .any(|token| text.strip_prefix(token.as_str()).is_some_and(|rest| self.could_trail(rest)))
+    }
+}
+
+/// Strips a model's special tokens from the end of a streamed reply
+///
+/// A token may be split across deltas, and one that isn't the end of the
+/// reply must be kept, so text that could still turn out to be trailing
+/// tokens is held back until the next delta or the end of the stream.
+#[derive(Debug)]
+pub struct SpecialTokenFilter {
+    tokens: std::sync::Arc<SpecialTokens>,
+    pending: String,
+}
+
+impl SpecialTokenFilter {
+    pub fn new(tokens: std::sync::Arc<SpecialTokens>) -> Self {
+        Self {
+            tokens,
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a delta, returning the text that is safe to emit; `finished`
+    /// marks the last delta, which flushes what was held back
+    pub fn push(&mut self, delta: &str, finished: bool) -> String {
+        let mut buf = std::mem::take(&mut self.pending);
+        buf.push_str(delta);
+        if finished {
+            return self.tokens.strip(&buf);
+        }
+
+        let cut = (0..=buf.len())
+            .find(|&i| buf.is_char_boundary(i) && self.tokens.could_trail(&buf[i..]))
+            .unwrap_or(buf.len());
+        self.pending = buf.split_off(cut);
+        buf
+    }
+
+    /// Everything still held back, for a stream that ended without a
+    /// last delta
+    pub fn flush(&mut self) -> String {
+        self.push("", true)
+    }
+}
+
+/// A model's own end-of-sequence tokens, without the common ones
+///
+/// The tokenizer's `eos_token`, plus every `eos_token_id` listed in the
+/// generation config resolved through the tokenizer's `added_tokens_decoder`.
+pub(crate) fn model_eos_tokens(tokenizer_config: &Value, generation_config: Option<&Value>) -> Vec<String> {
+    let mut tokens = Vec::new();
+    if let Some(eos) = tokenizer_config.get("eos_token").and_then(token_content) {
+        tokens.push(eos);
+    }
+
+    let eos_ids = generation_config
+        .and_then(|g| g.get("eos_token_id"))
+        .map(|ids| match ids {
+            Value::Array(ids) => ids.iter().filter_map(Value::as_u64).collect(),
+            id => id.as_u64().into_iter().collect::<Vec<_>>(),
+        })
+        .unwrap_or_default();
+
+    if let Some(decoder) = tokenizer_config.get("added_tokens_decoder").and_then(Value::as_object) {
+        for id in eos_ids {
+            if let Some(content) = decoder.get(&id.to_string()).and_then(token_content) {
+                tokens.push(content);
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Token text from either `"<tok>"` or `{"content": "<tok>", ...}`
+pub(crate) fn token_content(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Object(obj) => obj.get("content").and_then(Value::as_str).map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tokens(list: &[&str]) -> SpecialTokens {
+        SpecialTokens {
+            tokens: list.iter().map(|t| t.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_strip_trailing_special_token() {
+        let tokens = tokens(&["<|eot_id|>", "<|end_of_text|>"]);
+        assert_eq!(tokens.strip("The answer is 4.<|eot_id|>"), "The answer is 4.");
+        assert_eq!(tokens.strip("Done<|eot_id|><|end_of_text|>"), "Done");
+        assert_eq!(tokens.strip("plain text"), "plain text");
+        // Only the end of the reply is touched
+        assert_eq!(tokens.strip("Write <|eot_id|> to end a turn"), "Write <|eot_id|> to end a turn");
+    }
+
+    #[test]
+    fn test_unknown_model_strips_nothing() {
+        let tokens = SpecialTokens::from_configs(None, None);
+        assert!(tokens.tokens().is_empty());
+        assert_eq!(tokens.strip("<p>a</p><s>b</s>"), "<p>a</p><s>b</s>");
+    }
+
+    #[test]
+    fn test_filter_split_token() {
+        let mut filter = SpecialTokenFilter::new(std::sync::Arc::new(tokens(&["<|eot_id|>"])));
+        let mut out = String::new();
+        for delta in ["Hi <|eo", "t_id|> and <|e", "ot_", "id|>"] {
+            out.push_str(&filter.push(delta, false));
+        }
+        // The last token might still end the reply
+        assert_eq!(out, "Hi <|eot_id|> and ");
+        out.push_str(&filter.push("", true));
+        assert_eq!(out, "Hi <|eot_id|> and ");
+
+        let mut filter = SpecialTokenFilter::new(std::sync::Arc::new(tokens(&["</s>"])));
+        let out = filter.push("a </", false) + &filter.push("s> b", false) + &filter.push("", true);
+        assert_eq!(out, "a </s> b");
+    }
+
+    #[test]
+    fn test_tokens_from_configs() {
+        let tokenizer = json!({
+            "eos_token": {"content": "<|custom_eos|>", "special": true},
+            "added_tokens_decoder": {
+                "128009": {"content": "<|custom_eot|>", "special": true}
+            }
+        });
+        let generation = json!({"eos_token_id": [128001, 128009]});
+
+        let tokens = SpecialTokens::from_configs(Some(tokenizer), Some(generation));
+        assert_eq!(tokens.tokens(), ["<|custom_eos|>", "<|custom_eot|>"]);
+        assert_eq!(tokens.strip("Hi<|custom_eot|>"), "Hi");
+    }
+}
@@ -0,0 +1,559 @@
+//! Chat prompt templates
+//!
+//! Paths that send a raw prompt to vLLM's completions endpoint (streaming
+//! chat, OpenAI chat on engines without a chat API) have to render the
+//! conversation themselves. The model's own Jinja `chat_template` is used
+//! when its `tokenizer_config.json` has one; otherwise a built-in format is
+//! picked from the special tokens in `tokenizer_config.json` /
+//! `generation_config.json`, with the model name as a fallback when those
+//! files aren't available.
+
+use minijinja::ErrorKind;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::model::ModelSource;
+use crate::special_tokens::{model_eos_tokens, token_content};
+use crate::{ChatMessage, ChatRole, Error};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatTemplate {
+    /// `<|start_header_id|>role<|end_header_id|>` ... `<|eot_id|>`
+    Llama3,
+    /// `<|im_start|>role` ... `<|im_end|>` (Qwen and others)
+    ChatMl,
+    /// `[INST]` ... `[/INST]` (Mistral, Mixtral)
+    Mistral,
+    /// `<start_of_turn>role` ... `<end_of_turn>` (Gemma)
+    Gemma,
+    /// `<|role|>` ... `<|end|>` (Phi-3)
+    Phi,
+    /// `System:` / `User:` / `Assistant:` lines for models without a known format
+    Plain,
+    /// The model's own Jinja `chat_template`
+    Jinja(JinjaTemplate),
+}
+
+/// A `chat_template` from a model's tokenizer config
+#[derive(Debug, Clone)]
+pub struct JinjaTemplate {
+    source: String,
+    /// [`environment`] with `source` compiled in as [`JinjaTemplate::NAME`]
+    env: Arc<minijinja::Environment<'static>>,
+    eos_token: String,
+    /// Built-in format used if the template fails to render
+    fallback: Box<ChatTemplate>,
+}
+
+impl PartialEq for JinjaTemplate {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source && self.eos_token == other.eos_token && self.fallback == other.fallback
+    }
+}
+
+impl Eq for JinjaTemplate {}
+
+impl JinjaTemplate {
+    const NAME: &'static str = "chat_template";
+
+    /// Compile `source`, failing if it isn't a valid template
+    fn new(source: String, eos_token: String, fallback: ChatTemplate) -> Result<Self, minijinja::Error> {
+        let mut env = environment();
+        env.add_template_owned(Self::NAME, source.clone())?;
+        Ok(Self {
+            source,
+            env: Arc::new(env),
+            eos_token,
+            fallback: Box::new(fallback),
+        })
+    }
+
+    fn render(&self, messages: &[ChatMessage]) -> Result<String, minijinja::Error> {
+        // vLLM's tokenizer adds the BOS token itself, so templates that
+        // start with `{{ bos_token }}` mustn't add a second one
+        let context = minijinja::context! {
+            messages => messages,
+            add_generation_prompt => true,
+            bos_token => "",
+            eos_token => self.eos_token,
+        };
+        self.env.get_template(Self::NAME)?.render(context)
+    }
+}
+
+impl ChatTemplate {
+    /// Short lowercase name, as shown by `vllama export`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChatTemplate::Llama3 => "llama3",
+            ChatTemplate::ChatMl => "chatml",
+            ChatTemplate::Mistral => "mistral",
+            ChatTemplate::Gemma => "gemma",
+            ChatTemplate::Phi => "phi",
+            ChatTemplate::Plain => "plain",
+            ChatTemplate::Jinja(_) => "jinja",
+        }
+    }
+
+    /// Render `messages` as a prompt that ends where the assistant's reply starts
+    pub fn render(&self, messages: &[ChatMessage]) -> String {
+        match self {
+            // No <|begin_of_text|>: vLLM's tokenizer adds the BOS token itself
+            ChatTemplate::Llama3 => {
+                let mut prompt = String::new();
+                for msg in messages {
+                    let role = match msg.role {
+                        ChatRole::Tool => "ipython",
+                        _ => role_name(&msg.role),
+                    };
+                    prompt.push_str(&format!(
+                        "<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>",
+                        role, msg.content
+                    ));
+                }
+                prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+                prompt
+            }
+            ChatTemplate::ChatMl => {
+                let mut prompt = String::new();
+                for msg in messages {
+                    prompt.push_str(&format!(
+                        "<|im_start|>{}\n{}<|im_end|>\n",
+                        role_name(&msg.role),
+                        msg.content
+                    ));
+                }
+                prompt.push_str("<|im_start|>assistant\n");
+                prompt
+            }
+            // No system role: the system prompt goes in front of the next user turn
+            ChatTemplate::Mistral => {
+                let mut prompt = String::new();
+                for (role, content) in fold_system(messages) {
+                    if role == ChatRole::Assistant {
+                        prompt.push_str(&format!("{}</s>", content));
+                    } else {
+                        prompt.push_str(&format!("[INST] {} [/INST]", content));
+                    }
+                }
+                prompt
+            }
+            ChatTemplate::Gemma => {
+                let mut prompt = String::new();
+                for (role, content) in fold_system(messages) {
+                    let role = if role == ChatRole::Assistant { "model" } else { "user" };
+                    prompt.push_str(&format!("<start_of_turn>{}\n{}<end_of_turn>\n", role, content));
+                }
+                prompt.push_str("<start_of_turn>model\n");
+                prompt
+            }
+            ChatTemplate::Phi => {
+                let mut prompt = String::new();
+                for msg in messages {
+                    let role = match msg.role {
+                        ChatRole::Tool => "user",
+                        _ => role_name(&msg.role),
+                    };
+                    prompt.push_str(&format!("<|{}|>\n{}<|end|>\n", role, msg.content));
+                }
+                prompt.push_str("<|assistant|>\n");
+                prompt
+            }
+            ChatTemplate::Plain => messages
+                .iter()
+                .map(|msg| match msg.role {
+                    ChatRole::System => format!("System: {}", msg.content),
+                    ChatRole::User => format!("User: {}", msg.content),
+                    ChatRole::Assistant => format!("Assistant: {}", msg.content),
+                    ChatRole::Tool => format!("Tool: {}", msg.content),
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            ChatTemplate::Jinja(template) => template.render(messages).unwrap_or_else(|e| {
+                tracing::warn!("Model chat_template failed to render, using {}: {}", template.fallback.name(), e);
+                template.fallback.render(messages)
+            }),
+        }
+    }
+
+    /// Template for the model whose `tokenizer_config.json` is at `path`
+    ///
+    /// Uses the model's `chat_template` (or a `chat_template.jinja` next to
+    /// the config) when there is one that compiles, and otherwise the
+    /// built-in format chosen by [`template_from_configs`], which is also
+    /// the fallback if the Jinja template fails on a conversation. Fails if
+    /// the config can't be read or parsed.
+    pub fn from_tokenizer_config(path: &Path) -> crate::Result<ChatTemplate> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::ConfigError(format!("failed to read {}: {}", path.display(), e)))?;
+        let tokenizer_config: Value = serde_json::from_str(&contents)
+            .map_err(|e| Error::ConfigError(format!("invalid {}: {}", path.display(), e)))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let generation_config = read_json(&dir.join("generation_config.json"));
+        let builtin = template_from_configs(&tokenizer_config, generation_config.as_ref());
+
+        let source = std::fs::read_to_string(dir.join("chat_template.jinja"))
+            .ok()
+            .or_else(|| config_chat_template(&tokenizer_config));
+        let Some(source) = source else {
+            return Ok(builtin);
+        };
+        let eos_token = tokenizer_config.get("eos_token").and_then(token_content).unwrap_or_default();
+        match JinjaTemplate::new(source, eos_token, builtin.clone()) {
+            Ok(template) => Ok(ChatTemplate::Jinja(template)),
+            Err(e) => {
+                tracing::warn!("Ignoring chat_template in {}: {}", path.display(), e);
+                Ok(builtin)
+            }
+        }
+    }
+}
+
+/// `chat_template` from a tokenizer config: a string, or a list of named
+/// templates from which the default one is taken
+fn config_chat_template(tokenizer_config: &Value) -> Option<String> {
+    match tokenizer_config.get("chat_template")? {
+        Value::String(source) => Some(source.clone()),
+        Value::Array(templates) => templates
+            .iter()
+            .find(|t| t.get("name").and_then(Value::as_str) == Some("default"))
+            .and_then(|t| t.get("template"))
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        _ => None,
+    }
+}
+
+/// minijinja environment with the helpers HuggingFace chat templates use
+///
+/// Adds `raise_exception` and, through minijinja-contrib's `pycompat`, the
+/// Python string and dict methods templates commonly call (`strip`,
+/// `startswith`, `items`, ...).
+fn environment() -> minijinja::Environment<'static> {
+    let mut env = minijinja::Environment::new();
+    env.add_function("raise_exception", |message: String| -> Result<(), minijinja::Error> {
+        Err(minijinja::Error::new(ErrorKind::InvalidOperation, message))
+    });
+    env.set_unknown_method_callback(minijinja_contrib::pycompat::unknown_method_callback);
+    env
+}
+
+fn role_name(role: &ChatRole) -> &'static str {
+    match role {
+        ChatRole::System => "system",
+        ChatRole::User => "user",
+        ChatRole::Assistant => "assistant",
+        ChatRole::Tool => "tool",
+    }
+}
+
+/// Turns for formats with only user and assistant roles
+///
+/// System messages are prepended to the following user turn and tool
+/// results are sent as user turns.
+fn fold_system(messages: &[ChatMessage]) -> Vec<(ChatRole, String)> {
+    let mut turns = Vec::new();
+    let mut system: Vec<&str> = Vec::new();
+    for msg in messages {
+        match msg.role {
+            ChatRole::System => system.push(&msg.content),
+            ChatRole::Assistant => turns.push((ChatRole::Assistant, msg.content.clone())),
+            ChatRole::User | ChatRole::Tool => {
+                system.push(&msg.content);
+                turns.push((ChatRole::User, system.join("\n\n")));
+                system.clear();
+            }
+        }
+    }
+    // A system prompt with no user turn after it still gets sent
+    if !system.is_empty() {
+        turns.push((ChatRole::User, system.join("\n\n")));
+    }
+    turns
+}
+
+/// Template for `model` (a repo id, short name or local path)
+///
+/// Reads the model's config files from disk or the HuggingFace cache and
+/// falls back to [`get_template_for_model`] when they aren't there.
+pub fn template_for_model(model: &str) -> ChatTemplate {
+    model_dir(model)
+        .and_then(|dir| detect_template(&dir))
+        .unwrap_or_else(|| get_template_for_model(model))
+}
+
+/// Pick the template for the model in `model_dir`
+///
+/// See [`ChatTemplate::from_tokenizer_config`]. Among the built-in formats,
+/// `<|eot_id|>` / `<|begin_of_text|>` mean Llama 3, `<|im_end|>` ChatML,
+/// `<end_of_turn>` Gemma and `<|end|>` Phi. Mistral shares `</s>` with
+/// other models, so it is recognized by `[INST]` in the tokenizer's
+/// `chat_template`. Returns None if the directory has no
+/// `tokenizer_config.json`.
+pub fn detect_template(model_dir: &Path) -> Option<ChatTemplate> {
+    ChatTemplate::from_tokenizer_config(&model_dir.join("tokenizer_config.json")).ok()
+}
+
+/// Pick a built-in template from parsed tokenizer and generation configs
+pub fn template_from_configs(tokenizer_config: &Value, generation_config: Option<&Value>) -> ChatTemplate {
+    let mut tokens = model_eos_tokens(tokenizer_config, generation_config);
+    if let Some(bos) = tokenizer_config.get("bos_token").and_then(token_content) {
+        tokens.push(bos);
+    }
+
+    if tokens.iter().any(|t| t == "<|eot_id|>" || t == "<|begin_of_text|>") {
+        ChatTemplate::Llama3
+    } else if tokens.iter().any(|t| t == "<|im_end|>") {
+        ChatTemplate::ChatMl
+    } else if tokens.iter().any(|t| t == "<end_of_turn>") {
+        ChatTemplate::Gemma
+    } else if tokens.iter().any(|t| t == "<|end|>") {
+        ChatTemplate::Phi
+    } else if tokenizer_config
+        .get("chat_template")
+        .and_then(Value::as_str)
+        .is_some_and(|t| t.contains("[INST]"))
+    {
+        ChatTemplate::Mistral
+    } else {
+        ChatTemplate::Plain
+    }
+}
+
+/// Guess the template from the model name alone
+pub fn get_template_for_model(model: &str) -> ChatTemplate {
+    let name = model.to_lowercase();
+    // Repo name without the org, so "dolphin" isn't taken for Phi
+    let base = name.rsplit('/').next().unwrap_or(&name);
+    if name.contains("llama-3") || name.contains("llama3") {
+        ChatTemplate::Llama3
+    } else if name.contains("qwen") {
+        ChatTemplate::ChatMl
+    } else if name.contains("mistral") || name.contains("mixtral") {
+        ChatTemplate::Mistral
+    } else if name.contains("gemma") {
+        ChatTemplate::Gemma
+    } else if base.starts_with("phi-3") || base.starts_with("phi3") {
+        ChatTemplate::Phi
+    } else {
+        ChatTemplate::Plain
+    }
+}
+
+/// Instructions a caller-supplied template may run before rendering is
+/// stopped; far more than a prompt format needs
+const CUSTOM_TEMPLATE_FUEL: u64 = 1_000_000;
+
+/// Render `messages` with a caller-supplied Jinja template instead of the
+/// model's own format
+///
+/// The template sees `messages` (each with `role` and `content`), `prompt`
+/// (the last user message), `system` (the first system message, if any)
+/// and `add_generation_prompt`, which is always true. A template that
+/// doesn't compile, fails to render or runs out of fuel (a runaway loop)
+/// is an `InvalidRequest`. Rendering is CPU-bound; call it off the async
+/// runtime.
+pub fn render_custom(template: &str, messages: &[ChatMessage]) -> crate::Result<String> {
+    let mut env = environment();
+    env.set_fuel(Some(CUSTOM_TEMPLATE_FUEL));
+    env.add_template("request", template)
+        .map_err(|e| Error::InvalidRequest(format!("invalid template: {}", e)))?;
+
+    let prompt = messages.iter().rev().find(|m| m.role == ChatRole::User).map(|m| m.content.as_str());
+    let system = messages.iter().find(|m| m.role == ChatRole::System).map(|m| m.content.as_str());
+    let context = minijinja::context! {
+        messages => messages,
+        prompt => prompt.unwrap_or_default(),
+        system => system,
+        add_generation_prompt => true,
+    };
+    env.get_template("request")
+        .and_then(|t| t.render(context))
+        .map_err(|e| Error::InvalidRequest(format!("template failed to render: {}", e)))
+}
+
+/// Directory holding `model`'s config files, if it is on disk
+fn model_dir(model: &str) -> Option<PathBuf> {
+    match ModelSource::resolve(model).ok()? {
+        ModelSource::Local(path) if path.is_dir() => Some(path),
+        ModelSource::Local(path) => path.parent().map(Path::to_path_buf),
+        ModelSource::Repo(repo_id) => {
+            let config = hf_hub::Cache::from_env().model(repo_id).get("tokenizer_config.json")?;
+            config.parent().map(Path::to_path_buf)
+        }
+    }
+}
+
+fn read_json(path: &Path) -> Option<Value> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let messages = vec![ChatMessage::system("Be brief."), ChatMessage::user("Hi")];
+
+        assert_eq!(
+            ChatTemplate::ChatMl.render(&messages),
+            "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert!(ChatTemplate::Llama3
+            .render(&messages)
+            .ends_with("Hi<|eot_id|><|start_header_id|>assistant<|end_header_id|>\n\n"));
+        assert_eq!(ChatTemplate::Plain.render(&messages), "System: Be brief.\n\nUser: Hi");
+    }
+
+    #[test]
+    fn test_render_mistral() {
+        let messages = vec![
+            ChatMessage::system("Be brief."),
+            ChatMessage::user("Hi"),
+            ChatMessage::assistant("Hello."),
+            ChatMessage::user("Bye"),
+        ];
+        assert_eq!(
+            ChatTemplate::Mistral.render(&messages),
+            "[INST] Be brief.\n\nHi [/INST]Hello.</s>[INST] Bye [/INST]"
+        );
+    }
+
+    #[test]
+    fn test_render_gemma() {
+        let messages = vec![ChatMessage::system("Be brief."), ChatMessage::user("Hi"), ChatMessage::assistant("Hello.")];
+        assert_eq!(
+            ChatTemplate::Gemma.render(&messages),
+            "<start_of_turn>user\nBe brief.\n\nHi<end_of_turn>\n<start_of_turn>model\nHello.<end_of_turn>\n\
+             <start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn test_render_phi() {
+        let messages = vec![ChatMessage::system("Be brief."), ChatMessage::user("Hi")];
+        assert_eq!(
+            ChatTemplate::Phi.render(&messages),
+            "<|system|>\nBe brief.<|end|>\n<|user|>\nHi<|end|>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn test_render_custom() {
+        let messages = vec![ChatMessage::system("Be brief."), ChatMessage::user("Hi")];
+
+        let template = "{% for m in messages %}[{{ m.role }}] {{ m.content }}\n{% endfor %}>";
+        assert_eq!(render_custom(template, &messages).unwrap(), "[system] Be brief.\n[user] Hi\n>");
+        assert_eq!(render_custom("{{ system }}|{{ prompt }}", &messages).unwrap(), "Be brief.|Hi");
+
+        assert!(matches!(render_custom("{% if %}", &messages), Err(Error::InvalidRequest(_))));
+
+        // A runaway loop is stopped rather than tying up the thread
+        let runaway = "{% for i in range(10000) %}{% for j in range(10000) %}{{ j }}{% endfor %}{% endfor %}";
+        assert!(matches!(render_custom(runaway, &messages), Err(Error::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_template_from_configs() {
+        // Llama 3 Instruct: eos is <|eot_id|> via generation_config's id list
+        let llama = json!({
+            "bos_token": "<|begin_of_text|>",
+            "eos_token": "<|end_of_text|>",
+            "added_tokens_decoder": {"128009": {"content": "<|eot_id|>"}}
+        });
+        let generation = json!({"eos_token_id": [128001, 128009]});
+        assert_eq!(template_from_configs(&llama, Some(&generation)), ChatTemplate::Llama3);
+
+        let qwen = json!({"eos_token": "<|im_end|>"});
+        assert_eq!(template_from_configs(&qwen, None), ChatTemplate::ChatMl);
+
+        let gemma = json!({
+            "eos_token": "<eos>",
+            "added_tokens_decoder": {"107": {"content": "<end_of_turn>"}}
+        });
+        assert_eq!(template_from_configs(&gemma, Some(&json!({"eos_token_id": [1, 107]}))), ChatTemplate::Gemma);
+
+        let phi = json!({
+            "eos_token": "<|endoftext|>",
+            "added_tokens_decoder": {"32007": {"content": "<|end|>"}}
+        });
+        assert_eq!(template_from_configs(&phi, Some(&json!({"eos_token_id": [32000, 32007]}))), ChatTemplate::Phi);
+
+        let mistral = json!({"bos_token": "<s>", "eos_token": "</s>", "chat_template": "{{ '[INST] ' + message['content'] + ' [/INST]' }}"});
+        assert_eq!(template_from_configs(&mistral, None), ChatTemplate::Mistral);
+
+        // </s> alone doesn't say which format the model expects
+        let unknown = json!({"bos_token": "<s>", "eos_token": "</s>"});
+        assert_eq!(template_from_configs(&unknown, None), ChatTemplate::Plain);
+    }
+
+    #[test]
+    fn test_detect_template_reads_model_dir() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        assert_eq!(detect_template(dir), None);
+
+        std::fs::write(dir.join("tokenizer_config.json"), r#"{"eos_token": {"content": "<|im_end|>"}}"#).unwrap();
+        assert_eq!(detect_template(dir), Some(ChatTemplate::ChatMl));
+    }
+
+    #[test]
+    fn test_jinja_chat_template() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("tokenizer_config.json");
+
+        // Gemma-style: no system role, bos_token, Python string methods
+        let source = "{{ bos_token }}{% for m in messages %}\
+            {% if m['role'] == 'system' %}{{ raise_exception('System role not supported') }}{% endif %}\
+            <start_of_turn>{{ 'model' if m['role'] == 'assistant' else m['role'] }}\n\
+            {{ m['content'].strip() }}<end_of_turn>\n\
+            {% endfor %}{% if add_generation_prompt %}<start_of_turn>model\n{% endif %}";
+        let config = json!({"bos_token": "<bos>", "eos_token": "<eos>", "chat_template": source});
+        std::fs::write(&path, config.to_string()).unwrap();
+
+        let template = ChatTemplate::from_tokenizer_config(&path).unwrap();
+        assert_eq!(template.name(), "jinja");
+        assert_eq!(
+            template.render(&[ChatMessage::user(" Hi "), ChatMessage::assistant("Hello.")]),
+            "<start_of_turn>user\nHi<end_of_turn>\n<start_of_turn>model\nHello.<end_of_turn>\n<start_of_turn>model\n"
+        );
+        // raise_exception falls back to the built-in format (Plain here)
+        assert_eq!(template.render(&[ChatMessage::system("Be brief.")]), "System: Be brief.");
+
+        // Named templates: the default one is used
+        let config = json!({
+            "eos_token": "<|im_end|>",
+            "chat_template": [
+                {"name": "tool_use", "template": "tools"},
+                {"name": "default", "template": "{% for m in messages %}{{ m.content }}{{ eos_token }}{% endfor %}"}
+            ]
+        });
+        std::fs::write(&path, config.to_string()).unwrap();
+        let template = ChatTemplate::from_tokenizer_config(&path).unwrap();
+        assert_eq!(template.render(&[ChatMessage::user("Hi")]), "Hi<|im_end|>");
+
+        // A template that doesn't compile is skipped
+        let config = json!({"eos_token": "<|im_end|>", "chat_template": "{% for %}"});
+        std::fs::write(&path, config.to_string()).unwrap();
+        assert_eq!(ChatTemplate::from_tokenizer_config(&path).unwrap(), ChatTemplate::ChatMl);
+
+        assert!(ChatTemplate::from_tokenizer_config(&dir.join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn test_name_fallback() {
+        assert_eq!(get_template_for_model("meta-llama/Llama-3.2-1B-Instruct"), ChatTemplate::Llama3);
+        assert_eq!(get_template_for_model("Qwen/Qwen2.5-1.5B-Instruct"), ChatTemplate::ChatMl);
+        assert_eq!(get_template_for_model("mistralai/Mistral-7B-Instruct-v0.3"), ChatTemplate::Mistral);
+        assert_eq!(get_template_for_model("mistralai/Mixtral-8x7B-Instruct-v0.1"), ChatTemplate::Mistral);
+        assert_eq!(get_template_for_model("google/gemma-2-2b-it"), ChatTemplate::Gemma);
+        assert_eq!(get_template_for_model("microsoft/Phi-3-mini-4k-instruct"), ChatTemplate::Phi);
+        assert_eq!(get_template_for_model("cognitivecomputations/dolphin-2.9-llama3-8b"), ChatTemplate::Llama3);
+        assert_eq!(get_template_for_model("facebook/opt-125m"), ChatTemplate::Plain);
+    }
+}
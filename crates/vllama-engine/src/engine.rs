@@ -1,7 +1,11 @@
 use async_trait::async_trait;
-use vllama_core::{GenerateRequest, GenerateResponse, Hardware, ModelHandle, Result};
+use vllama_core::{
+    ChatCompletionResponse, ChatMessage, Error, GenerateOptions, GenerateRequest,
+    GenerateResponse, Hardware, ModelHandle, Result,
+};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
+use vllama_core::openai::EmbeddingResponse;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EngineType {
@@ -49,10 +53,41 @@ pub trait InferenceEngine: Send + Sync {
 
     async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse>;
 
+    /// Stream a generation
+    ///
+    /// Cancellation contract: dropping the returned stream must stop the
+    /// generation upstream (for HTTP backends, by closing the connection so
+    /// the backend aborts the request) rather than letting it run to
+    /// completion unobserved.
     async fn generate_stream(
         &self,
         request: GenerateRequest,
     ) -> Result<futures::stream::BoxStream<'static, Result<GenerateResponse>>>;
 
+    /// Generate a chat completion from structured messages
+    ///
+    /// Engines without a native chat API return an error; callers fall back
+    /// to rendering a prompt and calling [`InferenceEngine::generate`].
+    async fn generate_chat_completion(
+        &self,
+        _model: String,
+        _messages: Vec<ChatMessage>,
+        _options: GenerateOptions,
+    ) -> Result<ChatCompletionResponse> {
+        Err(Error::EngineNotAvailable(format!(
+            "{:?} engine does not support chat completions",
+            self.engine_type()
+        )))
+    }
+
+    /// Embed each of `inputs` with `model`, one vector per input in order,
+    /// with the inputs' token count when the backend reports it
+    async fn embed(&self, _model: String, _inputs: Vec<String>) -> Result<EmbeddingResponse> {
+        Err(Error::EngineNotAvailable(format!(
+            "{:?} engine does not support embeddings",
+            self.engine_type()
+        )))
+    }
+
     async fn health_check(&self) -> Result<bool>;
 }
@@ -1,7 +1,11 @@
 pub mod engine;
 pub mod vllm_openai;
 pub mod orchestrator;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock;
 
 pub use engine::{InferenceEngine, EngineCapabilities, EngineType};
 pub use vllm_openai::VllmOpenAIEngine;
 pub use orchestrator::EngineOrchestrator;
+#[cfg(any(test, feature = "testing"))]
+pub use mock::MockEngine;
@@ -0,0 +1,298 @@
+/// Scripted engine for tests
+///
+/// `MockEngine` implements `InferenceEngine` without a backend so server
+/// handlers can be exercised deterministically. Every call returns the
+/// configured response, split into the configured stream chunks, and the
+/// requests it received are recorded for assertions.
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use vllama_core::{
+    openai::{ChatCompletionChoice, ChatMessage as OpenAIChatMessage, EmbeddingData, EmbeddingResponse, Usage},
+    ChatCompletionResponse, ChatMessage, Error, GenerateOptions, GenerateRequest,
+    GenerateResponse, GenerationStats, Hardware, ModelHandle, Result,
+};
+
+use crate::engine::{EngineCapabilities, EngineType, InferenceEngine};
+
+#[derive(Clone)]
+pub struct MockEngine {
+    chunks: Vec<String>,
+    finish_reason: String,
+    error: Option<Arc<dyn Fn() -> Error + Send + Sync>>,
+    healthy: bool,
+    embedding: Vec<f32>,
+    chunk_delay: Option<Duration>,
+    fail_load: bool,
+    requests: Arc<Mutex<Vec<GenerateRequest>>>,
+}
+
+impl MockEngine {
+    pub fn new() -> Self {
+        Self {
+            chunks: vec!["Hello".to_string(), " world".to_string()],
+            finish_reason: "stop".to_string(),
+            error: None,
+            healthy: true,
+            embedding: vec![3.0, 4.0],
+            chunk_delay: None,
+            fail_load: false,
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Fail every model load
+    pub fn with_load_failure(mut self) -> Self {
+        self.fail_load = true;
+        self
+    }
+
+    /// Respond with `text`, streamed as a single chunk
+    pub fn with_response(mut self, text: impl Into<String>) -> Self {
+        self.chunks = vec![text.into()];
+        self
+    }
+
+    /// Respond with the concatenation of `chunks`, streamed one per event
+    pub fn with_chunks<I, S>(mut self, chunks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.chunks = chunks.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_finish_reason(mut self, reason: impl Into<String>) -> Self {
+        self.finish_reason = reason.into();
+        self
+    }
+
+    /// Fail every generation call with `InferenceFailed(message)`
+    pub fn with_error(self, message: impl Into<String>) -> Self {
+        let message = message.into();
+        self.with_error_fn(move || Error::InferenceFailed(message.clone()))
+    }
+
+    /// Fail every generation call with the error built by `make_error`
+    ///
+    /// `Error` isn't `Clone`, so a fresh one is built per call.
+    pub fn with_error_fn(mut self, make_error: impl Fn() -> Error + Send + Sync + 'static) -> Self {
+        self.error = Some(Arc::new(make_error));
+        self
+    }
+
+    pub fn with_health(mut self, healthy: bool) -> Self {
+        self.healthy = healthy;
+        self
+    }
+
+    /// Return `embedding` for every input passed to `embed`
+    pub fn with_embedding(mut self, embedding: Vec<f32>) -> Self {
+        self.embedding = embedding;
+        self
+    }
+
+    /// Wait `delay` before each streamed chunk, like a backend generating tokens
+    pub fn with_chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = Some(delay);
+        self
+    }
+
+    /// Requests received so far, in order
+    ///
+    /// Shared between clones, so a test can keep a clone after handing the
+    /// engine to the server.
+    pub fn requests(&self) -> Vec<GenerateRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn record(&self, request: &GenerateRequest) -> Result<()> {
+        self.requests.lock().unwrap().push(request.clone());
+        match &self.error {
+            Some(make_error) => Err(make_error()),
+            None => Ok(()),
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chunks.concat()
+    }
+
+    fn stats(&self, prompt: &str) -> GenerationStats {
+        GenerationStats::new(prompt.split_whitespace().count(), self.chunks.len())
+    }
+}
+
+impl Default for MockEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl InferenceEngine for MockEngine {
+    fn engine_type(&self) -> EngineType {
+        EngineType::Vllm
+    }
+
+    fn capabilities(&self) -> EngineCapabilities {
+        EngineCapabilities::default()
+    }
+
+    fn supports_hardware(&self, _hardware: &Hardware) -> bool {
+        true
+    }
+
+    async fn load_model(&mut self, path: &Path) -> Result<ModelHandle> {
+        if self.fail_load {
+            return Err(Error::ModelLoadFailed(path.display().to_string()));
+        }
+        Ok(ModelHandle(0))
+    }
+
+    async fn unload_model(&mut self, _handle: ModelHandle) -> Result<()> {
+        Ok(())
+    }
+
+    async fn generate(&self, request: GenerateRequest) -> Result<GenerateResponse> {
+        self.record(&request)?;
+
+        Ok(GenerateResponse {
+            id: request.id,
+            stats: self.stats(&request.prompt),
+            model: request.model,
+            text: self.text(),
+            tokens: Vec::new(),
+            finished: true,
+            finish_reason: Some(self.finish_reason.clone()),
+        })
+    }
+
+    async fn generate_stream(
+        &self,
+        request: GenerateRequest,
+    ) -> Result<BoxStream<'static, Result<GenerateResponse>>> {
+        self.record(&request)?;
+
+        let last = self.chunks.len().saturating_sub(1);
+        let stats = self.stats(&request.prompt);
+        let responses: Vec<Result<GenerateResponse>> = self
+            .chunks
+            .iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let finished = i == last;
+                Ok(GenerateResponse {
+                    id: request.id,
+                    model: request.model.clone(),
+                    text: chunk.clone(),
+                    tokens: Vec::new(),
+                    stats: if finished { stats.clone() } else { GenerationStats::new(0, 0) },
+                    finished,
+                    finish_reason: finished.then(|| self.finish_reason.clone()),
+                })
+            })
+            .collect();
+
+        let delay = self.chunk_delay;
+        Ok(Box::pin(stream::iter(responses).then(move |response| async move {
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            response
+        })))
+    }
+
+    async fn generate_chat_completion(
+        &self,
+        model: String,
+        messages: Vec<ChatMessage>,
+        options: GenerateOptions,
+    ) -> Result<ChatCompletionResponse> {
+        let prompt = messages
+            .iter()
+            .map(|m| m.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut request = GenerateRequest::new(0, model.clone(), prompt);
+        request.options = options;
+        let response = self.generate(request).await?;
+
+        Ok(ChatCompletionResponse {
+            id: "chatcmpl-mock".to_string(),
+            object: "chat.completion".to_string(),
+            created: 0,
+            model,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: OpenAIChatMessage {
+                    role: "assistant".to_string(),
+                    content: response.text,
+                },
+                finish_reason: response.finish_reason,
+            }],
+            usage: Usage {
+                prompt_tokens: response.stats.prompt_tokens,
+                completion_tokens: response.stats.generated_tokens,
+                total_tokens: response.stats.total_tokens,
+            },
+        })
+    }
+
+    async fn embed(&self, model: String, inputs: Vec<String>) -> Result<EmbeddingResponse> {
+        if let Some(make_error) = &self.error {
+            return Err(make_error());
+        }
+        let prompt_tokens = inputs.iter().map(|input| input.split_whitespace().count()).sum();
+        Ok(EmbeddingResponse {
+            model,
+            data: (0..inputs.len())
+                .map(|index| EmbeddingData { index, embedding: self.embedding.clone() })
+                .collect(),
+            usage: Some(Usage { prompt_tokens, completion_tokens: 0, total_tokens: prompt_tokens }),
+        })
+    }
+
+    async fn health_check(&self) -> Result<bool> {
+        Ok(self.healthy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_mock_stream_matches_response() {
+        let engine = MockEngine::new().with_chunks(["a", "b", "c"]);
+        let request = GenerateRequest::new(1, "m".to_string(), "hi there".to_string());
+
+        let chunks: Vec<_> = engine
+            .generate_stream(request.clone())
+            .await
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].finished);
+        assert_eq!(chunks[2].finish_reason.as_deref(), Some("stop"));
+        assert_eq!(chunks[2].stats.prompt_tokens, 2);
+
+        let full = engine.generate(request).await.unwrap();
+        assert_eq!(full.text, "abc");
+        assert_eq!(engine.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_mock_error() {
+        let engine = MockEngine::new().with_error("boom");
+        let request = GenerateRequest::new(1, "m".to_string(), "hi".to_string());
+        assert!(engine.generate(request).await.is_err());
+    }
+}
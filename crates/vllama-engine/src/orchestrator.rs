@@ -1,6 +1,6 @@
 use crate::engine::{EngineType, InferenceEngine};
 use crate::vllm_openai::VllmOpenAIEngine;
-use vllama_core::{Hardware, Result};
+use vllama_core::{openai::DEFAULT_VLLM_URL, Hardware, Result};
 use std::sync::Arc;
 use tracing::info;
 
@@ -11,7 +11,12 @@ pub struct EngineOrchestrator {
 
 impl EngineOrchestrator {
     pub fn new(hardware: Hardware) -> Self {
-        let engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
+        Self::with_engine_url(hardware, DEFAULT_VLLM_URL)
+    }
+
+    /// Orchestrate a vLLM server at `base_url` (`http://` or `https://`)
+    pub fn with_engine_url(hardware: Hardware, base_url: impl Into<String>) -> Self {
+        let engine = VllmOpenAIEngine::new(base_url);
 
         Self {
             engine: Arc::new(engine),
@@ -5,12 +5,16 @@
 use async_trait::async_trait;
 use futures::stream::BoxStream;
 use futures::StreamExt;
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 use tracing::info;
 use vllama_core::{
     CompletionRequest, GenerateRequest, GenerateResponse, GenerationStats,
-    Hardware, ModelHandle, OpenAIClient, Result,
+    Hardware, ModelHandle, OpenAIClient, Result, SamplingParams, SpecialTokens, StreamOptions,
 };
+use vllama_core::openai::{EmbeddingRequest, EmbeddingResponse};
+use vllama_core::special_tokens::SpecialTokenFilter;
 
 use crate::engine::{EngineCapabilities, EngineType, InferenceEngine};
 
@@ -18,6 +22,9 @@ pub struct VllmOpenAIEngine {
     client: OpenAIClient,
     #[allow(dead_code)]
     base_url: String,
+    strip_special_tokens: bool,
+    /// Per-model special tokens, resolved from the HF cache on first use
+    special_tokens: Mutex<HashMap<String, Arc<SpecialTokens>>>,
 }
 
 impl VllmOpenAIEngine {
@@ -25,46 +32,113 @@ impl VllmOpenAIEngine {
         let base_url = base_url.into();
         let client = OpenAIClient::new(base_url.clone());
 
-        Self { client, base_url }
+        Self {
+            client,
+            base_url,
+            strip_special_tokens: true,
+            special_tokens: Mutex::new(HashMap::new()),
+        }
     }
 
-    /// Generate chat completion using OpenAI chat API
-    pub async fn generate_chat_completion(
-        &self,
-        model: String,
-        messages: Vec<vllama_core::ChatMessage>,
-        options: vllama_core::GenerateOptions,
-    ) -> Result<vllama_core::ChatCompletionResponse> {
-        use vllama_core::openai::ChatMessage as OpenAIChatMessage;
-        use vllama_core::openai::ChatCompletionRequest;
+    /// Skip TLS certificate verification for an `https://` backend with a
+    /// self-signed certificate
+    pub fn with_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.client = self.client.with_accept_invalid_certs(accept);
+        self
+    }
 
-        let openai_messages: Vec<OpenAIChatMessage> = messages
-            .iter()
-            .map(|msg| {
-                use vllama_core::ChatRole;
-                let role = match msg.role {
-                    ChatRole::System => "system",
-                    ChatRole::User => "user",
-                    ChatRole::Assistant => "assistant",
-                    ChatRole::Tool => "tool",
-                };
-                OpenAIChatMessage {
-                    role: role.to_string(),
-                    content: msg.content.clone(),
-                }
-            })
-            .collect();
+    /// How long a request to vLLM may take; see
+    /// [`OpenAIClient::with_timeout`]
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.client = self.client.with_timeout(timeout);
+        self
+    }
 
-        let request = ChatCompletionRequest {
-            model: model.clone(),
-            messages: openai_messages,
-            max_tokens: options.sampling.max_tokens,
-            temperature: Some(options.sampling.temperature),
-            top_p: Some(options.sampling.top_p),
-            stream: Some(false),
-        };
+    /// Remove the model's leaked end-of-turn tokens (e.g. `<|eot_id|>`) from
+    /// the end of output text (default: on)
+    pub fn with_special_token_stripping(mut self, enabled: bool) -> Self {
+        self.strip_special_tokens = enabled;
+        self
+    }
 
-        self.client.create_chat_completion(request).await
+    /// Special tokens to strip from `model`'s output, or None if disabled
+    fn special_tokens(&self, model: &str) -> Option<Arc<SpecialTokens>> {
+        if !self.strip_special_tokens {
+            return None;
+        }
+        let mut cache = self.special_tokens.lock().unwrap();
+        let tokens = cache
+            .entry(model.to_string())
+            .or_insert_with(|| Arc::new(SpecialTokens::for_model(model)));
+        Some(tokens.clone())
+    }
+
+    fn clean(&self, model: &str, text: String) -> String {
+        match self.special_tokens(model) {
+            Some(tokens) => tokens.strip(&text),
+            None => text,
+        }
+    }
+}
+
+/// Sampling fields sent to vLLM as (temperature, top_p, top_k)
+///
+/// `temperature == 0` requests greedy decoding; top_p and top_k are dropped
+/// so nothing but the argmax can influence the chosen token.
+fn sampling_fields(sampling: &SamplingParams) -> (Option<f32>, Option<f32>, Option<i32>) {
+    if sampling.temperature == 0.0 {
+        return (Some(0.0), None, None);
+    }
+    (
+        Some(sampling.temperature),
+        Some(sampling.top_p),
+        sampling.top_k.map(|k| k as i32),
+    )
+}
+
+/// Penalties sent to vLLM as (frequency, presence, repetition); neutral
+/// values are left out so vLLM's own defaults apply
+fn penalty_fields(sampling: &SamplingParams) -> (Option<f32>, Option<f32>, Option<f32>) {
+    (
+        (sampling.frequency_penalty != 0.0).then_some(sampling.frequency_penalty),
+        (sampling.presence_penalty != 0.0).then_some(sampling.presence_penalty),
+        (sampling.repetition_penalty != 1.0).then_some(sampling.repetition_penalty),
+    )
+}
+
+fn stop_field(sampling: &SamplingParams) -> Option<Vec<String>> {
+    (!sampling.stop_sequences.is_empty()).then(|| sampling.stop_sequences.clone())
+}
+
+/// Sent alongside `stop` so a matched stop string is trimmed unless asked for
+fn include_stop_field(sampling: &SamplingParams) -> Option<bool> {
+    (!sampling.stop_sequences.is_empty()).then_some(sampling.include_stop_str_in_output)
+}
+
+/// Build the OpenAI completion request for a generation
+fn completion_request(request: &GenerateRequest, stream: bool) -> CompletionRequest {
+    let (temperature, top_p, top_k) = sampling_fields(&request.options.sampling);
+    let (frequency_penalty, presence_penalty, repetition_penalty) = penalty_fields(&request.options.sampling);
+    CompletionRequest {
+        model: request.model.clone(),
+        prompt: request.prompt.clone(),
+        max_tokens: request.options.sampling.max_tokens,
+        min_tokens: request.options.sampling.min_tokens,
+        seed: request.options.sampling.seed,
+        temperature,
+        top_p,
+        top_k,
+        min_p: request.options.sampling.min_p,
+        frequency_penalty,
+        presence_penalty,
+        repetition_penalty,
+        stream: Some(stream),
+        stream_options: (stream && request.options.include_usage)
+            .then_some(StreamOptions { include_usage: true }),
+        stop: stop_field(&request.options.sampling),
+        include_stop_str_in_output: include_stop_field(&request.options.sampling),
+        guided: request.options.guided.clone(),
+        response_format: request.options.response_format.clone(),
     }
 }
 
@@ -92,7 +166,8 @@ impl InferenceEngine for VllmOpenAIEngine {
     }
 
     fn supports_hardware(&self, hardware: &Hardware) -> bool {
-        hardware.has_gpu()
+        // Inconclusive detection isn't a reason to refuse; vLLM finds out itself
+        hardware.may_have_gpu()
     }
 
     async fn load_model(&mut self, _path: &Path) -> Result<ModelHandle> {
@@ -111,15 +186,7 @@ impl InferenceEngine for VllmOpenAIEngine {
         info!("Generating via vLLM OpenAI API: {}", request.model);
 
         // Convert to OpenAI completion request
-        let completion_request = CompletionRequest {
-            model: request.model.clone(),
-            prompt: request.prompt.clone(),
-            max_tokens: request.options.sampling.max_tokens,
-            temperature: Some(request.options.sampling.temperature),
-            top_p: Some(request.options.sampling.top_p),
-            stream: Some(false),
-            stop: None,
-        };
+        let completion_request = completion_request(&request, false);
 
         let response = self.client.create_completion(completion_request).await?;
 
@@ -129,6 +196,7 @@ impl InferenceEngine for VllmOpenAIEngine {
             .first()
             .map(|c| c.text.clone())
             .unwrap_or_default();
+        let text = self.clean(&request.model, text);
 
         let stats = GenerationStats::new(
             response.usage.prompt_tokens,
@@ -157,17 +225,10 @@ impl InferenceEngine for VllmOpenAIEngine {
 
         let request_id = request.id;
         let model = request.model.clone();
+        let special_tokens = self.special_tokens(&model).map(SpecialTokenFilter::new);
 
         // Convert to OpenAI completion request with streaming
-        let completion_request = CompletionRequest {
-            model: request.model.clone(),
-            prompt: request.prompt.clone(),
-            max_tokens: request.options.sampling.max_tokens,
-            temperature: Some(request.options.sampling.temperature),
-            top_p: Some(request.options.sampling.top_p),
-            stream: Some(true),
-            stop: None,
-        };
+        let completion_request = completion_request(&request, true);
 
         let stream = self
             .client
@@ -175,34 +236,108 @@ impl InferenceEngine for VllmOpenAIEngine {
             .await?;
 
         // Convert chunks to GenerateResponse
-        let response_stream = stream.map(move |result| {
-            result.map(|chunk| {
-                let text = chunk
-                    .choices
-                    .first()
-                    .map(|c| c.text.clone())
-                    .unwrap_or_default();
-
-                let finish_reason = chunk
-                    .choices
-                    .first()
-                    .and_then(|c| c.finish_reason.clone());
-
-                GenerateResponse {
-                    id: request_id,
-                    model: model.clone(),
-                    text,
-                    tokens: Vec::new(),
-                    stats: GenerationStats::new(0, 0),
-                    finished: finish_reason.is_some(),
-                    finish_reason,
-                }
-            })
+        let response = move |text: String, finish_reason: Option<String>, stats: GenerationStats| GenerateResponse {
+            id: request_id,
+            model: model.clone(),
+            text,
+            tokens: Vec::new(),
+            stats,
+            finished: finish_reason.is_some(),
+            finish_reason,
+        };
+        let upstream = (stream.boxed(), special_tokens);
+        let response_stream = futures::stream::unfold(Some(upstream), move |upstream| {
+            let response = response.clone();
+            async move {
+                let (mut stream, mut filter) = upstream?;
+                let Some(result) = stream.next().await else {
+                    // Ended without a finish_reason (or it came on a
+                    // usage-only chunk): release what was held back
+                    let tail = filter.as_mut().map(SpecialTokenFilter::flush).unwrap_or_default();
+                    return (!tail.is_empty()).then(|| (Ok(response(tail, None, GenerationStats::new(0, 0))), None));
+                };
+                let item = result.map(|chunk| {
+                    let text = chunk
+                        .choices
+                        .first()
+                        .map(|c| c.text.clone())
+                        .unwrap_or_default();
+
+                    let finish_reason = chunk
+                        .choices
+                        .first()
+                        .and_then(|c| c.finish_reason.clone());
+
+                    let text = match &mut filter {
+                        Some(filter) => filter.push(&text, finish_reason.is_some()),
+                        None => text,
+                    };
+
+                    // Usage arrives on a trailing chunk with no choices
+                    let stats = chunk
+                        .usage
+                        .map(|u| GenerationStats::new(u.prompt_tokens, u.completion_tokens))
+                        .unwrap_or_else(|| GenerationStats::new(0, 0));
+
+                    response(text, finish_reason, stats)
+                });
+                Some((item, Some((stream, filter))))
+            }
         });
 
         Ok(Box::pin(response_stream))
     }
 
+    /// Generate chat completion using OpenAI chat API
+    async fn generate_chat_completion(
+        &self,
+        model: String,
+        messages: Vec<vllama_core::ChatMessage>,
+        options: vllama_core::GenerateOptions,
+    ) -> Result<vllama_core::ChatCompletionResponse> {
+        use vllama_core::openai::ChatMessage as OpenAIChatMessage;
+        use vllama_core::openai::ChatCompletionRequest;
+
+        let openai_messages: Vec<OpenAIChatMessage> = messages.into_iter().map(Into::into).collect();
+
+        let (temperature, top_p, top_k) = sampling_fields(&options.sampling);
+        let (frequency_penalty, presence_penalty, repetition_penalty) = penalty_fields(&options.sampling);
+        let request = ChatCompletionRequest {
+            model: model.clone(),
+            messages: openai_messages,
+            max_tokens: options.sampling.max_tokens,
+            min_tokens: options.sampling.min_tokens,
+            seed: options.sampling.seed,
+            temperature,
+            top_p,
+            top_k,
+            min_p: options.sampling.min_p,
+            frequency_penalty,
+            presence_penalty,
+            repetition_penalty,
+            stream: Some(false),
+            stop: stop_field(&options.sampling),
+            include_stop_str_in_output: include_stop_field(&options.sampling),
+            guided: options.guided.clone(),
+            response_format: options.response_format.clone(),
+        };
+
+        let mut response = self.client.create_chat_completion(request).await?;
+        for choice in &mut response.choices {
+            choice.message.content = self.clean(&model, std::mem::take(&mut choice.message.content));
+        }
+        Ok(response)
+    }
+
+    async fn embed(&self, model: String, inputs: Vec<String>) -> Result<EmbeddingResponse> {
+        let mut response = self
+            .client
+            .create_embeddings(EmbeddingRequest { model, input: inputs })
+            .await?;
+        response.data.sort_by_key(|d| d.index);
+        Ok(response)
+    }
+
     async fn health_check(&self) -> Result<bool> {
         self.client.health().await
     }
@@ -227,4 +362,128 @@ mod tests {
         assert!(caps.supports_paged_attention);
         assert_eq!(caps.max_batch_size, 256);
     }
+
+    #[tokio::test]
+    async fn test_dropping_stream_closes_upstream() {
+        use futures::StreamExt;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // Streams chunks until the client goes away, then reports how many it wrote
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+
+            let chunk = "data: {\"id\":\"c\",\"object\":\"text_completion\",\"created\":0,\"model\":\"m\",\"choices\":[{\"text\":\"x\",\"index\":0,\"finish_reason\":null}]}\n\n";
+            let mut written = 0usize;
+            while socket.write_all(chunk.as_bytes()).await.is_ok() {
+                written += 1;
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+            let _ = closed_tx.send(written);
+        });
+
+        let engine = VllmOpenAIEngine::new(url).with_special_token_stripping(false);
+        let request = GenerateRequest::new(1, "m".to_string(), "hi".to_string());
+        let mut stream = engine.generate_stream(request).await.unwrap();
+
+        assert_eq!(stream.next().await.unwrap().unwrap().text, "x");
+        drop(stream);
+
+        let written = tokio::time::timeout(std::time::Duration::from_secs(5), closed_rx)
+            .await
+            .expect("upstream kept streaming after the consumer went away")
+            .unwrap();
+        assert!(written < 400);
+    }
+
+    #[tokio::test]
+    async fn test_stream_end_flushes_held_back_text() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // "<" could start <|im_end|>, and the stream closes without a finish_reason
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await;
+            let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\nconnection: close\r\n\r\n";
+            let chunk = "data: {\"id\":\"c\",\"object\":\"text_completion\",\"created\":0,\"model\":\"m\",\"choices\":[{\"text\":\"1 <\",\"index\":0,\"finish_reason\":null}]}\n\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(chunk.as_bytes()).await.unwrap();
+        });
+
+        let engine = VllmOpenAIEngine::new(url);
+        let tokens = SpecialTokens::from_configs(Some(serde_json::json!({"eos_token": "<|im_end|>"})), None);
+        engine.special_tokens.lock().unwrap().insert("m".to_string(), Arc::new(tokens));
+        let request = GenerateRequest::new(1, "m".to_string(), "hi".to_string());
+        let stream = engine.generate_stream(request).await.unwrap();
+
+        let text: String = stream.map(|chunk| chunk.unwrap().text).collect::<Vec<_>>().await.concat();
+        assert_eq!(text, "1 <");
+    }
+
+    #[test]
+    fn test_temperature_zero_is_greedy() {
+        let mut request = GenerateRequest::new(1, "m".to_string(), "hi".to_string());
+        request.options.sampling.temperature = 0.0;
+        request.options.sampling.top_k = Some(40);
+
+        let completion = completion_request(&request, false);
+        assert_eq!(completion.temperature, Some(0.0));
+        assert_eq!(completion.top_p, None);
+        assert_eq!(completion.top_k, None);
+
+        let json = serde_json::to_string(&completion).unwrap();
+        assert!(!json.contains("top_p"));
+        assert!(!json.contains("top_k"));
+    }
+
+    #[test]
+    fn test_sampling_forwarded() {
+        let mut request = GenerateRequest::new(1, "m".to_string(), "hi".to_string());
+        request.options.sampling.temperature = 0.8;
+        request.options.sampling.top_k = Some(40);
+
+        let completion = completion_request(&request, true);
+        assert_eq!(completion.temperature, Some(0.8));
+        assert_eq!(completion.top_p, Some(0.9));
+        assert_eq!(completion.top_k, Some(40));
+        assert!(completion.stream_options.is_none());
+        assert_eq!(completion.frequency_penalty, None);
+        assert_eq!(completion.stop, None);
+        assert_eq!(completion.include_stop_str_in_output, None);
+
+        request.options.sampling.frequency_penalty = 0.5;
+        request.options.sampling.presence_penalty = -0.5;
+        request.options.sampling.stop_sequences = vec!["\n\n".to_string()];
+        let completion = completion_request(&request, false);
+        assert_eq!(completion.frequency_penalty, Some(0.5));
+        assert_eq!(completion.presence_penalty, Some(-0.5));
+        assert_eq!(completion.repetition_penalty, None);
+        assert_eq!(completion.stop, Some(vec!["\n\n".to_string()]));
+        // A matched stop string is trimmed unless the request keeps it
+        assert_eq!(completion.include_stop_str_in_output, Some(false));
+        request.options.sampling.include_stop_str_in_output = true;
+        assert_eq!(completion_request(&request, false).include_stop_str_in_output, Some(true));
+
+        request.options.guided = Some(vllama_core::GuidedDecoding::Choice(vec!["yes".to_string(), "no".to_string()]));
+        let json = serde_json::to_value(completion_request(&request, false)).unwrap();
+        assert_eq!(json["guided_choice"], serde_json::json!(["yes", "no"]));
+        assert!(json.get("guided").is_none());
+        assert!(json.get("response_format").is_none());
+
+        request.options.guided = None;
+        request.options.response_format = Some(vllama_core::ResponseFormat::JsonObject);
+        let json = serde_json::to_value(completion_request(&request, false)).unwrap();
+        assert_eq!(json["response_format"], serde_json::json!({"type": "json_object"}));
+    }
 }
@@ -1,32 +1,410 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response, sse::{Event, Sse}},
     Json,
 };
+use dashmap::mapref::entry::Entry;
+use futures::future::{AbortHandle, Abortable, Aborted};
 use futures::stream::{self};
-use vllama_core::{ChatMessage, ChatRole, GenerateRequest, GenerateOptions};
-use vllama_engine::InferenceEngine;
+use vllama_core::{ChatMessage, GenerateRequest, GenerateOptions, GenerateResponse, GuidedDecoding, ModelSource, RequestId, ResponseFormat, SamplingParams, StreamOptions};
 use serde::{Deserialize, Serialize};
 use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::cell::Cell;
 use std::convert::Infallible;
 use std::hash::{Hash, Hasher};
-use std::time::Instant;
-use tracing::{error, info};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, MutexGuard};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::prometheus;
+use crate::state::{InFlightGuard, KeepAlive, ModelStatsSnapshot, ModelUsage, PullTask, QueueSnapshot, ServerState};
+use vllama_engine::InferenceEngine;
+
+/// Sentinel that terminates OpenAI-style SSE streams; clients wait for it
+/// to detect the end of the stream
+const OPENAI_STREAM_DONE: &str = "[DONE]";
+
+/// HTTP status for an engine error
+///
+/// Client mistakes and backend conditions get their own codes so callers
+/// can react (shrink the prompt, back off, pick another model) instead of
+/// treating every failure as a 500.
+fn error_status(err: &vllama_core::Error) -> StatusCode {
+    use vllama_core::Error;
+    match err {
+        Error::ContextLengthExceeded { .. } | Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        Error::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        Error::ModelNotServed { .. } | Error::ModelNotFound(_) => StatusCode::NOT_FOUND,
+        Error::Upstream { .. } => StatusCode::BAD_GATEWAY,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// vLLM's context window for `model`, or None if vLLM can't be asked
+async fn max_model_len(state: &ServerState, model: &str) -> Option<usize> {
+    if let Some(len) = state.context_lengths.get(model) {
+        return Some(*len);
+    }
+
+    #[derive(Deserialize)]
+    struct VllmModels {
+        data: Vec<VllmModel>,
+    }
+
+    #[derive(Deserialize)]
+    struct VllmModel {
+        id: String,
+        max_model_len: Option<usize>,
+    }
+
+    let response = state.http.get(format!("{}/v1/models", state.vllm_url)).send().await.ok()?;
+    let models: VllmModels = response.json().await.ok()?;
+    for m in models.data {
+        if let Some(len) = m.max_model_len {
+            state.context_lengths.insert(m.id, len);
+        }
+    }
+    state.context_lengths.get(model).map(|len| *len)
+}
+
+/// Reject an Ollama `num_ctx` larger than the window vLLM was launched with
+///
+/// vLLM's context length is fixed at startup, so a smaller `num_ctx` is
+/// accepted (the window simply isn't shrunk, which is returned as a
+/// warning) and a larger one is a 400.
+async fn check_num_ctx(
+    state: &ServerState,
+    model: &str,
+    options: Option<&OllamaOptions>,
+) -> Result<Option<String>, Response> {
+    let Some(num_ctx) = options.and_then(|o| o.num_ctx) else {
+        return Ok(None);
+    };
+    match max_model_len(state, model).await {
+        Some(max) if num_ctx > max => Err((StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": format!(
+                "num_ctx {} exceeds the model's context length {}; restart vllama with a larger --max-model-len",
+                num_ctx, max
+            )
+        }))).into_response()),
+        Some(max) if num_ctx < max => Ok(Some(format!(
+            "num_ctx {} ignored: the context length is fixed at {} when vLLM starts",
+            num_ctx, max
+        ))),
+        _ => Ok(None),
+    }
+}
+
+/// Translate Ollama's `num_predict` into a `max_tokens` limit
+///
+/// `-1` leaves the limit unset so vLLM applies its own. `-2` fills whatever
+/// context remains after the prompt, which takes a round trip to vLLM's
+/// `/tokenize`; if vLLM can't be asked it falls back to `-1`.
+async fn num_predict_limit(
+    state: &ServerState,
+    model: &str,
+    num_predict: i64,
+    tokenize: serde_json::Value,
+) -> vllama_core::Result<Option<usize>> {
+    match num_predict {
+        n if n >= 0 => Ok(Some(n as usize)),
+        -1 => Ok(None),
+        -2 => {
+            let Some(max) = max_model_len(state, model).await else {
+                return Ok(None);
+            };
+            match prompt_tokens(state, tokenize).await {
+                Some(count) if count >= max => Err(vllama_core::Error::ContextLengthExceeded {
+                    max,
+                    requested: count,
+                }),
+                Some(count) => Ok(Some(max - count)),
+                None => Ok(None),
+            }
+        }
+        n => Err(vllama_core::Error::InvalidRequest(format!(
+            "num_predict must be -1 (unlimited), -2 (fill context) or non-negative, got {}",
+            n
+        ))),
+    }
+}
+
+/// Drop the oldest turns of a chat until it fits `model`'s context window
+/// with `reserve` tokens left for the reply
+///
+/// The first `n_keep` messages, system messages and the last message are
+/// kept; an assistant or tool reply goes together with the turn before it.
+/// Tokens are counted by vLLM's `/tokenize`, so this is a no-op if vLLM
+/// can't be asked. The cut point is binary-searched, so a long history
+/// costs a logarithmic number of round trips. Fails if the kept messages
+/// alone don't fit.
+async fn fit_context(
+    state: &ServerState,
+    model: &str,
+    messages: &mut Vec<ChatMessage>,
+    n_keep: usize,
+    reserve: usize,
+) -> vllama_core::Result<()> {
+    let Some(max) = max_model_len(state, model).await else {
+        return Ok(());
+    };
+    let Some(count) = chat_tokens(state, model, messages).await else {
+        return Ok(());
+    };
+    if count + reserve <= max {
+        return Ok(());
+    }
+
+    let turns = droppable_turns(messages, n_keep);
+    let fewest = match turns.len() {
+        0 => count,
+        all => match chat_tokens(state, model, &without_turns(messages, &turns, all)).await {
+            Some(fewest) => fewest,
+            None => return Ok(()),
+        },
+    };
+    if fewest + reserve > max {
+        return Err(vllama_core::Error::ContextLengthExceeded { max, requested: fewest + reserve });
+    }
+
+    // Fewest oldest turns whose removal makes the chat fit; dropping all of them does
+    let (mut low, mut high) = (1, turns.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        match chat_tokens(state, model, &without_turns(messages, &turns, mid)).await {
+            Some(count) if count + reserve <= max => high = mid,
+            Some(_) => low = mid + 1,
+            None => return Ok(()),
+        }
+    }
+
+    let dropped: usize = turns[..high].iter().map(|turn| turn.len()).sum();
+    *messages = without_turns(messages, &turns, high);
+    info!("Dropped the {} oldest messages to fit {}'s context of {} tokens", dropped, model, max);
+    Ok(())
+}
 
-use crate::state::ServerState;
+/// Index ranges of the turns [`fit_context`] may drop, oldest first
+///
+/// A turn is a message outside the first `n_keep` that is neither a system
+/// message nor the last one, plus the assistant or tool replies after it.
+fn droppable_turns(messages: &[ChatMessage], n_keep: usize) -> Vec<std::ops::Range<usize>> {
+    let droppable = |i: usize| {
+        i >= n_keep && i + 1 < messages.len() && messages[i].role != vllama_core::ChatRole::System
+    };
+
+    let mut turns = Vec::new();
+    let mut i = 0;
+    while i < messages.len() {
+        if !droppable(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        while i < messages.len()
+            && droppable(i)
+            && matches!(messages[i].role, vllama_core::ChatRole::Assistant | vllama_core::ChatRole::Tool)
+        {
+            i += 1;
+        }
+        turns.push(start..i);
+    }
+    turns
+}
 
-fn messages_to_prompt(messages: &[ChatMessage]) -> String {
+/// `messages` without the oldest `count` of `turns`
+fn without_turns(messages: &[ChatMessage], turns: &[std::ops::Range<usize>], count: usize) -> Vec<ChatMessage> {
     messages
         .iter()
-        .map(|msg| match msg.role {
-            ChatRole::System => format!("System: {}", msg.content),
-            ChatRole::User => format!("User: {}", msg.content),
-            ChatRole::Assistant => format!("Assistant: {}", msg.content),
-            ChatRole::Tool => format!("Tool: {}", msg.content),
+        .enumerate()
+        .filter(|(i, _)| !turns[..count].iter().any(|turn| turn.contains(i)))
+        .map(|(_, message)| message.clone())
+        .collect()
+}
+
+/// Length of a chat in tokens according to vLLM's `/tokenize`
+async fn chat_tokens(state: &ServerState, model: &str, messages: &[ChatMessage]) -> Option<usize> {
+    let openai: Vec<vllama_core::openai::ChatMessage> = messages.iter().cloned().map(Into::into).collect();
+    prompt_tokens(state, serde_json::json!({"model": model, "messages": openai})).await
+}
+
+/// Prompt length in tokens according to vLLM's `/tokenize`
+async fn prompt_tokens(state: &ServerState, body: serde_json::Value) -> Option<usize> {
+    #[derive(Deserialize)]
+    struct Tokenized {
+        count: usize,
+    }
+
+    let response = state
+        .http
+        .post(format!("{}/tokenize", state.vllm_url))
+        .json(&body)
+        .send()
+        .await
+        .ok()?;
+    let tokenized: Tokenized = response.error_for_status().ok()?.json().await.ok()?;
+    Some(tokenized.count)
+}
+
+tokio::task_local! {
+    /// How long the current request waited for the engine, for `x-queue-time-ms`
+    static QUEUE_TIME: Cell<Option<Duration>>;
+}
+
+/// Wait for the engine, giving up after `config.queue_timeout`
+///
+/// Only one request drives the engine at a time, so this is where requests
+/// queue under load. The wait is recorded in [`ServerState::queue`] and
+/// reported to the client by [`queue_time_header`]; a request that times
+/// out gets 503 with `Retry-After`.
+///
+/// Streams returned by the engine don't borrow it, so streaming handlers
+/// drop the guard as soon as the stream is open; holding it while the
+/// client reads would serialize every stream behind the slowest one.
+async fn lock_engine(state: &ServerState) -> Result<MutexGuard<'_, dyn InferenceEngine>, Response> {
+    let start = Instant::now();
+    let engine = match state.config().queue_timeout {
+        Some(limit) => match tokio::time::timeout(limit, state.engine.lock()).await {
+            Ok(engine) => engine,
+            Err(_) => {
+                state.queue.record_timeout();
+                warn!("Request waited {:?} for the engine, rejecting", limit);
+                let retry_after = limit.as_secs().max(1).to_string();
+                return Err((
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::RETRY_AFTER, retry_after)],
+                    Json(serde_json::json!({"error": "server busy, retry later"})),
+                ).into_response());
+            }
+        },
+        None => state.engine.lock().await,
+    };
+
+    let wait = start.elapsed();
+    state.queue.record_wait(wait);
+    let _ = QUEUE_TIME.try_with(|queue_time| queue_time.set(Some(wait)));
+    Ok(engine)
+}
+
+/// Middleware adding `x-queue-time-ms` to responses of requests that
+/// waited for the engine
+pub async fn queue_time_header(request: Request, next: Next) -> Response {
+    QUEUE_TIME
+        .scope(Cell::new(None), async move {
+            let mut response = next.run(request).await;
+            if let Some(wait) = QUEUE_TIME.with(Cell::get) {
+                response
+                    .headers_mut()
+                    .insert("x-queue-time-ms", HeaderValue::from(wait.as_millis() as u64));
+            }
+            response
         })
-        .collect::<Vec<_>>()
-        .join("\n\n")
+        .await
+}
+
+/// Middleware resetting the idle-shutdown timer on every request except
+/// health probes and scrapes, which would otherwise keep an unused server
+/// up forever
+pub async fn record_activity(State(state): State<ServerState>, request: Request, next: Next) -> Response {
+    if !matches!(request.uri().path(), "/health" | "/ready" | "/metrics") {
+        state.record_activity();
+    }
+    next.run(request).await
+}
+
+/// Middleware counting requests and error responses per route for `/metrics`
+///
+/// Requests are labelled with the route pattern, so unknown paths share
+/// one `unmatched` series instead of growing one per URL.
+pub async fn record_metrics(request: Request, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+    let response = next.run(request).await;
+
+    metrics::counter!(prometheus::REQUESTS_TOTAL, "endpoint" => endpoint.clone()).increment(1);
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        metrics::counter!(
+            prometheus::REQUEST_ERRORS_TOTAL,
+            "endpoint" => endpoint,
+            "status" => status.as_str().to_string(),
+        )
+        .increment(1);
+    }
+    response
+}
+
+/// Frame a stream of JSON objects for an Ollama `/api/*` endpoint
+///
+/// Ollama streams newline-delimited JSON, which its client libraries read
+/// line by line. Browsers' `EventSource` needs SSE instead, so a client that
+/// sends `Accept: text/event-stream` gets `data:` events. `/v1/*` is always SSE.
+fn ollama_stream(
+    headers: &HeaderMap,
+    lines: impl futures::Stream<Item = String> + Send + 'static,
+) -> Response {
+    use futures::StreamExt;
+
+    let wants_sse = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+
+    if wants_sse {
+        Sse::new(lines.map(|json| Ok::<_, Infallible>(Event::default().data(json)))).into_response()
+    } else {
+        let body = Body::from_stream(lines.map(|json| Ok::<_, Infallible>(format!("{}\n", json))));
+        ([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response()
+    }
+}
+
+/// Keep `guard` alive as long as `stream`, so a streamed response counts
+/// as in flight until it has been sent or the client disconnects
+fn hold_while_streaming<S: futures::Stream>(
+    stream: S,
+    guard: InFlightGuard,
+) -> impl futures::Stream<Item = S::Item> {
+    use futures::StreamExt;
+
+    stream.map(move |item| {
+        let _ = &guard;
+        item
+    })
+}
+
+/// Map a backend finish reason onto Ollama's `done_reason`
+///
+/// Ollama reports "length" when generation hit the token limit and "stop"
+/// otherwise, including streams where vLLM left the reason unset.
+fn done_reason(finish_reason: Option<&str>) -> String {
+    match finish_reason {
+        Some("length") => "length",
+        _ => "stop",
+    }
+    .to_string()
+}
+
+/// [`vllama_core::templates::render_custom`] on the blocking pool, so a
+/// heavy client template doesn't stall a runtime worker
+async fn render_custom_template(template: String, messages: Vec<ChatMessage>) -> vllama_core::Result<String> {
+    tokio::task::spawn_blocking(move || vllama_core::templates::render_custom(&template, &messages))
+        .await
+        .unwrap_or_else(|e| Err(vllama_core::Error::InferenceFailed(format!("template render task failed: {}", e))))
+}
+
+/// Render a conversation for the completions endpoint in the model's chat format
+async fn messages_to_prompt(state: &ServerState, model: &str, messages: &[ChatMessage]) -> String {
+    state.chat_template(model).await.render(messages)
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,40 +413,343 @@ pub struct GenerateApiRequest {
     pub prompt: String,
     #[serde(default = "default_stream")]
     pub stream: bool,
-    pub options: Option<GenerateOptionsApi>,
+    pub options: Option<OllamaOptions>,
+    /// Jinja template used instead of the model's prompt format
+    #[serde(default)]
+    pub template: Option<String>,
+    /// `"json"` for JSON output, or a JSON schema the output must match
+    #[serde(default)]
+    pub format: Option<serde_json::Value>,
+    /// How long the model stays loaded after this request: a duration such
+    /// as `"5m"` or seconds; negative keeps it loaded, 0 unloads it
+    #[serde(default)]
+    pub keep_alive: Option<serde_json::Value>,
 }
 
 fn default_stream() -> bool {
     true
 }
 
+impl GenerateApiRequest {
+    fn validate(&self) -> vllama_core::Result<()> {
+        if self.prompt.is_empty() {
+            return Err(vllama_core::Error::InvalidRequest("prompt must not be empty".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Ollama's `options` object on `/api/generate` and `/api/chat`
+///
+/// Sampling options vLLM supports are forwarded. The ones it lacks
+/// (mirostat, tail-free sampling, ...) are accepted and logged as ignored,
+/// as are Ollama's runtime settings (`num_gpu`, `num_thread`, `use_mmap`,
+/// ...), which vLLM takes at launch instead. Unknown keys land in `other`,
+/// and options serialize under the names the client sent, so a client's
+/// options survive a round trip.
 #[derive(Debug, Deserialize, Serialize)]
-pub struct GenerateOptionsApi {
-    #[serde(default)]
+pub struct OllamaOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
-    #[serde(default)]
+    /// Drop tokens below this fraction of the most likely token's probability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Ollama's `repeat_penalty`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f32>,
+    /// vLLM's name for `repeat_penalty`, used if that isn't set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// One stop sequence or a list of them
+    #[serde(
+        default,
+        deserialize_with = "vllama_core::request::optional_stop_sequences",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub stop: Option<Vec<String>>,
+    /// Keep a matched `stop` string at the end of the response (trimmed
+    /// by default)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include_stop_str_in_output: Option<bool>,
+    /// Ollama's token limit; `-1` is unlimited and `-2` fills the context
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_predict: Option<i64>,
+    /// Requested context window; validated against vLLM's `--max-model-len`,
+    /// which is fixed at launch and can't be grown per request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<usize>,
+    /// `/api/chat` only: a conversation too long for the context window
+    /// loses its oldest turns instead of failing, keeping this many leading
+    /// messages, every system message and the last message. Not Ollama's
+    /// `num_keep`, which counts prompt tokens; that one lands in `other`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_keep: Option<usize>,
+    /// Tokens the repeat, frequency and presence penalties look back over.
+    /// vLLM always uses the whole context, which is `-1`; `0` turns the
+    /// penalties off and any other window is ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat_last_n: Option<i64>,
+
+    // Ollama sampling options vLLM has no equivalent for
+    /// 0 is off; 1 and 2 are ignored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat_tau: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirostat_eta: Option<f32>,
+    /// Tail-free sampling; 1.0 is off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tfs_z: Option<f32>,
+    /// Locally typical sampling; 1.0 is off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typical_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub penalize_newline: Option<bool>,
+
+    /// Everything else, e.g. Ollama's runtime settings; ignored
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl OllamaOptions {
+    /// Names of the options that were set but have no effect
+    ///
+    /// Settings that are off anyway (`mirostat: 0`, `tfs_z: 1.0`, ...) don't
+    /// count.
+    fn ignored(&self) -> Vec<String> {
+        let sampling = [
+            ("repeat_last_n", self.repeat_last_n.is_some_and(|n| n > 0)),
+            ("mirostat", self.mirostat.is_some_and(|m| m != 0)),
+            ("mirostat_tau", self.mirostat_tau.is_some() && self.mirostat.is_some_and(|m| m != 0)),
+            ("mirostat_eta", self.mirostat_eta.is_some() && self.mirostat.is_some_and(|m| m != 0)),
+            ("tfs_z", self.tfs_z.is_some_and(|z| z != 1.0)),
+            ("typical_p", self.typical_p.is_some_and(|p| p != 1.0)),
+            ("penalize_newline", self.penalize_newline.is_some()),
+        ];
+        sampling
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| name.to_string())
+            .chain(self.other.keys().cloned())
+            .collect()
+    }
+
+    /// Copy the options that were set onto `sampling`, keeping its defaults
+    /// for the rest; `num_predict` and `num_ctx` need the backend and are
+    /// handled separately
+    ///
+    /// Returns a warning for each option that was ignored.
+    fn apply(&self, sampling: &mut SamplingParams) -> Vec<String> {
+        let ignored = self.ignored();
+        if !ignored.is_empty() {
+            warn!("Ignoring options vLLM doesn't support: {}", ignored.join(", "));
+        }
+
+        if let Some(temp) = self.temperature {
+            sampling.temperature = temp;
+        }
+        if let Some(top_p) = self.top_p {
+            sampling.top_p = top_p;
+        }
+        if let Some(min_p) = self.min_p {
+            sampling.min_p = Some(min_p);
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            sampling.max_tokens = Some(max_tokens);
+        }
+        if let Some(min_tokens) = self.min_tokens {
+            sampling.min_tokens = Some(min_tokens);
+        }
+        if let Some(seed) = self.seed {
+            sampling.seed = Some(seed);
+        }
+        if let Some(top_k) = self.top_k {
+            sampling.top_k = Some(top_k);
+        }
+        if let Some(penalty) = self.repeat_penalty.or(self.repetition_penalty) {
+            sampling.repetition_penalty = penalty;
+        }
+        if let Some(penalty) = self.frequency_penalty {
+            sampling.frequency_penalty = penalty;
+        }
+        if let Some(penalty) = self.presence_penalty {
+            sampling.presence_penalty = penalty;
+        }
+        if let Some(stop) = &self.stop {
+            sampling.stop_sequences = stop.clone();
+        }
+        if let Some(include) = self.include_stop_str_in_output {
+            sampling.include_stop_str_in_output = include;
+        }
+        if self.repeat_last_n == Some(0) {
+            // Nothing to look back over, so nothing is penalized
+            sampling.repetition_penalty = 1.0;
+            sampling.frequency_penalty = 0.0;
+            sampling.presence_penalty = 0.0;
+        }
+
+        ignored
+            .into_iter()
+            .map(|name| format!("option '{}' ignored: not supported by vLLM", name))
+            .collect()
+    }
+}
+
+/// Apply Ollama's `format` to `options`
+///
+/// `"json"` turns on vLLM's JSON mode and a schema object becomes
+/// `guided_json`; an empty string or null means no constraint.
+fn apply_format(format: Option<&serde_json::Value>, options: &mut GenerateOptions) -> vllama_core::Result<()> {
+    use serde_json::Value;
+    match format {
+        None | Some(Value::Null) => {}
+        Some(Value::String(f)) if f.is_empty() => {}
+        Some(Value::String(f)) if f == "json" => options.response_format = Some(ResponseFormat::JsonObject),
+        Some(schema @ Value::Object(_)) => options.guided = Some(GuidedDecoding::Json(schema.clone())),
+        Some(other) => {
+            return Err(vllama_core::Error::InvalidRequest(format!(
+                "format must be \"json\" or a JSON schema, got {}",
+                other
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Ollama's `keep_alive`: seconds, or a Go-style duration (`"90s"`,
+/// `"5m"`, `"1h30m"`); anything negative keeps the model loaded
+fn parse_keep_alive(keep_alive: Option<&serde_json::Value>) -> vllama_core::Result<Option<KeepAlive>> {
+    use serde_json::Value;
+    let invalid = || {
+        vllama_core::Error::InvalidRequest(format!(
+            "keep_alive must be a duration such as \"5m\" or a number of seconds, got {}",
+            keep_alive.unwrap_or(&Value::Null)
+        ))
+    };
+    let seconds = |secs: f64| match secs {
+        s if s < 0.0 => Ok(KeepAlive::Forever),
+        s if s.is_finite() => Ok(KeepAlive::For(Duration::from_secs_f64(s))),
+        _ => Err(invalid()),
+    };
+
+    let text = match keep_alive {
+        None | Some(Value::Null) => return Ok(None),
+        Some(Value::Number(n)) => return n.as_f64().map(seconds).unwrap_or_else(|| Err(invalid())).map(Some),
+        Some(Value::String(text)) => text.trim(),
+        Some(_) => return Err(invalid()),
+    };
+    if text.starts_with('-') {
+        return Ok(Some(KeepAlive::Forever));
+    }
+    if let Ok(secs) = text.parse::<f64>() {
+        return seconds(secs).map(Some);
+    }
+
+    let mut total = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(number_len);
+        let unit_len = tail.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let scale = match unit {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        total += number.parse::<f64>().map_err(|_| invalid())? * scale;
+        rest = tail;
+    }
+    seconds(total).map(Some)
+}
+
+/// An OpenAI `response_format` to forward to vLLM
+///
+/// `text` is the same as leaving it out. vLLM takes only one way of
+/// constraining output, so it can't be combined with a `guided_*` field.
+fn response_format(format: Option<&ResponseFormat>, guided: bool) -> vllama_core::Result<Option<ResponseFormat>> {
+    match format {
+        None | Some(ResponseFormat::Text) => Ok(None),
+        Some(_) if guided => Err(vllama_core::Error::InvalidRequest(
+            "response_format can't be combined with guided_json, guided_regex or guided_choice".to_string(),
+        )),
+        Some(format) => Ok(Some(format.clone())),
+    }
+}
+
+/// Warnings for the parameters of an OpenAI request that are ignored
+///
+/// Explicit nulls don't count, since clients send them for "unset".
+fn ignored_parameters(other: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+    let ignored: Vec<&String> = other.iter().filter(|(_, value)| !value.is_null()).map(|(name, _)| name).collect();
+    if !ignored.is_empty() {
+        let names: Vec<&str> = ignored.iter().map(|name| name.as_str()).collect();
+        warn!("Ignoring parameters vllama doesn't forward: {}", names.join(", "));
+    }
+    ignored
+        .into_iter()
+        .map(|name| format!("parameter '{}' ignored: not supported by vllama", name))
+        .collect()
+}
+
+/// `warnings` for a reply: everything collected, or nothing unless
+/// `server.report_warnings` is on
+fn reported_warnings(state: &ServerState, warnings: Vec<String>) -> Vec<String> {
+    if state.config().report_warnings {
+        warnings
+    } else {
+        Vec::new()
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct GenerateApiResponse {
+    /// Server-assigned ID, also used in logs
+    pub request_id: RequestId,
     pub model: String,
     pub response: String,
     pub done: bool,
+    /// Why generation stopped ("stop" or "length"); set on the final event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_duration: Option<u64>,
+    /// Tokens generated; set on the final event
     #[serde(skip_serializing_if = "Option::is_none")]
     pub eval_count: Option<usize>,
+    /// Tokens in the prompt; set on the final event when the backend
+    /// reports usage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_eval_count: Option<usize>,
+    /// Request options that had no effect; set on the final event when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TagsResponse {
     pub models: Vec<ModelInfo>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ModelInfo {
     pub name: String,
     pub size: u64,
@@ -81,18 +762,57 @@ pub struct ChatApiRequest {
     pub messages: Vec<ChatMessage>,
     #[serde(default = "default_stream")]
     pub stream: bool,
-    pub options: Option<GenerateOptionsApi>,
+    pub options: Option<OllamaOptions>,
+    /// Jinja template used instead of the model's chat format; the reply is
+    /// then generated from the rendered prompt rather than vLLM's chat API
+    #[serde(default)]
+    pub template: Option<String>,
+    /// `"json"` for JSON output, or a JSON schema the output must match
+    #[serde(default)]
+    pub format: Option<serde_json::Value>,
+    /// How long the model stays loaded after this request: a duration such
+    /// as `"5m"` or seconds; negative keeps it loaded, 0 unloads it
+    #[serde(default)]
+    pub keep_alive: Option<serde_json::Value>,
 }
 
+/// A `/api/chat` reply
+///
+/// When streaming, `message.content` holds only the text generated since
+/// the previous event, so concatenating all events yields the full reply.
+/// The final event has `done: true`, empty content and the stats.
 #[derive(Debug, Serialize)]
 pub struct ChatApiResponse {
+    /// Server-assigned ID, also used in logs
+    pub request_id: RequestId,
     pub model: String,
     pub message: ChatMessage,
     pub done: bool,
+    /// Why generation stopped ("stop" or "length"); set on the final event
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub done_reason: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_duration: Option<u64>,
+    /// Tokens generated; set on the final event
     #[serde(skip_serializing_if = "Option::is_none")]
     pub eval_count: Option<usize>,
+    /// Tokens in the prompt; set on the final event when the backend
+    /// reports usage
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_eval_count: Option<usize>,
+    /// Request options that had no effect; set on the final event when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl ChatApiRequest {
+    fn validate(&self) -> vllama_core::Result<()> {
+        if self.messages.is_empty() {
+            return Err(vllama_core::Error::InvalidRequest("messages must not be empty".to_string()));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -110,6 +830,8 @@ pub struct ShowApiResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub template: Option<String>,
     pub details: ModelDetails,
+    /// What the model supports ("completion", "chat", "vision", "embedding")
+    pub capabilities: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -127,9 +849,13 @@ pub struct PullApiRequest {
     pub model: String,
     #[serde(default = "default_stream")]
     pub stream: bool,
+    /// Branch, tag or commit to pull instead of `main`; `model@revision`
+    /// works too
+    #[serde(default)]
+    pub revision: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PullApiResponse {
     pub status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -138,6 +864,86 @@ pub struct PullApiResponse {
     pub total: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl PullApiResponse {
+    fn status(status: impl Into<String>) -> Self {
+        Self {
+            status: status.into(),
+            digest: None,
+            total: None,
+            completed: None,
+            error: None,
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            error: Some(message.into()),
+            ..Self::status("error")
+        }
+    }
+}
+
+/// Request for `/api/embed` and `/api/embeddings`
+///
+/// `prompt` is the single-text form and gets `{"embedding": [...]}` back;
+/// `input` (a string or a list) gets `{"model", "embeddings": [[...]]}`.
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingsApiRequest {
+    pub model: String,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub input: Option<EmbedInput>,
+    /// Scale each vector to unit L2 norm
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbedInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingApiResponse {
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedApiResponse {
+    pub model: String,
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PullCancelApiRequest {
+    #[serde(alias = "name")]
+    pub model: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteApiRequest {
+    #[serde(alias = "name")]
+    pub model: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyApiRequest {
+    pub source: String,
+    pub destination: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteApiResponse {
+    /// Repository that was removed from the cache
+    pub model: String,
+    pub freed_mb: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -146,10 +952,46 @@ pub struct OpenAIChatRequest {
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
     pub stream: bool,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// vLLM extension, accepted as OpenAI clients targeting vLLM send it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
+    /// One stop sequence or a list of them
+    #[serde(default, deserialize_with = "vllama_core::request::optional_stop_sequences")]
+    pub stop: Option<Vec<String>>,
+    /// vLLM extension; keep a matched `stop` string in the output
+    #[serde(default)]
+    pub include_stop_str_in_output: Option<bool>,
+    /// vLLM guided decoding; at most one of these may be set
+    #[serde(default)]
+    pub guided_json: Option<serde_json::Value>,
+    #[serde(default)]
+    pub guided_regex: Option<String>,
+    #[serde(default)]
+    pub guided_choice: Option<Vec<String>>,
+    /// `{"type": "json_object"}` for JSON mode, or a `json_schema`
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// Everything else (`n`, `logprobs`, `logit_bias`, ...); ignored
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -161,6 +1003,10 @@ pub struct OpenAIChatResponse {
     pub choices: Vec<OpenAIChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<OpenAIUsage>,
+    /// Request parameters that had no effect; set when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -184,6 +1030,12 @@ pub struct OpenAIChatChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<OpenAIChunkChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+    /// Request parameters that had no effect; set on the final chunk when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -201,6 +1053,30 @@ pub struct OpenAIDelta {
     pub content: Option<String>,
 }
 
+// OpenAI Embeddings API
+#[derive(Debug, Deserialize)]
+pub struct OpenAIEmbeddingRequest {
+    pub model: String,
+    pub input: EmbedInput,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenAIEmbeddingResponse {
+    pub object: String,
+    pub data: Vec<OpenAIEmbedding>,
+    pub model: String,
+    /// Left out when vLLM doesn't report token counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<OpenAIUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenAIEmbedding {
+    pub object: String,
+    pub embedding: Vec<f32>,
+    pub index: usize,
+}
+
 // OpenAI Models API
 #[derive(Debug, Serialize)]
 pub struct OpenAIModelsResponse {
@@ -208,12 +1084,27 @@ pub struct OpenAIModelsResponse {
     pub data: Vec<OpenAIModel>,
 }
 
+impl OpenAIModelsResponse {
+    fn empty() -> Self {
+        Self {
+            object: "list".to_string(),
+            data: Vec::new(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct OpenAIModel {
     pub id: String,
     pub object: String,
     pub created: u64,
     pub owned_by: String,
+    /// Model path behind `id`, which may be a --served-model-name alias
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
+    /// Base model a LoRA adapter is applied to (None for base models)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
 }
 
 // OpenAI Completions API (legacy)
@@ -226,9 +1117,41 @@ pub struct OpenAICompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// vLLM extension, accepted as OpenAI clients targeting vLLM send it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repetition_penalty: Option<f32>,
+    /// One stop sequence or a list of them
+    #[serde(default, deserialize_with = "vllama_core::request::optional_stop_sequences")]
+    pub stop: Option<Vec<String>>,
+    /// vLLM extension; keep a matched `stop` string in the output
+    #[serde(default)]
+    pub include_stop_str_in_output: Option<bool>,
+    /// vLLM guided decoding; at most one of these may be set
+    #[serde(default)]
+    pub guided_json: Option<serde_json::Value>,
+    #[serde(default)]
+    pub guided_regex: Option<String>,
+    #[serde(default)]
+    pub guided_choice: Option<Vec<String>>,
+    /// `{"type": "json_object"}` for JSON mode, or a `json_schema`
+    #[serde(default)]
+    pub response_format: Option<ResponseFormat>,
+    /// Everything else (`n`, `logprobs`, `logit_bias`, ...); ignored
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -240,6 +1163,10 @@ pub struct OpenAICompletionResponse {
     pub choices: Vec<OpenAICompletionChoice>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<OpenAIUsage>,
+    /// Request parameters that had no effect; set when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -256,6 +1183,10 @@ pub struct OpenAICompletionChunk {
     pub created: u64,
     pub model: String,
     pub choices: Vec<OpenAICompletionChunkChoice>,
+    /// Request parameters that had no effect; set on the final chunk when
+    /// `server.report_warnings` is on
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -267,102 +1198,171 @@ pub struct OpenAICompletionChunkChoice {
 
 pub async fn generate(
     State(state): State<ServerState>,
-    Json(req): Json<GenerateApiRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<GenerateApiRequest>,
 ) -> Response {
-    info!("Generate request for model: {}", req.model);
+    req.model = state.resolve_model_name(&req.model);
+    let request_id = state.next_request_id();
+    info!("Generate request {} for model: {}", request_id, req.model);
 
-    let mut gen_req = GenerateRequest::new(
-        0,  // Request ID
-        req.model.clone(),
-        req.prompt.clone(),
+    if let Err(e) = req.validate() {
+        return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+    }
+    match parse_keep_alive(req.keep_alive.as_ref()) {
+        Ok(keep_alive) => state.set_keep_alive(&req.model, keep_alive),
+        Err(e) => {
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+        }
+    }
+
+    let prompt = match &req.template {
+        Some(template) => match render_custom_template(template.clone(), vec![ChatMessage::user(req.prompt.as_str())]).await {
+            Ok(prompt) => prompt,
+            Err(e) => {
+                return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+            }
+        },
+        None => req.prompt.clone(),
+    };
+
+    let mut warnings: Vec<String> = match check_num_ctx(&state, &req.model, req.options.as_ref()).await {
+        Ok(warning) => warning.into_iter().collect(),
+        Err(response) => return response,
+    };
+
+    let mut gen_req = GenerateRequest::new(
+        request_id.0,
+        req.model.clone(),
+        prompt.clone(),
     );
 
     if let Some(opts) = req.options {
         let mut gen_opts = GenerateOptions::default();
-        if let Some(temp) = opts.temperature {
-            gen_opts.sampling.temperature = temp;
-        }
-        if let Some(top_p) = opts.top_p {
-            gen_opts.sampling.top_p = top_p;
-        }
-        if let Some(max_tokens) = opts.max_tokens {
-            gen_opts.sampling.max_tokens = Some(max_tokens);
+        warnings.extend(opts.apply(&mut gen_opts.sampling));
+        if let Some(num_predict) = opts.num_predict {
+            let tokenize = serde_json::json!({"model": req.model, "prompt": prompt});
+            match num_predict_limit(&state, &req.model, num_predict, tokenize).await {
+                Ok(limit) => gen_opts.sampling.max_tokens = limit,
+                Err(e) => {
+                    return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+                }
+            }
         }
         gen_req.options = gen_opts;
     }
 
+    let validation = gen_req
+        .options
+        .sampling
+        .validate()
+        .and_then(|_| apply_format(req.format.as_ref(), &mut gen_req.options));
+    if let Err(e) = validation {
+        return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+    }
+    let warnings = reported_warnings(&state, warnings);
+
+    let in_flight = state.track_in_flight(&req.model);
+    let stats = state.model_stats(&req.model);
+    tracing::Span::current().record("model", req.model.as_str());
+    let start = Instant::now();
+
     if req.stream {
-        let engine = state.engine.lock().await;
-        match engine.generate_stream(gen_req).await {
+        // For the token counts on the final event
+        gen_req.options.include_usage = true;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        let result = engine.generate_stream(gen_req).await;
+        drop(engine);
+        match result {
             Ok(stream) => {
                 use futures::StreamExt;
 
                 let event_stream = stream::unfold(
-                    (stream, req.model.clone(), 0usize, false),
-                    |(mut s, model, count, done)| async move {
+                    (stream, req.model.clone(), StreamCounts::default(), None::<String>, stats, warnings, false),
+                    move |(mut s, model, mut counts, finish_reason, stats, warnings, done)| async move {
                         if done {
                             return None;
                         }
                         match s.next().await {
                             Some(Ok(resp)) => {
+                                counts.add(&resp);
+                                let finish_reason = resp.finish_reason.or(finish_reason);
+                                let text = resp.text.clone();
                                 let event = GenerateApiResponse {
+                                    request_id,
                                     model: model.clone(),
                                     response: resp.text,
                                     done: false,
+                                    done_reason: None,
                                     total_duration: None,
                                     eval_count: None,
+                                    prompt_eval_count: None,
+                                    warnings: Vec::new(),
                                 };
-                                let json = serde_json::to_string(&event).unwrap();
-                                Some((
-                                    Ok::<_, Infallible>(Event::default().data(json)),
-                                    (s, model, count + 1, false)
-                                ))
+                                // vLLM's trailing usage chunk has no text to send
+                                let json = (!text.is_empty()).then(|| serde_json::to_string(&event).unwrap());
+                                Some((json, (s, model, counts, finish_reason, stats, warnings, false)))
                             }
                             Some(Err(e)) => {
-                                error!("Stream error: {}", e);
+                                error!("Stream error (request {}): {}", request_id, e);
                                 None
                             }
                             None => {
+                                record_completion(&stats, counts.eval_count(), start.elapsed());
                                 let final_event = GenerateApiResponse {
+                                    request_id,
                                     model,
                                     response: String::new(),
                                     done: true,
+                                    done_reason: Some(done_reason(finish_reason.as_deref())),
                                     total_duration: None,
-                                    eval_count: Some(count),
+                                    eval_count: Some(counts.eval_count()),
+                                    prompt_eval_count: counts.prompt_eval_count(),
+                                    warnings,
                                 };
                                 let json = serde_json::to_string(&final_event).unwrap();
-                                Some((Ok(Event::default().data(json)), (s, String::new(), count, true)))
+                                Some((Some(json), (s, String::new(), counts, None, stats, Vec::new(), true)))
                             }
                         }
                     }
-                );
+                )
+                .filter_map(futures::future::ready);
 
-                Sse::new(event_stream).into_response()
+                ollama_stream(&headers, hold_while_streaming(event_stream, in_flight))
             }
             Err(e) => {
-                error!("Streaming generation failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Streaming generation failed (request {}): {}", request_id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": format!("Generation failed: {}", e)
                 }))).into_response()
             }
         }
     } else {
-        let start = Instant::now();
-        let engine = state.engine.lock().await;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
         match engine.generate(gen_req).await {
             Ok(resp) => {
                 let duration = start.elapsed();
+                record_completion(&stats, resp.stats.generated_tokens, duration);
                 Json(GenerateApiResponse {
+                    request_id,
                     model: req.model,
                     response: resp.text,
                     done: true,
+                    done_reason: Some(done_reason(resp.finish_reason.as_deref())),
                     total_duration: Some(duration.as_nanos() as u64),
-                    eval_count: None,
+                    eval_count: Some(resp.stats.generated_tokens),
+                    prompt_eval_count: Some(resp.stats.prompt_tokens),
+                    warnings,
                 }).into_response()
             }
             Err(e) => {
-                error!("Generation failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Generation failed (request {}): {}", request_id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": format!("Generation failed: {}", e)
                 }))).into_response()
             }
@@ -370,7 +1370,187 @@ pub async fn generate(
     }
 }
 
-pub async fn tags(State(state): State<ServerState>) -> Json<TagsResponse> {
+/// Token counts gathered while streaming an Ollama reply
+#[derive(Debug, Default, Clone, Copy)]
+struct StreamCounts {
+    /// Chunks that carried text
+    chunks: usize,
+    /// Prompt and generated tokens from vLLM's usage, once it arrives
+    usage: Option<(usize, usize)>,
+}
+
+impl StreamCounts {
+    fn add(&mut self, resp: &GenerateResponse) {
+        if !resp.text.is_empty() {
+            self.chunks += 1;
+        }
+        if resp.stats.total_tokens > 0 {
+            self.usage = Some((resp.stats.prompt_tokens, resp.stats.generated_tokens));
+        }
+    }
+
+    /// Generated tokens as vLLM counted them, or one per chunk if it didn't
+    /// report usage
+    fn eval_count(&self) -> usize {
+        self.usage.map_or(self.chunks, |(_, generated)| generated)
+    }
+
+    fn prompt_eval_count(&self) -> Option<usize> {
+        self.usage.map(|(prompt, _)| prompt)
+    }
+}
+
+/// Count a finished generation in the model's stats, the Prometheus
+/// histograms and the request log
+fn record_completion(stats: &ModelUsage, tokens: usize, latency: Duration) {
+    stats.record(tokens, latency);
+    tracing::Span::current().record("eval_count", tokens);
+}
+
+/// Scale `vector` to unit length; zero vectors are left as they are
+fn l2_normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+pub async fn embeddings(
+    State(state): State<ServerState>,
+    Json(mut req): Json<EmbeddingsApiRequest>,
+) -> Response {
+    req.model = state.resolve_model_name(&req.model);
+    let (inputs, batch) = match (req.input, req.prompt) {
+        (Some(EmbedInput::Many(inputs)), _) => (inputs, true),
+        (Some(EmbedInput::One(input)), _) => (vec![input], true),
+        (None, Some(prompt)) => (vec![prompt], false),
+        (None, None) => (Vec::new(), true),
+    };
+    if inputs.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": "input must not be empty"
+        }))).into_response();
+    }
+
+    info!("Embedding request for model {} ({} inputs)", req.model, inputs.len());
+
+    let engine = match lock_engine(&state).await {
+        Ok(engine) => engine,
+        Err(response) => return response,
+    };
+    let mut embeddings: Vec<Vec<f32>> = match engine.embed(req.model.clone(), inputs).await {
+        Ok(response) => response.data.into_iter().map(|data| data.embedding).collect(),
+        Err(e) => {
+            error!("Embedding failed: {}", e);
+            return (error_status(&e), Json(serde_json::json!({
+                "error": format!("Embedding failed: {}", e)
+            }))).into_response();
+        }
+    };
+    drop(engine);
+
+    if req.normalize {
+        embeddings.iter_mut().for_each(|v| l2_normalize(v));
+    }
+
+    if batch {
+        Json(EmbedApiResponse {
+            model: req.model,
+            embeddings,
+        }).into_response()
+    } else {
+        Json(EmbeddingApiResponse {
+            embedding: embeddings.into_iter().next().unwrap_or_default(),
+        }).into_response()
+    }
+}
+
+pub async fn openai_embeddings(
+    State(state): State<ServerState>,
+    Json(mut req): Json<OpenAIEmbeddingRequest>,
+) -> Response {
+    req.model = state.resolve_model_name(&req.model);
+    let inputs = match req.input {
+        EmbedInput::One(input) => vec![input],
+        EmbedInput::Many(inputs) => inputs,
+    };
+    if inputs.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": {
+                "message": "input must not be empty",
+                "type": "invalid_request_error"
+            }
+        }))).into_response();
+    }
+
+    info!("OpenAI embeddings request for model {} ({} inputs)", req.model, inputs.len());
+
+    let engine = match lock_engine(&state).await {
+        Ok(engine) => engine,
+        Err(response) => return response,
+    };
+    match engine.embed(req.model.clone(), inputs).await {
+        Ok(response) => Json(OpenAIEmbeddingResponse {
+            object: "list".to_string(),
+            data: response
+                .data
+                .into_iter()
+                .enumerate()
+                .map(|(index, data)| OpenAIEmbedding {
+                    object: "embedding".to_string(),
+                    embedding: data.embedding,
+                    index,
+                })
+                .collect(),
+            model: req.model,
+            usage: response.usage.map(|usage| OpenAIUsage {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: 0,
+                total_tokens: usage.total_tokens,
+            }),
+        }).into_response(),
+        Err(e) => {
+            error!("Embedding failed: {}", e);
+            let status = error_status(&e);
+            let error_type = if status.is_client_error() { "invalid_request_error" } else { "server_error" };
+            (status, Json(serde_json::json!({
+                "error": {
+                    "message": e.to_string(),
+                    "type": error_type
+                }
+            }))).into_response()
+        }
+    }
+}
+
+/// Validator for `/api/tags`: the loaded model set
+///
+/// The listing is built from the loaded model names alone, so hashing them
+/// is enough for polling clients to be answered with a 304 without
+/// rebuilding it.
+fn tags_etag(state: &ServerState) -> String {
+    let mut models: Vec<String> = state.loaded_models.iter().map(|e| e.key().clone()).collect();
+    models.sort();
+
+    let mut hasher = DefaultHasher::new();
+    models.hash(&mut hasher);
+    format!("\"{:x}\"", hasher.finish())
+}
+
+/// Whether an `If-None-Match` header matches `etag`
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == etag || tag == "*")
+        })
+}
+
+fn build_tags(state: &ServerState) -> TagsResponse {
     let mut models = Vec::new();
 
     for entry in state.loaded_models.iter() {
@@ -392,7 +1572,32 @@ pub async fn tags(State(state): State<ServerState>) -> Json<TagsResponse> {
         });
     }
 
-    Json(TagsResponse { models })
+    TagsResponse { models }
+}
+
+pub async fn tags(State(state): State<ServerState>, headers: HeaderMap) -> Response {
+    let etag = tags_etag(&state);
+    if etag_matches(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let cached = state
+        .tags_cache
+        .read()
+        .unwrap()
+        .as_ref()
+        .filter(|(cached_etag, _)| *cached_etag == etag)
+        .map(|(_, tags)| tags.clone());
+    let tags = match cached {
+        Some(tags) => tags,
+        None => {
+            let tags = build_tags(&state);
+            *state.tags_cache.write().unwrap() = Some((etag.clone(), tags.clone()));
+            tags
+        }
+    };
+
+    ([(header::ETAG, etag)], Json(tags)).into_response()
 }
 
 /// Health check response with detailed system information
@@ -407,10 +1612,23 @@ pub struct HealthResponse {
     /// GPU information (if available)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub gpu: Option<GpuInfo>,
+    /// "detected", "not_found", or "unknown" when the driver is present
+    /// but can't be queried (vLLM may still be using CUDA)
+    pub gpu_status: vllama_core::GpuStatus,
     /// System memory usage
     pub memory: MemoryInfo,
     /// Server uptime in seconds
     pub uptime_seconds: u64,
+    /// Time requests have spent waiting for the engine
+    pub queue: QueueSnapshot,
+    /// Model the server was started with (`serve --model`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub configured_model: Option<String>,
+    /// Models vLLM reports serving; empty when it can't be reached
+    pub vllm_models: Vec<String>,
+    /// Set when vLLM isn't serving `configured_model`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_mismatch: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -433,7 +1651,7 @@ pub async fn health(State(state): State<ServerState>) -> Json<HealthResponse> {
     use sysinfo::System;
 
     // Check vLLM server status
-    let vllm_status = check_vllm_health().await;
+    let vllm_status = check_vllm_health(&state.http, &state.vllm_url).await;
 
     // Get loaded models
     let models: Vec<String> = state
@@ -444,6 +1662,11 @@ pub async fn health(State(state): State<ServerState>) -> Json<HealthResponse> {
 
     // Get GPU info via nvidia-smi
     let gpu = get_gpu_info().await;
+    let gpu_status = match (&gpu, vllama_core::hardware::nvidia_driver_present()) {
+        (Some(_), _) => vllama_core::GpuStatus::Detected,
+        (None, true) => vllama_core::GpuStatus::Unknown,
+        (None, false) => vllama_core::GpuStatus::NotFound,
+    };
 
     // Get system memory info
     let mut sys = System::new_all();
@@ -458,21 +1681,43 @@ pub async fn health(State(state): State<ServerState>) -> Json<HealthResponse> {
     // Calculate uptime (simplified - just return 0 for now, could be enhanced)
     let uptime_seconds = 0;
 
+    let configured_model = state.config().default_model.clone();
+    let served = served_models(&state.http, &state.vllm_url).await;
+    let model_mismatch = model_mismatch(configured_model.as_deref(), &served);
+
     Json(HealthResponse {
         status: "ok".to_string(),
         vllm_status,
         models,
         gpu,
+        gpu_status,
         memory,
         uptime_seconds,
+        queue: state.queue.snapshot(),
+        configured_model,
+        vllm_models: served.into_iter().map(|(id, _)| id).collect(),
+        model_mismatch,
     })
 }
 
-async fn check_vllm_health() -> String {
+/// Readiness probe: 200 once vLLM answers its health check
+///
+/// vLLM only reports healthy after the model has loaded, so this gates
+/// traffic; `/health` stays 200 while the process is up and gates restarts.
+/// vLLM is asked directly rather than through the engine, so a probe never
+/// waits behind generations or model loads holding the engine lock.
+pub async fn ready(State(state): State<ServerState>) -> Response {
+    if check_vllm_health(&state.http, &state.vllm_url).await == "connected" {
+        Json(serde_json::json!({"status": "ready"})).into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({"status": "not ready"}))).into_response()
+    }
+}
+
+async fn check_vllm_health(client: &reqwest::Client, vllm_url: &str) -> String {
     // Try to query vLLM health endpoint
-    let client = reqwest::Client::new();
     match client
-        .get("http://127.0.0.1:8100/health")
+        .get(format!("{}/health", vllm_url))
         .timeout(std::time::Duration::from_secs(2))
         .send()
         .await
@@ -485,6 +1730,50 @@ async fn check_vllm_health() -> String {
     }
 }
 
+/// `(id, root)` of each model vLLM serves; empty if it can't be reached
+async fn served_models(client: &reqwest::Client, vllm_url: &str) -> Vec<(String, Option<String>)> {
+    #[derive(Deserialize)]
+    struct VllmModelsResponse {
+        data: Vec<VllmModelInfo>,
+    }
+
+    #[derive(Deserialize)]
+    struct VllmModelInfo {
+        id: String,
+        #[serde(default)]
+        root: Option<String>,
+    }
+
+    let response = client
+        .get(format!("{}/v1/models", vllm_url))
+        .timeout(std::time::Duration::from_secs(2))
+        .send()
+        .await;
+    match response {
+        Ok(resp) => match resp.json::<VllmModelsResponse>().await {
+            Ok(models) => models.data.into_iter().map(|m| (m.id, m.root)).collect(),
+            Err(_) => Vec::new(),
+        },
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Warning when vLLM answers but doesn't serve the configured model under
+/// either its served name or its repository
+fn model_mismatch(configured: Option<&str>, served: &[(String, Option<String>)]) -> Option<String> {
+    let configured = configured?;
+    if served.is_empty() || served.iter().any(|(id, root)| id == configured || root.as_deref() == Some(configured)) {
+        return None;
+    }
+
+    let names: Vec<&str> = served.iter().map(|(id, _)| id.as_str()).collect();
+    Some(format!(
+        "configured model '{}' is not what vLLM is serving ({}); requests for it will fail",
+        configured,
+        names.join(", ")
+    ))
+}
+
 async fn get_gpu_info() -> Option<GpuInfo> {
     // Query nvidia-smi for GPU information
     let output = tokio::process::Command::new("nvidia-smi")
@@ -516,25 +1805,236 @@ async fn get_gpu_info() -> Option<GpuInfo> {
     }
 }
 
+/// `/api/pull` status for `progress`: the downloader's, with the file's
+/// position when the model has several, e.g. `Downloading
+/// model-00002-of-00004.safetensors (6/8)`
+fn pull_status(progress: &vllama_core::DownloadProgress) -> String {
+    match &progress.current_file {
+        Some(_) if progress.file_count > 1 => {
+            format!("{} ({}/{})", progress.status, progress.file_index + 1, progress.file_count)
+        }
+        _ => progress.status.clone(),
+    }
+}
+
+/// Download a model (unless it is already on disk) at `revision` and load
+/// it into the engine, reporting progress to `progress`
+async fn download_and_load(
+    state: &ServerState,
+    downloader: &vllama_core::ModelDownloader,
+    model: &str,
+    source: &ModelSource,
+    revision: Option<&str>,
+    progress: impl Fn(vllama_core::DownloadProgress),
+) -> Result<(), String> {
+    let model_path = match source {
+        ModelSource::Local(path) => path.clone(),
+        ModelSource::Repo(repo_id) => downloader.download_model(repo_id, revision, progress).await.map_err(|e| {
+            error!("Failed to download model: {}", e);
+            match e {
+                vllama_core::Error::ModelNotFound(msg) => msg,
+                e => format!("Failed to download model from HuggingFace: {}. Check that the model repo and file exist. Example: 'Qwen/Qwen2.5-1.5B-Instruct'", e),
+            }
+        })?,
+    };
+
+    let need_mb = vram_estimate(state, &model_path);
+
+    let mut engine = state.engine.lock().await;
+    state.make_room(&mut *engine, model).await.map_err(|e| {
+        error!("Failed to make room for model: {}", e);
+        e.to_string()
+    })?;
+    if let Some(need_mb) = need_mb {
+        state.make_vram_room(&mut *engine, model, need_mb).await.map_err(|e| {
+            error!("Failed to make room for model: {}", e);
+            e.to_string()
+        })?;
+    }
+    let handle = engine.load_model(&model_path).await.map_err(|e| {
+        error!("Failed to load model: {}", e);
+        format!("Downloaded model successfully but failed to load it: {}. This may be due to MAX Engine limitations (only supports whitelisted models).", e)
+    })?;
+    // Its config files may be new
+    state.chat_templates.remove(model);
+    // Only a model that loaded holds VRAM
+    if let Some(need_mb) = need_mb {
+        state.model_vram.insert(model.to_string(), need_mb);
+    }
+    state.loaded_models.insert(model.to_string(), handle);
+    state.touch(model);
+    drop(engine);
+
+    if state.config().warmup_on_load {
+        state.warm_up(model).await;
+    }
+    Ok(())
+}
+
+/// Estimated VRAM, in MB, for the model at `path`
+///
+/// None unless `max_loaded_vram_mb` is set; a model whose `config.json`
+/// doesn't give its size is loaded without the check.
+fn vram_estimate(state: &ServerState, path: &std::path::Path) -> Option<u64> {
+    state.config().max_loaded_vram_mb?;
+    let config = vllama_core::model::read_model_json(&path.to_string_lossy(), "config.json");
+    let need_mb = config.as_ref().and_then(vllama_core::model::estimate_vram_mb);
+    if need_mb.is_none() {
+        warn!("Can't estimate the VRAM {} needs, loading it without the max_loaded_vram_mb check", path.display());
+    }
+    need_mb
+}
+
+/// Buffered progress events per pull; slow subscribers skip ahead rather
+/// than stall the download
+const PULL_EVENT_CAPACITY: usize = 100;
+
+/// A requester's view of a (possibly shared) pull
+///
+/// Dropping the last subscription aborts the download, so a pull whose
+/// clients have all disconnected doesn't keep running.
+struct PullSubscription {
+    events: broadcast::Receiver<PullApiResponse>,
+    abort: AbortHandle,
+    subscribers: Arc<AtomicUsize>,
+}
+
+impl PullSubscription {
+    fn new(task: &PullTask) -> Self {
+        task.subscribers.fetch_add(1, Ordering::SeqCst);
+        Self {
+            events: task.events.subscribe(),
+            abort: task.abort.clone(),
+            subscribers: task.subscribers.clone(),
+        }
+    }
+
+    /// Next event, or None once the pull has finished
+    async fn next(&mut self) -> Option<PullApiResponse> {
+        loop {
+            match self.events.recv().await {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Pull subscriber lagged, skipped {} progress events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for PullSubscription {
+    fn drop(&mut self) {
+        if self.subscribers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.abort.abort();
+        }
+    }
+}
+
+impl PullApiResponse {
+    /// Whether this is the last event of a pull
+    fn is_final(&self) -> bool {
+        self.status == "success" || self.error.is_some()
+    }
+}
+
+/// Key of a pull in `state.pulls`: the repo, plus `@revision` when one was asked for
+fn pull_key(model: &str, revision: Option<&str>) -> String {
+    match revision {
+        Some(revision) => format!("{}@{}", model, revision),
+        None => model.to_string(),
+    }
+}
+
+/// Join the in-flight pull of `model` at `revision`, or start one if none is running
+fn join_or_start_pull(
+    state: &ServerState,
+    model: &str,
+    source: ModelSource,
+    revision: Option<String>,
+    downloader: vllama_core::ModelDownloader,
+) -> PullSubscription {
+    let key = pull_key(model, revision.as_deref());
+    let (task, registration, subscription) = match state.pulls.entry(key.clone()) {
+        Entry::Occupied(entry) => {
+            info!("Joining in-flight pull for {}", key);
+            return PullSubscription::new(entry.get());
+        }
+        Entry::Vacant(entry) => {
+            let (abort, registration) = AbortHandle::new_pair();
+            let (events, _) = broadcast::channel(PULL_EVENT_CAPACITY);
+            let task = PullTask {
+                id: Uuid::new_v4(),
+                abort,
+                events,
+                subscribers: Arc::new(AtomicUsize::new(0)),
+            };
+            // Subscribe before publishing the task so a joiner that leaves
+            // straight away can't drop the count to zero and abort it
+            let subscription = PullSubscription::new(&task);
+            entry.insert(task.clone());
+            (task, registration, subscription)
+        }
+    };
+
+    let state = state.clone();
+    let model = model.to_string();
+
+    tokio::spawn(async move {
+        let work = download_and_load(&state, &downloader, &model, &source, revision.as_deref(), |progress| {
+            let _ = task.events.send(PullApiResponse {
+                status: pull_status(&progress),
+                digest: None,
+                total: if progress.total > 0 { Some(progress.total) } else { None },
+                completed: if progress.downloaded > 0 { Some(progress.downloaded) } else { None },
+                error: None,
+            });
+        });
+
+        let final_event = match Abortable::new(work, registration).await {
+            Ok(Ok(())) => PullApiResponse::status("success"),
+            Ok(Err(message)) => PullApiResponse::error(message),
+            Err(Aborted) => {
+                info!("Pull of {} cancelled", model);
+                PullApiResponse::error(format!("pull of {} was cancelled", model))
+            }
+        };
+
+        // Unregister before the final event so late joiners start a fresh pull
+        // instead of subscribing to one that has already finished
+        state.pulls.remove_if(&key, |_, t| t.id == task.id);
+        let _ = task.events.send(final_event);
+    });
+
+    subscription
+}
+
 pub async fn pull(
     State(state): State<ServerState>,
-    Json(req): Json<PullApiRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<PullApiRequest>,
 ) -> Response {
+    req.model = state.resolve_model_name(&req.model);
     info!("Pull request for model: {}", req.model);
 
-    if state.loaded_models.contains_key(&req.model) {
-        return Json(PullApiResponse {
-            status: "success".to_string(),
-            digest: None,
-            total: None,
-            completed: None,
-        }).into_response();
+    let (model, inline_revision) = vllama_core::split_revision(&req.model);
+    let revision = req.revision.clone().or(inline_revision.map(str::to_string));
+
+    // Which revision is loaded isn't tracked, so only a pull without one
+    // can be answered by the loaded model
+    if revision.is_none() && state.loaded_models.contains_key(model) {
+        return Json(PullApiResponse::status("success")).into_response();
     }
 
-    use vllama_core::{ModelDownloader, DownloadProgress};
-    use tokio::sync::mpsc;
+    // Bad paths and unknown names fail here, before a pull is started
+    let source = match ModelSource::resolve(model) {
+        Ok(source) => source,
+        Err(e) => {
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+        }
+    };
 
-    let downloader = match ModelDownloader::new() {
+    let downloader = match state.downloader() {
         Ok(d) => d,
         Err(e) => {
             error!("Failed to create downloader: {}", e);
@@ -544,149 +2044,274 @@ pub async fn pull(
         }
     };
 
-    if req.stream {
-        let model_name = req.model.clone();
-        let engine_clone = state.engine.clone();
-        let loaded_models_clone = state.loaded_models.clone();
-
-        let (tx, rx) = mpsc::channel::<DownloadProgress>(100);
-
-        tokio::spawn(async move {
-            let result = downloader.download_model(
-                &model_name,
-                |progress| {
-                    let _ = tx.try_send(progress);
-                }
-            ).await;
-
-            if let Ok(model_path) = result {
-                let mut engine = engine_clone.lock().await;
-                if let Ok(handle) = engine.load_model(&model_path).await {
-                    loaded_models_clone.insert(model_name, handle);
-                }
-            }
-        });
+    let mut subscription = join_or_start_pull(&state, model, source, revision, downloader);
 
+    if req.stream {
         let event_stream = stream::unfold(
-            rx,
-            |mut receiver| async move {
-                match receiver.recv().await {
-                    Some(progress) => {
-                        let event = PullApiResponse {
-                            status: progress.status.clone(),
-                            digest: None,
-                            total: if progress.total > 0 { Some(progress.total) } else { None },
-                            completed: if progress.downloaded > 0 { Some(progress.downloaded) } else { None },
-                        };
-                        Some((
-                            Ok::<_, Infallible>(Event::default().data(serde_json::to_string(&event).unwrap())),
-                            receiver
-                        ))
-                    }
-                    None => {
-                        let final_event = PullApiResponse {
-                            status: "success".to_string(),
-                            digest: None,
-                            total: None,
-                            completed: None,
-                        };
-                        Some((
-                            Ok(Event::default().data(serde_json::to_string(&final_event).unwrap())),
-                            receiver
-                        ))
-                    }
-                }
+            Some(subscription),
+            |subscription| async move {
+                let mut subscription = subscription?;
+                let event = subscription.next().await?;
+                let data = serde_json::to_string(&event).unwrap();
+                let next = if event.is_final() { None } else { Some(subscription) };
+                Some((data, next))
             }
         );
 
-        Sse::new(event_stream).into_response()
+        ollama_stream(&headers, event_stream)
     } else {
-        match downloader.download_model(&req.model, |_| {}).await {
-            Ok(model_path) => {
-                let mut engine = state.engine.lock().await;
-                match engine.load_model(&model_path).await {
-                    Ok(handle) => {
-                        state.loaded_models.insert(req.model.clone(), handle);
-                        Json(PullApiResponse {
-                            status: "success".to_string(),
-                            digest: None,
-                            total: None,
-                            completed: None,
-                        }).into_response()
-                    }
-                    Err(e) => {
-                        error!("Failed to load model: {}", e);
-                        (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
-                            "error": format!("Downloaded model successfully but failed to load it: {}. This may be due to MAX Engine limitations (only supports whitelisted models).", e)
-                        }))).into_response()
-                    }
-                }
+        let final_event = loop {
+            match subscription.next().await {
+                Some(event) if event.is_final() => break Some(event),
+                Some(_) => continue,
+                None => break None,
             }
-            Err(e) => {
-                error!("Failed to download model: {}", e);
+        };
+
+        match final_event {
+            Some(event) if event.error.is_none() => Json(event).into_response(),
+            Some(event) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                    "error": event.error
+                }))).into_response()
+            }
+            None => {
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
-                    "error": format!("Failed to download model from HuggingFace: {}. Check that the model repo and file exist. Example: 'bartowski/Llama-3.2-1B-Instruct-GGUF'", e)
+                    "error": format!("pull of {} ended unexpectedly", req.model)
                 }))).into_response()
             }
         }
     }
 }
 
-pub async fn show(
-    State(_state): State<ServerState>,
-    Json(req): Json<ShowApiRequest>,
+pub async fn pull_cancel(
+    State(state): State<ServerState>,
+    Json(req): Json<PullCancelApiRequest>,
 ) -> Response {
-    info!("Show request for model: {}", req.model);
+    info!("Pull cancel request for model: {}", req.model);
 
-    #[derive(Debug, Deserialize)]
-    struct VllmModelsResponse {
-        data: Vec<VllmModelInfo>,
+    match state.pulls.remove(&req.model) {
+        Some((_, task)) => {
+            task.abort.abort();
+            Json(PullApiResponse::status("cancelled")).into_response()
+        }
+        None => {
+            (StatusCode::NOT_FOUND, Json(serde_json::json!({
+                "error": format!("no pull in progress for model '{}'", req.model)
+            }))).into_response()
+        }
     }
+}
 
-    #[derive(Debug, Deserialize)]
-    struct VllmModelInfo {
-        id: String,
-        #[serde(default)]
-        #[allow(dead_code)]
-        max_model_len: Option<u64>,
-    }
+/// Remove a model from the HuggingFace cache, unloading it first if loaded
+///
+/// Deleting an alias removes only the name. 404 if the model isn't cached,
+/// 409 while it is being pulled. vLLM keeps serving a model it already has
+/// in memory; this only frees the disk space.
+pub async fn delete(
+    State(state): State<ServerState>,
+    Json(req): Json<DeleteApiRequest>,
+) -> Response {
+    info!("Delete request for model: {}", req.model);
 
-    let client = reqwest::Client::new();
-    let models_response = match client.get("http://127.0.0.1:8100/v1/models").send().await {
-        Ok(response) => match response.json::<VllmModelsResponse>().await {
-            Ok(data) => data,
-            Err(e) => {
-                error!("Failed to parse vLLM models response: {}", e);
-                return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
-                    "error": "Failed to query vLLM server"
-                }))).into_response();
-            }
-        },
+    // Deleting a copy only drops the name
+    match state.remove_alias(&req.model) {
+        Ok(Some(_)) => {
+            return Json(DeleteApiResponse { model: req.model, freed_mb: 0 }).into_response();
+        }
+        Ok(None) => {}
         Err(e) => {
-            error!("Failed to query vLLM models: {}", e);
-            return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({
-                "error": "vLLM server not available"
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+        }
+    }
+
+    // Not resolved through aliases: deleting one drops only the name, above
+    let repo_id = match ModelSource::resolve(&req.model) {
+        Ok(ModelSource::Repo(repo_id)) => repo_id,
+        Ok(ModelSource::Local(path)) => {
+            return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+                "error": format!("{} is a local path, not a cached model", path.display())
             }))).into_response();
         }
+        Err(e) => {
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+        }
     };
 
-    let model_info = models_response.data.iter().find(|m| m.id == req.model);
-    if model_info.is_none() {
-        return (StatusCode::NOT_FOUND, Json(serde_json::json!({
-            "error": format!("Model '{}' not found in vLLM server", req.model)
+    // Pulls are keyed by name, possibly with a revision
+    if state.pulls.iter().any(|pull| vllama_core::split_revision(pull.key()).0 == repo_id) {
+        return (StatusCode::CONFLICT, Json(serde_json::json!({
+            "error": format!("model '{}' is being pulled; cancel the pull or wait for it to finish", req.model)
         }))).into_response();
     }
 
-    let model_name = &req.model;
-
-    let (family, parameter_size) = if model_name.contains("llama") || model_name.contains("Llama") {
-        let size = if model_name.contains("70B") || model_name.contains("70b") {
-            "70B"
-        } else if model_name.contains("8B") || model_name.contains("8b") {
-            "8B"
-        } else if model_name.contains("3B") || model_name.contains("3b") {
-            "3B"
-        } else if model_name.contains("1B") || model_name.contains("1b") {
+    let downloader = match state.downloader() {
+        Ok(d) => Arc::new(d),
+        Err(e) => {
+            error!("Failed to create downloader: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                "error": format!("Failed to initialize downloader: {}", e)
+            }))).into_response();
+        }
+    };
+    // Scanning and removing the cache walk the filesystem
+    let list = downloader.clone();
+    let cached = tokio::task::spawn_blocking(move || list.list_cached_models().unwrap_or_default())
+        .await
+        .unwrap_or_default();
+    let Some(entry) = cached.into_iter().find(|m| m.name == repo_id) else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({
+            "error": format!("model '{}' not found", req.model)
+        }))).into_response();
+    };
+
+    // Loaded under whichever name requested it: a short name or the repo
+    // id, either possibly with a revision
+    let is_repo = |name: &str| {
+        matches!(ModelSource::resolve(vllama_core::split_revision(name).0), Ok(ModelSource::Repo(id)) if id == repo_id)
+    };
+    let loaded: Vec<String> = state
+        .loaded_models
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|name| is_repo(name))
+        .collect();
+    if !loaded.is_empty() {
+        // Locked before the entries go, so a timeout leaves the model tracked
+        let mut engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        for name in &loaded {
+            if let Some((_, handle)) = state.loaded_models.remove(name) {
+                if let Err(e) = engine.unload_model(handle).await {
+                    warn!("Failed to unload {} before deleting it: {}", name, e);
+                }
+                state.last_used.remove(name);
+                state.model_vram.remove(name);
+            }
+        }
+    }
+
+    let remove_id = repo_id.clone();
+    let deleted = tokio::task::spawn_blocking(move || downloader.delete_model(&remove_id))
+        .await
+        .unwrap_or_else(|e| Err(vllama_core::Error::ConfigError(format!("delete task failed: {}", e))));
+    state.chat_templates.retain(|name, _| !is_repo(name));
+    match deleted {
+        Ok(()) => Json(DeleteApiResponse {
+            model: repo_id,
+            freed_mb: entry.size_mb,
+        }).into_response(),
+        Err(e) => {
+            error!("Failed to delete {}: {}", repo_id, e);
+            (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response()
+        }
+    }
+}
+
+/// Make `destination` another name for the cached model `source`
+///
+/// Models are cached once per repository, so the copy is an alias that
+/// `/api/generate`, `/api/chat` and `/api/show` resolve. Copying an alias
+/// points the new name at the original model. 404 if `source` isn't cached
+/// (or, for a local model, doesn't exist).
+pub async fn copy(
+    State(state): State<ServerState>,
+    Json(req): Json<CopyApiRequest>,
+) -> Response {
+    info!("Copy request: {} -> {}", req.source, req.destination);
+
+    let source = state.resolve_model_name(&req.source);
+    if req.destination.is_empty() || req.destination == source {
+        return (StatusCode::BAD_REQUEST, Json(serde_json::json!({
+            "error": "destination must be a new name for the model"
+        }))).into_response();
+    }
+
+    let cached = match ModelSource::resolve(&source) {
+        Ok(ModelSource::Local(_)) => true,
+        Ok(ModelSource::Repo(repo_id)) => state
+            .downloader()
+            .and_then(|d| d.list_cached_models())
+            .is_ok_and(|models| models.iter().any(|m| m.name == repo_id)),
+        Err(_) => false,
+    };
+    if !cached {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({
+            "error": format!("model '{}' not found", req.source)
+        }))).into_response();
+    }
+
+    match state.set_alias(&req.destination, &source) {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(e) => (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response(),
+    }
+}
+
+pub async fn show(
+    State(state): State<ServerState>,
+    Json(mut req): Json<ShowApiRequest>,
+) -> Response {
+    info!("Show request for model: {}", req.model);
+    req.model = state.resolve_model_name(&req.model);
+
+    #[derive(Debug, Deserialize)]
+    struct VllmModelsResponse {
+        data: Vec<VllmModelInfo>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct VllmModelInfo {
+        id: String,
+        /// Underlying model path; differs from `id` under --served-model-name
+        #[serde(default)]
+        root: Option<String>,
+        #[serde(default)]
+        #[allow(dead_code)]
+        max_model_len: Option<u64>,
+    }
+
+    let models_response = match state.http.get(format!("{}/v1/models", state.vllm_url)).send().await {
+        Ok(response) => match response.json::<VllmModelsResponse>().await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to parse vLLM models response: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                    "error": "Failed to query vLLM server"
+                }))).into_response();
+            }
+        },
+        Err(e) => {
+            error!("Failed to query vLLM models: {}", e);
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({
+                "error": "vLLM server not available"
+            }))).into_response();
+        }
+    };
+
+    // Accept both the served alias and the repository it points at
+    let model_info = models_response
+        .data
+        .iter()
+        .find(|m| m.id == req.model || m.root.as_deref() == Some(req.model.as_str()));
+    let Some(model_info) = model_info else {
+        return (StatusCode::NOT_FOUND, Json(serde_json::json!({
+            "error": format!("Model '{}' not found in vLLM server", req.model)
+        }))).into_response();
+    };
+
+    // Metadata comes from the repository, not the alias
+    let model_name = model_info.root.as_ref().unwrap_or(&model_info.id);
+
+    let (family, parameter_size) = if model_name.contains("llama") || model_name.contains("Llama") {
+        let size = if model_name.contains("70B") || model_name.contains("70b") {
+            "70B"
+        } else if model_name.contains("8B") || model_name.contains("8b") {
+            "8B"
+        } else if model_name.contains("3B") || model_name.contains("3b") {
+            "3B"
+        } else if model_name.contains("1B") || model_name.contains("1b") {
             "1B"
         } else {
             "unknown"
@@ -715,7 +2340,7 @@ pub async fn show(
     };
 
     let response = ShowApiResponse {
-        modelfile: format!("# Modelfile for {}\n# Loaded via vLLama + vLLM", model_name),
+        modelfile: format!("# Modelfile for {}\n# Loaded via vLLama + vLLM", model_info.id),
         parameters: "temperature 0.7\ntop_p 0.9\nrepetition_penalty 1.0".to_string(),
         template: Some("{{ .System }}\n{{ .Prompt }}".to_string()),
         details: ModelDetails {
@@ -725,6 +2350,7 @@ pub async fn show(
             parameter_size: parameter_size.to_string(),
             quantization_level: "none".to_string(),
         },
+        capabilities: vllama_core::model::model_capabilities(model_name),
     };
 
     Json(response).into_response()
@@ -732,14 +2358,16 @@ pub async fn show(
 
 pub async fn openai_chat_completions(
     State(state): State<ServerState>,
-    Json(req): Json<OpenAIChatRequest>,
+    Json(mut req): Json<OpenAIChatRequest>,
 ) -> Response {
-    info!("OpenAI chat completions request for model: {}", req.model);
+    req.model = state.resolve_model_name(&req.model);
+    let id = state.next_request_id();
+    info!("OpenAI chat completions request {} for model: {}", id, req.model);
 
-    let prompt = messages_to_prompt(&req.messages);
+    let prompt = messages_to_prompt(&state, &req.model, &req.messages).await;
 
     let mut gen_req = GenerateRequest::new(
-        0,  // Request ID
+        id.0,
         req.model.clone(),
         prompt,
     );
@@ -751,36 +2379,138 @@ pub async fn openai_chat_completions(
     if let Some(max_tokens) = req.max_tokens {
         gen_opts.sampling.max_tokens = Some(max_tokens);
     }
+    gen_opts.sampling.min_tokens = req.min_tokens;
+    gen_opts.sampling.seed = req.seed;
+    if let Some(top_p) = req.top_p {
+        gen_opts.sampling.top_p = top_p;
+    }
+    gen_opts.sampling.top_k = req.top_k;
+    if let Some(penalty) = req.frequency_penalty {
+        gen_opts.sampling.frequency_penalty = penalty;
+    }
+    if let Some(penalty) = req.presence_penalty {
+        gen_opts.sampling.presence_penalty = penalty;
+    }
+    if let Some(penalty) = req.repetition_penalty {
+        gen_opts.sampling.repetition_penalty = penalty;
+    }
+    if let Some(stop) = req.stop.clone() {
+        gen_opts.sampling.stop_sequences = stop;
+    }
+    if let Some(include) = req.include_stop_str_in_output {
+        gen_opts.sampling.include_stop_str_in_output = include;
+    }
+    let include_usage = req
+        .stream_options
+        .as_ref()
+        .is_some_and(|opts| opts.include_usage);
+    gen_opts.include_usage = include_usage;
+
+    let warnings = ignored_parameters(&req.other);
     gen_req.options = gen_opts;
 
-    let request_id = format!("chatcmpl-{:x}", std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_nanos());
+    let validation = gen_req.options.sampling.validate().and_then(|_| {
+        gen_req.options.guided = GuidedDecoding::from_fields(
+            req.guided_json.clone(),
+            req.guided_regex.clone(),
+            req.guided_choice.clone(),
+        )?;
+        gen_req.options.response_format =
+            response_format(req.response_format.as_ref(), gen_req.options.guided.is_some())?;
+        Ok(())
+    });
+    if let Err(e) = validation {
+        return (error_status(&e), Json(serde_json::json!({
+            "error": {
+                "message": e.to_string(),
+                "type": "invalid_request_error",
+                "code": "invalid_parameters"
+            }
+        }))).into_response();
+    }
+
+    let warnings = reported_warnings(&state, warnings);
+
+    let request_id = format!("chatcmpl-{}", id);
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs();
 
+    let in_flight = state.track_in_flight(&req.model);
+    let stats = state.model_stats(&req.model);
+    tracing::Span::current().record("model", req.model.as_str());
+    let start = Instant::now();
+
     if req.stream {
-        let engine = state.engine.lock().await;
-        match engine.generate_stream(gen_req).await {
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        let result = engine.generate_stream(gen_req).await;
+        drop(engine);
+        match result {
             Ok(stream) => {
                 use futures::StreamExt;
 
-                let event_stream = stream::unfold(
-                    (stream, req.model.clone(), request_id.clone(), created, 0usize, false),
-                    |(mut s, model, id, timestamp, count, done)| async move {
-                        if done {
+                // Events still to be sent once the upstream stream has ended
+                // (final finish chunk, optional usage chunk)
+                struct ChatStreamState<S> {
+                    upstream: S,
+                    model: String,
+                    id: String,
+                    created: u64,
+                    include_usage: bool,
+                    usage: Option<OpenAIUsage>,
+                    pending: VecDeque<String>,
+                    finished: bool,
+                    chunks: usize,
+                    stats: ModelUsage,
+                    warnings: Vec<String>,
+                }
+
+                let state = ChatStreamState {
+                    upstream: stream,
+                    model: req.model.clone(),
+                    id: request_id.clone(),
+                    created,
+                    include_usage,
+                    usage: None,
+                    pending: VecDeque::new(),
+                    finished: false,
+                    chunks: 0,
+                    stats,
+                    warnings,
+                };
+
+                let event_stream = stream::unfold(state, move |mut st| async move {
+                    loop {
+                        if let Some(data) = st.pending.pop_front() {
+                            return Some((Ok::<_, Infallible>(Event::default().data(data)), st));
+                        }
+                        if st.finished {
                             return None;
                         }
-                        match s.next().await {
+
+                        match st.upstream.next().await {
                             Some(Ok(resp)) => {
+                                if resp.stats.total_tokens > 0 {
+                                    st.usage = Some(OpenAIUsage {
+                                        prompt_tokens: resp.stats.prompt_tokens,
+                                        completion_tokens: resp.stats.generated_tokens,
+                                        total_tokens: resp.stats.total_tokens,
+                                    });
+                                }
+                                // The usage-only chunk has no text to forward
+                                if resp.text.is_empty() {
+                                    continue;
+                                }
+                                st.chunks += 1;
                                 let chunk = OpenAIChatChunk {
-                                    id: id.clone(),
+                                    id: st.id.clone(),
                                     object: "chat.completion.chunk".to_string(),
-                                    created: timestamp,
-                                    model: model.clone(),
+                                    created: st.created,
+                                    model: st.model.clone(),
                                     choices: vec![OpenAIChunkChoice {
                                         index: 0,
                                         delta: OpenAIDelta {
@@ -789,23 +2519,24 @@ pub async fn openai_chat_completions(
                                         },
                                         finish_reason: None,
                                     }],
+                                    usage: None,
+                                    warnings: Vec::new(),
                                 };
-                                let json = serde_json::to_string(&chunk).unwrap();
-                                Some((
-                                    Ok::<_, Infallible>(Event::default().data(json)),
-                                    (s, model, id, timestamp, count + 1, false)
-                                ))
+                                st.pending.push_back(serde_json::to_string(&chunk).unwrap());
                             }
                             Some(Err(e)) => {
-                                error!("Stream error: {}", e);
-                                None
+                                error!("Stream error (request {}): {}", id, e);
+                                return None;
                             }
                             None => {
+                                st.finished = true;
+                                let tokens = st.usage.as_ref().map_or(st.chunks, |u| u.completion_tokens);
+                                record_completion(&st.stats, tokens, start.elapsed());
                                 let final_chunk = OpenAIChatChunk {
-                                    id,
+                                    id: st.id.clone(),
                                     object: "chat.completion.chunk".to_string(),
-                                    created: timestamp,
-                                    model,
+                                    created: st.created,
+                                    model: st.model.clone(),
                                     choices: vec![OpenAIChunkChoice {
                                         index: 0,
                                         delta: OpenAIDelta {
@@ -814,19 +2545,40 @@ pub async fn openai_chat_completions(
                                         },
                                         finish_reason: Some("stop".to_string()),
                                     }],
+                                    usage: None,
+                                    warnings: std::mem::take(&mut st.warnings),
                                 };
-                                let json = serde_json::to_string(&final_chunk).unwrap();
-                                Some((Ok(Event::default().data(json)), (s, String::new(), String::new(), timestamp, count, true)))
+                                st.pending.push_back(serde_json::to_string(&final_chunk).unwrap());
+
+                                // OpenAI sends usage as a separate chunk with empty choices
+                                if st.include_usage {
+                                    let usage_chunk = OpenAIChatChunk {
+                                        id: st.id.clone(),
+                                        object: "chat.completion.chunk".to_string(),
+                                        created: st.created,
+                                        model: st.model.clone(),
+                                        choices: Vec::new(),
+                                        usage: Some(st.usage.take().unwrap_or(OpenAIUsage {
+                                            prompt_tokens: 0,
+                                            completion_tokens: 0,
+                                            total_tokens: 0,
+                                        })),
+                                        warnings: Vec::new(),
+                                    };
+                                    st.pending.push_back(serde_json::to_string(&usage_chunk).unwrap());
+                                }
+
+                                st.pending.push_back(OPENAI_STREAM_DONE.to_string());
                             }
                         }
                     }
-                );
+                });
 
-                Sse::new(event_stream).into_response()
+                Sse::new(hold_while_streaming(event_stream, in_flight)).into_response()
             }
             Err(e) => {
-                error!("Streaming chat failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Streaming chat failed (request {}): {}", id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": {
                         "message": format!("Generation failed: {}", e),
                         "type": "server_error"
@@ -835,9 +2587,13 @@ pub async fn openai_chat_completions(
             }
         }
     } else {
-        let engine = state.engine.lock().await;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
         match engine.generate(gen_req).await {
             Ok(resp) => {
+                record_completion(&stats, resp.stats.generated_tokens, start.elapsed());
                 let response = OpenAIChatResponse {
                     id: request_id,
                     object: "chat.completion".to_string(),
@@ -846,19 +2602,20 @@ pub async fn openai_chat_completions(
                     choices: vec![OpenAIChoice {
                         index: 0,
                         message: ChatMessage::assistant(resp.text),
-                        finish_reason: "stop".to_string(),
+                        finish_reason: resp.finish_reason.unwrap_or_else(|| "stop".to_string()),
                     }],
                     usage: Some(OpenAIUsage {
-                        prompt_tokens: 0,
-                        completion_tokens: 0,
-                        total_tokens: 0,
+                        prompt_tokens: resp.stats.prompt_tokens,
+                        completion_tokens: resp.stats.generated_tokens,
+                        total_tokens: resp.stats.total_tokens,
                     }),
+                    warnings,
                 };
                 Json(response).into_response()
             }
             Err(e) => {
-                error!("Chat failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Chat failed (request {}): {}", id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": {
                         "message": format!("Generation failed: {}", e),
                         "type": "server_error"
@@ -871,92 +2628,198 @@ pub async fn openai_chat_completions(
 
 pub async fn chat(
     State(state): State<ServerState>,
-    Json(req): Json<ChatApiRequest>,
+    headers: HeaderMap,
+    Json(mut req): Json<ChatApiRequest>,
 ) -> Response {
-    info!("Chat request for model: {}", req.model);
+    req.model = state.resolve_model_name(&req.model);
+    let request_id = state.next_request_id();
+    info!("Chat request {} for model: {}", request_id, req.model);
 
-    let mut gen_opts = GenerateOptions::default();
-    if let Some(opts) = req.options {
-        if let Some(temp) = opts.temperature {
-            gen_opts.sampling.temperature = temp;
+    if let Err(e) = req.validate() {
+        return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+    }
+    match parse_keep_alive(req.keep_alive.as_ref()) {
+        Ok(keep_alive) => state.set_keep_alive(&req.model, keep_alive),
+        Err(e) => {
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
         }
-        if let Some(top_p) = opts.top_p {
-            gen_opts.sampling.top_p = top_p;
+    }
+
+    let mut warnings: Vec<String> = match check_num_ctx(&state, &req.model, req.options.as_ref()).await {
+        Ok(warning) => warning.into_iter().collect(),
+        Err(response) => return response,
+    };
+
+    if let Some((opts, n_keep)) = req.options.as_ref().and_then(|o| o.n_keep.map(|n| (o, n))) {
+        let reserve = opts
+            .max_tokens
+            .or(opts.num_predict.and_then(|n| usize::try_from(n).ok()))
+            .unwrap_or(0);
+        if let Err(e) = fit_context(&state, &req.model, &mut req.messages, n_keep, reserve).await {
+            return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
         }
-        if let Some(max_tokens) = opts.max_tokens {
-            gen_opts.sampling.max_tokens = Some(max_tokens);
+    }
+
+    let custom_prompt = match req.template.clone() {
+        Some(template) => match render_custom_template(template, req.messages.clone()).await {
+            Ok(prompt) => Some(prompt),
+            Err(e) => {
+                return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+            }
+        },
+        None => None,
+    };
+
+    let mut gen_opts = GenerateOptions::default();
+    if let Some(opts) = req.options {
+        warnings.extend(opts.apply(&mut gen_opts.sampling));
+        if let Some(num_predict) = opts.num_predict {
+            let messages: Vec<vllama_core::openai::ChatMessage> =
+                req.messages.iter().cloned().map(Into::into).collect();
+            let tokenize = serde_json::json!({"model": req.model, "messages": messages});
+            match num_predict_limit(&state, &req.model, num_predict, tokenize).await {
+                Ok(limit) => gen_opts.sampling.max_tokens = limit,
+                Err(e) => {
+                    return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+                }
+            }
         }
     }
 
+    if let Err(e) = gen_opts.sampling.validate().and_then(|_| apply_format(req.format.as_ref(), &mut gen_opts)) {
+        return (error_status(&e), Json(serde_json::json!({"error": e.to_string()}))).into_response();
+    }
+    let warnings = reported_warnings(&state, warnings);
+
+    let in_flight = state.track_in_flight(&req.model);
+    let stats = state.model_stats(&req.model);
+    tracing::Span::current().record("model", req.model.as_str());
+    let start = Instant::now();
+
     if req.stream {
         // Streaming still uses prompt-based approach
-        let prompt = messages_to_prompt(&req.messages);
-        let mut gen_req = GenerateRequest::new(0, req.model.clone(), prompt);
+        let prompt = match custom_prompt {
+            Some(prompt) => prompt,
+            None => messages_to_prompt(&state, &req.model, &req.messages).await,
+        };
+        let mut gen_req = GenerateRequest::new(request_id.0, req.model.clone(), prompt);
         gen_req.options = gen_opts;
-        let engine = state.engine.lock().await;
-        match engine.generate_stream(gen_req).await {
+        // For the token counts on the final event
+        gen_req.options.include_usage = true;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        let result = engine.generate_stream(gen_req).await;
+        drop(engine);
+        match result {
             Ok(stream) => {
                 use futures::StreamExt;
 
+                // Each event carries only the new text; the final `done`
+                // event has empty content and the stats, as in Ollama
                 let event_stream = stream::unfold(
-                    (stream, req.model.clone(), String::new(), 0usize, false),
-                    |(mut s, model, mut accumulated, count, done)| async move {
+                    (stream, req.model.clone(), StreamCounts::default(), None::<String>, stats, warnings, false),
+                    move |(mut s, model, mut counts, finish_reason, stats, warnings, done)| async move {
                         if done {
                             return None;
                         }
                         match s.next().await {
                             Some(Ok(resp)) => {
-                                accumulated.push_str(&resp.text);
-                                let msg = ChatMessage::assistant(resp.text);
+                                counts.add(&resp);
+                                let finish_reason = resp.finish_reason.or(finish_reason);
+                                let text = resp.text.clone();
                                 let event = ChatApiResponse {
+                                    request_id,
                                     model: model.clone(),
-                                    message: msg,
+                                    message: ChatMessage::assistant(resp.text),
                                     done: false,
+                                    done_reason: None,
                                     total_duration: None,
                                     eval_count: None,
+                                    prompt_eval_count: None,
+                                    warnings: Vec::new(),
                                 };
-                                let json = serde_json::to_string(&event).unwrap();
-                                Some((
-                                    Ok::<_, Infallible>(Event::default().data(json)),
-                                    (s, model, accumulated, count + 1, false)
-                                ))
+                                // vLLM's trailing usage chunk has no text to send
+                                let json = (!text.is_empty()).then(|| serde_json::to_string(&event).unwrap());
+                                Some((json, (s, model, counts, finish_reason, stats, warnings, false)))
                             }
                             Some(Err(e)) => {
-                                error!("Stream error: {}", e);
+                                error!("Stream error (request {}): {}", request_id, e);
                                 None
                             }
                             None => {
-                                let msg = ChatMessage::assistant("");
+                                record_completion(&stats, counts.eval_count(), start.elapsed());
                                 let final_event = ChatApiResponse {
+                                    request_id,
                                     model,
-                                    message: msg,
+                                    message: ChatMessage::assistant(""),
                                     done: true,
-                                    total_duration: None,
-                                    eval_count: Some(count),
+                                    done_reason: Some(done_reason(finish_reason.as_deref())),
+                                    total_duration: Some(start.elapsed().as_nanos() as u64),
+                                    eval_count: Some(counts.eval_count()),
+                                    prompt_eval_count: counts.prompt_eval_count(),
+                                    warnings,
                                 };
                                 let json = serde_json::to_string(&final_event).unwrap();
-                                Some((Ok(Event::default().data(json)), (s, String::new(), accumulated, count, true)))
+                                Some((Some(json), (s, String::new(), counts, None, stats, Vec::new(), true)))
                             }
                         }
                     }
-                );
+                )
+                .filter_map(futures::future::ready);
 
-                Sse::new(event_stream).into_response()
+                ollama_stream(&headers, hold_while_streaming(event_stream, in_flight))
             }
             Err(e) => {
-                error!("Streaming chat failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Streaming chat failed (request {}): {}", request_id, e);
+                (error_status(&e), Json(serde_json::json!({
+                    "error": format!("Chat failed: {}", e)
+                }))).into_response()
+            }
+        }
+    } else if let Some(prompt) = custom_prompt {
+        // vLLM's chat API would apply the model's own template, so a custom
+        // one has to go through the completions endpoint
+        let mut gen_req = GenerateRequest::new(request_id.0, req.model.clone(), prompt);
+        gen_req.options = gen_opts;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        match engine.generate(gen_req).await {
+            Ok(resp) => {
+                let duration = start.elapsed();
+                record_completion(&stats, resp.stats.generated_tokens, duration);
+                Json(ChatApiResponse {
+                    request_id,
+                    model: req.model,
+                    message: ChatMessage::assistant(resp.text),
+                    done: true,
+                    done_reason: Some(done_reason(resp.finish_reason.as_deref())),
+                    total_duration: Some(duration.as_nanos() as u64),
+                    eval_count: Some(resp.stats.generated_tokens),
+                    prompt_eval_count: Some(resp.stats.prompt_tokens),
+                    warnings,
+                }).into_response()
+            }
+            Err(e) => {
+                error!("Chat failed (request {}): {}", request_id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": format!("Chat failed: {}", e)
                 }))).into_response()
             }
         }
     } else {
         // Non-streaming: use proper chat completion endpoint
-        let start = Instant::now();
-        let engine = state.engine.lock().await;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
         match engine.generate_chat_completion(req.model.clone(), req.messages.clone(), gen_opts).await {
             Ok(chat_response) => {
                 let duration = start.elapsed();
+                record_completion(&stats, chat_response.usage.completion_tokens, duration);
                 let message = chat_response.choices
                     .first()
                     .map(|choice| choice.message.clone())
@@ -971,17 +2834,25 @@ pub async fn chat(
                     images: None,
                 };
 
+                let finish_reason = chat_response.choices
+                    .first()
+                    .and_then(|choice| choice.finish_reason.as_deref());
+
                 Json(ChatApiResponse {
+                    request_id,
                     model: req.model,
                     message: msg,
                     done: true,
+                    done_reason: Some(done_reason(finish_reason)),
                     total_duration: Some(duration.as_nanos() as u64),
                     eval_count: Some(chat_response.usage.completion_tokens),
+                    prompt_eval_count: Some(chat_response.usage.prompt_tokens),
+                    warnings,
                 }).into_response()
             }
             Err(e) => {
-                error!("Chat failed: {}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({
+                error!("Chat failed (request {}): {}", request_id, e);
+                (error_status(&e), Json(serde_json::json!({
                     "error": format!("Chat failed: {}", e)
                 }))).into_response()
             }
@@ -1014,6 +2885,35 @@ pub struct ProcessInfo {
     pub expires_at: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub size_vram: Option<u64>,
+    /// Generation requests currently being served for this model
+    pub in_flight: usize,
+}
+
+/// `time` as an RFC 3339 UTC timestamp, e.g. `2025-01-31T17:05:09Z`
+fn rfc3339(time: std::time::SystemTime) -> String {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, day_secs) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        day_secs / 3600,
+        day_secs % 3600 / 60,
+        day_secs % 60
+    )
 }
 
 #[derive(Debug, Serialize)]
@@ -1021,7 +2921,7 @@ pub struct PsResponse {
     pub models: Vec<ProcessInfo>,
 }
 
-pub async fn ps(State(_state): State<ServerState>) -> Response {
+pub async fn ps(State(state): State<ServerState>) -> Response {
     info!("Process status request");
 
     #[derive(Debug, Deserialize)]
@@ -1035,16 +2935,18 @@ pub async fn ps(State(_state): State<ServerState>) -> Response {
         #[allow(dead_code)]
         created: u64,
         #[serde(default)]
+        root: Option<String>,
+        #[serde(default)]
         max_model_len: Option<u64>,
     }
 
-    let client = reqwest::Client::new();
-    match client.get("http://127.0.0.1:8100/v1/models").send().await {
+    match state.http.get(format!("{}/v1/models", state.vllm_url)).send().await {
         Ok(response) => {
             match response.json::<VllmModelsResponse>().await {
                 Ok(vllm_models) => {
                     let models = vllm_models.data.into_iter().map(|m| {
-                        let model_name = m.id.clone();
+                        // Family/size come from the repository behind a served alias
+                        let model_name = m.root.clone().unwrap_or_else(|| m.id.clone());
 
                         let (family, parameter_size) = if model_name.contains("llama") || model_name.contains("Llama") {
                             let size = if model_name.contains("70B") || model_name.contains("70b") {
@@ -1070,9 +2972,17 @@ pub async fn ps(State(_state): State<ServerState>) -> Response {
                             ("unknown", "unknown")
                         };
 
+                        // Clients may address the model by alias or by repository
+                        let mut in_flight = state.in_flight_count(&m.id);
+                        if model_name != m.id {
+                            in_flight += state.in_flight_count(&model_name);
+                        }
+
+                        let expires_at = state.expires_at(&m.id).or_else(|| state.expires_at(&model_name));
+
                         ProcessInfo {
-                            name: model_name.clone(),
-                            model: model_name.clone(),
+                            name: m.id.clone(),
+                            model: m.id.clone(),
                             size: 0,
                             digest: None,
                             details: ModelDetails {
@@ -1082,8 +2992,9 @@ pub async fn ps(State(_state): State<ServerState>) -> Response {
                                 parameter_size: parameter_size.to_string(),
                                 quantization_level: "none".to_string(),
                             },
-                            expires_at: None,
+                            expires_at: expires_at.map(rfc3339),
                             size_vram: m.max_model_len,
+                            in_flight,
                         }
                     }).collect();
 
@@ -1102,8 +3013,34 @@ pub async fn ps(State(_state): State<ServerState>) -> Response {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub models: Vec<ModelStatsSnapshot>,
+}
+
+/// Cumulative per-model usage since the server started
+pub async fn stats(State(state): State<ServerState>) -> Json<StatsResponse> {
+    Json(StatsResponse { models: state.stats_snapshot() })
+}
+
+/// Prometheus metrics in the text exposition format
+pub async fn metrics(State(state): State<ServerState>) -> Response {
+    let mut in_flight: HashMap<String, usize> = HashMap::new();
+    for (model, count) in state.in_flight_counts() {
+        *in_flight.entry(state.metrics_label(&model)).or_default() += count;
+    }
+    for (label, count) in in_flight {
+        metrics::gauge!(prometheus::REQUESTS_IN_FLIGHT, "model" => label).set(count as f64);
+    }
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        prometheus::handle().render(),
+    )
+        .into_response()
+}
+
 pub async fn openai_models(
-    State(_state): State<ServerState>,
+    State(state): State<ServerState>,
 ) -> Response {
     info!("OpenAI models list request");
 
@@ -1117,10 +3054,14 @@ pub async fn openai_models(
         id: String,
         #[allow(dead_code)]
         created: u64,
+        /// Set by vLLM for LoRA adapters registered via --lora-modules
+        #[serde(default)]
+        parent: Option<String>,
+        #[serde(default)]
+        root: Option<String>,
     }
 
-    let client = reqwest::Client::new();
-    match client.get("http://127.0.0.1:8100/v1/models").send().await {
+    match state.http.get(format!("{}/v1/models", state.vllm_url)).send().await {
         Ok(response) => {
             match response.json::<VllmModelsResponse>().await {
                 Ok(vllm_models) => {
@@ -1130,6 +3071,8 @@ pub async fn openai_models(
                             object: "model".to_string(),
                             created: m.created,
                             owned_by: "vllama".to_string(),
+                            root: m.root,
+                            parent: m.parent,
                         }
                     }).collect();
 
@@ -1140,43 +3083,29 @@ pub async fn openai_models(
                 }
                 Err(e) => {
                     error!("Failed to parse vLLM models response: {}", e);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        Json(serde_json::json!({
-                            "error": {
-                                "message": "Failed to parse vLLM models response",
-                                "type": "internal_error",
-                                "code": "model_list_error"
-                            }
-                        }))
-                    ).into_response()
+                    Json(OpenAIModelsResponse::empty()).into_response()
                 }
             }
         }
+        // No models to list while vLLM is down, as in `ps`; clients calling
+        // `models.list()` at startup shouldn't see an error
         Err(e) => {
             error!("Failed to query vLLM models: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({
-                    "error": {
-                        "message": format!("Failed to query vLLM: {}", e),
-                        "type": "internal_error",
-                        "code": "vllm_connection_error"
-                    }
-                }))
-            ).into_response()
+            Json(OpenAIModelsResponse::empty()).into_response()
         }
     }
 }
 
 pub async fn openai_completions(
     State(state): State<ServerState>,
-    Json(req): Json<OpenAICompletionRequest>,
+    Json(mut req): Json<OpenAICompletionRequest>,
 ) -> Response {
-    info!("OpenAI completions request for model: {}", req.model);
+    req.model = state.resolve_model_name(&req.model);
+    let id = state.next_request_id();
+    info!("OpenAI completions request {} for model: {}", id, req.model);
 
     let mut gen_req = GenerateRequest::new(
-        0,  // Request ID
+        id.0,
         req.model.clone(),
         req.prompt.clone(),
     );
@@ -1188,29 +3117,78 @@ pub async fn openai_completions(
     if let Some(max_tokens) = req.max_tokens {
         gen_opts.sampling.max_tokens = Some(max_tokens);
     }
+    gen_opts.sampling.min_tokens = req.min_tokens;
+    gen_opts.sampling.seed = req.seed;
     if let Some(top_p) = req.top_p {
         gen_opts.sampling.top_p = top_p;
     }
+    gen_opts.sampling.top_k = req.top_k;
+    if let Some(penalty) = req.frequency_penalty {
+        gen_opts.sampling.frequency_penalty = penalty;
+    }
+    if let Some(penalty) = req.presence_penalty {
+        gen_opts.sampling.presence_penalty = penalty;
+    }
+    if let Some(penalty) = req.repetition_penalty {
+        gen_opts.sampling.repetition_penalty = penalty;
+    }
+    if let Some(stop) = req.stop.clone() {
+        gen_opts.sampling.stop_sequences = stop;
+    }
+    if let Some(include) = req.include_stop_str_in_output {
+        gen_opts.sampling.include_stop_str_in_output = include;
+    }
+
+    let warnings = ignored_parameters(&req.other);
     gen_req.options = gen_opts;
 
-    let request_id = format!("cmpl-{:x}", std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_nanos());
+    let validation = gen_req.options.sampling.validate().and_then(|_| {
+        gen_req.options.guided = GuidedDecoding::from_fields(
+            req.guided_json.clone(),
+            req.guided_regex.clone(),
+            req.guided_choice.clone(),
+        )?;
+        gen_req.options.response_format =
+            response_format(req.response_format.as_ref(), gen_req.options.guided.is_some())?;
+        Ok(())
+    });
+    if let Err(e) = validation {
+        return (error_status(&e), Json(serde_json::json!({
+            "error": {
+                "message": e.to_string(),
+                "type": "invalid_request_error",
+                "code": "invalid_parameters"
+            }
+        }))).into_response();
+    }
+
+    let warnings = reported_warnings(&state, warnings);
+
+    let request_id = format!("cmpl-{}", id);
     let created = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs();
 
+    let in_flight = state.track_in_flight(&req.model);
+    let stats = state.model_stats(&req.model);
+    tracing::Span::current().record("model", req.model.as_str());
+    let start = Instant::now();
+
     if req.stream {
-        let engine = state.engine.lock().await;
-        match engine.generate_stream(gen_req).await {
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
+        let result = engine.generate_stream(gen_req).await;
+        drop(engine);
+        match result {
             Ok(stream) => {
                 use futures::StreamExt;
 
                 let event_stream = stream::unfold(
-                    (stream, req.model.clone(), request_id.clone(), created, false),
-                    |(mut s, model, id, timestamp, done)| async move {
+                    (stream, req.model.clone(), request_id.clone(), created, 0usize, stats, warnings, false),
+                    move |(mut s, model, id, timestamp, count, stats, mut warnings, done)| async move {
                         if done {
                             return None;
                         }
@@ -1224,31 +3202,40 @@ pub async fn openai_completions(
                                     choices: vec![OpenAICompletionChunkChoice {
                                         text: resp.text.clone(),
                                         index: 0,
-                                        finish_reason: if resp.finished { Some("stop".to_string()) } else { None },
+                                        finish_reason: resp.finished.then(|| {
+                                            resp.finish_reason.clone().unwrap_or_else(|| "stop".to_string())
+                                        }),
                                     }],
+                                    warnings: if resp.finished { std::mem::take(&mut warnings) } else { Vec::new() },
                                 };
 
                                 let json = serde_json::to_string(&chunk).unwrap();
                                 let event = Event::default()
                                     .data(json);
 
-                                Some((Ok::<_, Infallible>(event), (s, model, id, timestamp, resp.finished)))
+                                Some((Ok::<_, Infallible>(event), (s, model, id, timestamp, count + 1, stats, warnings, false)))
                             }
                             Some(Err(e)) => {
-                                error!("Stream error: {}", e);
+                                error!("Stream error (request {}): {}", id, e);
                                 None
                             }
-                            None => None,
+                            None => {
+                                record_completion(&stats, count, start.elapsed());
+                                Some((
+                                    Ok(Event::default().data(OPENAI_STREAM_DONE)),
+                                    (s, model, id, timestamp, count, stats, warnings, true),
+                                ))
+                            }
                         }
                     }
                 );
 
-                Sse::new(event_stream).into_response()
+                Sse::new(hold_while_streaming(event_stream, in_flight)).into_response()
             }
             Err(e) => {
-                error!("Failed to generate stream: {}", e);
+                error!("Failed to generate stream (request {}): {}", id, e);
                 (
-                    StatusCode::INTERNAL_SERVER_ERROR,
+                    error_status(&e),
                     Json(serde_json::json!({
                         "error": {
                             "message": format!("Failed to generate: {}", e),
@@ -1260,9 +3247,13 @@ pub async fn openai_completions(
             }
         }
     } else {
-        let engine = state.engine.lock().await;
+        let engine = match lock_engine(&state).await {
+            Ok(engine) => engine,
+            Err(response) => return response,
+        };
         match engine.generate(gen_req).await {
             Ok(resp) => {
+                record_completion(&stats, resp.stats.generated_tokens, start.elapsed());
                 let response = OpenAICompletionResponse {
                     id: request_id,
                     object: "text_completion".to_string(),
@@ -1271,21 +3262,22 @@ pub async fn openai_completions(
                     choices: vec![OpenAICompletionChoice {
                         text: resp.text,
                         index: 0,
-                        finish_reason: "stop".to_string(),
+                        finish_reason: resp.finish_reason.unwrap_or_else(|| "stop".to_string()),
                     }],
                     usage: Some(OpenAIUsage {
-                        prompt_tokens: 0,  // vLLM doesn't return this easily
-                        completion_tokens: 0,
-                        total_tokens: 0,
+                        prompt_tokens: resp.stats.prompt_tokens,
+                        completion_tokens: resp.stats.generated_tokens,
+                        total_tokens: resp.stats.total_tokens,
                     }),
+                    warnings,
                 };
 
                 Json(response).into_response()
             }
             Err(e) => {
-                error!("Failed to generate: {}", e);
+                error!("Failed to generate (request {}): {}", id, e);
                 (
-                    StatusCode::INTERNAL_SERVER_ERROR,
+                    error_status(&e),
                     Json(serde_json::json!({
                         "error": {
                             "message": format!("Failed to generate: {}", e),
@@ -1298,3 +3290,1526 @@ pub async fn openai_completions(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Server, ServerConfig};
+    use axum::body::{to_bytes, Body};
+    use axum::http::Request;
+    use tower::ServiceExt;
+    use vllama_core::ModelHandle;
+    use vllama_engine::MockEngine;
+
+    async fn post_json(
+        engine: MockEngine,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, String) {
+        let state = ServerState::with_engine(engine, "http://127.0.0.1:0", ServerConfig::default());
+        send_json(state, uri, body).await
+    }
+
+    async fn send_json(
+        state: ServerState,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, String) {
+        let app = Server::router(state);
+        let request = Request::post(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    /// Payloads of the `data:` lines of an SSE body
+    fn sse_data(body: &str) -> Vec<String> {
+        body.lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_batch_and_normalize() {
+        let (status, body) = post_json(
+            MockEngine::new(),
+            "/api/embed",
+            serde_json::json!({"model": "m", "input": ["a", "b"], "normalize": true}),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["embeddings"], serde_json::json!([[0.6, 0.8], [0.6, 0.8]]));
+
+        // Legacy single-prompt form, not normalized
+        let (status, body) = post_json(
+            MockEngine::new(),
+            "/api/embeddings",
+            serde_json::json!({"model": "m", "prompt": "a"}),
+        ).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["embedding"], serde_json::json!([3.0, 4.0]));
+
+        let (status, _) = post_json(
+            MockEngine::new(),
+            "/api/embed",
+            serde_json::json!({"model": "m", "input": []}),
+        ).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_ready_vs_health() {
+        async fn get_status(state: ServerState, uri: &str) -> StatusCode {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            Server::router(state).oneshot(request).await.unwrap().status()
+        }
+
+        let vllm_url = fake_vllm(20).await;
+        let up = ServerState::with_engine(MockEngine::new(), &vllm_url, ServerConfig::default());
+        let down = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        assert_eq!(get_status(up.clone(), "/ready").await, StatusCode::OK);
+        assert_eq!(get_status(down.clone(), "/ready").await, StatusCode::SERVICE_UNAVAILABLE);
+        // Liveness doesn't depend on vLLM
+        assert_eq!(get_status(down, "/health").await, StatusCode::OK);
+
+        // A busy engine doesn't hold up the probe
+        let _engine = up.engine.lock().await;
+        let status = tokio::time::timeout(Duration::from_secs(1), get_status(up.clone(), "/ready")).await;
+        assert_eq!(status.unwrap(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let config = ServerConfig {
+            queue_timeout: Some(Duration::from_millis(50)),
+            ..ServerConfig::default()
+        };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        let body = serde_json::json!({"model": "m", "prompt": "hi", "stream": false});
+        let request = || {
+            Request::post("/api/generate")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let response = Server::router(state.clone()).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key("x-queue-time-ms"));
+
+        // Another request holds the engine past the timeout
+        let busy = state.engine.lock().await;
+        let response = Server::router(state.clone()).oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        drop(busy);
+
+        let queue = state.queue.snapshot();
+        assert_eq!((queue.requests, queue.timeouts), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_stats_per_model() {
+        let engine = MockEngine::new().with_chunks(["a", "b", "c"]);
+        let state = ServerState::with_engine(engine, "http://127.0.0.1:0", ServerConfig::default());
+
+        let (status, _) = send_json(state.clone(), "/api/generate", serde_json::json!({"model": "m", "prompt": "hi"})).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": true}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let response = Server::router(state)
+            .oneshot(Request::get("/api/stats").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let models = stats["models"].as_array().unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0]["model"], "m");
+        assert_eq!(models[0]["requests"], 2);
+        assert_eq!(models[0]["eval_count"], 6);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let engine = MockEngine::new().with_chunks(["a", "b", "c"]);
+        let state = ServerState::with_engine(engine, "http://127.0.0.1:0", ServerConfig::default());
+        state.loaded_models.insert("metrics-m".to_string(), ModelHandle(0));
+
+        for model in ["metrics-m", "metrics-unknown"] {
+            let (status, _) =
+                send_json(state.clone(), "/api/generate", serde_json::json!({"model": model, "prompt": "hi"})).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+
+        let response = Server::router(state)
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/plain"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(body.to_vec()).unwrap();
+
+        // The recorder is shared by every test, so only look for this test's series
+        assert!(text.contains("vllama_requests_total{endpoint=\"/api/generate\"}"), "{}", text);
+        assert!(text.contains("vllama_requests_in_flight{model=\"metrics-m\"} 0"), "{}", text);
+        assert!(text.contains("vllama_generated_tokens_bucket{model=\"metrics-m\",le=\"8\"} 1"), "{}", text);
+        assert!(text.contains("vllama_generation_duration_seconds_count{model=\"metrics-m\"} 1"), "{}", text);
+
+        // Models that aren't loaded or served share one series
+        assert!(!text.contains("metrics-unknown"), "{}", text);
+        assert!(text.contains("vllama_generation_duration_seconds_count{model=\"other\"}"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn test_openai_sampling_fields_forwarded() {
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/v1/chat/completions",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "hi"}],
+                "top_p": 0.5,
+                "top_k": 20,
+                "frequency_penalty": 0.4,
+                "presence_penalty": -0.3,
+                "stop": "END"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = post_json(
+            engine.clone(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "hi", "stop": ["a", "b"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let requests = engine.requests();
+        let sampling = &requests[0].options.sampling;
+        assert_eq!(sampling.top_p, 0.5);
+        assert_eq!(sampling.top_k, Some(20));
+        assert_eq!(sampling.frequency_penalty, 0.4);
+        assert_eq!(sampling.presence_penalty, -0.3);
+        assert_eq!(sampling.stop_sequences, vec!["END"]);
+        assert_eq!(requests[1].options.sampling.stop_sequences, vec!["a", "b"]);
+
+        let (status, _) = post_json(
+            MockEngine::new(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "hi", "frequency_penalty": 3.0}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_generate_options_apply() {
+        let opts: OllamaOptions = serde_json::from_value(serde_json::json!({
+            "temperature": 0.2,
+            "top_p": 0.8,
+            "top_k": 30,
+            "min_p": 0.05,
+            "max_tokens": 64,
+            "min_tokens": 4,
+            "seed": 7,
+            "repeat_penalty": 1.1,
+            "frequency_penalty": 0.5,
+            "presence_penalty": 0.25,
+            "stop": ["</s>", "\n\n"],
+            "include_stop_str_in_output": true
+        }))
+        .unwrap();
+        let mut sampling = SamplingParams::default();
+        opts.apply(&mut sampling);
+
+        assert_eq!(sampling.temperature, 0.2);
+        assert_eq!(sampling.top_p, 0.8);
+        assert_eq!(sampling.top_k, Some(30));
+        assert_eq!(sampling.min_p, Some(0.05));
+        assert_eq!(sampling.max_tokens, Some(64));
+        assert_eq!(sampling.min_tokens, Some(4));
+        assert_eq!(sampling.seed, Some(7));
+        assert_eq!(sampling.repetition_penalty, 1.1);
+        assert_eq!(sampling.frequency_penalty, 0.5);
+        assert_eq!(sampling.presence_penalty, 0.25);
+        assert_eq!(sampling.stop_sequences, vec!["</s>", "\n\n"]);
+        assert!(sampling.include_stop_str_in_output);
+
+        // Unset options leave the defaults alone
+        let opts: OllamaOptions =
+            serde_json::from_value(serde_json::json!({"repetition_penalty": 1.3})).unwrap();
+        let mut sampling = SamplingParams::default();
+        opts.apply(&mut sampling);
+        assert_eq!(sampling.repetition_penalty, 1.3);
+        assert_eq!(sampling.temperature, SamplingParams::default().temperature);
+        assert_eq!(sampling.top_k, None);
+        assert!(sampling.stop_sequences.is_empty());
+    }
+
+    #[test]
+    fn test_ollama_options_ignored() {
+        let opts: OllamaOptions = serde_json::from_value(serde_json::json!({
+            "temperature": 0.7,
+            "mirostat": 2,
+            "mirostat_tau": 5.0,
+            "tfs_z": 1.0,
+            "typical_p": 0.9,
+            "num_gpu": 99,
+            "num_keep": 24,
+            "use_mmap": false
+        }))
+        .unwrap();
+        // tfs_z at 1.0 is off, so it isn't reported
+        assert_eq!(opts.ignored(), vec!["mirostat", "mirostat_tau", "typical_p", "num_gpu", "num_keep", "use_mmap"]);
+        assert_eq!(opts.n_keep, None);
+
+        // vLLM penalizes over the whole context, which is what -1 asks for
+        let window = |n: i64| -> OllamaOptions {
+            serde_json::from_value(serde_json::json!({"repeat_penalty": 1.2, "repeat_last_n": n})).unwrap()
+        };
+        assert!(window(-1).ignored().is_empty());
+        assert_eq!(window(64).ignored(), vec!["repeat_last_n"]);
+        let mut sampling = SamplingParams::default();
+        window(0).apply(&mut sampling);
+        assert_eq!(sampling.repetition_penalty, 1.0);
+
+        // Mirostat's tuning knobs don't matter while it's off
+        let opts: OllamaOptions =
+            serde_json::from_value(serde_json::json!({"mirostat": 0, "mirostat_eta": 0.1})).unwrap();
+        assert!(opts.ignored().is_empty());
+    }
+
+    #[test]
+    fn test_ollama_options_round_trip() {
+        // Exactly representable in f32, so values compare equal after the trip
+        let json = serde_json::json!({
+            "temperature": 0.25,
+            "top_p": 0.5,
+            "min_p": 0.125,
+            "repetition_penalty": 1.5,
+            "num_keep": 2,
+            "repeat_last_n": 64,
+            "mirostat": 1,
+            "penalize_newline": true,
+            "num_thread": 8
+        });
+        let opts: OllamaOptions = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(opts.other.get("num_thread"), Some(&serde_json::json!(8)));
+
+        // Comes back exactly as sent: no nulls for unset options, and
+        // aliases keep the client's names
+        let back = serde_json::to_value(&opts).unwrap();
+        assert_eq!(back, json);
+    }
+
+    #[tokio::test]
+    async fn test_openai_models_without_vllm() {
+        // Nothing listens on port 1, so the vLLM query fails
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:1", ServerConfig::default());
+        let response = Server::router(state)
+            .oneshot(Request::get("/v1/models").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json, serde_json::json!({"object": "list", "data": []}));
+    }
+
+    #[tokio::test]
+    async fn test_openai_embeddings() {
+        let engine = MockEngine::new().with_embedding(vec![0.5, 0.25]);
+        let (status, body) = post_json(
+            engine,
+            "/v1/embeddings",
+            serde_json::json!({"model": "e", "input": ["a", "b"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["object"], "list");
+        assert_eq!(json["model"], "e");
+        let data = json["data"].as_array().unwrap();
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[1]["object"], "embedding");
+        assert_eq!(data[1]["index"], 1);
+        assert_eq!(data[1]["embedding"], serde_json::json!([0.5, 0.25]));
+        assert_eq!(json["usage"]["prompt_tokens"], 2);
+        assert_eq!(json["usage"]["total_tokens"], 2);
+
+        let engine = MockEngine::new()
+            .with_error_fn(|| vllama_core::Error::InvalidRequest("not an embedding model".to_string()));
+        let (status, body) = post_json(engine, "/v1/embeddings", serde_json::json!({"model": "m", "input": "a"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("not an embedding model"));
+    }
+
+    #[tokio::test]
+    async fn test_guided_decoding_modes() {
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/v1/chat/completions",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "Is the sky green?"}],
+                "guided_choice": ["yes", "no"]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            engine.requests()[0].options.guided,
+            Some(GuidedDecoding::Choice(vec!["yes".to_string(), "no".to_string()]))
+        );
+
+        let (status, body) = post_json(
+            MockEngine::new(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "id:", "guided_regex": "[0-9]+", "guided_choice": ["a"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("only one of"));
+    }
+
+    #[tokio::test]
+    async fn test_stop_string_or_list() {
+        // Ollama options take a bare string as well as a list
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": {"stop": "\n"}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.sampling.stop_sequences, vec!["\n"]);
+
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/v1/chat/completions",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "hi"}], "stop": ["User:", "###"]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.sampling.stop_sequences, vec!["User:", "###"]);
+
+        let (status, _) = post_json(
+            MockEngine::new(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "hi", "stop": 3}),
+        )
+        .await;
+        assert!(status.is_client_error());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_format() {
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "List three colors", "stream": false, "format": "json"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.response_format, Some(ResponseFormat::JsonObject));
+
+        // A schema becomes guided_json
+        let schema = serde_json::json!({"type": "object", "properties": {"age": {"type": "integer"}}});
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "hi"}], "format": schema}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.guided, Some(GuidedDecoding::Json(schema)));
+        assert_eq!(engine.requests()[0].options.response_format, None);
+
+        let (status, body) = post_json(
+            MockEngine::new(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "format": "yaml"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("format must be"));
+
+        // OpenAI's response_format is forwarded, but not alongside guided_*
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "{", "response_format": {"type": "json_object"}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.response_format, Some(ResponseFormat::JsonObject));
+
+        let (status, body) = post_json(
+            MockEngine::new(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "{", "response_format": {"type": "json_object"}, "guided_regex": "a"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("response_format"));
+    }
+
+    #[tokio::test]
+    async fn test_delete_uncached_model() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        let model = format!("vllama-test/not-cached-{}", std::process::id());
+        // Ollama clients send either `model` or `name`
+        for body in [serde_json::json!({"model": model}), serde_json::json!({"name": model})] {
+            let request = Request::delete("/api/delete")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let response = Server::router(state.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+    }
+
+    /// Cache `repo_id` in `cache` (a HuggingFace cache directory),
+    /// returning it
+    fn fake_cached_model(cache: &std::path::Path, repo_id: &str) -> String {
+        let repo = format!("models--{}", repo_id.replace('/', "--"));
+        let snapshot = cache.join("hub").join(repo).join("snapshots").join("abc");
+        std::fs::create_dir_all(&snapshot).unwrap();
+        std::fs::write(snapshot.join("config.json"), "{}").unwrap();
+        repo_id.to_string()
+    }
+
+    /// Config pointing the server at the HuggingFace cache `cache`
+    fn with_cache(cache: &tempfile::TempDir) -> ServerConfig {
+        ServerConfig { cache_dir: Some(cache.path().to_path_buf()), ..ServerConfig::default() }
+    }
+
+    async fn send_delete(state: ServerState, model: &str) -> StatusCode {
+        let request = Request::delete("/api/delete")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({"model": model}).to_string()))
+            .unwrap();
+        Server::router(state).oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_delete_loaded_model() {
+        let cache = tempfile::tempdir().unwrap();
+        let model = fake_cached_model(cache.path(), "vllama-test/delete-loaded");
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", with_cache(&cache));
+        state.loaded_models.insert(model.clone(), ModelHandle(0));
+        state.model_vram.insert(model.clone(), 1000);
+        state.touch(&model);
+
+        assert_eq!(send_delete(state.clone(), &model).await, StatusCode::OK);
+        assert!(!state.loaded_models.contains_key(&model));
+        assert!(state.model_vram.is_empty());
+        assert!(state.last_used.get(&model).is_none());
+        assert_eq!(send_delete(state, &model).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_delete_unloads_every_name_of_the_model() {
+        let cache = tempfile::tempdir().unwrap();
+        let repo_id = fake_cached_model(cache.path(), "Qwen/Qwen2.5-1.5B-Instruct");
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", with_cache(&cache));
+        for (handle, name) in ["qwen2.5:1.5b", "Qwen/Qwen2.5-1.5B-Instruct@v2"].into_iter().enumerate() {
+            state.loaded_models.insert(name.to_string(), ModelHandle(handle as u64));
+            state.model_vram.insert(name.to_string(), 1000);
+        }
+        state.loaded_models.insert("other".to_string(), ModelHandle(2));
+
+        // Deleted by repo id, loaded by short name and with a revision
+        assert_eq!(send_delete(state.clone(), &repo_id).await, StatusCode::OK);
+        let loaded: Vec<_> = state.loaded_models.iter().map(|entry| entry.key().clone()).collect();
+        assert_eq!(loaded, ["other"]);
+        assert!(state.model_vram.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delete_busy_engine_keeps_model_loaded() {
+        let cache = tempfile::tempdir().unwrap();
+        let model = fake_cached_model(cache.path(), "vllama-test/delete-busy");
+        let config = ServerConfig { queue_timeout: Some(Duration::from_millis(50)), ..with_cache(&cache) };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        state.loaded_models.insert(model.clone(), ModelHandle(0));
+
+        // The unload can't get the engine: nothing is dropped or deleted
+        let engine = state.engine.lock().await;
+        assert_eq!(send_delete(state.clone(), &model).await, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(state.loaded_models.contains_key(&model));
+        drop(engine);
+
+        assert_eq!(send_delete(state.clone(), &model).await, StatusCode::OK);
+        assert!(!state.loaded_models.contains_key(&model));
+    }
+
+    #[tokio::test]
+    async fn test_delete_during_pull() {
+        let cache = tempfile::tempdir().unwrap();
+        let model = fake_cached_model(cache.path(), "vllama-test/delete-pulling");
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", with_cache(&cache));
+        let (abort, _) = AbortHandle::new_pair();
+        let task = PullTask {
+            id: Uuid::new_v4(),
+            abort,
+            events: broadcast::channel(1).0,
+            subscribers: Arc::new(AtomicUsize::new(0)),
+        };
+        state.pulls.insert(format!("{}@v2", model), task);
+
+        assert_eq!(send_delete(state.clone(), &model).await, StatusCode::CONFLICT);
+        state.pulls.clear();
+        assert_eq!(send_delete(state, &model).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_copy_creates_alias() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let source = dir.to_str().unwrap().to_string();
+        let config = ServerConfig { aliases_file: Some(dir.join("aliases.toml")), ..Default::default() };
+        let engine = MockEngine::new();
+        let state = ServerState::with_engine(engine.clone(), "http://127.0.0.1:0", config.clone());
+
+        let (status, _) =
+            send_json(state.clone(), "/api/copy", serde_json::json!({"source": source, "destination": "mine"})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Requests for the copy go to the source model
+        let (status, _) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "mine", "prompt": "hi", "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].model, source);
+
+        // Saved for the next run
+        let restarted = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config.clone());
+        assert_eq!(restarted.resolve_model_name("mine"), source);
+
+        let (status, _) = send_json(
+            state.clone(),
+            "/api/copy",
+            serde_json::json!({"source": "vllama-test/not-cached", "destination": "other"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // Deleting the copy leaves the source alone
+        let request = Request::delete("/api/delete")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({"model": "mine"}).to_string()))
+            .unwrap();
+        let response = Server::router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(state.resolve_model_name("mine"), "mine");
+        assert!(dir.exists());
+        let restarted = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        assert_eq!(restarted.resolve_model_name("mine"), "mine");
+    }
+
+    #[tokio::test]
+    async fn test_user_aliases() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let path = dir.join("aliases.toml");
+        std::fs::write(&path, "tiny = \"llama-1b\"\n\"llama-1b\" = \"meta-llama/Llama-3.2-1B-Instruct\"\nloop = \"loop\"\n").unwrap();
+        let config = ServerConfig { aliases_file: Some(path.clone()), ..Default::default() };
+        let engine = MockEngine::new();
+        let state = ServerState::with_engine(engine.clone(), "http://127.0.0.1:0", config);
+
+        let (status, _) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "tiny", "prompt": "hi", "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_json(
+            state.clone(),
+            "/v1/chat/completions",
+            serde_json::json!({"model": "tiny", "messages": [{"role": "user", "content": "hi"}]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let requests = engine.requests();
+        assert_eq!(requests[0].model, "meta-llama/Llama-3.2-1B-Instruct");
+        assert_eq!(requests[1].model, "meta-llama/Llama-3.2-1B-Instruct");
+
+        // A cyclic alias is passed through rather than looped on
+        assert_eq!(state.resolve_model_name("loop"), "loop");
+        assert_eq!(state.resolve_model_name("other"), "other");
+
+        // `/api/copy` adds to the same file, keeping aliases added since startup
+        std::fs::write(&path, std::fs::read_to_string(&path).unwrap() + "added = \"tiny\"\n").unwrap();
+        let model = dir.to_str().unwrap().to_string();
+        let (status, _) =
+            send_json(state.clone(), "/api/copy", serde_json::json!({"source": model, "destination": "local"})).await;
+        assert_eq!(status, StatusCode::OK);
+        let saved = vllama_core::ModelAliases::load(&path).unwrap();
+        assert_eq!(saved.resolve("added").unwrap(), "meta-llama/Llama-3.2-1B-Instruct");
+        assert_eq!(saved.resolve("local").unwrap(), model);
+
+        // Deleting an alias drops the name, never the model it stands for
+        let request = Request::delete("/api/delete")
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::json!({"model": "tiny"}).to_string()))
+            .unwrap();
+        let response = Server::router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let deleted: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(deleted["freed_mb"], 0);
+        assert_eq!(state.resolve_model_name("tiny"), "tiny");
+        assert_eq!(vllama_core::ModelAliases::load(&path).unwrap().resolve("tiny").unwrap(), "tiny");
+    }
+
+    #[tokio::test]
+    async fn test_tags_etag() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        let get_tags = |if_none_match: Option<String>| {
+            let mut request = Request::get("/api/tags");
+            if let Some(etag) = if_none_match {
+                request = request.header("if-none-match", etag);
+            }
+            Server::router(state.clone()).oneshot(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get_tags(None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+        let response = get_tags(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // Loading a model changes the listing, so the old tag no longer matches
+        state.loaded_models.insert("m".to_string(), vllama_core::ModelHandle(1));
+        let response = get_tags(Some(etag.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()["etag"].to_str().unwrap(), etag);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8(bytes.to_vec()).unwrap().contains("\"m\""));
+    }
+
+    #[tokio::test]
+    async fn test_generate_non_streaming() {
+        let engine = MockEngine::new().with_response("4");
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "2+2?", "stream": false}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["response"], "4");
+        assert_eq!(json["done"], true);
+        assert_eq!(json["done_reason"], "stop");
+        assert_eq!(engine.requests()[0].prompt, "2+2?");
+    }
+
+    #[tokio::test]
+    async fn test_template_override() {
+        let engine = MockEngine::new().with_response("4");
+        let (status, _) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "2+2?", "stream": false, "template": "Q: {{ prompt }}\nA:"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].prompt, "Q: 2+2?\nA:");
+
+        // A templated chat goes through the prompt path, not vLLM's chat API
+        let engine = MockEngine::new().with_response("Hi");
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/chat",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "Hello"}],
+                "stream": false,
+                "template": "{% for m in messages %}{{ m.role }}: {{ m.content }}\n{% endfor %}assistant:"
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].prompt, "user: Hello\nassistant:");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["message"]["content"], "Hi");
+    }
+
+    /// A stand-in vLLM serving `/v1/models` and a `/tokenize` that counts words
+    async fn fake_vllm(max_model_len: usize) -> String {
+        async fn tokenize(Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+            let count: usize = body["messages"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|m| m["content"].as_str().unwrap_or_default().split_whitespace().count())
+                .sum();
+            Json(serde_json::json!({"count": count}))
+        }
+
+        let models = serde_json::json!({"data": [{"id": "m", "max_model_len": max_model_len}]});
+        let app = axum::Router::new()
+            .route("/health", axum::routing::get(|| async {}))
+            .route("/v1/models", axum::routing::get(move || async move { Json(models) }))
+            .route("/tokenize", axum::routing::post(tokenize));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_n_keep_trims_oldest_turns() {
+        let messages = serde_json::json!([
+            {"role": "system", "content": "be brief"},
+            {"role": "user", "content": "one two three four five six seven eight"},
+            {"role": "assistant", "content": "one two three four five six seven eight"},
+            {"role": "user", "content": "last question here"}
+        ]);
+        let vllm_url = fake_vllm(20).await;
+
+        let engine = MockEngine::new().with_response("ok");
+        let state = ServerState::with_engine(engine.clone(), vllm_url.as_str(), ServerConfig::default());
+        let (status, _) = send_json(
+            state,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": messages, "stream": false, "options": {"n_keep": 1}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].prompt, "be brief\nlast question here");
+
+        // Without n_keep the whole conversation is sent
+        let engine = MockEngine::new().with_response("ok");
+        let state = ServerState::with_engine(engine.clone(), vllm_url.as_str(), ServerConfig::default());
+        let (status, _) = send_json(
+            state,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": messages, "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].prompt.lines().count(), 4);
+
+        // The kept messages alone don't fit
+        let state = ServerState::with_engine(MockEngine::new(), vllm_url.as_str(), ServerConfig::default());
+        let (status, body) = send_json(
+            state,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": messages, "stream": false, "options": {"n_keep": 3}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("Context length exceeded"), "{}", body);
+    }
+
+    #[test]
+    fn test_droppable_turns() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "system", "content": "s"},
+            {"role": "user", "content": "u1"},
+            {"role": "assistant", "content": "a1"},
+            {"role": "tool", "content": "t1"},
+            {"role": "user", "content": "u2"},
+            {"role": "assistant", "content": "a2"},
+            {"role": "user", "content": "u3"}
+        ]))
+        .unwrap();
+        assert_eq!(droppable_turns(&messages, 0), vec![1..4, 4..6]);
+        assert_eq!(droppable_turns(&messages, 2), vec![2..4, 4..6]);
+
+        let kept = without_turns(&messages, &droppable_turns(&messages, 0), 1);
+        let contents: Vec<&str> = kept.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["s", "u2", "a2", "u3"]);
+    }
+
+    #[tokio::test]
+    async fn test_fit_context_long_history() {
+        // 40 turns of 4 words each against a 20 token context
+        let mut messages = vec![ChatMessage::system("be brief")];
+        for i in 0..40 {
+            messages.push(ChatMessage::user(format!("question {}", i)));
+            messages.push(ChatMessage::assistant(format!("answer {}", i)));
+        }
+        messages.push(ChatMessage::user("last question"));
+        let vllm_url = fake_vllm(20).await;
+        let state = ServerState::with_engine(MockEngine::new(), vllm_url.as_str(), ServerConfig::default());
+
+        fit_context(&state, "m", &mut messages, 0, 4).await.unwrap();
+        // The system prompt, last question and reply leave room for 3 turns
+        let contents: Vec<&str> = messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(
+            contents,
+            [
+                "be brief", "question 37", "answer 37", "question 38", "answer 38", "question 39", "answer 39",
+                "last question"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_model_mismatch() {
+        async fn health_json(vllm_url: &str, default_model: &str) -> serde_json::Value {
+            let config = ServerConfig { default_model: Some(default_model.to_string()), ..ServerConfig::default() };
+            let state = ServerState::with_engine(MockEngine::new(), vllm_url, config);
+            let response = Server::router(state)
+                .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice(&bytes).unwrap()
+        }
+
+        let vllm_url = fake_vllm(20).await;
+        let json = health_json(&vllm_url, "m").await;
+        assert_eq!(json["vllm_models"], serde_json::json!(["m"]));
+        assert!(json.get("model_mismatch").is_none());
+
+        let json = health_json(&vllm_url, "other").await;
+        assert_eq!(json["configured_model"], "other");
+        assert!(json["model_mismatch"].as_str().unwrap().contains("'other'"));
+
+        // Nothing to compare against when vLLM is down
+        let json = health_json("http://127.0.0.1:0", "other").await;
+        assert!(json.get("model_mismatch").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bad_template_is_rejected() {
+        let engine = MockEngine::new();
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "template": "{% if %}"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("invalid template"));
+        assert!(engine.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_streams_interleave() {
+        use futures::StreamExt;
+
+        let engine = MockEngine::new()
+            .with_chunks(["a", "b", "c"])
+            .with_chunk_delay(Duration::from_millis(50));
+        let state = ServerState::with_engine(engine, "http://127.0.0.1:0", ServerConfig::default());
+        let app = Server::router(state);
+        let request = || {
+            Request::post("/api/generate")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::json!({"model": "m", "prompt": "hi"}).to_string()))
+                .unwrap()
+        };
+
+        // Both responses start before either stream has produced anything
+        let first = app.clone().oneshot(request()).await.unwrap();
+        let second = tokio::time::timeout(Duration::from_millis(40), app.oneshot(request()))
+            .await
+            .expect("second stream waited for the first")
+            .unwrap();
+
+        let first = first.into_body().into_data_stream().map(|_| 1);
+        let second = second.into_body().into_data_stream().map(|_| 2);
+        let order: Vec<u8> = futures::stream::select(first, second).collect().await;
+
+        let first_of_second = order.iter().position(|&s| s == 2).unwrap();
+        let last_of_first = order.iter().rposition(|&s| s == 1).unwrap();
+        assert!(first_of_second < last_of_first, "streams ran one after the other: {:?}", order);
+    }
+
+    #[tokio::test]
+    async fn test_generate_streaming() {
+        let engine = MockEngine::new().with_chunks(["Hel", "lo"]);
+        let (status, body) = post_json(
+            engine,
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi"}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let events: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let text: String = events.iter().filter_map(|e| e["response"].as_str()).collect();
+        assert_eq!(text, "Hello");
+        assert_eq!(events.last().unwrap()["done"], true);
+        assert_eq!(events.last().unwrap()["done_reason"], "stop");
+        assert!(events[0].get("done_reason").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_api_stream_framing() {
+        for (accept, content_type) in [
+            (None, "application/x-ndjson"),
+            (Some("text/event-stream"), "text/event-stream"),
+        ] {
+            let state = ServerState::with_engine(
+                MockEngine::new().with_chunks(["Hel", "lo"]),
+                "http://127.0.0.1:0",
+                ServerConfig::default(),
+            );
+            let mut request = Request::post("/api/generate").header("content-type", "application/json");
+            if let Some(accept) = accept {
+                request = request.header("accept", accept);
+            }
+            let request = request
+                .body(Body::from(serde_json::json!({"model": "m", "prompt": "hi"}).to_string()))
+                .unwrap();
+
+            let response = Server::router(state).oneshot(request).await.unwrap();
+            assert_eq!(response.headers()["content-type"], content_type);
+            let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(bytes.to_vec()).unwrap();
+            let payloads = match accept {
+                Some(_) => sse_data(&body),
+                None => body.lines().map(str::to_string).collect(),
+            };
+            assert_eq!(payloads.len(), 3);
+            for payload in payloads {
+                serde_json::from_str::<serde_json::Value>(&payload).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_deltas_concatenate() {
+        let engine = MockEngine::new().with_chunks(["The ", "answer ", "is 4."]);
+        let (status, body) = post_json(
+            engine,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "2+2?"}]}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let events: Vec<serde_json::Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let text: String = events.iter().filter_map(|e| e["message"]["content"].as_str()).collect();
+        assert_eq!(text, "The answer is 4.");
+
+        let last = events.last().unwrap();
+        assert_eq!(last["done"], true);
+        assert_eq!(last["message"]["content"], "");
+        assert_eq!(last["eval_count"], 3);
+        assert!(last["total_duration"].is_u64());
+    }
+
+    #[test]
+    fn test_parse_keep_alive() {
+        let parse = |value: serde_json::Value| parse_keep_alive(Some(&value));
+        let secs = |s: u64| Some(KeepAlive::For(Duration::from_secs(s)));
+
+        assert_eq!(parse_keep_alive(None).unwrap(), None);
+        assert_eq!(parse(serde_json::json!(null)).unwrap(), None);
+        assert_eq!(parse(serde_json::json!(300)).unwrap(), secs(300));
+        assert_eq!(parse(serde_json::json!("5m")).unwrap(), secs(300));
+        assert_eq!(parse(serde_json::json!("1h30m")).unwrap(), secs(5400));
+        assert_eq!(parse(serde_json::json!("90")).unwrap(), secs(90));
+        assert_eq!(parse(serde_json::json!(0)).unwrap(), secs(0));
+        assert_eq!(parse(serde_json::json!(-1)).unwrap(), Some(KeepAlive::Forever));
+        assert_eq!(parse(serde_json::json!("-1m")).unwrap(), Some(KeepAlive::Forever));
+        assert_eq!(
+            parse(serde_json::json!("500ms")).unwrap(),
+            Some(KeepAlive::For(Duration::from_millis(500)))
+        );
+
+        for bad in [serde_json::json!("soon"), serde_json::json!("5d"), serde_json::json!("m5"), serde_json::json!(true)] {
+            assert!(parse(bad).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let time = std::time::UNIX_EPOCH + Duration::from_secs(1_738_343_109);
+        assert_eq!(rfc3339(time), "2025-01-31T17:05:09Z");
+        assert_eq!(rfc3339(std::time::UNIX_EPOCH + Duration::from_secs(951_782_400)), "2000-02-29T00:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive_zero_unloads_after_request() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        state.loaded_models.insert("m".to_string(), vllama_core::ModelHandle(1));
+
+        let (status, _) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "keep_alive": 0}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(state.unload_expired().await, vec!["m"]);
+        assert!(state.loaded_models.is_empty());
+
+        let (status, body) = send_json(
+            state,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "hi"}], "keep_alive": "later"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("keep_alive"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_load_holds_no_vram() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config = serde_json::json!({"hidden_size": 2048, "num_hidden_layers": 16, "vocab_size": 128256});
+        std::fs::write(dir.join("config.json"), config.to_string()).unwrap();
+        let model = dir.to_str().unwrap().to_string();
+        let downloader = vllama_core::ModelDownloader::new().unwrap();
+
+        let config = ServerConfig { max_loaded_vram_mb: Some(100_000), ..ServerConfig::default() };
+        let state = ServerState::with_engine(MockEngine::new().with_load_failure(), "http://127.0.0.1:0", config.clone());
+        let source = ModelSource::Local(dir.to_path_buf());
+        assert!(download_and_load(&state, &downloader, &model, &source, None, |_| {}).await.is_err());
+        assert!(state.model_vram.is_empty());
+        assert!(state.loaded_models.is_empty());
+
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        download_and_load(&state, &downloader, &model, &source, None, |_| {}).await.unwrap();
+        assert!(state.model_vram.get(&model).is_some_and(|mb| *mb > 0));
+    }
+
+    #[test]
+    fn test_pull_status() {
+        let mut progress = vllama_core::DownloadProgress {
+            status: "Downloading config.json".to_string(),
+            current_file: Some("config.json".to_string()),
+            file_index: 0,
+            file_count: 3,
+            ..Default::default()
+        };
+        assert_eq!(pull_status(&progress), "Downloading config.json (1/3)");
+
+        progress.file_count = 1;
+        assert_eq!(pull_status(&progress), "Downloading config.json");
+
+        let done = vllama_core::DownloadProgress { status: "completed".to_string(), ..Default::default() };
+        assert_eq!(pull_status(&done), "completed");
+    }
+
+    #[test]
+    fn test_pull_key() {
+        assert_eq!(pull_key("org/model", None), "org/model");
+        assert_eq!(pull_key("org/model", Some("v2")), "org/model@v2");
+        assert_ne!(pull_key("org/model", Some("v2")), pull_key("org/model", Some("main")));
+    }
+
+    #[tokio::test]
+    async fn test_token_counts() {
+        // MockEngine reports one token per prompt word and per chunk
+        let engine = MockEngine::new().with_chunks(["a", "b", "c"]);
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "one two", "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let reply: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(reply["eval_count"], 3);
+        assert_eq!(reply["prompt_eval_count"], 2);
+
+        // Streams ask vLLM for usage and report it on the final event only
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "one two three"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(engine.requests()[1].options.include_usage);
+        let events: Vec<serde_json::Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert!(events[..events.len() - 1].iter().all(|e| e.get("prompt_eval_count").is_none()));
+        let last = events.last().unwrap();
+        assert_eq!(last["eval_count"], 3);
+        assert_eq!(last["prompt_eval_count"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_num_ctx_exceeding_max_model_len() {
+        let engine = MockEngine::new();
+        let state = ServerState::with_engine(engine.clone(), "http://127.0.0.1:0", ServerConfig::default());
+        state.context_lengths.insert("m".to_string(), 4096);
+
+        let (status, body) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": {"num_ctx": 8192}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("4096"));
+        assert!(engine.requests().is_empty());
+
+        let (status, _) = send_json(
+            state,
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": {"num_ctx": 2048}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_report_warnings() {
+        let options = serde_json::json!({"temperature": 0.5, "mirostat": 2, "num_ctx": 2048});
+
+        // Off by default
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        state.context_lengths.insert("m".to_string(), 4096);
+        let (status, body) = send_json(
+            state,
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": options}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains("warnings"));
+
+        let config = ServerConfig { report_warnings: true, ..Default::default() };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        state.context_lengths.insert("m".to_string(), 4096);
+        let (_, body) = send_json(
+            state.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": options}),
+        )
+        .await;
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let warnings = json["warnings"].as_array().unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].as_str().unwrap().starts_with("num_ctx 2048 ignored"));
+        assert!(warnings[1].as_str().unwrap().contains("'mirostat'"));
+
+        // Streaming chat reports them on the final event only
+        let (_, body) = send_json(
+            state,
+            "/api/chat",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "hi"}],
+                "options": {"tfs_z": 0.9}
+            }),
+        )
+        .await;
+        let events: Vec<serde_json::Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let last = events.last().unwrap();
+        assert_eq!(last["done"], true);
+        assert!(last["warnings"][0].as_str().unwrap().contains("'tfs_z'"));
+        assert!(events[..events.len() - 1].iter().all(|e| e.get("warnings").is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_report_openai_warnings() {
+        let config = ServerConfig { report_warnings: true, ..Default::default() };
+        let state = ServerState::with_engine(MockEngine::new().with_response("ok"), "http://127.0.0.1:0", config);
+
+        // Parameters that aren't forwarded are listed; explicit nulls aren't
+        let (status, body) = send_json(
+            state.clone(),
+            "/v1/chat/completions",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "hi"}],
+                "max_tokens": 50,
+                "n": 2,
+                "logit_bias": null
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let warnings = json["warnings"].as_array().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].as_str().unwrap().contains("'n'"));
+
+        // Streaming puts them on the final chunk only
+        let (_, body) = send_json(
+            state,
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": true, "logprobs": 1}),
+        )
+        .await;
+        let chunks: Vec<serde_json::Value> = sse_data(&body)
+            .iter()
+            .filter(|data| *data != "[DONE]")
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(last["warnings"][0].as_str().unwrap().contains("'logprobs'"));
+        assert!(rest.iter().all(|chunk| chunk.get("warnings").is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_are_unique() {
+        let engine = MockEngine::new();
+        let state = ServerState::with_engine(engine.clone(), "http://127.0.0.1:0", ServerConfig::default());
+        let body = serde_json::json!({"model": "m", "prompt": "hi", "stream": false});
+
+        let (_, first) = send_json(state.clone(), "/api/generate", body.clone()).await;
+        let (_, second) = send_json(state, "/api/generate", body).await;
+        let first: serde_json::Value = serde_json::from_str(&first).unwrap();
+        let second: serde_json::Value = serde_json::from_str(&second).unwrap();
+
+        assert_ne!(first["request_id"], second["request_id"]);
+        let ids: Vec<u64> = engine.requests().iter().map(|r| r.id.0).collect();
+        assert_eq!(serde_json::json!(ids), serde_json::json!([first["request_id"], second["request_id"]]));
+    }
+
+    #[tokio::test]
+    async fn test_empty_prompt_and_messages_rejected() {
+        let engine = MockEngine::new();
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": ""}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("prompt must not be empty"));
+
+        let (status, body) = post_json(
+            engine.clone(),
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": []}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("messages must not be empty"));
+
+        assert!(engine.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_num_predict_sentinels() {
+        let engine = MockEngine::new();
+        for num_predict in [32, -1, -2] {
+            let (status, _) = post_json(
+                engine.clone(),
+                "/api/generate",
+                serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": {"num_predict": num_predict}}),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let requests = engine.requests();
+        assert_eq!(requests[0].options.sampling.max_tokens, Some(32));
+        assert_eq!(requests[1].options.sampling.max_tokens, None);
+        // vLLM can't be asked for the prompt length, so -2 falls back to -1
+        assert_eq!(requests[2].options.sampling.max_tokens, None);
+
+        let (status, body) = post_json(
+            engine,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "hi"}], "stream": false, "options": {"num_predict": -3}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("num_predict"));
+    }
+
+    #[tokio::test]
+    async fn test_min_tokens() {
+        let engine = MockEngine::new();
+        let (status, _) = post_json(
+            engine.clone(),
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false, "options": {"min_tokens": 10, "max_tokens": 50}}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(engine.requests()[0].options.sampling.min_tokens, Some(10));
+
+        let (status, body) = post_json(
+            engine,
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "hi", "min_tokens": 100, "max_tokens": 5}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("min_tokens"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_done_reason_length() {
+        let engine = MockEngine::new().with_finish_reason("length");
+        for stream in [false, true] {
+            let (status, body) = post_json(
+                engine.clone(),
+                "/api/chat",
+                serde_json::json!({
+                    "model": "m",
+                    "messages": [{"role": "user", "content": "Hello"}],
+                    "stream": stream
+                }),
+            )
+            .await;
+
+            assert_eq!(status, StatusCode::OK);
+            let final_event = match stream {
+                true => body.lines().last().unwrap().to_string(),
+                false => body,
+            };
+            let json: serde_json::Value = serde_json::from_str(&final_event).unwrap();
+            assert_eq!(json["done"], true);
+            assert_eq!(json["done_reason"], "length");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generate_engine_error() {
+        let engine = MockEngine::new().with_error("backend down");
+        let (status, body) = post_json(
+            engine,
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("backend down"));
+    }
+
+    #[tokio::test]
+    async fn test_typed_engine_error_status() {
+        let engine = MockEngine::new()
+            .with_error_fn(|| vllama_core::Error::ContextLengthExceeded { max: 2048, requested: 3000 });
+        let (status, body) = post_json(
+            engine,
+            "/api/generate",
+            serde_json::json!({"model": "m", "prompt": "hi", "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("2048"));
+
+        let engine = MockEngine::new().with_error_fn(|| vllama_core::Error::RateLimited);
+        let (status, _) = post_json(
+            engine,
+            "/api/chat",
+            serde_json::json!({"model": "m", "messages": [{"role": "user", "content": "hi"}], "stream": false}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_openai_chat_completions_non_streaming() {
+        let engine = MockEngine::new().with_response("Hi there");
+        let (status, body) = post_json(
+            engine,
+            "/v1/chat/completions",
+            serde_json::json!({
+                "model": "m",
+                "messages": [{"role": "user", "content": "Hello"}],
+                "stream": false
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["choices"][0]["message"]["content"], "Hi there");
+        assert_eq!(json["choices"][0]["finish_reason"], "stop");
+        let usage = &json["usage"];
+        assert_eq!(usage["completion_tokens"], 1);
+        assert!(usage["prompt_tokens"].as_u64().unwrap() > 0);
+        assert_eq!(
+            usage["total_tokens"].as_u64(),
+            Some(usage["prompt_tokens"].as_u64().unwrap() + 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_openai_completions_stream_ends_with_done() {
+        let engine = MockEngine::new().with_chunks(["a", "b"]);
+        let (status, body) = post_json(
+            engine,
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "x", "stream": true}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(sse_data(&body).last().map(String::as_str), Some(OPENAI_STREAM_DONE));
+    }
+
+    #[tokio::test]
+    async fn test_openai_completions_text_chunks() {
+        let engine = MockEngine::new().with_chunks(["Sunny ", "and ", "warm"]);
+        let (status, body) = post_json(
+            engine.clone(),
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "The weather today", "stream": true}),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        let data = sse_data(&body);
+        let chunks: Vec<serde_json::Value> = data[..data.len() - 1]
+            .iter()
+            .map(|d| serde_json::from_str(d).unwrap())
+            .collect();
+        assert!(chunks.iter().all(|c| c["object"] == "text_completion" && c["choices"][0]["index"] == 0));
+        let text: String = chunks.iter().filter_map(|c| c["choices"][0]["text"].as_str()).collect();
+        assert_eq!(text, "Sunny and warm");
+        assert!(chunks[0]["choices"][0]["finish_reason"].is_null());
+        assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+
+        let (status, body) = post_json(
+            engine,
+            "/v1/completions",
+            serde_json::json!({"model": "m", "prompt": "The weather today"}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let response: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["object"], "text_completion");
+        assert_eq!(response["choices"][0]["text"], "Sunny and warm");
+        assert_eq!(response["choices"][0]["finish_reason"], "stop");
+        assert_eq!(response["usage"]["completion_tokens"], 3);
+    }
+}
@@ -0,0 +1,157 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use vllama_core::Error;
+
+/// Server configuration
+///
+/// Settings resolved by the caller (CLI flags, config file) and handed to
+/// [`crate::Server`] and [`crate::ServerState`].
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Address the API listens on: an IPv4 or IPv6 address (`::`, `[::1]`)
+    pub host: String,
+    /// Port the API listens on
+    pub port: u16,
+    /// Accept IPv4 and IPv6 clients on one `[::]` socket; `host` must be
+    /// `0.0.0.0` or `::`
+    pub dual_stack: bool,
+    /// Port of the vLLM OpenAI-compatible server on localhost
+    pub vllm_port: u16,
+    /// Full URL of a vLLM server elsewhere (`http://` or `https://`);
+    /// overrides `vllm_port`
+    pub vllm_base_url: Option<String>,
+    /// Skip TLS certificate verification when talking to vLLM
+    pub accept_invalid_certs: bool,
+    /// Remove special tokens (e.g. `<|eot_id|>`) that leak into generated text
+    pub strip_special_tokens: bool,
+    /// Send a one-token generation after a model loads so the first real
+    /// request doesn't pay for warmup
+    pub warmup_on_load: bool,
+    /// Combined bandwidth cap for model downloads, in bytes per second
+    pub max_download_rate: Option<u64>,
+    /// How long a request may wait for the engine before failing with 503
+    pub queue_timeout: Option<Duration>,
+    /// How long a request to vLLM may take, generation included (default
+    /// unlimited)
+    pub request_timeout: Option<Duration>,
+    /// Models kept loaded at once; loading another evicts the least
+    /// recently used
+    pub max_loaded_models: Option<usize>,
+    /// VRAM (MB) the loaded models' weights may use together; loading one
+    /// that wouldn't fit, here or in the GPU's free VRAM, evicts the least
+    /// recently used first
+    pub max_loaded_vram_mb: Option<u64>,
+    /// Model vLLM was started with (its served name); `/health` warns when
+    /// vLLM reports serving something else
+    pub default_model: Option<String>,
+    /// Stop the server after this long without API requests; see
+    /// [`crate::ServerState::wait_until_idle`]
+    pub idle_shutdown: Option<Duration>,
+    /// List request options that were ignored in a `warnings` field of
+    /// generation and chat replies, Ollama and OpenAI alike
+    pub report_warnings: bool,
+    /// Model aliases (`aliases.toml`, see [`vllama_core::ModelAliases`]),
+    /// shared by `vllama alias` and `/api/copy`; None keeps `/api/copy`
+    /// aliases in memory only
+    pub aliases_file: Option<PathBuf>,
+    /// How long a model stays loaded after its last request when the
+    /// request doesn't say (`keep_alive`); None keeps models loaded
+    pub keep_alive: Option<Duration>,
+    /// HuggingFace cache models are pulled into, listed and deleted from;
+    /// None uses `$HF_HOME` or `~/.cache/huggingface`
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    /// Base URL of the vLLM OpenAI-compatible server
+    pub fn vllm_url(&self) -> String {
+        match &self.vllm_base_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("http://127.0.0.1:{}", self.vllm_port),
+        }
+    }
+
+    /// Socket address the API binds to
+    ///
+    /// Fails unless `host` is an IP address (brackets around IPv6 are
+    /// optional; `localhost` means `127.0.0.1`). With `dual_stack` this is
+    /// always the IPv6 wildcard.
+    pub fn bind_addr(&self) -> Result<SocketAddr, Error> {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let ip: IpAddr = if host == "localhost" {
+            Ipv4Addr::LOCALHOST.into()
+        } else {
+            host.parse().map_err(|_| {
+                Error::ConfigError(format!(
+                    "invalid host '{}': expected an IP address such as 127.0.0.1, 0.0.0.0 or ::",
+                    self.host
+                ))
+            })?
+        };
+
+        if self.dual_stack {
+            if !ip.is_unspecified() {
+                return Err(Error::ConfigError(format!(
+                    "dual-stack listens on all interfaces; use host 0.0.0.0 or :: instead of '{}'",
+                    self.host
+                )));
+            }
+            return Ok(SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), self.port));
+        }
+
+        Ok(SocketAddr::new(ip, self.port))
+    }
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 11435,
+            dual_stack: false,
+            vllm_port: 8100,
+            vllm_base_url: None,
+            accept_invalid_certs: false,
+            strip_special_tokens: true,
+            warmup_on_load: true,
+            max_download_rate: None,
+            queue_timeout: None,
+            request_timeout: None,
+            max_loaded_models: None,
+            max_loaded_vram_mb: None,
+            default_model: None,
+            idle_shutdown: None,
+            report_warnings: false,
+            aliases_file: None,
+            keep_alive: None,
+            cache_dir: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_host(host: &str, dual_stack: bool) -> ServerConfig {
+        ServerConfig { host: host.to_string(), port: 11435, dual_stack, ..ServerConfig::default() }
+    }
+
+    #[test]
+    fn test_bind_addr() {
+        assert_eq!(with_host("0.0.0.0", false).bind_addr().unwrap().to_string(), "0.0.0.0:11435");
+        assert_eq!(with_host("::", false).bind_addr().unwrap().to_string(), "[::]:11435");
+        assert_eq!(with_host("[::1]", false).bind_addr().unwrap().to_string(), "[::1]:11435");
+        assert_eq!(with_host("localhost", false).bind_addr().unwrap().to_string(), "127.0.0.1:11435");
+
+        // Either wildcard becomes one IPv6 socket that also takes IPv4
+        assert_eq!(with_host("0.0.0.0", true).bind_addr().unwrap().to_string(), "[::]:11435");
+        assert_eq!(with_host("::", true).bind_addr().unwrap().to_string(), "[::]:11435");
+
+        assert!(with_host("127.0.0.1", true).bind_addr().is_err());
+        assert!(with_host("my-host", false).bind_addr().is_err());
+        assert!(with_host("127.0.0.1:80", false).bind_addr().is_err());
+    }
+}
@@ -1,9 +1,12 @@
 mod api;
+mod config;
+mod prometheus;
 mod server;
 mod state;
 
+pub use config::ServerConfig;
 pub use server::Server;
-pub use state::ServerState;
+pub use state::{InFlightGuard, ModelStats, ModelStatsSnapshot, ModelUsage, ServerState};
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
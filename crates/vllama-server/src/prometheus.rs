@@ -0,0 +1,64 @@
+//! Prometheus metrics served at `/metrics`
+//!
+//! The `metrics` macros record into one process-wide recorder, installed
+//! the first time a server state is built. If the embedding program has
+//! installed its own recorder, `/metrics` renders empty.
+
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// `model` label of the series for requests naming a model that is neither
+/// loaded nor served
+pub const OTHER_MODEL: &str = "other";
+
+/// Every request, labelled by route (`endpoint`)
+pub const REQUESTS_TOTAL: &str = "vllama_requests_total";
+/// Requests answered with a 4xx or 5xx status, labelled by route and `status`
+pub const REQUEST_ERRORS_TOTAL: &str = "vllama_request_errors_total";
+/// Generation requests running or streaming, per model (see
+/// [`OTHER_MODEL`])
+pub const REQUESTS_IN_FLIGHT: &str = "vllama_requests_in_flight";
+/// Time from receiving a generation request to its last token, per model
+pub const GENERATION_DURATION: &str = "vllama_generation_duration_seconds";
+/// Tokens generated per completed request, per model
+pub const GENERATED_TOKENS: &str = "vllama_generated_tokens";
+
+/// How often histogram samples are folded into their buckets between scrapes
+pub const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+const DURATION_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+const TOKEN_BUCKETS: &[f64] = &[1.0, 8.0, 32.0, 128.0, 256.0, 512.0, 1024.0, 2048.0, 4096.0, 8192.0];
+
+static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+/// The recorder's handle, installing the recorder on first use
+pub fn handle() -> &'static PrometheusHandle {
+    HANDLE.get_or_init(|| {
+        let recorder = PrometheusBuilder::new()
+            .set_buckets_for_metric(Matcher::Full(GENERATION_DURATION.to_string()), DURATION_BUCKETS)
+            .and_then(|builder| builder.set_buckets_for_metric(Matcher::Full(GENERATED_TOKENS.to_string()), TOKEN_BUCKETS))
+            .expect("histogram buckets are not empty")
+            .build_recorder();
+        let handle = recorder.handle();
+        if metrics::set_global_recorder(recorder).is_err() {
+            tracing::warn!("A metrics recorder is already installed; /metrics will be empty");
+        }
+        handle
+    })
+}
+
+/// Record one completed generation of the model labelled `model`
+pub fn record_generation(model: &str, tokens: usize, latency: Duration) {
+    metrics::histogram!(GENERATION_DURATION, "model" => model.to_string()).record(latency.as_secs_f64());
+    metrics::histogram!(GENERATED_TOKENS, "model" => model.to_string()).record(tokens as f64);
+}
+
+/// Fold histogram samples into buckets until the process exits, so an
+/// unscraped server doesn't accumulate them
+pub async fn run_upkeep() {
+    loop {
+        tokio::time::sleep(UPKEEP_INTERVAL).await;
+        handle().run_upkeep();
+    }
+}
@@ -1,36 +1,88 @@
 use axum::{
-    routing::{get, post},
+    extract,
+    middleware::{self, Next},
+    routing::{delete, get, post},
     Router,
-    http::{Request, Response},
+    http::{header, Request, Response},
     body::Body,
 };
+use futures::StreamExt;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
 use tracing::{info, Span};
-use std::time::Instant;
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use socket2::{Domain, Protocol, Socket, Type};
 use uuid::Uuid;
 
 use crate::api;
+use crate::config::ServerConfig;
 use crate::state::ServerState;
 
 pub struct Server {
     state: ServerState,
-    host: String,
-    port: u16,
 }
 
 impl Server {
     pub fn new(host: impl Into<String>, port: u16) -> crate::Result<Self> {
-        let state = ServerState::new()?;
-
-        Ok(Self {
-            state,
+        Self::from_config(ServerConfig {
             host: host.into(),
             port,
+            ..ServerConfig::default()
         })
     }
 
+    /// Server backed by the vLLM instance described by `config`
+    pub fn from_config(config: ServerConfig) -> crate::Result<Self> {
+        Ok(Self::with_state(ServerState::from_config(config)?))
+    }
+
+    /// Server around pre-built state; listens on the state's configured address
+    pub fn with_state(state: ServerState) -> Self {
+        Self { state }
+    }
+
+    /// Build the application router around `state`
+    pub fn router(state: ServerState) -> Router {
+        // Install the metrics recorder before the first request is counted
+        crate::prometheus::handle();
+
+        Router::new()
+            // Ollama-compatible API
+            .route("/api/generate", post(api::generate))
+            .route("/api/chat", post(api::chat))
+            .route("/api/pull", post(api::pull))
+            .route("/api/pull/cancel", post(api::pull_cancel))
+            .route("/api/show", post(api::show))
+            .route("/api/delete", delete(api::delete))
+            .route("/api/copy", post(api::copy))
+            .route("/api/embed", post(api::embeddings))
+            .route("/api/embeddings", post(api::embeddings))
+            .route("/api/tags", get(api::tags))
+            .route("/api/ps", get(api::ps))
+            .route("/api/stats", get(api::stats))
+            .route("/api/version", get(api::version))
+            // OpenAI-compatible API
+            .route("/v1/models", get(api::openai_models))
+            .route("/v1/completions", post(api::openai_completions))
+            .route("/v1/chat/completions", post(api::openai_chat_completions))
+            .route("/v1/embeddings", post(api::openai_embeddings))
+            // Liveness and readiness probes
+            .route("/health", get(api::health))
+            .route("/ready", get(api::ready))
+            // Prometheus scrape target
+            .route("/metrics", get(api::metrics))
+            .layer(middleware::from_fn(api::record_metrics))
+            .layer(middleware::from_fn(api::queue_time_header))
+            .layer(middleware::from_fn_with_state(state.clone(), api::record_activity))
+            .layer(CorsLayer::permissive())
+            .with_state(state)
+    }
+
     pub async fn run(self) -> crate::Result<()> {
+        let addr = self.state.config().bind_addr()?;
+        let dual_stack = self.state.config().dual_stack;
+
         // Custom trace layer with request IDs and latency tracking
         let trace_layer = TraceLayer::new_for_http()
             .make_span_with(|request: &Request<Body>| {
@@ -38,18 +90,25 @@ impl Server {
                 let method = request.method().as_str();
                 let uri = request.uri().path();
 
+                // Handlers fill in `model` and `eval_count`; `response_bytes`
+                // is recorded once the body has been sent
                 tracing::info_span!(
                     "request",
                     request_id = %request_id,
                     method = %method,
                     uri = %uri,
+                    start_time = tracing::field::Empty,
+                    model = tracing::field::Empty,
+                    eval_count = tracing::field::Empty,
+                    response_bytes = tracing::field::Empty,
                     latency_ms = tracing::field::Empty,
                     status = tracing::field::Empty,
                 )
             })
-            .on_request(|_request: &Request<Body>, _span: &Span| {
-                // Record request start time
-                _span.record("start_time", Instant::now().elapsed().as_millis() as u64);
+            .on_request(|_request: &Request<Body>, span: &Span| {
+                // Wall-clock start, in Unix milliseconds, for correlating with other logs
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                span.record("start_time", now.as_millis() as u64);
             })
             .on_response(|response: &Response<Body>, latency: std::time::Duration, span: &Span| {
                 let latency_ms = latency.as_millis() as u64;
@@ -63,33 +122,147 @@ impl Server {
                     status = status,
                     "request completed"
                 );
+            })
+            .on_eos(|_trailers: Option<&axum::http::HeaderMap>, stream_duration: std::time::Duration, _span: &Span| {
+                // Streams finish after "request completed"; log the totals they record
+                tracing::info!(stream_ms = stream_duration.as_millis() as u64, "stream completed");
             });
 
-        let app = Router::new()
-            // Ollama-compatible API
-            .route("/api/generate", post(api::generate))
-            .route("/api/chat", post(api::chat))
-            .route("/api/pull", post(api::pull))
-            .route("/api/show", post(api::show))
-            .route("/api/tags", get(api::tags))
-            .route("/api/ps", get(api::ps))
-            .route("/api/version", get(api::version))
-            // OpenAI-compatible API
-            .route("/v1/models", get(api::openai_models))
-            .route("/v1/completions", post(api::openai_completions))
-            .route("/v1/chat/completions", post(api::openai_chat_completions))
-            // Health check
-            .route("/health", get(api::health))
-            .layer(CorsLayer::permissive())
-            .layer(trace_layer)
-            .with_state(self.state);
+        let sweeper = self.state.clone();
+        tokio::spawn(async move { sweeper.unload_idle_models().await });
+        tokio::spawn(crate::prometheus::run_upkeep());
 
-        let addr = format!("{}:{}", self.host, self.port);
-        info!("Starting vLLama server on {}", addr);
+        let app = Self::router(self.state)
+            .layer(middleware::from_fn(record_response_bytes))
+            .layer(trace_layer);
 
-        let listener = tokio::net::TcpListener::bind(&addr).await?;
+        info!("Starting vLLama server on {}{}", addr, if dual_stack { " (dual-stack)" } else { "" });
+
+        let listener = if dual_stack {
+            bind_dual_stack(addr)?
+        } else {
+            tokio::net::TcpListener::bind(addr).await?
+        };
         axum::serve(listener, app).await?;
 
         Ok(())
     }
 }
+
+/// Record `response_bytes` on the request span
+///
+/// Sized responses are recorded up front; streamed ones are counted as they
+/// are sent and recorded when the body finishes or the client goes away.
+async fn record_response_bytes(request: extract::Request, next: Next) -> axum::response::Response {
+    let response = next.run(request).await;
+    let span = Span::current();
+
+    let content_length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(length) = content_length {
+        span.record("response_bytes", length);
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let mut counter = ByteCounter { span, bytes: 0 };
+    let body = body.into_data_stream().map(move |chunk| {
+        if let Ok(bytes) = &chunk {
+            counter.add(bytes.len());
+        }
+        chunk
+    });
+    axum::response::Response::from_parts(parts, Body::from_stream(body))
+}
+
+/// Records the bytes a streamed body sent when it is dropped
+struct ByteCounter {
+    span: Span,
+    bytes: u64,
+}
+
+impl ByteCounter {
+    fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for ByteCounter {
+    fn drop(&mut self) {
+        self.span.record("response_bytes", self.bytes);
+    }
+}
+
+/// IPv6 listener with `IPV6_V6ONLY` cleared, so IPv4 clients arrive as
+/// mapped addresses on the same socket regardless of the OS default
+fn bind_dual_stack(addr: SocketAddr) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing::Instrument;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    /// Collects every `response_bytes` value recorded on a span
+    #[derive(Clone, Default)]
+    struct RecordedBytes(Arc<Mutex<Vec<u64>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for RecordedBytes {
+        fn on_record(&self, _span: &tracing::span::Id, values: &tracing::span::Record<'_>, _ctx: Context<'_, S>) {
+            struct Visitor<'a>(&'a mut Vec<u64>);
+            impl Visit for Visitor<'_> {
+                fn record_u64(&mut self, field: &Field, value: u64) {
+                    if field.name() == "response_bytes" {
+                        self.0.push(value);
+                    }
+                }
+                fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+            }
+            values.record(&mut Visitor(&mut self.0.lock().unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_response_bytes_passes_streams_through() {
+        let recorded = RecordedBytes::default();
+        let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorded.clone()));
+
+        let app = Router::new()
+            .route(
+                "/stream",
+                get(|| async {
+                    let chunks = futures::stream::iter(["a", "bc", "def"].map(Ok::<_, std::io::Error>));
+                    Body::from_stream(chunks)
+                }),
+            )
+            .layer(middleware::from_fn(record_response_bytes));
+
+        let span = tracing::info_span!("request", response_bytes = tracing::field::Empty);
+        let response = app
+            .oneshot(Request::get("/stream").body(Body::empty()).unwrap())
+            .instrument(span)
+            .await
+            .unwrap();
+        // Counted as the body streams, recorded once it has all been sent
+        assert!(recorded.0.lock().unwrap().is_empty());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"abcdef");
+        assert_eq!(*recorded.0.lock().unwrap(), [6]);
+    }
+}
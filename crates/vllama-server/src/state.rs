@@ -1,24 +1,698 @@
 use dashmap::DashMap;
-use vllama_engine::VllmOpenAIEngine;
-use vllama_core::ModelHandle;
+use futures::future::AbortHandle;
+use uuid::Uuid;
+use vllama_engine::{InferenceEngine, VllmOpenAIEngine};
+use vllama_core::{
+    ChatTemplate, Error, GenerateRequest, ModelAliases, ModelDownloader, ModelHandle, RateLimiter, RequestId,
+};
 use tokio::sync::Mutex;
-use std::sync::Arc;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use crate::api::{PullApiResponse, TagsResponse};
+use crate::config::ServerConfig;
+use crate::prometheus;
+
+/// How often idle models are checked against their keep-alive
+const KEEP_ALIVE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a model stays loaded after its last request, as set by
+/// Ollama's `keep_alive`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    /// Never unloaded for being idle
+    Forever,
+    /// Unloaded once idle this long; zero unloads it when the request ends
+    For(Duration),
+}
+
+/// An in-flight `/api/pull`, keyed by model in [`ServerState::pulls`]
+///
+/// Concurrent pulls of the same model share one download; each requester
+/// subscribes to `events` and counts itself in `subscribers`.
+#[derive(Clone)]
+pub struct PullTask {
+    /// Distinguishes this pull from a later one for the same model
+    pub id: Uuid,
+    pub abort: AbortHandle,
+    pub events: broadcast::Sender<PullApiResponse>,
+    pub subscribers: Arc<AtomicUsize>,
+}
 
 #[derive(Clone)]
 pub struct ServerState {
-    pub engine: Arc<Mutex<VllmOpenAIEngine>>,
+    pub engine: Arc<Mutex<dyn InferenceEngine>>,
     pub loaded_models: Arc<DashMap<String, ModelHandle>>,
+    pub pulls: Arc<DashMap<String, PullTask>>,
+    /// Last `/api/tags` listing and the ETag it was built for
+    pub tags_cache: Arc<RwLock<Option<(String, TagsResponse)>>>,
+    /// Shared by every pull so they stay under `max_download_rate` together
+    pub download_limiter: Option<RateLimiter>,
+    /// vLLM's `max_model_len` per served model, fetched on first use
+    pub context_lengths: Arc<DashMap<String, usize>>,
+    /// Chat template per model, read from its config files on first use;
+    /// dropped when the model is pulled or deleted
+    pub chat_templates: Arc<DashMap<String, Arc<ChatTemplate>>>,
+    /// Base URL of the vLLM server, for endpoints that query it directly
+    pub vllm_url: String,
+    /// Client for those direct queries, honouring `accept_invalid_certs`
+    pub http: reqwest::Client,
+    /// See [`ServerState::config`]
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+    /// Source of per-request IDs; see [`ServerState::next_request_id`]
+    request_counter: Arc<AtomicU64>,
+    /// Generation requests being served, keyed by requested model
+    in_flight: Arc<DashMap<String, Arc<AtomicUsize>>>,
+    /// Time requests spent waiting for the engine
+    pub queue: Arc<QueueStats>,
+    /// Lifetime usage per model with a completed request; see
+    /// [`ServerState::model_stats`]
+    stats: Arc<DashMap<String, Arc<ModelStats>>>,
+    /// When each model last loaded or served a request
+    pub last_used: Arc<DashMap<String, Instant>>,
+    /// Estimated VRAM (MB) of each loaded model's weights, for
+    /// `max_loaded_vram_mb`
+    pub model_vram: Arc<DashMap<String, u64>>,
+    /// When the last API request (other than health probes) arrived
+    last_activity: Arc<RwLock<Instant>>,
+    /// Other names for models, from `vllama alias` and `/api/copy`
+    pub aliases: Arc<RwLock<ModelAliases>>,
+    /// Keep-alive set by each model's latest request; models without one
+    /// use `config.keep_alive`
+    pub keep_alive: Arc<DashMap<String, KeepAlive>>,
+    /// Wakes [`ServerState::unload_idle_models`] when a request ends
+    unload_check: Arc<Notify>,
+}
+
+/// Cumulative usage of one model since the server started
+#[derive(Debug, Default)]
+pub struct ModelStats {
+    requests: AtomicU64,
+    tokens: AtomicU64,
+    total_latency_ms: AtomicU64,
+}
+
+/// Point-in-time copy of [`ModelStats`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelStatsSnapshot {
+    pub model: String,
+    /// Completed generation requests
+    pub requests: u64,
+    /// Tokens generated across those requests
+    pub eval_count: u64,
+    pub total_latency_ms: u64,
+    pub avg_latency_ms: u64,
+}
+
+impl ModelStats {
+    /// Count one completed request that generated `tokens` in `latency`
+    pub fn record(&self, tokens: usize, latency: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.tokens.fetch_add(tokens as u64, Ordering::Relaxed);
+        self.total_latency_ms.fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self, model: &str) -> ModelStatsSnapshot {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let total_latency_ms = self.total_latency_ms.load(Ordering::Relaxed);
+        ModelStatsSnapshot {
+            model: model.to_string(),
+            requests,
+            eval_count: self.tokens.load(Ordering::Relaxed),
+            total_latency_ms,
+            avg_latency_ms: total_latency_ms.checked_div(requests).unwrap_or(0),
+        }
+    }
+}
+
+/// A request's handle on its model's [`ModelStats`] and Prometheus series;
+/// see [`ServerState::model_stats`]
+#[derive(Debug)]
+pub struct ModelUsage {
+    stats: Arc<DashMap<String, Arc<ModelStats>>>,
+    model: String,
+    /// See [`ServerState::metrics_label`]
+    label: String,
+}
+
+impl ModelUsage {
+    /// Count one completed request that generated `tokens` in `latency`,
+    /// in the model's stats and the Prometheus histograms
+    ///
+    /// The model's stats are created here, so names whose requests all
+    /// fail never show up in them.
+    pub fn record(&self, tokens: usize, latency: Duration) {
+        self.stats.entry(self.model.clone()).or_default().record(tokens, latency);
+        prometheus::record_generation(&self.label, tokens, latency);
+    }
+}
+
+/// Counters for requests waiting on the engine
+#[derive(Debug, Default)]
+pub struct QueueStats {
+    requests: AtomicU64,
+    timeouts: AtomicU64,
+    total_wait_ms: AtomicU64,
+    max_wait_ms: AtomicU64,
+}
+
+/// Point-in-time copy of [`QueueStats`]
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub requests: u64,
+    pub timeouts: u64,
+    pub total_wait_ms: u64,
+    pub max_wait_ms: u64,
+}
+
+impl QueueStats {
+    pub fn record_wait(&self, wait: Duration) {
+        let ms = wait.as_millis() as u64;
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.total_wait_ms.fetch_add(ms, Ordering::Relaxed);
+        self.max_wait_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            total_wait_ms: self.total_wait_ms.load(Ordering::Relaxed),
+            max_wait_ms: self.max_wait_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Counts one request in [`ServerState::in_flight_count`] until dropped
+pub struct InFlightGuard {
+    model: String,
+    counter: Arc<AtomicUsize>,
+    last_activity: Arc<RwLock<Instant>>,
+    last_used: Arc<DashMap<String, Instant>>,
+    unload_check: Arc<Notify>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+        // A finished request is activity too, so a long stream isn't idle time
+        *self.last_activity.write().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+        // Keep-alive counts from the end of the request
+        self.last_used.insert(self.model.clone(), Instant::now());
+        self.unload_check.notify_one();
+    }
 }
 
 impl ServerState {
+    /// State for the default configuration, backed by vLLM
     pub fn new() -> crate::Result<Self> {
-        let engine = VllmOpenAIEngine::new("http://127.0.0.1:8100");
+        Self::from_config(ServerConfig::default())
+    }
 
-        Ok(Self {
+    /// State backed by the vLLM server described by `config`
+    pub fn from_config(config: ServerConfig) -> crate::Result<Self> {
+        let vllm_url = config.vllm_url();
+        let mut engine = VllmOpenAIEngine::new(vllm_url.clone())
+            .with_special_token_stripping(config.strip_special_tokens)
+            .with_accept_invalid_certs(config.accept_invalid_certs);
+        if let Some(timeout) = config.request_timeout {
+            engine = engine.with_timeout(timeout);
+        }
+        Ok(Self::with_engine(engine, vllm_url, config))
+    }
+
+    /// Build state around an arbitrary engine, e.g. a `MockEngine` in tests
+    pub fn with_engine(
+        engine: impl InferenceEngine + 'static,
+        vllm_url: impl Into<String>,
+        config: ServerConfig,
+    ) -> Self {
+        Self {
             engine: Arc::new(Mutex::new(engine)),
             loaded_models: Arc::new(DashMap::new()),
+            pulls: Arc::new(DashMap::new()),
+            tags_cache: Arc::new(RwLock::new(None)),
+            download_limiter: config.max_download_rate.map(RateLimiter::new),
+            context_lengths: Arc::new(DashMap::new()),
+            chat_templates: Arc::new(DashMap::new()),
+            vllm_url: vllm_url.into(),
+            http: vllama_core::openai::http_client(config.accept_invalid_certs),
+            request_counter: Arc::new(AtomicU64::new(1)),
+            in_flight: Arc::new(DashMap::new()),
+            queue: Arc::new(QueueStats::default()),
+            stats: Arc::new(DashMap::new()),
+            last_used: Arc::new(DashMap::new()),
+            model_vram: Arc::new(DashMap::new()),
+            last_activity: Arc::new(RwLock::new(Instant::now())),
+            aliases: Arc::new(RwLock::new(load_aliases(config.aliases_file.as_deref()))),
+            keep_alive: Arc::new(DashMap::new()),
+            unload_check: Arc::new(Notify::new()),
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+}
+
+/// Saved aliases; an unreadable file is reported and treated as empty
+fn load_aliases(path: Option<&Path>) -> ModelAliases {
+    let Some(path) = path else {
+        return ModelAliases::default();
+    };
+    ModelAliases::load(path).unwrap_or_else(|e| {
+        warn!("Ignoring model aliases: {}", e);
+        ModelAliases::default()
+    })
+}
+
+impl ServerState {
+    /// The current configuration
+    ///
+    /// Settings read per request can change while the server runs (see
+    /// [`ServerState::update_config`]); take a fresh copy rather than
+    /// holding on to one.
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Change settings of the running server
+    ///
+    /// Only settings read per request take effect; ones used at startup
+    /// (addresses, the engine, rate limits) need a restart.
+    pub fn update_config(&self, update: impl FnOnce(&mut ServerConfig)) {
+        let mut config = self.config.write().unwrap_or_else(|e| e.into_inner());
+        let mut updated = ServerConfig::clone(&config);
+        update(&mut updated);
+        *config = Arc::new(updated);
+    }
+
+    /// A downloader for the configured cache, sharing the download rate
+    /// limit with every other pull
+    pub fn downloader(&self) -> vllama_core::Result<ModelDownloader> {
+        let downloader = match &self.config().cache_dir {
+            Some(dir) => ModelDownloader::with_cache_dir(dir)?,
+            None => ModelDownloader::new()?,
+        };
+        Ok(match &self.download_limiter {
+            Some(limiter) => downloader.with_rate_limit(limiter.clone()),
+            None => downloader,
         })
     }
+
+    /// The model a request's `name` refers to, following aliases; other
+    /// names pass through unchanged
+    ///
+    /// A cycle in a hand-edited aliases file is logged and leaves `name` as
+    /// given.
+    pub fn resolve_model_name(&self, name: &str) -> String {
+        let aliases = self.aliases.read().unwrap_or_else(|e| e.into_inner());
+        aliases.resolve(name).unwrap_or_else(|e| {
+            warn!("{}", e);
+            name.to_string()
+        })
+    }
+
+    /// Make `alias` another name for `source` and save the aliases
+    pub fn set_alias(&self, alias: &str, source: &str) -> vllama_core::Result<()> {
+        self.update_aliases(|aliases| aliases.insert(alias, source).map(|()| true))
+    }
+
+    /// Drop `alias`, returning the model it stood for; the model is kept
+    pub fn remove_alias(&self, alias: &str) -> vllama_core::Result<Option<String>> {
+        let mut removed = None;
+        self.update_aliases(|aliases| {
+            removed = aliases.remove(alias);
+            Ok(removed.is_some())
+        })?;
+        Ok(removed)
+    }
+
+    /// Apply `change` to the aliases, saving them if it reports a change
+    ///
+    /// Starts from the file rather than the copy read at startup, so aliases
+    /// added with `vllama alias` since then are neither lost nor missed.
+    fn update_aliases(
+        &self,
+        change: impl FnOnce(&mut ModelAliases) -> vllama_core::Result<bool>,
+    ) -> vllama_core::Result<()> {
+        let mut aliases = self.aliases.write().unwrap_or_else(|e| e.into_inner());
+        let path = self.config().aliases_file.clone();
+        let mut updated = match &path {
+            Some(path) => ModelAliases::load(path)?,
+            None => aliases.clone(),
+        };
+        if change(&mut updated)? {
+            if let Some(path) = &path {
+                updated.save(path)?;
+            }
+        }
+        *aliases = updated;
+        Ok(())
+    }
+
+    /// Allocate the ID for a new generation request
+    ///
+    /// IDs count up from 1 for the lifetime of the server and are echoed in
+    /// response bodies and logs so a request can be traced end to end.
+    pub fn next_request_id(&self) -> RequestId {
+        RequestId(self.request_counter.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Count a request against `model` until the returned guard is dropped
+    ///
+    /// Also marks `model` as just used.
+    pub fn track_in_flight(&self, model: &str) -> InFlightGuard {
+        self.touch(model);
+        let counter = self.in_flight.entry(model.to_string()).or_default().clone();
+        counter.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard {
+            model: model.to_string(),
+            counter,
+            last_activity: self.last_activity.clone(),
+            last_used: self.last_used.clone(),
+            unload_check: self.unload_check.clone(),
+        }
+    }
+
+    /// Generation requests currently being served for `model`
+    pub fn in_flight_count(&self, model: &str) -> usize {
+        self.in_flight
+            .get(model)
+            .map_or(0, |counter| counter.load(Ordering::SeqCst))
+    }
+
+    /// Generation requests currently being served, per model seen so far
+    pub fn in_flight_counts(&self) -> Vec<(String, usize)> {
+        self.in_flight
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::SeqCst)))
+            .collect()
+    }
+
+    /// Mark `model` as just used
+    pub fn touch(&self, model: &str) {
+        self.last_used.insert(model.to_string(), Instant::now());
+    }
+
+    /// Keep `model` loaded for `keep_alive` after its requests, or for
+    /// `config.keep_alive` if None
+    pub fn set_keep_alive(&self, model: &str, keep_alive: Option<KeepAlive>) {
+        match keep_alive {
+            Some(keep_alive) => {
+                self.keep_alive.insert(model.to_string(), keep_alive);
+            }
+            None => {
+                self.keep_alive.remove(model);
+            }
+        }
+    }
+
+    fn keep_alive_for(&self, model: &str) -> KeepAlive {
+        match self.keep_alive.get(model) {
+            Some(keep_alive) => *keep_alive,
+            None => self.config().keep_alive.map_or(KeepAlive::Forever, KeepAlive::For),
+        }
+    }
+
+    /// When `model` will be unloaded if no request arrives first; None if
+    /// it isn't loaded or is kept loaded
+    ///
+    /// A model with requests in flight expires its keep-alive after they end.
+    pub fn expires_at(&self, model: &str) -> Option<SystemTime> {
+        if !self.loaded_models.contains_key(model) {
+            return None;
+        }
+        let KeepAlive::For(keep_alive) = self.keep_alive_for(model) else {
+            return None;
+        };
+        let idle = match self.last_used.get(model) {
+            Some(used) if self.in_flight_count(model) == 0 => used.elapsed(),
+            _ => Duration::ZERO,
+        };
+        Some(SystemTime::now() + keep_alive.saturating_sub(idle))
+    }
+
+    /// Unload every idle model whose keep-alive has run out, returning
+    /// their names
+    pub async fn unload_expired(&self) -> Vec<String> {
+        let expired: Vec<String> = self
+            .loaded_models
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|model| {
+                let KeepAlive::For(keep_alive) = self.keep_alive_for(model) else {
+                    return false;
+                };
+                self.in_flight_count(model) == 0
+                    && self.last_used.get(model).is_some_and(|used| used.elapsed() >= keep_alive)
+            })
+            .collect();
+        if expired.is_empty() {
+            return expired;
+        }
+
+        let mut engine = self.engine.lock().await;
+        let mut unloaded = Vec::with_capacity(expired.len());
+        for model in expired {
+            // A request may have started while waiting for the engine
+            if self.in_flight_count(&model) > 0 {
+                continue;
+            }
+            let Some((_, handle)) = self.loaded_models.remove(&model) else {
+                continue;
+            };
+            info!("Unloading {}: idle past its keep-alive", model);
+            if let Err(e) = engine.unload_model(handle).await {
+                warn!("Failed to unload {}: {}", model, e);
+            }
+            self.last_used.remove(&model);
+            self.model_vram.remove(&model);
+            self.keep_alive.remove(&model);
+            unloaded.push(model);
+        }
+        unloaded
+    }
+
+    /// Unload models as their keep-alive runs out; runs until dropped
+    ///
+    /// Checks every second, and as soon as a request ends so a
+    /// `keep_alive` of 0 takes effect right away.
+    pub async fn unload_idle_models(&self) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(KEEP_ALIVE_CHECK_INTERVAL) => {}
+                _ = self.unload_check.notified() => {}
+            }
+            self.unload_expired().await;
+        }
+    }
+
+    /// Note that an API request just arrived, resetting the idle timer
+    pub fn record_activity(&self) {
+        *self.last_activity.write().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    /// Resolve once no API request has arrived for `limit` and none is
+    /// still running
+    ///
+    /// Long streams and pulls keep the server busy; the timer restarts when a
+    /// request ends.
+    pub async fn wait_until_idle(&self, limit: Duration) {
+        loop {
+            let busy = self.in_flight.iter().any(|counter| counter.load(Ordering::SeqCst) > 0);
+            if busy || !self.pulls.is_empty() {
+                tokio::time::sleep(limit).await;
+                continue;
+            }
+            let last = *self.last_activity.read().unwrap_or_else(|e| e.into_inner());
+            let deadline = last + limit;
+            if Instant::now() >= deadline {
+                return;
+            }
+            tokio::time::sleep_until(deadline.into()).await;
+        }
+    }
+
+    /// Unload least-recently-used models until `model` fits under
+    /// `max_loaded_models`
+    ///
+    /// Models with requests in flight are never evicted; if every loaded
+    /// model is busy the new one can't be loaded.
+    pub async fn make_room(&self, engine: &mut dyn InferenceEngine, model: &str) -> vllama_core::Result<()> {
+        let Some(max) = self.config().max_loaded_models else {
+            return Ok(());
+        };
+        // Reloading a model takes no extra slot
+        if self.loaded_models.contains_key(model) {
+            return Ok(());
+        }
+
+        while self.loaded_models.len() >= max {
+            if !self.evict_one(engine, model).await? {
+                return Err(Error::ModelLoadFailed(format!(
+                    "cannot load {}: server.max_loaded_models is {} and no loaded model can be unloaded ({} in use)",
+                    model,
+                    max,
+                    self.loaded_models.len()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Unload least-recently-used models until `need_mb` of VRAM for
+    /// `model` fits under `max_loaded_vram_mb`, next to the estimates of the
+    /// models still loaded
+    ///
+    /// The GPU's free VRAM isn't consulted: vLLM preallocates its share of
+    /// the card, so it reads near zero once anything is loaded. A model that
+    /// can't fit even with every other model unloaded is refused up front,
+    /// rather than crashing vLLM out of memory halfway through loading.
+    pub async fn make_vram_room(
+        &self,
+        engine: &mut dyn InferenceEngine,
+        model: &str,
+        need_mb: u64,
+    ) -> vllama_core::Result<()> {
+        let Some(limit) = self.config().max_loaded_vram_mb else {
+            return Ok(());
+        };
+
+        let loaded_mb = || {
+            self.model_vram
+                .iter()
+                .filter(|entry| entry.key() != model)
+                .map(|entry| *entry.value())
+                .sum::<u64>()
+        };
+        if need_mb > limit {
+            return Err(Error::ModelLoadFailed(format!(
+                "not loading {} to avoid running out of GPU memory: it needs about {} MB of VRAM but at most {} MB is available",
+                model, need_mb, limit
+            )));
+        }
+
+        while loaded_mb() + need_mb > limit {
+            if !self.evict_one(engine, model).await? {
+                return Err(Error::ModelLoadFailed(format!(
+                    "not loading {} to avoid running out of GPU memory: it needs about {} MB of VRAM, {} of {} MB is held by models in use",
+                    model,
+                    need_mb,
+                    loaded_mb(),
+                    limit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Unload the least recently used idle model other than `model`
+    ///
+    /// Returns false if there is none.
+    async fn evict_one(&self, engine: &mut dyn InferenceEngine, model: &str) -> vllama_core::Result<bool> {
+        let candidates: Vec<String> = self
+            .loaded_models
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|name| name != model && self.in_flight_count(name) == 0)
+            .collect();
+        // Models never used since loading count as oldest
+        let Some(victim) = candidates
+            .into_iter()
+            .min_by_key(|name| self.last_used.get(name).map(|used| *used))
+        else {
+            return Ok(false);
+        };
+
+        if let Some((_, handle)) = self.loaded_models.remove(&victim) {
+            info!("Unloading least recently used model {} to make room for {}", victim, model);
+            engine.unload_model(handle).await?;
+        }
+        self.last_used.remove(&victim);
+        self.model_vram.remove(&victim);
+        Ok(true)
+    }
+
+    /// `model`'s chat template, resolved once and then reused
+    ///
+    /// The first call reads the model's config files, on the blocking pool.
+    pub async fn chat_template(&self, model: &str) -> Arc<ChatTemplate> {
+        if let Some(template) = self.chat_templates.get(model) {
+            return template.clone();
+        }
+        let name = model.to_string();
+        let template = tokio::task::spawn_blocking(move || vllama_core::templates::template_for_model(&name))
+            .await
+            .unwrap_or_else(|_| vllama_core::templates::get_template_for_model(model));
+        let template = Arc::new(template);
+        self.chat_templates.insert(model.to_string(), template.clone());
+        template
+    }
+
+    /// Usage counters for `model`, created when a request is first recorded
+    ///
+    /// Handlers record a request once it completes; streams hold on to the
+    /// returned handle until their final event.
+    pub fn model_stats(&self, model: &str) -> ModelUsage {
+        ModelUsage {
+            stats: self.stats.clone(),
+            model: model.to_string(),
+            label: self.metrics_label(model),
+        }
+    }
+
+    /// `model` as a Prometheus label: its name if it is loaded or is the
+    /// model vLLM serves, else [`prometheus::OTHER_MODEL`], so clients
+    /// sending arbitrary names can't create series without bound
+    pub fn metrics_label(&self, model: &str) -> String {
+        if self.loaded_models.contains_key(model) || self.config().default_model.as_deref() == Some(model) {
+            model.to_string()
+        } else {
+            prometheus::OTHER_MODEL.to_string()
+        }
+    }
+
+    /// Usage of every model requested so far, sorted by name
+    pub fn stats_snapshot(&self) -> Vec<ModelStatsSnapshot> {
+        let mut models: Vec<ModelStatsSnapshot> = self
+            .stats
+            .iter()
+            .map(|entry| entry.value().snapshot(entry.key()))
+            .collect();
+        models.sort_by(|a, b| a.model.cmp(&b.model));
+        models
+    }
+
+    /// Run a throwaway one-token generation against `model`
+    ///
+    /// The first request after a load pays for CUDA graph capture and cold
+    /// caches; doing it here keeps that out of user-facing latency. Failures
+    /// are logged and otherwise ignored. Returns how long warmup took.
+    pub async fn warm_up(&self, model: &str) -> Option<Duration> {
+        let mut request = GenerateRequest::new(self.next_request_id().0, model.to_string(), "Hello".to_string());
+        request.options.sampling.max_tokens = Some(1);
+
+        let start = Instant::now();
+        let engine = self.engine.lock().await;
+        match engine.generate(request).await {
+            Ok(_) => {
+                let elapsed = start.elapsed();
+                info!("Warmed up {} in {:?}", model, elapsed);
+                Some(elapsed)
+            }
+            Err(e) => {
+                warn!("Warmup request for {} failed: {}", model, e);
+                None
+            }
+        }
+    }
 }
 
 impl Default for ServerState {
@@ -26,3 +700,186 @@ impl Default for ServerState {
         Self::new().expect("Failed to create ServerState")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vllama_engine::MockEngine;
+
+    #[tokio::test]
+    async fn test_warm_up_requests_one_token() {
+        let engine = MockEngine::new();
+        let state = ServerState::with_engine(engine.clone(), "http://127.0.0.1:0", ServerConfig::default());
+
+        assert!(state.warm_up("m").await.is_some());
+
+        let requests = engine.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model, "m");
+        assert_eq!(requests[0].options.sampling.max_tokens, Some(1));
+    }
+
+    #[test]
+    fn test_in_flight_guard() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        assert_eq!(state.in_flight_count("m"), 0);
+
+        let first = state.track_in_flight("m");
+        let second = state.track_in_flight("m");
+        assert_eq!(state.in_flight_count("m"), 2);
+        assert_eq!(state.in_flight_count("other"), 0);
+
+        drop(first);
+        assert_eq!(state.in_flight_count("m"), 1);
+        drop(second);
+        assert_eq!(state.in_flight_count("m"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_idle() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        let limit = Duration::from_millis(100);
+
+        // A request in flight keeps the server busy past the limit
+        let guard = state.track_in_flight("m");
+        let idle = tokio::time::timeout(Duration::from_millis(300), state.wait_until_idle(limit)).await;
+        assert!(idle.is_err());
+
+        drop(guard);
+        let started = Instant::now();
+        state.wait_until_idle(limit).await;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_make_room_evicts_least_recently_used() {
+        let config = ServerConfig { max_loaded_models: Some(2), ..ServerConfig::default() };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        for model in ["a", "b"] {
+            state.loaded_models.insert(model.to_string(), ModelHandle(0));
+            state.touch(model);
+        }
+        state.touch("a");
+
+        let mut engine = MockEngine::new();
+        state.make_room(&mut engine, "c").await.unwrap();
+        assert!(state.loaded_models.contains_key("a"));
+        assert!(!state.loaded_models.contains_key("b"));
+
+        // Busy models stay loaded even when that means refusing the load
+        state.loaded_models.insert("c".to_string(), ModelHandle(0));
+        let _a = state.track_in_flight("a");
+        let _c = state.track_in_flight("c");
+        assert!(state.make_room(&mut engine, "d").await.is_err());
+        assert_eq!(state.loaded_models.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_make_room_for_loaded_model() {
+        let config = ServerConfig { max_loaded_models: Some(2), ..ServerConfig::default() };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        for model in ["a", "b"] {
+            state.loaded_models.insert(model.to_string(), ModelHandle(0));
+            state.touch(model);
+        }
+
+        let mut engine = MockEngine::new();
+        state.make_room(&mut engine, "b").await.unwrap();
+        assert!(state.loaded_models.contains_key("a"));
+        assert!(state.loaded_models.contains_key("b"));
+    }
+
+    #[tokio::test]
+    async fn test_chat_template_cached() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let config = dir.join("tokenizer_config.json");
+        std::fs::write(&config, r#"{"eos_token": "<|im_end|>"}"#).unwrap();
+        let model = dir.to_str().unwrap();
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        assert_eq!(*state.chat_template(model).await, ChatTemplate::ChatMl);
+
+        // Later requests don't read the config again
+        std::fs::write(&config, r#"{"eos_token": "<|eot_id|>"}"#).unwrap();
+        assert_eq!(*state.chat_template(model).await, ChatTemplate::ChatMl);
+        state.chat_templates.remove(model);
+        assert_eq!(*state.chat_template(model).await, ChatTemplate::Llama3);
+    }
+
+    #[tokio::test]
+    async fn test_make_vram_room() {
+        let config = ServerConfig { max_loaded_vram_mb: Some(10_000), ..ServerConfig::default() };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        for (model, vram) in [("a", 3000), ("b", 3000)] {
+            state.loaded_models.insert(model.to_string(), ModelHandle(0));
+            state.model_vram.insert(model.to_string(), vram);
+            state.touch(model);
+        }
+        state.touch("a");
+        let mut engine = MockEngine::new();
+
+        // Fits next to both
+        state.make_vram_room(&mut engine, "c", 4000).await.unwrap();
+        assert_eq!(state.loaded_models.len(), 2);
+
+        // Needs "b"'s share of the budget
+        state.make_vram_room(&mut engine, "c", 5000).await.unwrap();
+        assert!(state.loaded_models.contains_key("a"));
+        assert!(!state.loaded_models.contains_key("b"));
+
+        // Too big even with everything else unloaded: refused, nothing unloaded
+        let err = state.make_vram_room(&mut engine, "d", 11_000).await.unwrap_err();
+        assert!(err.to_string().contains("out of GPU memory"));
+        assert!(state.loaded_models.contains_key("a"));
+    }
+
+    #[tokio::test]
+    async fn test_unload_expired() {
+        let config = ServerConfig { keep_alive: Some(Duration::from_millis(50)), ..ServerConfig::default() };
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", config);
+        for model in ["a", "b", "c"] {
+            state.loaded_models.insert(model.to_string(), ModelHandle(0));
+            state.touch(model);
+        }
+        state.set_keep_alive("b", Some(KeepAlive::Forever));
+        assert!(state.expires_at("a").is_some());
+        assert!(state.expires_at("b").is_none());
+        assert!(state.unload_expired().await.is_empty());
+
+        // Busy models wait for their requests to end
+        let busy = state.track_in_flight("c");
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(state.unload_expired().await, vec!["a"]);
+        assert!(state.loaded_models.contains_key("b"));
+
+        // Keep-alive counts from the end of the request
+        drop(busy);
+        assert!(state.unload_expired().await.is_empty());
+        state.set_keep_alive("c", Some(KeepAlive::For(Duration::ZERO)));
+        assert_eq!(state.unload_expired().await, vec!["c"]);
+        assert!(state.last_used.get("c").is_none());
+    }
+
+    #[test]
+    fn test_model_stats() {
+        let state = ServerState::with_engine(MockEngine::new(), "http://127.0.0.1:0", ServerConfig::default());
+        assert!(state.stats_snapshot().is_empty());
+
+        // Nothing is counted until a request completes
+        let pending = state.model_stats("never-completed");
+        assert!(state.stats_snapshot().is_empty());
+        drop(pending);
+
+        state.model_stats("b").record(10, Duration::from_millis(300));
+        state.model_stats("b").record(4, Duration::from_millis(100));
+        state.model_stats("a").record(1, Duration::from_millis(50));
+
+        let stats = state.stats_snapshot();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].model, "a");
+        assert_eq!(stats[1].requests, 2);
+        assert_eq!(stats[1].eval_count, 14);
+        assert_eq!(stats[1].total_latency_ms, 400);
+        assert_eq!(stats[1].avg_latency_ms, 200);
+    }
+}